@@ -0,0 +1,2 @@
+ALTER TABLE buddies DROP COLUMN cadence_seconds;
+ALTER TABLE users DROP COLUMN digest_frequency;
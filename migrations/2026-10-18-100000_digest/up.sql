@@ -0,0 +1,2 @@
+ALTER TABLE users ADD COLUMN digest_frequency VARCHAR NOT NULL DEFAULT 'never';
+ALTER TABLE buddies ADD COLUMN cadence_seconds BIGINT;
@@ -0,0 +1,4 @@
+ALTER TABLE interactions DROP COLUMN initiator;
+ALTER TABLE interactions DROP COLUMN location;
+ALTER TABLE interactions DROP COLUMN duration_seconds;
+ALTER TABLE interactions DROP COLUMN kind;
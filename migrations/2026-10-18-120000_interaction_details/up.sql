@@ -0,0 +1,4 @@
+ALTER TABLE interactions ADD COLUMN kind VARCHAR;
+ALTER TABLE interactions ADD COLUMN duration_seconds BIGINT;
+ALTER TABLE interactions ADD COLUMN location VARCHAR;
+ALTER TABLE interactions ADD COLUMN initiator VARCHAR;
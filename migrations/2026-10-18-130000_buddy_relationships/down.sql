@@ -0,0 +1 @@
+DROP TABLE buddy_relationships
@@ -0,0 +1,12 @@
+CREATE TABLE buddy_relationships (
+  id SERIAL PRIMARY KEY,
+  uuid VARCHAR NOT NULL,
+  from_buddy_uuid VARCHAR NOT NULL,
+  to_buddy_uuid VARCHAR NOT NULL,
+  kind VARCHAR NOT NULL,
+  bidirectional BOOLEAN NOT NULL,
+  create_timestamp VARCHAR NOT NULL,
+  last_update_timestamp VARCHAR NOT NULL,
+  delete_timestamp VARCHAR,
+  user_uuid VARCHAR NOT NULL
+)
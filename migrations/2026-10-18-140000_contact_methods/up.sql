@@ -0,0 +1,13 @@
+CREATE TABLE contact_methods (
+  id SERIAL PRIMARY KEY,
+  uuid VARCHAR NOT NULL,
+  buddy_uuid VARCHAR NOT NULL,
+  kind VARCHAR NOT NULL,
+  value VARCHAR NOT NULL,
+  label VARCHAR,
+  preferred BOOLEAN NOT NULL,
+  create_timestamp VARCHAR NOT NULL,
+  last_update_timestamp VARCHAR NOT NULL,
+  delete_timestamp VARCHAR,
+  user_uuid VARCHAR NOT NULL
+)
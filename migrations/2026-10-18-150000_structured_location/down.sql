@@ -0,0 +1,6 @@
+ALTER TABLE buddies DROP COLUMN timezone;
+ALTER TABLE buddies DROP COLUMN longitude;
+ALTER TABLE buddies DROP COLUMN latitude;
+ALTER TABLE buddies DROP COLUMN country_code;
+ALTER TABLE buddies DROP COLUMN region;
+ALTER TABLE buddies DROP COLUMN city;
@@ -0,0 +1,18 @@
+ALTER TABLE buddies ADD COLUMN city VARCHAR;
+ALTER TABLE buddies ADD COLUMN region VARCHAR;
+ALTER TABLE buddies ADD COLUMN country_code VARCHAR;
+ALTER TABLE buddies ADD COLUMN latitude DOUBLE PRECISION;
+ALTER TABLE buddies ADD COLUMN longitude DOUBLE PRECISION;
+ALTER TABLE buddies ADD COLUMN timezone VARCHAR;
+
+-- Read "City", "City, Region" and "City, Region, CC" the same way Location::from_text does.
+-- The original string stays in location as the fallback.
+UPDATE buddies SET
+  city = NULLIF(trim(split_part(location, ',', 1)), ''),
+  region = NULLIF(trim(split_part(location, ',', 2)), ''),
+  country_code = CASE
+    WHEN trim(split_part(location, ',', 3)) ~ '^[A-Za-z]{2}$'
+    THEN upper(trim(split_part(location, ',', 3)))
+  END
+WHERE location IS NOT NULL
+  AND array_length(string_to_array(location, ','), 1) <= 3;
@@ -0,0 +1 @@
+DROP TABLE important_dates
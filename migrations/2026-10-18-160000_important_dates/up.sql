@@ -0,0 +1,13 @@
+CREATE TABLE important_dates (
+  id SERIAL PRIMARY KEY,
+  uuid VARCHAR NOT NULL,
+  buddy_uuid VARCHAR NOT NULL,
+  kind VARCHAR NOT NULL,
+  label VARCHAR,
+  date VARCHAR NOT NULL,
+  recurrence VARCHAR NOT NULL,
+  create_timestamp VARCHAR NOT NULL,
+  last_update_timestamp VARCHAR NOT NULL,
+  delete_timestamp VARCHAR,
+  user_uuid VARCHAR NOT NULL
+)
@@ -0,0 +1,13 @@
+CREATE TABLE follow_ups (
+  id SERIAL PRIMARY KEY,
+  uuid VARCHAR NOT NULL,
+  interaction_uuid VARCHAR NOT NULL,
+  buddy_uuid VARCHAR,
+  notes TEXT NOT NULL,
+  due VARCHAR,
+  done BOOLEAN NOT NULL DEFAULT FALSE,
+  create_timestamp VARCHAR NOT NULL,
+  last_update_timestamp VARCHAR NOT NULL,
+  delete_timestamp VARCHAR,
+  user_uuid VARCHAR NOT NULL
+)
@@ -0,0 +1,13 @@
+CREATE TABLE ideas (
+  id SERIAL PRIMARY KEY,
+  uuid VARCHAR NOT NULL,
+  buddy_uuid VARCHAR NOT NULL,
+  kind VARCHAR NOT NULL,
+  description TEXT NOT NULL,
+  status VARCHAR NOT NULL,
+  interaction_uuid VARCHAR,
+  create_timestamp VARCHAR NOT NULL,
+  last_update_timestamp VARCHAR NOT NULL,
+  delete_timestamp VARCHAR,
+  user_uuid VARCHAR NOT NULL
+)
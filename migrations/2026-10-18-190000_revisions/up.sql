@@ -0,0 +1,14 @@
+CREATE TABLE revisions (
+  id SERIAL PRIMARY KEY,
+  uuid VARCHAR NOT NULL,
+  entity_kind VARCHAR NOT NULL,
+  entity_uuid VARCHAR NOT NULL,
+  action VARCHAR NOT NULL,
+  before TEXT,
+  after TEXT,
+  reverted_to_uuid VARCHAR,
+  create_timestamp VARCHAR NOT NULL,
+  user_uuid VARCHAR NOT NULL
+);
+
+CREATE INDEX revisions_entity ON revisions (user_uuid, entity_uuid);
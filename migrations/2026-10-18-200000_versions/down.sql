@@ -0,0 +1,2 @@
+ALTER TABLE interactions DROP COLUMN version;
+ALTER TABLE buddies DROP COLUMN version;
@@ -0,0 +1,2 @@
+ALTER TABLE buddies ADD COLUMN version INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE interactions ADD COLUMN version INTEGER NOT NULL DEFAULT 1;
@@ -0,0 +1,2 @@
+DROP INDEX interactions_uuid;
+DROP INDEX buddies_uuid;
@@ -0,0 +1,3 @@
+-- Clients can now pick their own ids, so make sure they can't reuse one
+CREATE UNIQUE INDEX buddies_uuid ON buddies (uuid);
+CREATE UNIQUE INDEX interactions_uuid ON interactions (uuid);
@@ -0,0 +1,11 @@
+CREATE TABLE idempotency_keys (
+  id SERIAL PRIMARY KEY,
+  user_uuid VARCHAR NOT NULL,
+  operation VARCHAR NOT NULL,
+  key VARCHAR NOT NULL,
+  request TEXT NOT NULL,
+  response TEXT NOT NULL,
+  create_timestamp VARCHAR NOT NULL
+);
+
+CREATE UNIQUE INDEX idempotency_keys_user_key ON idempotency_keys (user_uuid, operation, key);
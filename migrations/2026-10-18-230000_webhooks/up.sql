@@ -0,0 +1,27 @@
+CREATE TABLE webhooks (
+  id SERIAL PRIMARY KEY,
+  uuid VARCHAR NOT NULL,
+  url VARCHAR NOT NULL,
+  secret VARCHAR NOT NULL,
+  create_timestamp VARCHAR NOT NULL,
+  delete_timestamp VARCHAR,
+  user_uuid VARCHAR NOT NULL
+);
+
+CREATE TABLE webhook_deliveries (
+  id SERIAL PRIMARY KEY,
+  uuid VARCHAR NOT NULL,
+  webhook_uuid VARCHAR NOT NULL,
+  payload TEXT NOT NULL,
+  status VARCHAR NOT NULL,
+  attempts INTEGER NOT NULL DEFAULT 0,
+  next_attempt_timestamp VARCHAR,
+  response_status INTEGER,
+  error TEXT,
+  create_timestamp VARCHAR NOT NULL,
+  last_update_timestamp VARCHAR NOT NULL,
+  user_uuid VARCHAR NOT NULL
+);
+
+CREATE INDEX webhook_deliveries_webhook ON webhook_deliveries (user_uuid, webhook_uuid);
+CREATE INDEX webhook_deliveries_pending ON webhook_deliveries (status, next_attempt_timestamp);
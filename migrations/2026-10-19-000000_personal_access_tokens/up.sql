@@ -0,0 +1,12 @@
+CREATE TABLE personal_access_tokens (
+  id SERIAL PRIMARY KEY,
+  uuid VARCHAR NOT NULL,
+  name VARCHAR NOT NULL,
+  scopes TEXT [] NOT NULL,
+  token_hash VARCHAR NOT NULL,
+  create_timestamp VARCHAR NOT NULL,
+  revoke_timestamp VARCHAR,
+  user_uuid VARCHAR NOT NULL
+);
+
+CREATE UNIQUE INDEX personal_access_tokens_hash ON personal_access_tokens (token_hash);
@@ -0,0 +1,380 @@
+use crate::lib::types::{AggregateStats, Buddy, BuddyStats, ContactHistory, Datestamp};
+use anyhow::{Context, Result};
+use chrono::NaiveDate;
+use std::collections::HashMap;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Average length of a month in days
+const DAYS_PER_MONTH: f64 = 30.44;
+/// Buddies without a cadence are expected to be contacted about once a month
+const DEFAULT_PERIOD_DAYS: i64 = 30;
+/// How many buddies to include in the most and least contacted lists
+const RANKING_SIZE: usize = 5;
+/// How many gaps between contacts we need to see before suggesting a cadence
+const MIN_GAPS_FOR_SUGGESTION: usize = 2;
+
+/// Running totals used to compute stats across every buddy
+#[derive(Default)]
+struct Totals {
+    gap_days: i64,
+    gaps: usize,
+    on_time: usize,
+    cadence_intervals: usize,
+}
+
+fn per_month(count: usize, window_days: i64) -> f64 {
+    let months = (window_days as f64 / DAYS_PER_MONTH).max(1.0 / DAYS_PER_MONTH);
+    count as f64 / months
+}
+
+fn percentage(part: usize, whole: usize) -> Option<f64> {
+    if whole == 0 {
+        None
+    } else {
+        Some(100.0 * part as f64 / whole as f64)
+    }
+}
+
+/// Split the window into periods ending today and count runs of periods with a contact.
+/// Returns the current and longest streak.
+fn streaks(
+    dates: &[NaiveDate],
+    window_start: NaiveDate,
+    today: NaiveDate,
+    period: i64,
+) -> (u32, u32) {
+    let periods = ((today - window_start).num_days() / period + 1) as usize;
+    let mut contacted = vec![false; periods];
+    for date in dates {
+        let periods_ago = ((today - *date).num_days() / period) as usize;
+        if periods_ago < periods {
+            contacted[periods - 1 - periods_ago] = true;
+        }
+    }
+
+    let mut longest = 0;
+    let mut run = 0;
+    for was_contacted in &contacted {
+        run = if *was_contacted { run + 1 } else { 0 };
+        longest = std::cmp::max(longest, run);
+    }
+    // The current period isn't over yet, so missing it doesn't break the streak
+    let unfinished = if contacted.last() == Some(&true) {
+        0
+    } else {
+        1
+    };
+    let current = contacted
+        .iter()
+        .rev()
+        .skip(unfinished)
+        .take_while(|was_contacted| **was_contacted)
+        .count();
+    (current as u32, longest)
+}
+
+/// Stats for one buddy from the dates they were contacted, oldest first
+fn buddy_stats(
+    buddy: &Buddy,
+    dates: &[NaiveDate],
+    window_start: NaiveDate,
+    today: NaiveDate,
+    totals: &mut Totals,
+) -> BuddyStats {
+    let window_days = (today - window_start).num_days() + 1;
+    // Several contacts on the same day count as one when measuring the gaps between them
+    let mut days = dates.to_vec();
+    days.dedup();
+    let gaps: Vec<i64> = days
+        .windows(2)
+        .map(|pair| (pair[1] - pair[0]).num_days())
+        .collect();
+    let average_gap_days = if gaps.is_empty() {
+        None
+    } else {
+        Some(gaps.iter().sum::<i64>() as f64 / gaps.len() as f64)
+    };
+    totals.gap_days += gaps.iter().sum::<i64>();
+    totals.gaps += gaps.len();
+
+    let cadence_days = buddy
+        .cadence
+        .map(|cadence| (cadence.as_secs() / 86400) as i64);
+    let cadence_adherence = match (cadence_days, dates.last()) {
+        (Some(cadence_days), Some(last)) => {
+            let mut on_time = gaps.iter().filter(|gap| **gap <= cadence_days).count();
+            let mut intervals = gaps.len();
+            // Time since the last contact only counts once it has already run past the cadence
+            let open_gap = (today - *last).num_days();
+            if open_gap > cadence_days {
+                intervals += 1;
+            } else if intervals == 0 {
+                on_time += 1;
+                intervals += 1;
+            }
+            totals.on_time += on_time;
+            totals.cadence_intervals += intervals;
+            percentage(on_time, intervals)
+        }
+        _ => None,
+    };
+
+    let period = std::cmp::max(cadence_days.unwrap_or(DEFAULT_PERIOD_DAYS), 1);
+    let (current_streak, longest_streak) = streaks(dates, window_start, today, period);
+
+    BuddyStats {
+        buddy_id: buddy.id,
+        name: buddy.name.clone(),
+        interaction_count: dates.len(),
+        interactions_per_month: per_month(dates.len(), window_days),
+        average_gap_days,
+        cadence_adherence,
+        current_streak,
+        longest_streak,
+        last_contacted: dates.last().map(|date| Datestamp::from(*date)),
+    }
+}
+
+/// Suggest a cadence from the median gap between contacts. Several contacts on the
+/// same day count as one, and we hold off until there's enough history to go on.
+pub fn suggest_cadence(dates: &[Datestamp]) -> Result<Option<Duration>> {
+    let mut dates = dates
+        .iter()
+        .map(|date| date.to_naive_date())
+        .collect::<Result<Vec<NaiveDate>>>()?;
+    dates.sort();
+    dates.dedup();
+    let mut gaps: Vec<i64> = dates
+        .windows(2)
+        .map(|pair| (pair[1] - pair[0]).num_days())
+        .collect();
+    if gaps.len() < MIN_GAPS_FOR_SUGGESTION {
+        return Ok(None);
+    }
+    gaps.sort_unstable();
+    // With an odd number of gaps both middles are the same one, otherwise round up between them
+    let (lower, upper) = (gaps[(gaps.len() - 1) / 2], gaps[gaps.len() / 2]);
+    let median = (lower + upper + 1) / 2;
+    Ok(Some(Duration::from_secs(median as u64 * 86400)))
+}
+
+/// Fill in `suggested_cadence` on every buddy that doesn't have a cadence of their own
+pub fn fill_suggested_cadences(
+    buddies: &mut HashMap<Uuid, Buddy>,
+    history: &ContactHistory,
+) -> Result<()> {
+    for buddy in buddies.values_mut() {
+        if buddy.cadence.is_some() {
+            continue;
+        }
+        if let Some(dates) = history.contact_dates.get(&buddy.id) {
+            buddy.suggested_cadence = suggest_cadence(dates)
+                .context(format!("Suggesting cadence for buddy {}", buddy.id))?;
+        }
+    }
+    Ok(())
+}
+
+/// Compute relationship health stats for every buddy over the window from
+/// `window_start` to `today`, inclusive.
+pub fn compute_stats(
+    buddies: &HashMap<Uuid, Buddy>,
+    history: &ContactHistory,
+    window_start: NaiveDate,
+    today: NaiveDate,
+) -> Result<(AggregateStats, Vec<BuddyStats>)> {
+    let mut totals = Totals::default();
+    let mut buddy_stats_list = Vec::new();
+    for buddy in buddies.values() {
+        if buddy.delete_timestamp.is_some() {
+            continue;
+        }
+        let dates = match history.contact_dates.get(&buddy.id) {
+            Some(dates) => dates
+                .iter()
+                .map(|date| date.to_naive_date())
+                .collect::<Result<Vec<NaiveDate>>>()
+                .context(format!("Reading contact dates for buddy {}", buddy.id))?
+                .into_iter()
+                .filter(|date| *date <= today)
+                .collect(),
+            None => Vec::new(),
+        };
+        buddy_stats_list.push(buddy_stats(buddy, &dates, window_start, today, &mut totals));
+    }
+
+    // Ties are broken by name so the rankings are stable
+    buddy_stats_list.sort_by(|a, b| {
+        b.interaction_count
+            .cmp(&a.interaction_count)
+            .then_with(|| a.name.cmp(&b.name))
+    });
+    let most_contacted = buddy_stats_list
+        .iter()
+        .take(RANKING_SIZE)
+        .map(|stats| stats.buddy_id)
+        .collect();
+    let least_contacted = buddy_stats_list
+        .iter()
+        .rev()
+        .take(RANKING_SIZE)
+        .map(|stats| stats.buddy_id)
+        .collect();
+
+    let window_days = (today - window_start).num_days() + 1;
+    let aggregate = AggregateStats {
+        total_interactions: history.total_interactions,
+        interactions_per_month: per_month(history.total_interactions, window_days),
+        average_gap_days: if totals.gaps == 0 {
+            None
+        } else {
+            Some(totals.gap_days as f64 / totals.gaps as f64)
+        },
+        cadence_adherence: percentage(totals.on_time, totals.cadence_intervals),
+        most_contacted,
+        least_contacted,
+    };
+    Ok((aggregate, buddy_stats_list))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::testing;
+
+    fn date(s: &str) -> NaiveDate {
+        Datestamp(s.to_string()).to_naive_date().unwrap()
+    }
+
+    fn buddy(name: &str, cadence_days: Option<u64>) -> Buddy {
+        Buddy {
+            cadence: cadence_days.map(|days| Duration::from_secs(days * 86400)),
+            ..testing::buddy(name)
+        }
+    }
+
+    /// Stats over January 2021 for `buddy`, contacted on `dates`
+    fn january_stats(buddy: &Buddy, dates: &[&str]) -> (AggregateStats, BuddyStats) {
+        let mut history = ContactHistory {
+            total_interactions: dates.len(),
+            ..Default::default()
+        };
+        if !dates.is_empty() {
+            let dates = dates.iter().map(|date| Datestamp(date.to_string()));
+            history.contact_dates.insert(buddy.id, dates.collect());
+        }
+        let buddies = vec![(buddy.id, buddy.clone())].into_iter().collect();
+        let (aggregate, mut stats) =
+            compute_stats(&buddies, &history, date("2021-01-01"), date("2021-01-31")).unwrap();
+        (aggregate, stats.remove(0))
+    }
+
+    #[test]
+    fn no_history_means_no_averages() {
+        let (aggregate, stats) = january_stats(&buddy("Jo", Some(7)), &[]);
+        assert_eq!(aggregate.total_interactions, 0);
+        assert_eq!(aggregate.interactions_per_month, 0.0);
+        assert_eq!(aggregate.average_gap_days, None);
+        assert_eq!(aggregate.cadence_adherence, None);
+        assert_eq!(stats.interaction_count, 0);
+        assert_eq!(stats.average_gap_days, None);
+        assert_eq!(stats.cadence_adherence, None);
+        assert_eq!((stats.current_streak, stats.longest_streak), (0, 0));
+        assert_eq!(stats.last_contacted, None);
+    }
+
+    #[test]
+    fn a_single_interaction_has_no_gaps() {
+        let (aggregate, stats) = january_stats(&buddy("Jo", Some(7)), &["2021-01-29"]);
+        assert_eq!(stats.interaction_count, 1);
+        assert_eq!(stats.average_gap_days, None);
+        assert_eq!(aggregate.average_gap_days, None);
+        // Still within the cadence of the only contact
+        assert_eq!(stats.cadence_adherence, Some(100.0));
+        assert_eq!((stats.current_streak, stats.longest_streak), (1, 1));
+        assert_eq!(
+            stats.last_contacted,
+            Some(Datestamp::from(date("2021-01-29")))
+        );
+
+        let (_, overdue) = january_stats(&buddy("Jo", Some(7)), &["2021-01-02"]);
+        assert_eq!(overdue.cadence_adherence, Some(0.0));
+    }
+
+    #[test]
+    fn same_day_interactions_count_but_leave_no_gap() {
+        let (aggregate, stats) = january_stats(
+            &buddy("Jo", Some(7)),
+            &["2021-01-01", "2021-01-01", "2021-01-15", "2021-01-15"],
+        );
+        assert_eq!(stats.interaction_count, 4);
+        assert_eq!(stats.average_gap_days, Some(14.0));
+        assert_eq!(aggregate.average_gap_days, Some(14.0));
+        // One late gap, and the open one since the 15th has run past the cadence too
+        assert_eq!(stats.cadence_adherence, Some(0.0));
+    }
+
+    fn suggestion(dates: &[&str]) -> Option<u64> {
+        let dates: Vec<Datestamp> = dates.iter().map(|d| Datestamp(d.to_string())).collect();
+        suggest_cadence(&dates)
+            .unwrap()
+            .map(|cadence| cadence.as_secs() / 86400)
+    }
+
+    #[test]
+    fn cadences_need_enough_history() {
+        assert_eq!(suggestion(&[]), None);
+        assert_eq!(suggestion(&["2021-01-01"]), None);
+        assert_eq!(suggestion(&["2021-01-01", "2021-01-08"]), None);
+        assert_eq!(
+            suggestion(&["2021-01-01", "2021-01-08", "2021-01-22"]),
+            Some(11)
+        );
+        assert_eq!(
+            suggestion(&["2021-01-01", "2021-01-08", "2021-01-22", "2021-01-23"]),
+            Some(7)
+        );
+    }
+
+    #[test]
+    fn same_day_interactions_suggest_like_one() {
+        assert_eq!(
+            suggestion(&["2021-01-01", "2021-01-01", "2021-01-01"]),
+            None
+        );
+        assert_eq!(
+            suggestion(&[
+                "2021-01-15",
+                "2021-01-01",
+                "2021-01-08",
+                "2021-01-08",
+                "2021-01-15"
+            ]),
+            Some(7)
+        );
+    }
+
+    #[test]
+    fn buddies_with_a_cadence_get_no_suggestion() {
+        let (with_cadence, without) = (buddy("Set", Some(30)), buddy("Unset", None));
+        let dates: Vec<Datestamp> = ["2021-01-01", "2021-01-08", "2021-01-15"]
+            .iter()
+            .map(|date| Datestamp(date.to_string()))
+            .collect();
+        let mut history = ContactHistory::default();
+        history.contact_dates.insert(with_cadence.id, dates.clone());
+        history.contact_dates.insert(without.id, dates);
+        let mut buddies: HashMap<Uuid, Buddy> = vec![with_cadence.clone(), without.clone()]
+            .into_iter()
+            .map(|buddy| (buddy.id, buddy))
+            .collect();
+
+        fill_suggested_cadences(&mut buddies, &history).unwrap();
+        assert_eq!(buddies[&with_cadence.id].suggested_cadence, None);
+        assert_eq!(
+            buddies[&without.id].suggested_cadence,
+            Some(Duration::from_secs(7 * 86400))
+        );
+    }
+}
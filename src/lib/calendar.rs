@@ -0,0 +1,220 @@
+use crate::lib::digest::next_contact_due;
+use crate::lib::types::{Buddy, ImportantDate, Recurrence};
+use anyhow::{Context, Result};
+use chrono::{Datelike, NaiveDate, NaiveDateTime};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+const PRODUCT_ID: &str = "-//buddies//buddies calendar//EN";
+/// RFC 5545 asks that content lines be no longer than 75 octets
+const MAX_LINE_OCTETS: usize = 75;
+
+/// Escape a value for use in a TEXT property. vCards share these rules.
+pub fn escape_text(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace("\r\n", "\\n")
+        .replace('\n', "\\n")
+}
+
+/// Fold a content line so that no line is longer than 75 octets. vCards share these rules.
+pub fn fold_line(line: &str) -> String {
+    let mut folded = String::new();
+    let mut line_octets = 0;
+    for c in line.chars() {
+        if line_octets + c.len_utf8() > MAX_LINE_OCTETS {
+            folded.push_str("\r\n ");
+            // The leading space counts towards the continuation line
+            line_octets = 1;
+        }
+        folded.push(c);
+        line_octets += c.len_utf8();
+    }
+    folded.push_str("\r\n");
+    folded
+}
+
+fn format_date(date: NaiveDate) -> String {
+    date.format("%Y%m%d").to_string()
+}
+
+struct Event {
+    uid: String,
+    summary: String,
+    description: Option<String>,
+    start: NaiveDate,
+    /// One-off events don't repeat
+    rrule: Option<String>,
+}
+
+impl Event {
+    fn write(&self, calendar: &mut String, stamp: &str) {
+        let mut lines = vec![
+            "BEGIN:VEVENT".to_string(),
+            format!("UID:{}", self.uid),
+            format!("DTSTAMP:{}", stamp),
+            format!("DTSTART;VALUE=DATE:{}", format_date(self.start)),
+        ];
+        if let Some(rrule) = &self.rrule {
+            lines.push(format!("RRULE:{}", rrule));
+        }
+        lines.push(format!("SUMMARY:{}", escape_text(&self.summary)));
+        if let Some(description) = &self.description {
+            lines.push(format!("DESCRIPTION:{}", escape_text(description)));
+        }
+        lines.push("TRANSP:TRANSPARENT".to_string());
+        lines.push("END:VEVENT".to_string());
+        for line in lines {
+            calendar.push_str(&fold_line(&line));
+        }
+    }
+}
+
+fn birthday_event(buddy: &Buddy) -> Result<Option<Event>> {
+    let birthday = match &buddy.birthday {
+        Some(birthday) => birthday
+            .to_naive_date()
+            .context(format!("Reading birthday for buddy {}", buddy.id))?,
+        None => return Ok(None),
+    };
+    Ok(Some(Event {
+        uid: format!("birthday-{}@buddies", buddy.id),
+        summary: format!("{}'s birthday", buddy.name),
+        description: None,
+        start: birthday,
+        rrule: Some(yearly_rrule(birthday)),
+    }))
+}
+
+/// Leap day events should still show up in the years without one, on the day
+/// `anniversary_in` gives. The 60th day of the year is February 29th in leap years and
+/// March 1st otherwise.
+fn yearly_rrule(date: NaiveDate) -> String {
+    if date.month() == 2 && date.day() == 29 {
+        "FREQ=YEARLY;BYYEARDAY=60".to_string()
+    } else {
+        "FREQ=YEARLY".to_string()
+    }
+}
+
+fn important_date_event(buddy: &Buddy, important_date: &ImportantDate) -> Result<Event> {
+    let date = important_date
+        .date
+        .to_naive_date()
+        .context(format!("Reading important date {}", important_date.id))?;
+    let rrule = match important_date.recurrence {
+        Recurrence::Yearly => Some(yearly_rrule(date)),
+        Recurrence::Once => None,
+    };
+    Ok(Event {
+        uid: format!("important-date-{}@buddies", important_date.id),
+        summary: format!("{}: {}", buddy.name, important_date.description()),
+        description: None,
+        start: date,
+        rrule,
+    })
+}
+
+fn reach_out_event(buddy: &Buddy, today: NaiveDate) -> Result<Option<Event>> {
+    let (due, cadence) = match (next_contact_due(buddy)?, buddy.cadence) {
+        (Some(due), Some(cadence)) => (due, cadence),
+        _ => return Ok(None),
+    };
+    let cadence_days = std::cmp::max(cadence.as_secs() / 86400, 1);
+    Ok(Some(Event {
+        uid: format!("reach-out-{}@buddies", buddy.id),
+        summary: format!("Reach out to {}", buddy.name),
+        description: Some(format!("Last contacted on {}", buddy.last_contacted.0)),
+        // Overdue buddies show up today rather than in the past
+        start: std::cmp::max(due, today),
+        rrule: Some(format!("FREQ=DAILY;INTERVAL={}", cadence_days)),
+    }))
+}
+
+/// Build an RFC 5545 calendar of buddy birthdays, important dates and reminders to reach out
+pub fn build_calendar(
+    buddies: &HashMap<Uuid, Buddy>,
+    today: NaiveDate,
+    now: NaiveDateTime,
+) -> Result<String> {
+    let stamp = now.format("%Y%m%dT%H%M%SZ").to_string();
+    let mut calendar = String::new();
+    for line in &[
+        "BEGIN:VCALENDAR",
+        "VERSION:2.0",
+        &format!("PRODID:{}", PRODUCT_ID),
+        "CALSCALE:GREGORIAN",
+        "METHOD:PUBLISH",
+        "X-WR-CALNAME:Buddies",
+    ] {
+        calendar.push_str(&fold_line(line));
+    }
+
+    let mut buddies: Vec<&Buddy> = buddies
+        .values()
+        .filter(|buddy| buddy.delete_timestamp.is_none())
+        .collect();
+    buddies.sort_by_key(|buddy| buddy.id);
+    for buddy in buddies {
+        if let Some(event) = birthday_event(buddy)? {
+            event.write(&mut calendar, &stamp);
+        }
+        if let Some(event) = reach_out_event(buddy, today)? {
+            event.write(&mut calendar, &stamp);
+        }
+        for important_date in &buddy.important_dates {
+            important_date_event(buddy, important_date)?.write(&mut calendar, &stamp);
+        }
+    }
+    calendar.push_str(&fold_line("END:VCALENDAR"));
+    Ok(calendar)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::digest::anniversary_in;
+
+    #[test]
+    fn text_escapes_its_separators() {
+        assert_eq!(escape_text("a\\b; c, d\r\ne\nf"), r"a\\b\; c\, d\ne\nf");
+    }
+
+    #[test]
+    fn long_lines_fold_at_75_octets_without_splitting_characters() {
+        assert_eq!(fold_line("SUMMARY:Short"), "SUMMARY:Short\r\n");
+
+        let line = format!("SUMMARY:{}", "é".repeat(80));
+        let folded = fold_line(&line);
+        assert!(folded.ends_with("\r\n"));
+        let lines: Vec<&str> = folded.trim_end_matches("\r\n").split("\r\n").collect();
+        assert!(lines.len() > 1);
+        assert!(lines.iter().all(|line| line.len() <= MAX_LINE_OCTETS));
+        assert!(lines[1..].iter().all(|line| line.starts_with(' ')));
+        let unfolded: String = lines
+            .iter()
+            .enumerate()
+            .map(|(i, line)| if i == 0 { *line } else { &line[1..] })
+            .collect();
+        assert_eq!(unfolded, line);
+    }
+
+    #[test]
+    fn leap_days_repeat_on_the_day_digests_use() {
+        let leap_day = NaiveDate::from_ymd(2000, 2, 29);
+        assert_eq!(yearly_rrule(leap_day), "FREQ=YEARLY;BYYEARDAY=60");
+        for year in 2001..2030 {
+            assert_eq!(
+                NaiveDate::from_yo(year, 60),
+                anniversary_in(leap_day, year),
+                "{}",
+                year
+            );
+        }
+        assert_eq!(
+            yearly_rrule(NaiveDate::from_ymd(2000, 2, 28)),
+            "FREQ=YEARLY"
+        );
+    }
+}
@@ -0,0 +1,370 @@
+use crate::lib::types::{
+    Buddy, Datestamp, Digest, FollowUp, ImportantDate, Interaction, OverdueBuddy, OverdueFollowUp,
+    Recurrence, UpcomingBirthday, UpcomingDate, User,
+};
+use anyhow::{Context, Result};
+use chrono::{Datelike, Duration, NaiveDate};
+use log::warn;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use tinytemplate::TinyTemplate;
+use uuid::Uuid;
+
+const TEXT_TEMPLATE: &str = include_str!("../../templates/digest.txt");
+const HTML_TEMPLATE: &str = include_str!("../../templates/digest.html");
+
+/// When the month and day of `date` come around in `year`. Leap days are marked on March
+/// 1st in years without one, here and in the calendar feed.
+pub fn anniversary_in(date: NaiveDate, year: i32) -> NaiveDate {
+    date.with_year(year)
+        .unwrap_or_else(|| NaiveDate::from_ymd(year, 3, 1))
+}
+
+/// The next time (on or after `today`) that the month and day of `date` come around
+pub fn next_anniversary(date: NaiveDate, today: NaiveDate) -> NaiveDate {
+    let this_year = anniversary_in(date, today.year());
+    if this_year >= today {
+        this_year
+    } else {
+        anniversary_in(date, today.year() + 1)
+    }
+}
+
+/// The next time (on or after `today`) an important date comes around, if it ever does again
+pub fn next_occurrence(
+    important_date: &ImportantDate,
+    today: NaiveDate,
+) -> Result<Option<NaiveDate>> {
+    let date = important_date
+        .date
+        .to_naive_date()
+        .context(format!("Reading important date {}", important_date.id))?;
+    Ok(match important_date.recurrence {
+        Recurrence::Yearly => Some(next_anniversary(date, today)),
+        Recurrence::Once if date >= today => Some(date),
+        Recurrence::Once => None,
+    })
+}
+
+/// Birthdays and important dates coming up in the `lookahead_days` starting with `today`,
+/// soonest first. Dates that can't be read are logged and left out.
+pub fn upcoming_dates(
+    buddies: &HashMap<Uuid, Buddy>,
+    today: NaiveDate,
+    lookahead_days: i64,
+) -> Result<(Vec<UpcomingBirthday>, Vec<UpcomingDate>)> {
+    let mut upcoming_birthdays = Vec::new();
+    let mut upcoming_dates = Vec::new();
+    for buddy in buddies.values() {
+        if buddy.delete_timestamp.is_some() {
+            continue;
+        }
+        let birthday = buddy.birthday.as_ref().map(|birthday| {
+            birthday
+                .to_naive_date()
+                .context(format!("Reading birthday for buddy {}", buddy.id))
+        });
+        if let Some(birthday) = birthday.and_then(skip_unreadable) {
+            let next = next_anniversary(birthday, today);
+            let days_until = (next - today).num_days();
+            if days_until < lookahead_days {
+                upcoming_birthdays.push(UpcomingBirthday {
+                    buddy_id: buddy.id,
+                    name: buddy.name.clone(),
+                    date: Datestamp::from(next),
+                    days_until,
+                });
+            }
+        }
+        for important_date in &buddy.important_dates {
+            let next = match skip_unreadable(next_occurrence(important_date, today)) {
+                Some(Some(next)) => next,
+                _ => continue,
+            };
+            let days_until = (next - today).num_days();
+            if days_until < lookahead_days {
+                upcoming_dates.push(UpcomingDate {
+                    important_date_id: important_date.id,
+                    buddy_id: buddy.id,
+                    name: buddy.name.clone(),
+                    kind: important_date.kind,
+                    description: important_date.description(),
+                    date: Datestamp::from(next),
+                    days_until,
+                });
+            }
+        }
+    }
+    upcoming_birthdays.sort_by_key(|birthday| birthday.days_until);
+    upcoming_dates.sort_by_key(|date| date.days_until);
+    Ok((upcoming_birthdays, upcoming_dates))
+}
+
+/// A row with a date we can't read shouldn't keep the rest from being shown, so log it and
+/// carry on without it
+fn skip_unreadable<T>(result: Result<T>) -> Option<T> {
+    result
+        .map_err(|e| warn!("Skipping unreadable date {:#}", e))
+        .ok()
+}
+
+/// The day we should reach out to a buddy by, if they have a cadence
+pub fn next_contact_due(buddy: &Buddy) -> Result<Option<NaiveDate>> {
+    let cadence = match buddy.cadence {
+        Some(cadence) => cadence,
+        None => return Ok(None),
+    };
+    let last_contacted = buddy
+        .last_contacted
+        .to_naive_date()
+        .context(format!("Reading last contacted for buddy {}", buddy.id))?;
+    Ok(Some(
+        last_contacted + Duration::days((cadence.as_secs() / 86400) as i64),
+    ))
+}
+
+/// How many days past due an open follow up is, if it's due by `today`
+pub fn follow_up_days_overdue(follow_up: &FollowUp, today: NaiveDate) -> Result<Option<i64>> {
+    let due = match (&follow_up.due, follow_up.done) {
+        (Some(due), false) => due
+            .to_naive_date()
+            .context(format!("Reading due date for follow up {}", follow_up.id))?,
+        _ => return Ok(None),
+    };
+    if due <= today {
+        Ok(Some((today - due).num_days()))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Find the most recent interaction that each buddy participated in
+pub fn latest_interactions(
+    interactions: &HashMap<Uuid, Interaction>,
+) -> HashMap<Uuid, &Interaction> {
+    let mut latest: HashMap<Uuid, &Interaction> = HashMap::new();
+    for interaction in interactions.values() {
+        for participant in &interaction.participants {
+            let newer = match latest.get(participant) {
+                Some(current) => interaction_sort_key(interaction) > interaction_sort_key(current),
+                None => true,
+            };
+            if newer {
+                latest.insert(*participant, interaction);
+            }
+        }
+    }
+    latest
+}
+
+/// Orders interactions by when they happened, falling back to when they were logged
+pub fn interaction_sort_key(interaction: &Interaction) -> (&Option<Datestamp>, u64) {
+    (&interaction.date, interaction.create_timestamp.0)
+}
+
+/// Gather up everything a user should hear about in their digest for `today`. Archived
+/// records are left out, as are any whose dates can't be read.
+pub fn build_digest(
+    user: &User,
+    buddies: &HashMap<Uuid, Buddy>,
+    interactions: &HashMap<Uuid, Interaction>,
+    follow_ups: &HashMap<Uuid, FollowUp>,
+    today: NaiveDate,
+) -> Result<Digest> {
+    let lookahead_days = user.digest_frequency.lookahead_days();
+    let live_interactions = interactions
+        .iter()
+        .filter(|(_, interaction)| interaction.delete_timestamp.is_none())
+        .map(|(id, interaction)| (*id, interaction.clone()))
+        .collect();
+    let latest = latest_interactions(&live_interactions);
+
+    let mut overdue = Vec::new();
+    for buddy in buddies.values() {
+        if buddy.delete_timestamp.is_some() {
+            continue;
+        }
+        if let Some(Some(due)) = skip_unreadable(next_contact_due(buddy)) {
+            if due <= today {
+                overdue.push(OverdueBuddy {
+                    buddy_id: buddy.id,
+                    name: buddy.name.clone(),
+                    due: Datestamp::from(due),
+                    days_overdue: (today - due).num_days(),
+                    last_interaction_notes: latest
+                        .get(&buddy.id)
+                        .map(|interaction| interaction.notes.clone())
+                        .filter(|notes| !notes.is_empty()),
+                });
+            }
+        }
+    }
+    overdue.sort_by_key(|buddy| Reverse(buddy.days_overdue));
+
+    let mut overdue_follow_ups = Vec::new();
+    for follow_up in follow_ups.values() {
+        if follow_up.delete_timestamp.is_some() {
+            continue;
+        }
+        if let (Some(due), Some(Some(days_overdue))) = (
+            &follow_up.due,
+            skip_unreadable(follow_up_days_overdue(follow_up, today)),
+        ) {
+            let buddy = follow_up
+                .buddy_id
+                .and_then(|buddy_id| buddies.get(&buddy_id));
+            overdue_follow_ups.push(OverdueFollowUp {
+                follow_up_id: follow_up.id,
+                interaction_id: follow_up.interaction_id,
+                buddy_id: follow_up.buddy_id,
+                name: buddy.map(|buddy| buddy.name.clone()),
+                notes: follow_up.notes.clone(),
+                due: due.clone(),
+                days_overdue,
+            });
+        }
+    }
+    overdue_follow_ups.sort_by_key(|follow_up| Reverse(follow_up.days_overdue));
+    let (upcoming_birthdays, upcoming_dates) = upcoming_dates(buddies, today, lookahead_days)?;
+
+    Ok(Digest {
+        user_id: user.id,
+        email: user.email.clone(),
+        frequency: user.digest_frequency,
+        date: Datestamp::from(today),
+        lookahead_days,
+        overdue,
+        overdue_follow_ups,
+        upcoming_birthdays,
+        upcoming_dates,
+    })
+}
+
+pub fn render_text(digest: &Digest) -> Result<String> {
+    let mut tt = TinyTemplate::new();
+    tt.set_default_formatter(&tinytemplate::format_unescaped);
+    tt.add_template("digest", TEXT_TEMPLATE)
+        .context("Parsing text digest template")?;
+    tt.render("digest", digest).context("Rendering text digest")
+}
+
+pub fn render_html(digest: &Digest) -> Result<String> {
+    let mut tt = TinyTemplate::new();
+    tt.add_template("digest", HTML_TEMPLATE)
+        .context("Parsing html digest template")?;
+    tt.render("digest", digest).context("Rendering html digest")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::testing;
+    use crate::lib::types::Timestamp;
+
+    fn date(s: &str) -> NaiveDate {
+        Datestamp(s.to_string()).to_naive_date().unwrap()
+    }
+
+    fn buddy(name: &str, last_contacted: &str) -> Buddy {
+        Buddy {
+            cadence: Some(std::time::Duration::from_secs(7 * 86400)),
+            last_contacted: Datestamp(last_contacted.to_string()),
+            ..testing::buddy(name)
+        }
+    }
+
+    fn interaction(buddy_id: Uuid, notes: &str, on: &str) -> Interaction {
+        Interaction {
+            id: Uuid::new_v4(),
+            notes: notes.to_string(),
+            participants: vec![buddy_id].into_iter().collect(),
+            date: Some(Datestamp(on.to_string())),
+            kind: None,
+            duration: None,
+            location: None,
+            initiator: None,
+            create_timestamp: Timestamp(0),
+            last_update_timestamp: Timestamp(0),
+            delete_timestamp: None,
+            user_id: Uuid::nil(),
+            version: 1,
+        }
+    }
+
+    fn follow_up(notes: &str, due: &str) -> FollowUp {
+        FollowUp {
+            id: Uuid::new_v4(),
+            interaction_id: Uuid::new_v4(),
+            buddy_id: None,
+            notes: notes.to_string(),
+            due: Some(Datestamp(due.to_string())),
+            done: false,
+            create_timestamp: Timestamp(0),
+            last_update_timestamp: Timestamp(0),
+            delete_timestamp: None,
+            user_id: Uuid::nil(),
+        }
+    }
+
+    fn by_id<T>(records: Vec<T>, id: impl Fn(&T) -> Uuid) -> HashMap<Uuid, T> {
+        records
+            .into_iter()
+            .map(|record| (id(&record), record))
+            .collect()
+    }
+
+    #[test]
+    fn archived_interactions_are_not_the_last_word() {
+        let ada = buddy("Ada", "2021-01-01");
+        let kept = interaction(ada.id, "Talked about engines", "2021-01-01");
+        let mut archived = interaction(ada.id, "Logged by mistake", "2021-01-05");
+        archived.delete_timestamp = Some(Timestamp(1));
+
+        let digest = build_digest(
+            &User::default(),
+            &by_id(vec![ada], |buddy| buddy.id),
+            &by_id(vec![kept, archived], |interaction| interaction.id),
+            &HashMap::new(),
+            date("2021-02-01"),
+        )
+        .unwrap();
+
+        assert_eq!(digest.overdue.len(), 1);
+        assert_eq!(
+            digest.overdue[0].last_interaction_notes.as_deref(),
+            Some("Talked about engines")
+        );
+    }
+
+    #[test]
+    fn unreadable_dates_are_skipped() {
+        let ada = buddy("Ada", "2021-01-01");
+        let unreadable = buddy("Unreadable", "last tuesday");
+        let mut bad_birthday = buddy("Bad birthday", "2021-01-01");
+        bad_birthday.birthday = Some(Datestamp("02/03".to_string()));
+        let due = follow_up("Send the article", "2021-01-15");
+        let bad_due = follow_up("Call back", "soon");
+        let mut archived = follow_up("Forgotten", "2021-01-15");
+        archived.delete_timestamp = Some(Timestamp(1));
+
+        let digest = build_digest(
+            &User::default(),
+            &by_id(vec![ada, unreadable, bad_birthday], |buddy| buddy.id),
+            &HashMap::new(),
+            &by_id(vec![due, bad_due, archived], |follow_up| follow_up.id),
+            date("2021-02-01"),
+        )
+        .unwrap();
+
+        let mut overdue: Vec<&str> = digest.overdue.iter().map(|b| b.name.as_str()).collect();
+        overdue.sort();
+        assert_eq!(overdue, vec!["Ada", "Bad birthday"]);
+        assert!(digest.upcoming_birthdays.is_empty());
+        let follow_ups: Vec<&str> = digest
+            .overdue_follow_ups
+            .iter()
+            .map(|follow_up| follow_up.notes.as_str())
+            .collect();
+        assert_eq!(follow_ups, vec!["Send the article"]);
+    }
+}
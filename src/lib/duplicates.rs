@@ -0,0 +1,134 @@
+use crate::lib::types::{Buddy, ContactMethod, ContactMethodKind, DuplicatePair, DuplicateReason};
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use uuid::Uuid;
+
+/// How alike two names have to be to count as the same person on their own
+const SIMILAR_NAME_THRESHOLD: f64 = 0.8;
+/// How alike two names have to be when something else already matches
+const SUPPORTING_NAME_THRESHOLD: f64 = 0.5;
+
+/// Lowercase a name, drop punctuation and put its words in order, so
+/// "Smith, John" and "john smith" come out the same
+fn normalize_name(name: &str) -> String {
+    let cleaned: String = name
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                ' '
+            }
+        })
+        .collect();
+    let mut words: Vec<&str> = cleaned.split_whitespace().collect();
+    words.sort_unstable();
+    words.join(" ")
+}
+
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + if a_char == *b_char { 0 } else { 1 };
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// How alike two names are, from 0 (nothing in common) to 1 (the same)
+pub fn name_similarity(a: &str, b: &str) -> f64 {
+    let a = normalize_name(a);
+    let b = normalize_name(b);
+    let longest = a.chars().count().max(b.chars().count());
+    if longest == 0 {
+        return 0.0;
+    }
+    1.0 - levenshtein(&a, &b) as f64 / longest as f64
+}
+
+/// Find pairs of buddies that are likely the same person, strongest matches first.
+/// Archived buddies and contact methods are ignored.
+pub fn find_duplicates(
+    buddies: &HashMap<Uuid, Buddy>,
+    contact_methods: &HashMap<Uuid, ContactMethod>,
+) -> Vec<DuplicatePair> {
+    let mut contacts: HashMap<Uuid, HashSet<(ContactMethodKind, String)>> = HashMap::new();
+    for contact_method in contact_methods.values() {
+        if contact_method.delete_timestamp.is_some() {
+            continue;
+        }
+        contacts
+            .entry(contact_method.buddy_id)
+            .or_default()
+            .insert((contact_method.kind, contact_method.value.to_lowercase()));
+    }
+
+    let mut live: Vec<&Buddy> = buddies
+        .values()
+        .filter(|buddy| buddy.delete_timestamp.is_none())
+        .collect();
+    // Keep the output stable between calls
+    live.sort_by_key(|buddy| (buddy.create_timestamp.0, buddy.id));
+
+    let no_contacts = HashSet::new();
+    let mut pairs = Vec::new();
+    for (i, first) in live.iter().enumerate() {
+        for second in &live[i + 1..] {
+            let similarity = name_similarity(&first.name, &second.name);
+            let mut reasons = Vec::new();
+            if first.birthday.is_some() && first.birthday == second.birthday {
+                reasons.push(DuplicateReason::SameBirthday);
+            }
+            let first_contacts = contacts.get(&first.id).unwrap_or(&no_contacts);
+            let second_contacts = contacts.get(&second.id).unwrap_or(&no_contacts);
+            if !first_contacts.is_disjoint(second_contacts) {
+                reasons.push(DuplicateReason::SharedContactMethod);
+            }
+
+            let threshold = if reasons.is_empty() {
+                SIMILAR_NAME_THRESHOLD
+            } else {
+                SUPPORTING_NAME_THRESHOLD
+            };
+            if similarity >= SIMILAR_NAME_THRESHOLD {
+                reasons.insert(0, DuplicateReason::SimilarName);
+            }
+            // A shared contact method is enough by itself, a birthday isn't
+            if similarity < threshold && !reasons.contains(&DuplicateReason::SharedContactMethod) {
+                continue;
+            }
+            pairs.push(DuplicatePair {
+                first: (*first).clone(),
+                second: (*second).clone(),
+                name_similarity: similarity,
+                reasons,
+            });
+        }
+    }
+
+    pairs.sort_by(|a, b| {
+        b.reasons.len().cmp(&a.reasons.len()).then(
+            b.name_similarity
+                .partial_cmp(&a.name_similarity)
+                .unwrap_or(Ordering::Equal),
+        )
+    });
+    pairs
+}
+
+/// Combine the notes of two buddies, leaving out the loser's if the winner already has them
+pub fn merge_notes(winner: &str, loser: &str) -> String {
+    let loser = loser.trim();
+    if loser.is_empty() || winner.contains(loser) {
+        return winner.to_string();
+    }
+    if winner.trim().is_empty() {
+        return loser.to_string();
+    }
+    format!("{}\n\n{}", winner.trim_end(), loser)
+}
@@ -0,0 +1,173 @@
+use crate::lib::types::{ChangeEvent, Revision, StreamEvent};
+use std::sync::{Arc, Mutex};
+use tokio::stream::{Stream, StreamExt};
+use tokio::sync::broadcast::{self, RecvError};
+use uuid::Uuid;
+
+/// How many changes a slow listener can fall behind before it misses some
+const EVENT_BUFFER: usize = 1024;
+
+/// Changes to buddies and interactions, for clients that want to hear about them as they
+/// happen. Everyone shares one channel and listeners pick out their own user's changes.
+#[derive(Clone)]
+pub struct Events {
+    sender: broadcast::Sender<ChangeEvent>,
+    /// Changes made in a transaction, held back until it commits
+    pending: Option<Arc<Mutex<Vec<ChangeEvent>>>>,
+}
+
+impl Events {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(EVENT_BUFFER);
+        Events {
+            sender,
+            pending: None,
+        }
+    }
+
+    /// Tell anyone listening about the change a revision records
+    pub fn publish(&self, revision: &Revision) {
+        let event = ChangeEvent::from(revision);
+        match &self.pending {
+            Some(pending) => pending.lock().unwrap().push(event),
+            // Nobody listening is fine
+            None => {
+                let _ = self.sender.send(event);
+            }
+        }
+    }
+
+    /// Events that hold on to what's published until `flush` is called with them, so
+    /// a transaction that gets rolled back doesn't announce anything
+    pub fn deferred(&self) -> Events {
+        Events {
+            sender: self.sender.clone(),
+            pending: Some(Arc::new(Mutex::new(Vec::new()))),
+        }
+    }
+
+    /// Send what `deferred` held back. If these events are deferred too, as they are in a
+    /// nested transaction, it's handed on to wait for the outer transaction instead.
+    pub fn flush(&self, deferred: Events) {
+        if let Some(pending) = deferred.pending {
+            let events: Vec<ChangeEvent> = pending.lock().unwrap().drain(..).collect();
+            match &self.pending {
+                Some(outer) => outer.lock().unwrap().extend(events),
+                None => {
+                    for event in events {
+                        let _ = self.sender.send(event);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Every user's changes from now on, for work done in the background
+    pub fn listen(&self) -> broadcast::Receiver<ChangeEvent> {
+        self.sender.subscribe()
+    }
+
+    /// Changes to one user's records from now on
+    pub fn subscribe(&self, user_id: Uuid) -> impl Stream<Item = StreamEvent> {
+        self.sender
+            .subscribe()
+            .take_while(|event| !matches!(event, Err(RecvError::Closed)))
+            .filter_map(move |event| match event {
+                Ok(event) if event.user_id == user_id => Some(StreamEvent::Change(event)),
+                Ok(_) | Err(RecvError::Closed) => None,
+                Err(RecvError::Lagged(missed)) => Some(StreamEvent::Resync { missed }),
+            })
+    }
+}
+
+impl Default for Events {
+    fn default() -> Self {
+        Events::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::types::{EntityKind, RevisionAction, Timestamp};
+
+    fn revision(user_id: Uuid) -> Revision {
+        Revision {
+            id: Uuid::new_v4(),
+            entity_kind: EntityKind::Buddy,
+            entity_id: Uuid::new_v4(),
+            action: RevisionAction::Create,
+            before: None,
+            after: None,
+            reverted_to: None,
+            create_timestamp: Timestamp(0),
+            user_id,
+        }
+    }
+
+    /// The revisions `stream` was told about, once every sender has gone
+    async fn heard(stream: impl Stream<Item = StreamEvent>) -> Vec<Uuid> {
+        stream
+            .map(|event| match event {
+                StreamEvent::Change(change) => change.revision_id,
+                StreamEvent::Resync { missed } => panic!("Missed {} changes", missed),
+            })
+            .collect()
+            .await
+    }
+
+    #[tokio::test]
+    async fn subscribers_only_hear_their_own_users_changes() {
+        let events = Events::new();
+        let (user_id, someone_else) = (Uuid::new_v4(), Uuid::new_v4());
+        let stream = events.subscribe(user_id);
+        let (mine, theirs, also_mine) =
+            (revision(user_id), revision(someone_else), revision(user_id));
+        for revision in &[&mine, &theirs, &also_mine] {
+            events.publish(revision);
+        }
+        drop(events);
+
+        assert_eq!(heard(stream).await, vec![mine.id, also_mine.id]);
+    }
+
+    #[tokio::test]
+    async fn deferred_changes_are_only_sent_once_flushed_all_the_way_out() {
+        let events = Events::new();
+        let user_id = Uuid::new_v4();
+        let stream = events.subscribe(user_id);
+        let (rolled_back, kept) = (revision(user_id), revision(user_id));
+
+        // A transaction that's rolled back never flushes
+        let deferred = events.deferred();
+        deferred.publish(&rolled_back);
+        drop(deferred);
+
+        // A nested transaction hands its changes to the outer one
+        let outer = events.deferred();
+        let inner = outer.deferred();
+        inner.publish(&kept);
+        outer.flush(inner);
+        let mut listener = events.listen();
+        assert!(listener.try_recv().is_err());
+        events.flush(outer);
+        drop(events);
+
+        assert_eq!(heard(stream).await, vec![kept.id]);
+    }
+
+    #[tokio::test]
+    async fn slow_subscribers_are_told_to_resync() {
+        let events = Events::new();
+        let user_id = Uuid::new_v4();
+        let mut stream = Box::pin(events.subscribe(user_id));
+        for _ in 0..EVENT_BUFFER + 3 {
+            events.publish(&revision(user_id));
+        }
+
+        match stream.next().await {
+            Some(StreamEvent::Resync { missed }) => assert_eq!(missed, 3),
+            other => panic!("Expected a resync, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,339 @@
+use crate::lib::types::{Buddy, InvalidRequestError, LocalTime, Location, NearFilter, NearbyBuddy};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Offset, SecondsFormat, Timelike, Utc};
+use chrono_tz::Tz;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// Mean radius of the earth
+const EARTH_RADIUS_KM: f64 = 6371.0088;
+/// How far to look around coordinates when no radius is given
+const DEFAULT_RADIUS_KM: f64 = 50.0;
+/// Nobody wants a call before 9am or after 9pm
+const REASONABLE_HOURS: std::ops::Range<u32> = 9..21;
+
+fn invalid(message: String) -> anyhow::Error {
+    InvalidRequestError { message }.into()
+}
+
+/// Coordinates that are on the map, given together or not at all
+fn coordinates(latitude: Option<f64>, longitude: Option<f64>) -> Result<Option<(f64, f64)>> {
+    match (latitude, longitude) {
+        (Some(latitude), Some(longitude)) => {
+            if !(-90.0..=90.0).contains(&latitude) || !(-180.0..=180.0).contains(&longitude) {
+                return Err(invalid(format!(
+                    "{}, {} are not valid coordinates",
+                    latitude, longitude
+                )));
+            }
+            Ok(Some((latitude, longitude)))
+        }
+        (None, None) => Ok(None),
+        _ => Err(invalid(
+            "Latitude and longitude must be given together".to_string(),
+        )),
+    }
+}
+
+fn non_empty(value: Option<String>) -> Option<String> {
+    value
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+/// Check a location makes sense, tidying it up for storage
+pub fn validate_location(location: Location) -> Result<Location> {
+    let mut location = Location {
+        text: non_empty(location.text),
+        city: non_empty(location.city),
+        region: non_empty(location.region),
+        country_code: non_empty(location.country_code),
+        timezone: non_empty(location.timezone),
+        ..location
+    };
+    if let Some(country_code) = &location.country_code {
+        if country_code.len() != 2 || !country_code.chars().all(|c| c.is_ascii_alphabetic()) {
+            return Err(invalid(format!(
+                "{} is not a two letter country code",
+                country_code
+            )));
+        }
+        location.country_code = Some(country_code.to_ascii_uppercase());
+    }
+    coordinates(location.latitude, location.longitude)?;
+    if let Some(timezone) = &location.timezone {
+        let tz: Tz = timezone
+            .parse()
+            .map_err(|e| invalid(format!("Unknown timezone {}: {}", timezone, e)))?;
+        // Use the canonical spelling of the name
+        location.timezone = Some(tz.name().to_string());
+    }
+    Ok(location)
+}
+
+/// The time at a location right now, if we know its timezone
+pub fn local_time(location: &Location, now: DateTime<Utc>) -> Result<Option<LocalTime>> {
+    let timezone = match &location.timezone {
+        Some(timezone) => timezone,
+        None => return Ok(None),
+    };
+    let tz: Tz = timezone
+        .parse()
+        .map_err(|e| anyhow!("Unknown timezone {}: {}", timezone, e))?;
+    let time = now.with_timezone(&tz);
+    Ok(Some(LocalTime {
+        time: time.to_rfc3339_opts(SecondsFormat::Secs, false),
+        utc_offset_seconds: time.offset().fix().local_minus_utc(),
+        reasonable_hour: REASONABLE_HOURS.contains(&time.hour()),
+    }))
+}
+
+/// Fill in `local_time` on every buddy whose timezone we know
+pub fn fill_local_times(buddies: &mut HashMap<Uuid, Buddy>, now: DateTime<Utc>) -> Result<()> {
+    for buddy in buddies.values_mut() {
+        if let Some(location) = &buddy.location {
+            buddy.local_time = local_time(location, now)?;
+        }
+    }
+    Ok(())
+}
+
+/// Great circle distance between two points, using the haversine formula
+pub fn distance_km(from: (f64, f64), to: (f64, f64)) -> f64 {
+    let (from_latitude, from_longitude) = (from.0.to_radians(), from.1.to_radians());
+    let (to_latitude, to_longitude) = (to.0.to_radians(), to.1.to_radians());
+    let a = ((to_latitude - from_latitude) / 2.0).sin().powi(2)
+        + from_latitude.cos()
+            * to_latitude.cos()
+            * ((to_longitude - from_longitude) / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_KM * a.sqrt().asin()
+}
+
+fn matches(wanted: &Option<String>, actual: &Option<String>) -> bool {
+    match (wanted, actual) {
+        (None, _) => true,
+        (Some(wanted), Some(actual)) => wanted.trim().eq_ignore_ascii_case(actual.trim()),
+        (Some(_), None) => false,
+    }
+}
+
+/// Find the buddies near a place, closest first. Buddies without coordinates only
+/// show up when searching by name.
+pub fn find_near(buddies: &HashMap<Uuid, Buddy>, near: &NearFilter) -> Result<Vec<NearbyBuddy>> {
+    let center = coordinates(near.latitude, near.longitude)?;
+    if center.is_none()
+        && near.city.is_none()
+        && near.region.is_none()
+        && near.country_code.is_none()
+    {
+        return Err(invalid(
+            "Give coordinates, a city, a region or a country code to search near".to_string(),
+        ));
+    }
+    let radius_km = near.radius_km.unwrap_or(DEFAULT_RADIUS_KM);
+    if radius_km.is_nan() || radius_km < 0.0 {
+        return Err(invalid(format!("{} is not a radius", radius_km)));
+    }
+
+    let mut nearby = Vec::new();
+    for buddy in buddies.values() {
+        if buddy.delete_timestamp.is_some() {
+            continue;
+        }
+        let location = match &buddy.location {
+            Some(location) => location,
+            None => continue,
+        };
+        if !matches(&near.city, &location.city)
+            || !matches(&near.region, &location.region)
+            || !matches(&near.country_code, &location.country_code)
+        {
+            continue;
+        }
+        let distance_km = match (center, location.latitude, location.longitude) {
+            (Some(center), Some(latitude), Some(longitude)) => {
+                let distance_km = distance_km(center, (latitude, longitude));
+                if distance_km > radius_km {
+                    continue;
+                }
+                Some(distance_km)
+            }
+            (Some(_), _, _) => continue,
+            (None, _, _) => None,
+        };
+        nearby.push(NearbyBuddy {
+            buddy: buddy.clone(),
+            distance_km,
+        });
+    }
+    nearby.sort_by(|a, b| {
+        a.distance_km
+            .partial_cmp(&b.distance_km)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then_with(|| a.buddy.name.cmp(&b.buddy.name))
+    });
+    Ok(nearby)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::testing;
+    use crate::lib::types::Timestamp;
+    use chrono::TimeZone;
+
+    fn place(latitude: f64, longitude: f64) -> Location {
+        Location {
+            text: None,
+            city: None,
+            region: None,
+            country_code: None,
+            latitude: Some(latitude),
+            longitude: Some(longitude),
+            timezone: None,
+        }
+    }
+
+    fn buddy(name: &str, location: Option<Location>) -> Buddy {
+        Buddy {
+            location,
+            ..testing::buddy(name)
+        }
+    }
+
+    fn names(nearby: &[NearbyBuddy]) -> Vec<&str> {
+        nearby.iter().map(|near| near.buddy.name.as_str()).collect()
+    }
+
+    fn is_invalid(result: Result<impl std::fmt::Debug>) -> bool {
+        match result {
+            Ok(_) => false,
+            Err(e) => e.downcast_ref::<InvalidRequestError>().is_some(),
+        }
+    }
+
+    #[test]
+    fn distances_are_great_circles() {
+        let (london, paris) = ((51.5074, -0.1278), (48.8566, 2.3522));
+        assert!((distance_km(london, paris) - 343.5).abs() < 1.0);
+        assert_eq!(distance_km(paris, paris), 0.0);
+        // Across the antimeridian is the short way round
+        let one_degree = 2.0 * std::f64::consts::PI * EARTH_RADIUS_KM / 360.0;
+        assert!((distance_km((0.0, 179.5), (0.0, -179.5)) - one_degree).abs() < 0.01);
+    }
+
+    #[test]
+    fn near_finds_buddies_within_the_radius_closest_first() {
+        let mut fiji = place(-18.1, 178.4);
+        fiji.city = Some("Suva".to_string());
+        let mut archived = buddy("Archived", Some(place(-18.1, 178.5)));
+        archived.delete_timestamp = Some(Timestamp(1));
+        let buddies: HashMap<Uuid, Buddy> = vec![
+            buddy("Suva", Some(fiji)),
+            buddy("Across the line", Some(place(-18.1, -179.9))),
+            buddy("Auckland", Some(place(-36.8, 174.8))),
+            buddy("Nowhere", None),
+            archived,
+        ]
+        .into_iter()
+        .map(|buddy| (buddy.id, buddy))
+        .collect();
+
+        let mut near = NearFilter {
+            latitude: Some(-18.1),
+            longitude: Some(178.5),
+            radius_km: Some(300.0),
+            ..Default::default()
+        };
+        let nearby = find_near(&buddies, &near).unwrap();
+        assert_eq!(names(&nearby), vec!["Suva", "Across the line"]);
+        assert!(nearby.iter().all(|near| near.distance_km.unwrap() <= 300.0));
+
+        near.city = Some(" suva ".to_string());
+        assert_eq!(names(&find_near(&buddies, &near).unwrap()), vec!["Suva"]);
+        let by_city = NearFilter {
+            city: Some("SUVA".to_string()),
+            ..Default::default()
+        };
+        let nearby = find_near(&buddies, &by_city).unwrap();
+        assert_eq!(names(&nearby), vec!["Suva"]);
+        assert_eq!(nearby[0].distance_km, None);
+    }
+
+    #[test]
+    fn bad_searches_are_invalid_requests() {
+        let buddies = HashMap::new();
+        let search = |latitude, longitude, radius_km| {
+            find_near(
+                &buddies,
+                &NearFilter {
+                    latitude,
+                    longitude,
+                    radius_km,
+                    ..Default::default()
+                },
+            )
+        };
+        assert!(is_invalid(search(None, None, None)));
+        assert!(is_invalid(search(Some(10.0), None, None)));
+        assert!(is_invalid(search(Some(91.0), Some(0.0), None)));
+        assert!(is_invalid(search(Some(0.0), Some(-180.5), None)));
+        assert!(is_invalid(search(Some(f64::NAN), Some(0.0), None)));
+        assert!(is_invalid(search(Some(0.0), Some(0.0), Some(-1.0))));
+        assert!(is_invalid(search(Some(0.0), Some(0.0), Some(f64::NAN))));
+        assert!(search(Some(-90.0), Some(180.0), Some(0.0)).is_ok());
+    }
+
+    #[test]
+    fn locations_are_tidied_or_rejected() {
+        let location = validate_location(Location {
+            text: Some("  ".to_string()),
+            city: Some(" Kolkata ".to_string()),
+            country_code: Some("in".to_string()),
+            timezone: Some("Asia/Kolkata".to_string()),
+            ..place(22.57, 88.36)
+        })
+        .unwrap();
+        assert_eq!(location.text, None);
+        assert_eq!(location.city.as_deref(), Some("Kolkata"));
+        assert_eq!(location.country_code.as_deref(), Some("IN"));
+
+        let bad = |location: Location| is_invalid(validate_location(location));
+        assert!(bad(place(90.5, 0.0)));
+        assert!(bad(Location {
+            longitude: None,
+            ..place(0.0, 0.0)
+        }));
+        assert!(bad(Location {
+            country_code: Some("IND".to_string()),
+            ..place(0.0, 0.0)
+        }));
+        assert!(bad(Location {
+            timezone: Some("Mars/Olympus_Mons".to_string()),
+            ..place(0.0, 0.0)
+        }));
+    }
+
+    #[test]
+    fn local_times_follow_the_timezone() {
+        let now = Utc.ymd(2021, 1, 1).and_hms(12, 0, 0);
+        let kolkata = Location {
+            timezone: Some("Asia/Kolkata".to_string()),
+            ..place(22.57, 88.36)
+        };
+        let time = local_time(&kolkata, now).unwrap().unwrap();
+        assert_eq!(time.time, "2021-01-01T17:30:00+05:30");
+        assert_eq!(time.utc_offset_seconds, 19800);
+        assert!(time.reasonable_hour);
+
+        let auckland = Location {
+            timezone: Some("Pacific/Auckland".to_string()),
+            ..place(-36.8, 174.8)
+        };
+        let time = local_time(&auckland, now).unwrap().unwrap();
+        assert_eq!(time.time, "2021-01-02T01:00:00+13:00");
+        assert!(!time.reasonable_hour);
+
+        assert!(local_time(&place(0.0, 0.0), now).unwrap().is_none());
+    }
+}
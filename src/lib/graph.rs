@@ -0,0 +1,249 @@
+use crate::lib::types::{
+    Buddy, Connection, Datestamp, EdgeDirection, GraphEdge, Interaction, NotFoundError,
+    Relationship,
+};
+use anyhow::Result;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// Everything we know about how a neighbor is connected to the center buddy
+#[derive(Default)]
+struct Neighbor {
+    relationships: Vec<Relationship>,
+    shared_interactions: usize,
+    last_shared_interaction: Option<Datestamp>,
+}
+
+/// A relationship as an edge, pointing the way it was recorded unless it goes both ways
+fn edge(center: Uuid, relationship: &Relationship) -> GraphEdge {
+    let direction = if relationship.bidirectional {
+        EdgeDirection::Mutual
+    } else if relationship.from_buddy_id == center {
+        EdgeDirection::Outgoing
+    } else {
+        EdgeDirection::Incoming
+    };
+    GraphEdge {
+        relationship_id: relationship.id,
+        kind: relationship.kind,
+        direction,
+    }
+}
+
+/// Find everyone directly connected to `buddy_id`, either through a recorded
+/// relationship or by showing up in the same interactions
+pub fn build_neighborhood(
+    buddy_id: Uuid,
+    buddies: &HashMap<Uuid, Buddy>,
+    relationships: &HashMap<Uuid, Relationship>,
+    interactions: &HashMap<Uuid, Interaction>,
+) -> Result<Vec<Connection>> {
+    match buddies.get(&buddy_id) {
+        Some(buddy) if buddy.delete_timestamp.is_none() => {}
+        _ => {
+            return Err(NotFoundError {
+                kind: "buddy",
+                id: buddy_id,
+            }
+            .into())
+        }
+    }
+
+    let mut neighbors: HashMap<Uuid, Neighbor> = HashMap::new();
+    for relationship in relationships.values() {
+        if relationship.delete_timestamp.is_some() {
+            continue;
+        }
+        let other = if relationship.from_buddy_id == buddy_id {
+            relationship.to_buddy_id
+        } else if relationship.to_buddy_id == buddy_id {
+            relationship.from_buddy_id
+        } else {
+            continue;
+        };
+        neighbors
+            .entry(other)
+            .or_default()
+            .relationships
+            .push(relationship.clone());
+    }
+
+    for interaction in interactions.values() {
+        if interaction.delete_timestamp.is_some() || !interaction.participants.contains(&buddy_id) {
+            continue;
+        }
+        for participant in &interaction.participants {
+            if *participant == buddy_id {
+                continue;
+            }
+            let neighbor = neighbors.entry(*participant).or_default();
+            neighbor.shared_interactions += 1;
+            if interaction.date > neighbor.last_shared_interaction {
+                neighbor.last_shared_interaction = interaction.date.clone();
+            }
+        }
+    }
+
+    let mut connections: Vec<Connection> = neighbors
+        .into_iter()
+        .filter_map(|(other, neighbor)| {
+            // Archived buddies fall out of the graph along with their edges
+            let buddy = buddies
+                .get(&other)
+                .filter(|buddy| buddy.delete_timestamp.is_none())?;
+            let edges = neighbor
+                .relationships
+                .iter()
+                .map(|relationship| edge(buddy_id, relationship))
+                .collect();
+            Some(Connection {
+                buddy: buddy.clone(),
+                relationships: neighbor.relationships,
+                edges,
+                shared_interactions: neighbor.shared_interactions,
+                last_shared_interaction: neighbor.last_shared_interaction,
+            })
+        })
+        .collect();
+    connections.sort_by_key(|connection| {
+        (
+            Reverse(connection.shared_interactions),
+            connection.buddy.name.clone(),
+        )
+    });
+    Ok(connections)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::testing::buddy;
+    use crate::lib::types::{RelationshipKind, Timestamp};
+
+    fn relationship(from: &Buddy, to: &Buddy, bidirectional: bool) -> Relationship {
+        Relationship {
+            id: Uuid::new_v4(),
+            from_buddy_id: from.id,
+            to_buddy_id: to.id,
+            kind: RelationshipKind::IntroducedBy,
+            bidirectional,
+            create_timestamp: Timestamp(0),
+            last_update_timestamp: Timestamp(0),
+            delete_timestamp: None,
+            user_id: Uuid::nil(),
+        }
+    }
+
+    fn interaction(participants: &[&Buddy], date: &str) -> Interaction {
+        Interaction {
+            id: Uuid::new_v4(),
+            notes: String::new(),
+            participants: participants.iter().map(|buddy| buddy.id).collect(),
+            date: Some(Datestamp(date.to_string())),
+            kind: None,
+            duration: None,
+            location: None,
+            initiator: None,
+            create_timestamp: Timestamp(0),
+            last_update_timestamp: Timestamp(0),
+            delete_timestamp: None,
+            user_id: Uuid::nil(),
+            version: 1,
+        }
+    }
+
+    fn by_id<T>(records: Vec<T>, id: impl Fn(&T) -> Uuid) -> HashMap<Uuid, T> {
+        records
+            .into_iter()
+            .map(|record| (id(&record), record))
+            .collect()
+    }
+
+    #[test]
+    fn neighbors_come_from_relationships_and_shared_interactions() {
+        let center = buddy("Center");
+        let (introducer, introduced, partner) =
+            (buddy("Introducer"), buddy("Introduced"), buddy("Partner"));
+        let (colleague, stranger) = (buddy("Colleague"), buddy("Stranger"));
+        let mut archived = buddy("Archived");
+        archived.delete_timestamp = Some(Timestamp(1));
+        let mut ended = relationship(&center, &stranger, true);
+        ended.delete_timestamp = Some(Timestamp(1));
+        let mut archived_interaction = interaction(&[&center, &stranger], "2021-01-09");
+        archived_interaction.delete_timestamp = Some(Timestamp(1));
+        let relationships = vec![
+            relationship(&center, &introducer, false),
+            relationship(&introduced, &center, false),
+            relationship(&partner, &center, true),
+            relationship(&center, &archived, true),
+            ended,
+        ];
+        let interactions = vec![
+            interaction(&[&center, &colleague], "2021-01-01"),
+            interaction(&[&center, &colleague, &partner], "2021-01-03"),
+            interaction(&[&colleague, &stranger], "2021-01-05"),
+            archived_interaction,
+        ];
+
+        let connections = build_neighborhood(
+            center.id,
+            &by_id(
+                vec![
+                    center.clone(),
+                    introducer.clone(),
+                    introduced.clone(),
+                    partner.clone(),
+                    colleague.clone(),
+                    stranger,
+                    archived,
+                ],
+                |buddy| buddy.id,
+            ),
+            &by_id(relationships, |relationship| relationship.id),
+            &by_id(interactions, |interaction| interaction.id),
+        )
+        .unwrap();
+
+        let summary: Vec<(&str, usize, Option<&str>, Vec<EdgeDirection>)> = connections
+            .iter()
+            .map(|connection| {
+                (
+                    connection.buddy.name.as_str(),
+                    connection.shared_interactions,
+                    connection
+                        .last_shared_interaction
+                        .as_ref()
+                        .map(|date| date.0.as_str()),
+                    connection.edges.iter().map(|edge| edge.direction).collect(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("Colleague", 2, Some("2021-01-03"), vec![]),
+                (
+                    "Partner",
+                    1,
+                    Some("2021-01-03"),
+                    vec![EdgeDirection::Mutual]
+                ),
+                ("Introduced", 0, None, vec![EdgeDirection::Incoming]),
+                ("Introducer", 0, None, vec![EdgeDirection::Outgoing]),
+            ]
+        );
+    }
+
+    #[test]
+    fn missing_and_archived_centers_are_not_found() {
+        let mut archived = buddy("Archived");
+        archived.delete_timestamp = Some(Timestamp(1));
+        let buddies = by_id(vec![archived.clone()], |buddy| buddy.id);
+        for buddy_id in &[archived.id, Uuid::new_v4()] {
+            let err = build_neighborhood(*buddy_id, &buddies, &HashMap::new(), &HashMap::new())
+                .unwrap_err();
+            assert!(err.downcast_ref::<NotFoundError>().is_some(), "{:?}", err);
+        }
+    }
+}
@@ -0,0 +1,758 @@
+use crate::lib::service::{BuddiesService, RequestHandler};
+use crate::lib::storage::BuddiesStore;
+use crate::lib::types::{
+    ArchiveBuddyRequest, ArchiveInteractionRequest, Buddy, ContactMethod, CreateBuddyRequest,
+    CreateInteractionRequest, Datestamp, GetBuddyRequest, GetInteractionRequest,
+    GetUpcomingDatesRequest, GetUpcomingDatesResponse, GetUserRequest, ImportantDate, Interaction,
+    InteractionFilter, InteractionKind, ListBuddiesRequest, ListInteractionsRequest, LocalTime,
+    Location, PublicUser, Scope, UpcomingBirthday, UpcomingDate, UpdateBuddyRequest,
+    UpdateInteractionRequest,
+};
+use anyhow::Result;
+use juniper::{FieldResult, RootNode, ID};
+use schemars::JsonSchema;
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::str::FromStr;
+use std::time::Duration;
+use uuid::Uuid;
+
+pub type Schema = RootNode<'static, Query, Mutation>;
+
+pub fn schema() -> Schema {
+    RootNode::new(Query, Mutation)
+}
+
+/// A GraphQL request, as sent by GraphQL clients
+#[derive(Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct GraphQLBody {
+    pub query: String,
+    pub operation_name: Option<String>,
+    pub variables: Option<serde_json::Value>,
+}
+
+/// The parts of `BuddiesService` the resolvers use, boxed up so the schema doesn't
+/// need to know which store is behind it
+pub trait GraphQLService {
+    fn get_user(&self, user_id: Uuid) -> Result<PublicUser>;
+    fn list_buddies(&self, user_id: Uuid) -> Result<Vec<Buddy>>;
+    fn get_buddy(&self, user_id: Uuid, buddy_id: Uuid) -> Result<Buddy>;
+    fn list_interactions(
+        &self,
+        user_id: Uuid,
+        filter: InteractionFilter,
+    ) -> Result<Vec<Interaction>>;
+    fn get_interaction(&self, user_id: Uuid, interaction_id: Uuid) -> Result<Interaction>;
+    fn get_upcoming_dates(
+        &self,
+        user_id: Uuid,
+        days: Option<i64>,
+    ) -> Result<GetUpcomingDatesResponse>;
+    fn create_buddy(&self, request: CreateBuddyRequest) -> Result<Buddy>;
+    fn update_buddy(&self, request: UpdateBuddyRequest) -> Result<()>;
+    fn archive_buddy(&self, request: ArchiveBuddyRequest) -> Result<()>;
+    fn create_interaction(&self, request: CreateInteractionRequest) -> Result<Interaction>;
+    fn update_interaction(&self, request: UpdateInteractionRequest) -> Result<()>;
+    fn archive_interaction(&self, request: ArchiveInteractionRequest) -> Result<()>;
+}
+
+impl<S: BuddiesStore> GraphQLService for RequestHandler<S> {
+    fn get_user(&self, user_id: Uuid) -> Result<PublicUser> {
+        BuddiesService::get_user(self, GetUserRequest { user_id })
+    }
+    fn list_buddies(&self, user_id: Uuid) -> Result<Vec<Buddy>> {
+        Ok(BuddiesService::list_buddies(self, ListBuddiesRequest { user_id })?.buddies)
+    }
+    fn get_buddy(&self, user_id: Uuid, buddy_id: Uuid) -> Result<Buddy> {
+        BuddiesService::get_buddy(self, GetBuddyRequest { user_id, buddy_id })
+    }
+    fn list_interactions(
+        &self,
+        user_id: Uuid,
+        filter: InteractionFilter,
+    ) -> Result<Vec<Interaction>> {
+        let request = ListInteractionsRequest { user_id, filter };
+        Ok(BuddiesService::list_interactions(self, request)?.interactions)
+    }
+    fn get_interaction(&self, user_id: Uuid, interaction_id: Uuid) -> Result<Interaction> {
+        BuddiesService::get_interaction(
+            self,
+            GetInteractionRequest {
+                user_id,
+                interaction_id,
+            },
+        )
+    }
+    fn get_upcoming_dates(
+        &self,
+        user_id: Uuid,
+        days: Option<i64>,
+    ) -> Result<GetUpcomingDatesResponse> {
+        BuddiesService::get_upcoming_dates(self, GetUpcomingDatesRequest { user_id, days })
+    }
+    fn create_buddy(&self, request: CreateBuddyRequest) -> Result<Buddy> {
+        Ok(BuddiesService::create_buddy(&mut self.clone(), request)?.buddy)
+    }
+    fn update_buddy(&self, request: UpdateBuddyRequest) -> Result<()> {
+        BuddiesService::update_buddy(&mut self.clone(), request)?;
+        Ok(())
+    }
+    fn archive_buddy(&self, request: ArchiveBuddyRequest) -> Result<()> {
+        BuddiesService::archive_buddy(&mut self.clone(), request)?;
+        Ok(())
+    }
+    fn create_interaction(&self, request: CreateInteractionRequest) -> Result<Interaction> {
+        Ok(BuddiesService::create_interaction(&mut self.clone(), request)?.interaction)
+    }
+    fn update_interaction(&self, request: UpdateInteractionRequest) -> Result<()> {
+        BuddiesService::update_interaction(&mut self.clone(), request)?;
+        Ok(())
+    }
+    fn archive_interaction(&self, request: ArchiveInteractionRequest) -> Result<()> {
+        BuddiesService::archive_interaction(&mut self.clone(), request)?;
+        Ok(())
+    }
+}
+
+/// Who is asking, and the service to ask on their behalf
+pub struct Context {
+    pub user_id: Uuid,
+    /// Reading takes the read scopes to get in the door. Mutations check for write scopes.
+    pub scopes: Vec<Scope>,
+    pub service: Box<dyn GraphQLService>,
+}
+
+impl juniper::Context for Context {}
+
+impl Context {
+    fn require(&self, scope: Scope) -> FieldResult<()> {
+        if self.scopes.contains(&scope) {
+            Ok(())
+        } else {
+            Err(format!("Missing scope {}", scope).into())
+        }
+    }
+}
+
+fn parse_id(id: &ID) -> FieldResult<Uuid> {
+    Ok(Uuid::parse_str(id)?)
+}
+
+fn to_id(id: Uuid) -> ID {
+    ID::new(id.to_string())
+}
+
+fn parse_date(date: Option<String>) -> Option<Datestamp> {
+    date.map(Datestamp)
+}
+
+fn parse_kind(kind: Option<String>) -> FieldResult<Option<InteractionKind>> {
+    Ok(kind
+        .map(|kind| InteractionKind::from_str(&kind))
+        .transpose()?)
+}
+
+fn parse_participants(participants: Vec<ID>) -> FieldResult<HashSet<Uuid>> {
+    participants.iter().map(parse_id).collect()
+}
+
+fn seconds(duration: &Option<Duration>) -> Option<i32> {
+    duration.map(|duration| duration.as_secs() as i32)
+}
+
+fn from_seconds(seconds: Option<i32>) -> Option<Duration> {
+    seconds.map(|seconds| Duration::from_secs(seconds.max(0) as u64))
+}
+
+pub struct Query;
+
+#[juniper::object(Context = Context)]
+impl Query {
+    /// Whoever is signed in
+    fn me(context: &Context) -> FieldResult<PublicUser> {
+        Ok(context.service.get_user(context.user_id)?)
+    }
+
+    /// Every buddy that hasn't been archived, by name
+    fn buddies(context: &Context) -> FieldResult<Vec<Buddy>> {
+        Ok(context.service.list_buddies(context.user_id)?)
+    }
+
+    fn buddy(context: &Context, id: ID) -> FieldResult<Buddy> {
+        Ok(context.service.get_buddy(context.user_id, parse_id(&id)?)?)
+    }
+
+    /// Interactions, most recent first
+    #[graphql(arguments(kind(description = "One of call, text, in_person, video or letter")))]
+    fn interactions(
+        context: &Context,
+        kind: Option<String>,
+        participant: Option<ID>,
+        since: Option<String>,
+        until: Option<String>,
+        limit: Option<i32>,
+    ) -> FieldResult<Vec<Interaction>> {
+        let filter = InteractionFilter {
+            kind: parse_kind(kind)?,
+            participant: participant.as_ref().map(parse_id).transpose()?,
+            initiator: None,
+            since: parse_date(since),
+            until: parse_date(until),
+            limit: limit.map(|limit| limit.max(0) as usize),
+        };
+        Ok(context.service.list_interactions(context.user_id, filter)?)
+    }
+
+    fn interaction(context: &Context, id: ID) -> FieldResult<Interaction> {
+        Ok(context
+            .service
+            .get_interaction(context.user_id, parse_id(&id)?)?)
+    }
+
+    /// Birthdays and important dates coming up, soonest first
+    #[graphql(arguments(days(description = "How many days ahead to look. Defaults to 30")))]
+    fn upcoming_dates(
+        context: &Context,
+        days: Option<i32>,
+    ) -> FieldResult<GetUpcomingDatesResponse> {
+        Ok(context
+            .service
+            .get_upcoming_dates(context.user_id, days.map(i64::from))?)
+    }
+}
+
+#[derive(juniper::GraphQLInputObject)]
+struct CreateBuddyInput {
+    name: String,
+    notes: Option<String>,
+    /// yyyy-mm-dd
+    birthday: Option<String>,
+    /// How often you'd like to talk, in seconds
+    cadence_seconds: Option<i32>,
+    /// Free text, like "Portland, OR, US"
+    location: Option<String>,
+}
+
+/// Fields left out are kept as they are
+#[derive(juniper::GraphQLInputObject)]
+struct UpdateBuddyInput {
+    id: ID,
+    name: Option<String>,
+    notes: Option<String>,
+    last_contacted: Option<String>,
+    birthday: Option<String>,
+    cadence_seconds: Option<i32>,
+    location: Option<String>,
+    /// The version the update is based on. Stale updates are rejected.
+    version: Option<i32>,
+}
+
+#[derive(juniper::GraphQLInputObject)]
+struct CreateInteractionInput {
+    notes: Option<String>,
+    participants: Vec<ID>,
+    date: Option<String>,
+    kind: Option<String>,
+    duration_seconds: Option<i32>,
+    location: Option<String>,
+    initiator: Option<ID>,
+}
+
+/// Fields left out are kept as they are
+#[derive(juniper::GraphQLInputObject)]
+struct UpdateInteractionInput {
+    id: ID,
+    notes: Option<String>,
+    participants: Option<Vec<ID>>,
+    date: Option<String>,
+    kind: Option<String>,
+    duration_seconds: Option<i32>,
+    location: Option<String>,
+    initiator: Option<ID>,
+    version: Option<i32>,
+}
+
+pub struct Mutation;
+
+#[juniper::object(Context = Context)]
+impl Mutation {
+    fn create_buddy(context: &Context, input: CreateBuddyInput) -> FieldResult<Buddy> {
+        context.require(Scope::BuddiesWrite)?;
+        Ok(context.service.create_buddy(CreateBuddyRequest {
+            user_id: context.user_id,
+            name: input.name,
+            birthday: parse_date(input.birthday),
+            cadence: from_seconds(input.cadence_seconds),
+            notes: input.notes.unwrap_or_default(),
+            location: input.location.map(Location::from_text),
+            ..Default::default()
+        })?)
+    }
+
+    fn update_buddy(context: &Context, input: UpdateBuddyInput) -> FieldResult<Buddy> {
+        context.require(Scope::BuddiesWrite)?;
+        let buddy_id = parse_id(&input.id)?;
+        context.service.update_buddy(UpdateBuddyRequest {
+            user_id: context.user_id,
+            buddy_id,
+            name: input.name,
+            notes: input.notes,
+            last_contacted: parse_date(input.last_contacted),
+            birthday: parse_date(input.birthday).map(Some),
+            cadence: from_seconds(input.cadence_seconds).map(Some),
+            location: input.location.map(|text| Some(Location::from_text(text))),
+            version: input.version,
+        })?;
+        Ok(context.service.get_buddy(context.user_id, buddy_id)?)
+    }
+
+    /// Returns whether the buddy was archived
+    fn archive_buddy(context: &Context, id: ID, version: Option<i32>) -> FieldResult<bool> {
+        context.require(Scope::BuddiesWrite)?;
+        context.service.archive_buddy(ArchiveBuddyRequest {
+            id: parse_id(&id)?,
+            user_id: context.user_id,
+            version,
+        })?;
+        Ok(true)
+    }
+
+    fn create_interaction(
+        context: &Context,
+        input: CreateInteractionInput,
+    ) -> FieldResult<Interaction> {
+        context.require(Scope::InteractionsWrite)?;
+        Ok(context
+            .service
+            .create_interaction(CreateInteractionRequest {
+                user_id: context.user_id,
+                notes: input.notes.unwrap_or_default(),
+                participants: parse_participants(input.participants)?,
+                date: parse_date(input.date),
+                kind: parse_kind(input.kind)?,
+                duration: from_seconds(input.duration_seconds),
+                location: input.location,
+                initiator: input.initiator.as_ref().map(parse_id).transpose()?,
+                ..Default::default()
+            })?)
+    }
+
+    fn update_interaction(
+        context: &Context,
+        input: UpdateInteractionInput,
+    ) -> FieldResult<Interaction> {
+        context.require(Scope::InteractionsWrite)?;
+        let interaction_id = parse_id(&input.id)?;
+        context
+            .service
+            .update_interaction(UpdateInteractionRequest {
+                user_id: context.user_id,
+                interaction_id,
+                notes: input.notes,
+                participants: input.participants.map(parse_participants).transpose()?,
+                date: parse_date(input.date).map(Some),
+                kind: parse_kind(input.kind)?.map(Some),
+                duration: from_seconds(input.duration_seconds).map(Some),
+                location: input.location.map(Some),
+                initiator: input
+                    .initiator
+                    .as_ref()
+                    .map(parse_id)
+                    .transpose()?
+                    .map(Some),
+                version: input.version,
+            })?;
+        Ok(context
+            .service
+            .get_interaction(context.user_id, interaction_id)?)
+    }
+
+    /// Returns whether the interaction was archived
+    fn archive_interaction(context: &Context, id: ID, version: Option<i32>) -> FieldResult<bool> {
+        context.require(Scope::InteractionsWrite)?;
+        context
+            .service
+            .archive_interaction(ArchiveInteractionRequest {
+                id: parse_id(&id)?,
+                user_id: context.user_id,
+                version,
+            })?;
+        Ok(true)
+    }
+}
+
+#[juniper::object(Context = Context, name = "User")]
+impl PublicUser {
+    fn id(&self) -> ID {
+        to_id(self.id)
+    }
+
+    fn email(&self) -> &str {
+        &self.email
+    }
+
+    /// One of daily, weekly or never
+    fn digest_frequency(&self) -> String {
+        self.digest_frequency.to_string()
+    }
+
+    fn buddies(&self, context: &Context) -> FieldResult<Vec<Buddy>> {
+        Ok(context.service.list_buddies(context.user_id)?)
+    }
+}
+
+#[juniper::object(Context = Context)]
+impl Buddy {
+    fn id(&self) -> ID {
+        to_id(self.id)
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// yyyy-mm-dd
+    fn birthday(&self) -> Option<&str> {
+        self.birthday.as_ref().map(|birthday| birthday.0.as_str())
+    }
+
+    /// How often you'd like to talk, in seconds
+    fn cadence_seconds(&self) -> Option<i32> {
+        seconds(&self.cadence)
+    }
+
+    /// How often you actually talk, in seconds
+    fn suggested_cadence_seconds(&self) -> Option<i32> {
+        seconds(&self.suggested_cadence)
+    }
+
+    fn notes(&self) -> &str {
+        &self.notes
+    }
+
+    fn location(&self) -> Option<&Location> {
+        self.location.as_ref()
+    }
+
+    /// What time it is wherever they are
+    fn local_time(&self) -> Option<&LocalTime> {
+        self.local_time.as_ref()
+    }
+
+    fn last_contacted(&self) -> &str {
+        &self.last_contacted.0
+    }
+
+    fn version(&self) -> i32 {
+        self.version
+    }
+
+    fn contact_methods(&self) -> &Vec<ContactMethod> {
+        &self.contact_methods
+    }
+
+    fn important_dates(&self) -> &Vec<ImportantDate> {
+        &self.important_dates
+    }
+
+    /// Interactions they took part in, most recent first
+    #[graphql(arguments(limit(description = "Return at most this many interactions")))]
+    fn interactions(&self, context: &Context, limit: Option<i32>) -> FieldResult<Vec<Interaction>> {
+        let filter = InteractionFilter {
+            participant: Some(self.id),
+            limit: limit.map(|limit| limit.max(0) as usize),
+            ..Default::default()
+        };
+        Ok(context.service.list_interactions(context.user_id, filter)?)
+    }
+}
+
+#[juniper::object(Context = Context)]
+impl Interaction {
+    fn id(&self) -> ID {
+        to_id(self.id)
+    }
+
+    fn notes(&self) -> &str {
+        &self.notes
+    }
+
+    /// Buddies that took part, leaving out any that have since been archived
+    fn participants(&self, context: &Context) -> Vec<Buddy> {
+        let mut participants: Vec<Buddy> = self
+            .participants
+            .iter()
+            .filter_map(|id| context.service.get_buddy(context.user_id, *id).ok())
+            .collect();
+        participants.sort_by(|a, b| a.name.cmp(&b.name));
+        participants
+    }
+
+    /// yyyy-mm-dd
+    fn date(&self) -> Option<&str> {
+        self.date.as_ref().map(|date| date.0.as_str())
+    }
+
+    /// One of call, text, in_person, video or letter
+    fn kind(&self) -> Option<String> {
+        self.kind.map(|kind| kind.to_string())
+    }
+
+    fn duration_seconds(&self) -> Option<i32> {
+        seconds(&self.duration)
+    }
+
+    fn location(&self) -> Option<&str> {
+        self.location.as_deref()
+    }
+
+    /// The user's id or one of the participants'
+    fn initiator(&self) -> Option<ID> {
+        self.initiator.map(to_id)
+    }
+
+    fn version(&self) -> i32 {
+        self.version
+    }
+}
+
+#[juniper::object(Context = Context)]
+impl Location {
+    /// The location as it was originally written
+    fn text(&self) -> Option<&str> {
+        self.text.as_deref()
+    }
+
+    fn city(&self) -> Option<&str> {
+        self.city.as_deref()
+    }
+
+    fn region(&self) -> Option<&str> {
+        self.region.as_deref()
+    }
+
+    fn country_code(&self) -> Option<&str> {
+        self.country_code.as_deref()
+    }
+
+    fn latitude(&self) -> Option<f64> {
+        self.latitude
+    }
+
+    fn longitude(&self) -> Option<f64> {
+        self.longitude
+    }
+
+    fn timezone(&self) -> Option<&str> {
+        self.timezone.as_deref()
+    }
+}
+
+#[juniper::object(Context = Context)]
+impl LocalTime {
+    /// RFC 3339 time in the buddy's timezone
+    fn time(&self) -> &str {
+        &self.time
+    }
+
+    fn utc_offset_seconds(&self) -> i32 {
+        self.utc_offset_seconds
+    }
+
+    /// Whether it's a reasonable hour to reach out
+    fn reasonable_hour(&self) -> bool {
+        self.reasonable_hour
+    }
+}
+
+#[juniper::object(Context = Context)]
+impl ContactMethod {
+    fn id(&self) -> ID {
+        to_id(self.id)
+    }
+
+    /// One of phone, email, url or social
+    fn kind(&self) -> String {
+        self.kind.to_string()
+    }
+
+    fn value(&self) -> &str {
+        &self.value
+    }
+
+    fn label(&self) -> Option<&str> {
+        self.label.as_deref()
+    }
+
+    fn preferred(&self) -> bool {
+        self.preferred
+    }
+}
+
+#[juniper::object(Context = Context)]
+impl ImportantDate {
+    fn id(&self) -> ID {
+        to_id(self.id)
+    }
+
+    fn kind(&self) -> String {
+        self.kind.to_string()
+    }
+
+    fn label(&self) -> Option<&str> {
+        self.label.as_deref()
+    }
+
+    /// yyyy-mm-dd
+    fn date(&self) -> &str {
+        &self.date.0
+    }
+
+    fn recurrence(&self) -> String {
+        self.recurrence.to_string()
+    }
+}
+
+#[juniper::object(Context = Context, name = "UpcomingDates")]
+impl GetUpcomingDatesResponse {
+    fn birthdays(&self) -> &Vec<UpcomingBirthday> {
+        &self.birthdays
+    }
+
+    fn important_dates(&self) -> &Vec<UpcomingDate> {
+        &self.important_dates
+    }
+}
+
+#[juniper::object(Context = Context)]
+impl UpcomingBirthday {
+    fn buddy(&self, context: &Context) -> FieldResult<Buddy> {
+        Ok(context.service.get_buddy(context.user_id, self.buddy_id)?)
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The next day they celebrate, yyyy-mm-dd
+    fn date(&self) -> &str {
+        &self.date.0
+    }
+
+    fn days_until(&self) -> i32 {
+        self.days_until as i32
+    }
+}
+
+#[juniper::object(Context = Context)]
+impl UpcomingDate {
+    fn buddy(&self, context: &Context) -> FieldResult<Buddy> {
+        Ok(context.service.get_buddy(context.user_id, self.buddy_id)?)
+    }
+
+    fn kind(&self) -> String {
+        self.kind.to_string()
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    /// The next day this comes around, yyyy-mm-dd
+    fn date(&self) -> &str {
+        &self.date.0
+    }
+
+    fn days_until(&self) -> i32 {
+        self.days_until as i32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lib::service::{BuddiesService, RequestHandler};
+    use crate::lib::storage::MemoryBuddiesStore;
+    use crate::lib::testing;
+    use crate::lib::types::{CreateBuddyRequest, CreateInteractionRequest, Scope};
+    use serde_json::{json, Value};
+    use uuid::Uuid;
+
+    async fn query(store: &MemoryBuddiesStore, user_id: Uuid, query: &str) -> Value {
+        let token = testing::token(store, user_id, Scope::ALL);
+        let response = warp::test::request()
+            .method("POST")
+            .path("/graphql")
+            .header("authorization", format!("Bearer {}", token))
+            .json(&json!({ "query": query }))
+            .reply(&testing::routes(store))
+            .await;
+        serde_json::from_slice(response.body()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn users_only_see_their_own_buddies_and_interactions() {
+        let store = MemoryBuddiesStore::new();
+        let mut handler = RequestHandler::new(store.clone());
+        let (me, them) = (Uuid::new_v4(), Uuid::new_v4());
+        let mut create_buddy = |user_id: Uuid, name: &str| {
+            handler
+                .create_buddy(CreateBuddyRequest {
+                    user_id,
+                    name: name.to_string(),
+                    ..Default::default()
+                })
+                .unwrap()
+                .buddy
+                .id
+        };
+        create_buddy(me, "Mine");
+        let their_buddy = create_buddy(them, "Theirs");
+        let their_interaction = handler
+            .create_interaction(CreateInteractionRequest {
+                user_id: them,
+                notes: "Theirs".to_string(),
+                participants: vec![their_buddy].into_iter().collect(),
+                ..Default::default()
+            })
+            .unwrap()
+            .interaction
+            .id;
+
+        let response = query(&store, me, "{ buddies { name } interactions { notes } }").await;
+        assert_eq!(
+            response["data"],
+            json!({ "buddies": [{ "name": "Mine" }], "interactions": [] })
+        );
+
+        let response = query(
+            &store,
+            me,
+            &format!("{{ buddy(id: \"{}\") {{ name }} }}", their_buddy),
+        )
+        .await;
+        assert!(response["data"].is_null(), "{}", response);
+        assert!(response["errors"].is_array(), "{}", response);
+
+        let response = query(
+            &store,
+            me,
+            &format!(
+                "{{ interaction(id: \"{}\") {{ notes }} }}",
+                their_interaction
+            ),
+        )
+        .await;
+        assert!(response["data"].is_null(), "{}", response);
+        assert!(response["errors"].is_array(), "{}", response);
+
+        // Passing someone else's id doesn't get their records
+        let response = query(
+            &store,
+            me,
+            &format!("{{ buddies(userId: \"{}\") {{ name }} }}", them),
+        )
+        .await;
+        assert!(!response.to_string().contains("Theirs"), "{}", response);
+    }
+}
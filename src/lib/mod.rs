@@ -1,4 +1,18 @@
+pub mod analytics;
+pub mod calendar;
+pub mod digest;
+pub mod duplicates;
+pub mod events;
+pub mod geo;
+pub mod graph;
+pub mod graphql;
+pub mod openapi;
+pub mod patch;
 pub mod routes;
 pub mod service;
 pub mod storage;
+#[cfg(test)]
+pub mod testing;
 pub mod types;
+pub mod vcard;
+pub mod webhooks;
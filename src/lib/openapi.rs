@@ -0,0 +1,317 @@
+use crate::lib::types::Scope;
+use schemars::gen::{SchemaGenerator, SchemaSettings};
+use schemars::schema::Schema;
+use schemars::JsonSchema;
+use serde_json::{json, Map, Value};
+
+/// A page that renders the OpenAPI document with Swagger UI
+pub const SWAGGER_UI: &str = include_str!("../../templates/swagger.html");
+
+/// Builds the schema of a type, adding anything it refers to as a component
+type SchemaFn = fn(&mut SchemaGenerator) -> Schema;
+
+fn schema<T: JsonSchema>(gen: &mut SchemaGenerator) -> Schema {
+    gen.subschema_for::<T>()
+}
+
+enum Body {
+    Empty,
+    Json(SchemaFn),
+    /// A JSON Merge Patch of the resource
+    MergePatch,
+}
+
+enum Response {
+    Json(u16, SchemaFn),
+    /// A document in some other format, like a vCard
+    Text(&'static str),
+    NoContent,
+}
+
+/// One route, as described in the OpenAPI document
+pub struct Endpoint {
+    method: &'static str,
+    /// With path parameters in braces, like `/buddy/{buddy_id}/graph`
+    path: &'static str,
+    summary: &'static str,
+    tag: &'static str,
+    authenticated: bool,
+    /// What the caller's credential must have been given
+    scopes: &'static [Scope],
+    query: Option<SchemaFn>,
+    headers: Vec<&'static str>,
+    body: Body,
+    response: Response,
+}
+
+impl Endpoint {
+    fn new(
+        method: &'static str,
+        path: &'static str,
+        tag: &'static str,
+        summary: &'static str,
+    ) -> Self {
+        Endpoint {
+            method,
+            path,
+            summary,
+            tag,
+            authenticated: true,
+            scopes: &[],
+            query: None,
+            headers: Vec::new(),
+            body: Body::Empty,
+            response: Response::NoContent,
+        }
+    }
+
+    pub fn get(path: &'static str, tag: &'static str, summary: &'static str) -> Self {
+        Endpoint::new("get", path, tag, summary)
+    }
+
+    pub fn post(path: &'static str, tag: &'static str, summary: &'static str) -> Self {
+        Endpoint::new("post", path, tag, summary)
+    }
+
+    pub fn patch(path: &'static str, tag: &'static str, summary: &'static str) -> Self {
+        Endpoint::new("patch", path, tag, summary)
+    }
+
+    pub fn delete(path: &'static str, tag: &'static str, summary: &'static str) -> Self {
+        Endpoint::new("delete", path, tag, summary)
+    }
+
+    /// Doesn't need an Authorization header
+    pub fn public(mut self) -> Self {
+        self.authenticated = false;
+        self
+    }
+
+    pub fn scopes(mut self, scopes: &'static [Scope]) -> Self {
+        self.scopes = scopes;
+        self
+    }
+
+    /// Takes its parameters from the query string
+    pub fn query<T: JsonSchema>(mut self) -> Self {
+        self.query = Some(schema::<T>);
+        self
+    }
+
+    /// Reads an optional request header
+    pub fn header(mut self, name: &'static str) -> Self {
+        self.headers.push(name);
+        self
+    }
+
+    pub fn body<T: JsonSchema>(mut self) -> Self {
+        self.body = Body::Json(schema::<T>);
+        self
+    }
+
+    pub fn merge_patch(mut self) -> Self {
+        self.body = Body::MergePatch;
+        self
+    }
+
+    pub fn returns<T: JsonSchema>(self) -> Self {
+        self.returns_status::<T>(200)
+    }
+
+    pub fn returns_status<T: JsonSchema>(mut self, status: u16) -> Self {
+        self.response = Response::Json(status, schema::<T>);
+        self
+    }
+
+    pub fn returns_text(mut self, content_type: &'static str) -> Self {
+        self.response = Response::Text(content_type);
+        self
+    }
+
+    fn operation_id(&self) -> String {
+        let mut id = self.method.to_string();
+        for segment in self.path.split(['/', '{', '}']) {
+            if !segment.is_empty() {
+                id.push('_');
+                id.push_str(segment);
+            }
+        }
+        id
+    }
+
+    fn parameters(&self) -> Vec<Value> {
+        let mut parameters = Vec::new();
+        for segment in self.path.split('/') {
+            if let Some(name) = segment.strip_prefix('{').and_then(|s| s.strip_suffix('}')) {
+                let schema = if name.ends_with("_id") {
+                    json!({"type": "string", "format": "uuid"})
+                } else {
+                    json!({"type": "string"})
+                };
+                parameters.push(json!({
+                    "name": name,
+                    "in": "path",
+                    "required": true,
+                    "schema": schema,
+                }));
+            }
+        }
+
+        if let Some(query) = self.query {
+            // Query parameters are listed one by one, so their schemas can't be references
+            let mut inline = SchemaSettings::openapi3()
+                .with(|settings| settings.inline_subschemas = true)
+                .into_generator();
+            let query = query(&mut inline).into_object();
+            if let Some(object) = query.object {
+                for (name, schema) in object.properties {
+                    parameters.push(json!({
+                        "name": name,
+                        "in": "query",
+                        "required": object.required.contains(&name),
+                        "schema": schema,
+                    }));
+                }
+            }
+        }
+
+        for header in &self.headers {
+            parameters.push(json!({
+                "name": header,
+                "in": "header",
+                "required": false,
+                "schema": {"type": "string"},
+            }));
+        }
+        parameters
+    }
+
+    fn operation(&self, gen: &mut SchemaGenerator) -> Value {
+        let mut operation = Map::new();
+        operation.insert("operationId".to_string(), json!(self.operation_id()));
+        operation.insert("summary".to_string(), json!(self.summary));
+        operation.insert("tags".to_string(), json!([self.tag]));
+        let parameters = self.parameters();
+        if !parameters.is_empty() {
+            operation.insert("parameters".to_string(), json!(parameters));
+        }
+        if !self.authenticated {
+            operation.insert("security".to_string(), json!([]));
+        } else if !self.scopes.is_empty() {
+            let scopes: Vec<String> = self.scopes.iter().map(|scope| scope.to_string()).collect();
+            operation.insert("security".to_string(), json!([{ "bearer": scopes }]));
+        }
+
+        match self.body {
+            Body::Empty => {}
+            Body::Json(body) => {
+                operation.insert(
+                    "requestBody".to_string(),
+                    json!({
+                        "required": true,
+                        "content": {"application/json": {"schema": body(gen)}},
+                    }),
+                );
+            }
+            Body::MergePatch => {
+                operation.insert(
+                    "requestBody".to_string(),
+                    json!({
+                        "required": true,
+                        "content": {"application/merge-patch+json": {"schema": {"type": "object"}}},
+                    }),
+                );
+            }
+        }
+
+        let mut responses = Map::new();
+        match self.response {
+            Response::Json(status, response) => {
+                responses.insert(
+                    status.to_string(),
+                    json!({
+                        "description": "Success",
+                        "content": {"application/json": {"schema": response(gen)}},
+                    }),
+                );
+            }
+            Response::Text(content_type) => {
+                let mut content = Map::new();
+                content.insert(
+                    content_type.to_string(),
+                    json!({"schema": {"type": "string"}}),
+                );
+                responses.insert(
+                    "200".to_string(),
+                    json!({"description": "Success", "content": content}),
+                );
+            }
+            Response::NoContent => {
+                responses.insert("204".to_string(), json!({"description": "Success"}));
+            }
+        }
+        responses.insert(
+            "default".to_string(),
+            json!({
+                "description": "Something went wrong",
+                "content": {"application/json": {"schema": {"$ref": "#/components/schemas/ErrorMessage"}}},
+            }),
+        );
+        operation.insert("responses".to_string(), Value::Object(responses));
+        Value::Object(operation)
+    }
+}
+
+/// Build an OpenAPI 3 document for the given routes, with the types they use as components
+pub fn build_spec(endpoints: &[Endpoint]) -> Value {
+    let mut gen = SchemaSettings::openapi3().into_generator();
+    let mut paths: Map<String, Value> = Map::new();
+    for endpoint in endpoints {
+        let operation = endpoint.operation(&mut gen);
+        let path = paths
+            .entry(endpoint.path.to_string())
+            .or_insert_with(|| json!({}));
+        if let Some(path) = path.as_object_mut() {
+            path.insert(endpoint.method.to_string(), operation);
+        }
+    }
+
+    let mut schemas: Map<String, Value> = gen
+        .take_definitions()
+        .into_iter()
+        .map(|(name, schema)| (name, json!(schema)))
+        .collect();
+    schemas.insert(
+        "ErrorMessage".to_string(),
+        json!({
+            "type": "object",
+            "required": ["code", "message"],
+            "properties": {
+                "code": {"type": "integer"},
+                "message": {"type": "string"},
+                "current": {"description": "On a conflict, the record as it stands"},
+            },
+        }),
+    );
+
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "Buddies",
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "paths": paths,
+        "components": {
+            "schemas": schemas,
+            "securitySchemes": {
+                "bearer": {
+                    "type": "http",
+                    "scheme": "bearer",
+                    "bearerFormat": "JWT",
+                    "description": "A JWT from /login, or a personal access token from /token/create",
+                },
+            },
+        },
+        "security": [{"bearer": []}],
+    })
+}
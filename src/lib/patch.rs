@@ -0,0 +1,113 @@
+use crate::lib::types::InvalidRequestError;
+use anyhow::Result;
+use serde_json::{Map, Value};
+
+/// Apply a JSON Merge Patch (RFC 7396) to a document
+pub fn merge_patch(target: &mut Value, patch: &Value) {
+    let patch = match patch {
+        Value::Object(patch) => patch,
+        _ => {
+            *target = patch.clone();
+            return;
+        }
+    };
+    if !target.is_object() {
+        *target = Value::Object(Map::new());
+    }
+    if let Value::Object(target) = target {
+        for (key, value) in patch {
+            if value.is_null() {
+                target.remove(key);
+            } else {
+                merge_patch(target.entry(key.as_str()).or_insert(Value::Null), value);
+            }
+        }
+    }
+}
+
+/// Turn a merge patch of a record into the fields of an update request. Each field the patch
+/// touches gets its value in the patched record, or null where the patch removed it.
+/// Only `fields` can be patched, and of those the `required` ones can't be removed.
+/// Patches that break those rules are invalid requests.
+pub fn patched_fields(
+    record: Value,
+    patch: &Value,
+    fields: &[&str],
+    required: &[&str],
+) -> Result<Map<String, Value>> {
+    let touched = patch.as_object().ok_or_else(|| InvalidRequestError {
+        message: "A merge patch must be a JSON object".to_string(),
+    })?;
+    let mut patched = record;
+    merge_patch(&mut patched, patch);
+
+    let mut update = Map::new();
+    for field in touched.keys() {
+        if !fields.contains(&field.as_str()) {
+            return Err(InvalidRequestError {
+                message: format!("{} can't be patched", field),
+            }
+            .into());
+        }
+        let value = patched.get(field).cloned().unwrap_or(Value::Null);
+        if value.is_null() && required.contains(&field.as_str()) {
+            return Err(InvalidRequestError {
+                message: format!("{} can't be removed", field),
+            }
+            .into());
+        }
+        update.insert(field.clone(), value);
+    }
+    Ok(update)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn null_removes_and_absent_keys_are_kept() {
+        let mut target =
+            json!({ "name": "Buddy", "location": { "city": "Paris", "region": "IDF" } });
+        merge_patch(
+            &mut target,
+            &json!({ "birthday": "1990-01-01", "location": { "region": null } }),
+        );
+        assert_eq!(
+            target,
+            json!({ "name": "Buddy", "birthday": "1990-01-01", "location": { "city": "Paris" } })
+        );
+    }
+
+    #[test]
+    fn patched_fields_are_the_touched_ones() {
+        let record = json!({ "name": "Buddy", "notes": "", "birthday": "1990-01-01" });
+        let update = patched_fields(
+            record,
+            &json!({ "birthday": null }),
+            &["name", "birthday"],
+            &["name"],
+        )
+        .unwrap();
+        assert_eq!(Value::Object(update), json!({ "birthday": null }));
+    }
+
+    #[test]
+    fn bad_patches_are_invalid_requests() {
+        let record = json!({ "name": "Buddy", "notes": "" });
+        for patch in [
+            json!(["name"]),
+            json!("name"),
+            json!({ "notes": "Not patchable" }),
+            json!({ "name": null }),
+        ] {
+            let err = patched_fields(record.clone(), &patch, &["name"], &["name"]).unwrap_err();
+            assert!(
+                err.downcast_ref::<InvalidRequestError>().is_some(),
+                "{}",
+                patch
+            );
+        }
+    }
+}
@@ -1,20 +1,73 @@
+use crate::lib::graphql::{self, Context as GraphQLContext, GraphQLBody, Schema};
+use crate::lib::openapi::{build_spec, Endpoint, SWAGGER_UI};
 use crate::lib::service::{AuthHandler, AuthService, BuddiesService, RequestHandler};
 use crate::lib::storage::{AuthStore, BuddiesStore};
 use crate::lib::types::{
-    ArchiveBuddyRequest, ArchiveInteractionRequest, AuthenticationRequest, CreateBuddyRequest,
-    CreateInteractionRequest, GetUserDataRequest, LoginRequest, SignUpRequest, UpdateBuddyRequest,
-    UpdateInteractionRequest,
+    ApplySuggestedCadenceRequest, ApplySuggestedCadenceResponse, ArchiveBuddyRequest,
+    ArchiveBuddyResponse, ArchiveContactMethodRequest, ArchiveContactMethodResponse,
+    ArchiveIdeaRequest, ArchiveIdeaResponse, ArchiveImportantDateRequest,
+    ArchiveImportantDateResponse, ArchiveInteractionRequest, ArchiveInteractionResponse,
+    ArchiveRelationshipRequest, ArchiveRelationshipResponse, ArchiveWebhookRequest,
+    ArchiveWebhookResponse, AuthenticationRequest, AuthenticationResponse, BatchRequest,
+    BatchResponse, BatchStatus, Buddy, CompleteFollowUpRequest, CompleteFollowUpResponse,
+    CreateBuddyRequest, CreateBuddyResponse, CreateCalendarTokenRequest,
+    CreateCalendarTokenResponse, CreateContactMethodRequest, CreateContactMethodResponse,
+    CreateFollowUpRequest, CreateFollowUpResponse, CreateIdeaRequest, CreateIdeaResponse,
+    CreateImportantDateRequest, CreateImportantDateResponse, CreateInteractionRequest,
+    CreateInteractionResponse, CreatePersonalAccessTokenRequest, CreatePersonalAccessTokenResponse,
+    CreateRelationshipRequest, CreateRelationshipResponse, CreateWebhookRequest,
+    CreateWebhookResponse, CurrentRecord, EntityKind, ExportVCardRequest, FindBuddiesNearRequest,
+    FindBuddiesNearResponse, FollowUpFilter, GetBuddyGraphRequest, GetBuddyGraphResponse,
+    GetBuddyRequest, GetCalendarRequest, GetChangesRequest, GetChangesResponse, GetDigestRequest,
+    GetDigestResponse, GetDuplicatesRequest, GetDuplicatesResponse, GetHistoryRequest,
+    GetHistoryResponse, GetInteractionRequest, GetStatsRequest, GetStatsResponse,
+    GetUpcomingDatesRequest, GetUpcomingDatesResponse, GetUserDataRequest, GetUserDataResponse,
+    IdempotencyKeyReusedError, Interaction, InteractionFilter, InvalidRequestError,
+    ListBuddiesRequest, ListBuddiesResponse, ListFollowUpsRequest, ListFollowUpsResponse,
+    ListInteractionsRequest, ListInteractionsResponse, ListPersonalAccessTokensRequest,
+    ListPersonalAccessTokensResponse, ListWebhookDeliveriesRequest, ListWebhookDeliveriesResponse,
+    ListWebhooksRequest, ListWebhooksResponse, LoginRequest, LoginResponse, MergeBuddiesRequest,
+    MergeBuddiesResponse, NearFilter, NotFoundError, PatchBuddyRequest, PatchBuddyResponse,
+    PatchInteractionRequest, PatchInteractionResponse, RevertBuddyRequest, RevertBuddyResponse,
+    RevertInteractionRequest, RevertInteractionResponse, RevokePersonalAccessTokenRequest,
+    RevokePersonalAccessTokenResponse, Scope, SignUpRequest, SignUpResponse, StaleWriteError,
+    StreamEvent, SyncRequest, SyncResponse, UnauthorizedError, UpdateBuddyRequest,
+    UpdateBuddyResponse, UpdateContactMethodRequest, UpdateContactMethodResponse,
+    UpdateIdeaRequest, UpdateIdeaResponse, UpdateImportantDateRequest, UpdateImportantDateResponse,
+    UpdateInteractionRequest, UpdateInteractionResponse, UpdateRelationshipRequest,
+    UpdateRelationshipResponse, UpdateUserRequest, UpdateUserResponse,
 };
 use log::error;
-use serde::Serialize;
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
 
+use std::convert::Infallible;
+use std::sync::Arc;
+use tokio::stream::StreamExt;
 use uuid::Uuid;
 use warp::http::StatusCode;
+use warp::hyper::body::Bytes;
 use warp::{filters::BoxedFilter, Filter, Reply};
 
 #[derive(Debug)]
 enum ErrorType {
     Unknown(String),
+    BadRequest(String),
+    NotFound(String),
+    /// The credential is malformed, expired, revoked or unknown
+    Unauthorized(String),
+    /// The credential is fine but wasn't given a scope the route needs
+    Forbidden(String),
+    /// A request we understood but won't carry out
+    Unprocessable(String),
+    /// A write based on an old version of a record. The record is left out for callers
+    /// who couldn't read it anyway.
+    Conflict {
+        version: i32,
+        current: Option<Box<CurrentRecord>>,
+    },
 }
 
 #[derive(Debug)]
@@ -28,6 +81,61 @@ impl CustomError {
             error: ErrorType::Unknown(message),
         }
     }
+
+    fn bad_request(message: String) -> Self {
+        CustomError {
+            error: ErrorType::BadRequest(message),
+        }
+    }
+
+    fn unauthorized(message: String) -> Self {
+        CustomError {
+            error: ErrorType::Unauthorized(message),
+        }
+    }
+
+    fn forbidden(message: String) -> Self {
+        CustomError {
+            error: ErrorType::Forbidden(message),
+        }
+    }
+
+    /// Stale writes turn into conflicts, missing records into not found, invalid requests
+    /// into bad requests, reused idempotency keys are unprocessable, and anything else is
+    /// unknown
+    fn from_service(e: anyhow::Error) -> Self {
+        if let Some(missing) = e.downcast_ref::<NotFoundError>() {
+            return CustomError {
+                error: ErrorType::NotFound(missing.to_string()),
+            };
+        }
+        if let Some(invalid) = e.downcast_ref::<InvalidRequestError>() {
+            return CustomError::bad_request(invalid.to_string());
+        }
+        if let Some(stale) = e.downcast_ref::<StaleWriteError>() {
+            return CustomError {
+                error: ErrorType::Conflict {
+                    version: stale.current.version(),
+                    current: Some(Box::new(stale.current.clone())),
+                },
+            };
+        }
+        if let Some(reused) = e.downcast_ref::<IdempotencyKeyReusedError>() {
+            return CustomError {
+                error: ErrorType::Unprocessable(reused.to_string()),
+            };
+        }
+        CustomError::unknown(format!("Failure {:?}", e))
+    }
+
+    /// Like `from_service`, for a write by a caller holding `scopes`
+    fn from_write(e: anyhow::Error, scopes: &[Scope]) -> Self {
+        let mut error = CustomError::from_service(e);
+        if let ErrorType::Conflict { current, .. } = &mut error.error {
+            *current = readable(current.take().map(|record| *record), scopes).map(Box::new);
+        }
+        error
+    }
 }
 
 impl warp::reject::Reject for CustomError {}
@@ -39,6 +147,65 @@ struct ErrorMessage {
     message: String,
 }
 
+/// A conflict along with the record as it stands, so clients can merge and retry
+#[derive(Serialize)]
+struct ConflictMessage {
+    code: u16,
+    message: String,
+    version: i32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    current: Option<CurrentRecord>,
+}
+
+/// All a caller who can change a record, but not read it, gets back after writing it
+#[derive(Serialize)]
+struct WrittenRecord {
+    id: Uuid,
+    version: i32,
+}
+
+fn etag(version: i32) -> String {
+    format!("\"{}\"", version)
+}
+
+/// `body` as JSON with the ETag of the record it's about. Callers without the scope to
+/// read records of `kind` only get its id and version.
+fn written<T: Serialize>(
+    body: &T,
+    kind: EntityKind,
+    id: Uuid,
+    version: i32,
+    scopes: &[Scope],
+) -> warp::reply::WithHeader<warp::reply::Json> {
+    let json = if scopes.contains(&Scope::read(kind)) {
+        warp::reply::json(body)
+    } else {
+        warp::reply::json(&WrittenRecord { id, version })
+    };
+    warp::reply::with_header(json, "ETag", etag(version))
+}
+
+/// `current`, as long as the caller holding `scopes` can read it
+fn readable(current: Option<CurrentRecord>, scopes: &[Scope]) -> Option<CurrentRecord> {
+    current.filter(|record| scopes.contains(&Scope::read(record.kind())))
+}
+
+/// Read the version out of an If-Match header. `*` matches any version.
+fn parse_if_match(if_match: Option<String>) -> Result<Option<i32>, warp::Rejection> {
+    let if_match = match if_match {
+        Some(if_match) if if_match.trim() != "*" => if_match,
+        _ => return Ok(None),
+    };
+    let tag = if_match.trim().trim_start_matches("W/").trim_matches('"');
+    match tag.parse() {
+        Ok(version) => Ok(Some(version)),
+        Err(_) => Err(warp::reject::custom(CustomError::bad_request(format!(
+            "Unrecognized If-Match {}",
+            if_match
+        )))),
+    }
+}
+
 async fn login<S: AuthStore>(
     request: LoginRequest,
     handler: AuthHandler<S>,
@@ -66,18 +233,29 @@ async fn sign_up<S: AuthStore>(
 }
 
 async fn create_buddy<S: BuddiesStore>(
-    request: CreateBuddyRequest,
-    auth_result: Result<Uuid, warp::Rejection>,
+    mut request: CreateBuddyRequest,
+    idempotency_key: Option<String>,
+    credentials: Result<AuthenticationResponse, warp::Rejection>,
     mut handler: RequestHandler<S>,
 ) -> Result<impl warp::Reply, warp::Rejection> {
-    let _user_uuid = auth_result?;
+    let credentials = credentials?;
+    request.user_id = credentials.user_uuid;
 
+    if idempotency_key.is_some() {
+        request.idempotency_key = idempotency_key;
+    }
     match handler.create_buddy(request) {
-        Ok(resp) => Ok(warp::reply::json(&resp)),
-        Err(e) => Err(warp::reject::custom(CustomError::unknown(format!(
-            "Failure {:?}",
-            e
-        )))),
+        Ok(resp) => Ok(written(
+            &resp,
+            EntityKind::Buddy,
+            resp.buddy.id,
+            resp.buddy.version,
+            &credentials.scopes,
+        )),
+        Err(e) => Err(warp::reject::custom(CustomError::from_write(
+            e,
+            &credentials.scopes,
+        ))),
     }
 }
 
@@ -86,7 +264,11 @@ async fn get_user_data<S: BuddiesStore>(
     auth_result: Result<Uuid, warp::Rejection>,
     handler: RequestHandler<S>,
 ) -> Result<impl warp::Reply, warp::Rejection> {
-    let _user_uuid = auth_result?;
+    if user_id != auth_result? {
+        return Err(warp::reject::custom(CustomError::forbidden(
+            "Can't read another user's data".to_string(),
+        )));
+    }
     match handler.get_user_data(GetUserDataRequest { user_id }) {
         Ok(resp) => Ok(warp::reply::json(&resp)),
         Err(e) => Err(warp::reject::custom(CustomError::unknown(format!(
@@ -97,72 +279,259 @@ async fn get_user_data<S: BuddiesStore>(
 }
 
 async fn archive_buddy<S: BuddiesStore>(
-    request: ArchiveBuddyRequest,
-    auth_result: Result<Uuid, warp::Rejection>,
+    mut request: ArchiveBuddyRequest,
+    credentials: Result<AuthenticationResponse, warp::Rejection>,
     mut handler: RequestHandler<S>,
 ) -> Result<impl warp::Reply, warp::Rejection> {
-    let _user_uuid = auth_result?;
+    let credentials = credentials?;
+    request.user_id = credentials.user_uuid;
     match handler.archive_buddy(request) {
         Ok(resp) => Ok(warp::reply::json(&resp)),
-        Err(e) => Err(warp::reject::custom(CustomError::unknown(format!(
-            "Failure {:?}",
-            e
-        )))),
+        Err(e) => Err(warp::reject::custom(CustomError::from_write(
+            e,
+            &credentials.scopes,
+        ))),
     }
 }
 
 async fn update_buddy<S: BuddiesStore>(
-    request: UpdateBuddyRequest,
-    auth_result: Result<Uuid, warp::Rejection>,
+    mut request: UpdateBuddyRequest,
+    if_match: Option<String>,
+    credentials: Result<AuthenticationResponse, warp::Rejection>,
     mut handler: RequestHandler<S>,
 ) -> Result<impl warp::Reply, warp::Rejection> {
-    let _user_uuid = auth_result?;
+    let credentials = credentials?;
+    request.user_id = credentials.user_uuid;
+    if let Some(version) = parse_if_match(if_match)? {
+        request.version = Some(version);
+    }
     match handler.update_buddy(request) {
+        Ok(resp) => Ok(warp::reply::with_header(
+            warp::reply::json(&resp),
+            "ETag",
+            etag(resp.version),
+        )),
+        Err(e) => Err(warp::reject::custom(CustomError::from_write(
+            e,
+            &credentials.scopes,
+        ))),
+    }
+}
+
+async fn apply_suggested_cadence<S: BuddiesStore>(
+    mut request: ApplySuggestedCadenceRequest,
+    auth_result: Result<Uuid, warp::Rejection>,
+    mut handler: RequestHandler<S>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    request.user_id = auth_result?;
+    match handler.apply_suggested_cadence(request) {
         Ok(resp) => Ok(warp::reply::json(&resp)),
-        Err(e) => Err(warp::reject::custom(CustomError::unknown(format!(
-            "Failure {:?}",
-            e
-        )))),
+        Err(e) => Err(warp::reject::custom(CustomError::from_service(e))),
     }
 }
 
 async fn create_interaction<S: BuddiesStore>(
-    request: CreateInteractionRequest,
-    auth_result: Result<Uuid, warp::Rejection>,
+    mut request: CreateInteractionRequest,
+    idempotency_key: Option<String>,
+    credentials: Result<AuthenticationResponse, warp::Rejection>,
     mut handler: RequestHandler<S>,
 ) -> Result<impl warp::Reply, warp::Rejection> {
-    let _user_uuid = auth_result?;
+    let credentials = credentials?;
+    request.user_id = credentials.user_uuid;
+    if idempotency_key.is_some() {
+        request.idempotency_key = idempotency_key;
+    }
     match handler.create_interaction(request) {
-        Ok(resp) => Ok(warp::reply::json(&resp)),
-        Err(e) => Err(warp::reject::custom(CustomError::unknown(format!(
-            "Failure {:?}",
-            e
-        )))),
+        Ok(resp) => Ok(written(
+            &resp,
+            EntityKind::Interaction,
+            resp.interaction.id,
+            resp.interaction.version,
+            &credentials.scopes,
+        )),
+        Err(e) => Err(warp::reject::custom(CustomError::from_write(
+            e,
+            &credentials.scopes,
+        ))),
     }
 }
 
 async fn archive_interaction<S: BuddiesStore>(
-    request: ArchiveInteractionRequest,
-    auth_result: Result<Uuid, warp::Rejection>,
+    mut request: ArchiveInteractionRequest,
+    credentials: Result<AuthenticationResponse, warp::Rejection>,
     mut handler: RequestHandler<S>,
 ) -> Result<impl warp::Reply, warp::Rejection> {
-    let _user_uuid = auth_result?;
+    let credentials = credentials?;
+    request.user_id = credentials.user_uuid;
     match handler.archive_interaction(request) {
         Ok(resp) => Ok(warp::reply::json(&resp)),
-        Err(e) => Err(warp::reject::custom(CustomError::unknown(format!(
-            "Failure {:?}",
-            e
-        )))),
+        Err(e) => Err(warp::reject::custom(CustomError::from_write(
+            e,
+            &credentials.scopes,
+        ))),
     }
 }
 
 async fn update_interaction<S: BuddiesStore>(
-    request: UpdateInteractionRequest,
-    auth_result: Result<Uuid, warp::Rejection>,
+    mut request: UpdateInteractionRequest,
+    if_match: Option<String>,
+    credentials: Result<AuthenticationResponse, warp::Rejection>,
     mut handler: RequestHandler<S>,
 ) -> Result<impl warp::Reply, warp::Rejection> {
-    let _user_uuid = auth_result?;
+    let credentials = credentials?;
+    request.user_id = credentials.user_uuid;
+    if let Some(version) = parse_if_match(if_match)? {
+        request.version = Some(version);
+    }
     match handler.update_interaction(request) {
+        Ok(resp) => Ok(warp::reply::with_header(
+            warp::reply::json(&resp),
+            "ETag",
+            etag(resp.version),
+        )),
+        Err(e) => Err(warp::reject::custom(CustomError::from_write(
+            e,
+            &credentials.scopes,
+        ))),
+    }
+}
+
+async fn create_relationship<S: BuddiesStore>(
+    mut request: CreateRelationshipRequest,
+    auth_result: Result<Uuid, warp::Rejection>,
+    mut handler: RequestHandler<S>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    request.user_id = auth_result?;
+    match handler.create_relationship(request) {
+        Ok(resp) => Ok(warp::reply::json(&resp)),
+        Err(e) => Err(warp::reject::custom(CustomError::from_service(e))),
+    }
+}
+
+async fn update_relationship<S: BuddiesStore>(
+    mut request: UpdateRelationshipRequest,
+    auth_result: Result<Uuid, warp::Rejection>,
+    mut handler: RequestHandler<S>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    request.user_id = auth_result?;
+    match handler.update_relationship(request) {
+        Ok(resp) => Ok(warp::reply::json(&resp)),
+        Err(e) => Err(warp::reject::custom(CustomError::from_service(e))),
+    }
+}
+
+async fn archive_relationship<S: BuddiesStore>(
+    mut request: ArchiveRelationshipRequest,
+    auth_result: Result<Uuid, warp::Rejection>,
+    mut handler: RequestHandler<S>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    request.user_id = auth_result?;
+    match handler.archive_relationship(request) {
+        Ok(resp) => Ok(warp::reply::json(&resp)),
+        Err(e) => Err(warp::reject::custom(CustomError::from_service(e))),
+    }
+}
+
+async fn get_buddy_graph<S: BuddiesStore>(
+    buddy_id: Uuid,
+    auth_result: Result<Uuid, warp::Rejection>,
+    handler: RequestHandler<S>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let user_id = auth_result?;
+    match handler.get_buddy_graph(GetBuddyGraphRequest { user_id, buddy_id }) {
+        Ok(resp) => Ok(warp::reply::json(&resp)),
+        Err(e) => Err(warp::reject::custom(CustomError::from_service(e))),
+    }
+}
+
+async fn create_contact_method<S: BuddiesStore>(
+    mut request: CreateContactMethodRequest,
+    auth_result: Result<Uuid, warp::Rejection>,
+    mut handler: RequestHandler<S>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    request.user_id = auth_result?;
+    match handler.create_contact_method(request) {
+        Ok(resp) => Ok(warp::reply::json(&resp)),
+        Err(e) => Err(warp::reject::custom(CustomError::from_service(e))),
+    }
+}
+
+async fn update_contact_method<S: BuddiesStore>(
+    mut request: UpdateContactMethodRequest,
+    auth_result: Result<Uuid, warp::Rejection>,
+    mut handler: RequestHandler<S>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    request.user_id = auth_result?;
+    match handler.update_contact_method(request) {
+        Ok(resp) => Ok(warp::reply::json(&resp)),
+        Err(e) => Err(warp::reject::custom(CustomError::from_service(e))),
+    }
+}
+
+async fn archive_contact_method<S: BuddiesStore>(
+    mut request: ArchiveContactMethodRequest,
+    auth_result: Result<Uuid, warp::Rejection>,
+    mut handler: RequestHandler<S>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    request.user_id = auth_result?;
+    match handler.archive_contact_method(request) {
+        Ok(resp) => Ok(warp::reply::json(&resp)),
+        Err(e) => Err(warp::reject::custom(CustomError::from_service(e))),
+    }
+}
+
+async fn create_important_date<S: BuddiesStore>(
+    mut request: CreateImportantDateRequest,
+    auth_result: Result<Uuid, warp::Rejection>,
+    mut handler: RequestHandler<S>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    request.user_id = auth_result?;
+    match handler.create_important_date(request) {
+        Ok(resp) => Ok(warp::reply::json(&resp)),
+        Err(e) => Err(warp::reject::custom(CustomError::from_service(e))),
+    }
+}
+
+async fn update_important_date<S: BuddiesStore>(
+    mut request: UpdateImportantDateRequest,
+    auth_result: Result<Uuid, warp::Rejection>,
+    mut handler: RequestHandler<S>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    request.user_id = auth_result?;
+    match handler.update_important_date(request) {
+        Ok(resp) => Ok(warp::reply::json(&resp)),
+        Err(e) => Err(warp::reject::custom(CustomError::from_service(e))),
+    }
+}
+
+async fn archive_important_date<S: BuddiesStore>(
+    mut request: ArchiveImportantDateRequest,
+    auth_result: Result<Uuid, warp::Rejection>,
+    mut handler: RequestHandler<S>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    request.user_id = auth_result?;
+    match handler.archive_important_date(request) {
+        Ok(resp) => Ok(warp::reply::json(&resp)),
+        Err(e) => Err(warp::reject::custom(CustomError::from_service(e))),
+    }
+}
+
+/// Query parameters accepted by the upcoming dates endpoint
+#[derive(Deserialize, JsonSchema)]
+struct UpcomingDatesQuery {
+    days: Option<i64>,
+}
+
+async fn get_upcoming_dates<S: BuddiesStore>(
+    query: UpcomingDatesQuery,
+    auth_result: Result<Uuid, warp::Rejection>,
+    handler: RequestHandler<S>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let user_id = auth_result?;
+    match handler.get_upcoming_dates(GetUpcomingDatesRequest {
+        user_id,
+        days: query.days,
+    }) {
         Ok(resp) => Ok(warp::reply::json(&resp)),
         Err(e) => Err(warp::reject::custom(CustomError::unknown(format!(
             "Failure {:?}",
@@ -171,25 +540,38 @@ async fn update_interaction<S: BuddiesStore>(
     }
 }
 
-fn authenticate<T: AuthStore>(
-    handler: AuthHandler<T>,
-    authorization_header: String,
-) -> Result<Uuid, warp::Rejection> {
-    let auth_header_components: Vec<&str> = authorization_header.split(' ').collect();
+async fn create_follow_up<S: BuddiesStore>(
+    mut request: CreateFollowUpRequest,
+    auth_result: Result<Uuid, warp::Rejection>,
+    mut handler: RequestHandler<S>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    request.user_id = auth_result?;
+    match handler.create_follow_up(request) {
+        Ok(resp) => Ok(warp::reply::json(&resp)),
+        Err(e) => Err(warp::reject::custom(CustomError::from_service(e))),
+    }
+}
 
-    let jwt = match &auth_header_components[..] {
-        ["Bearer", jwt] => jwt.to_string(),
-        bad_form => {
-            return Err(warp::reject::custom(CustomError::unknown(format!(
-                "Malformed Authorization Header {:?}",
-                bad_form,
-            ))));
-        }
-    };
-    match handler.authenticate(AuthenticationRequest {
-        json_web_token: jwt,
-    }) {
-        Ok(response) => Ok(response.user_uuid),
+async fn complete_follow_up<S: BuddiesStore>(
+    mut request: CompleteFollowUpRequest,
+    auth_result: Result<Uuid, warp::Rejection>,
+    mut handler: RequestHandler<S>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    request.user_id = auth_result?;
+    match handler.complete_follow_up(request) {
+        Ok(resp) => Ok(warp::reply::json(&resp)),
+        Err(e) => Err(warp::reject::custom(CustomError::from_service(e))),
+    }
+}
+
+async fn list_follow_ups<S: BuddiesStore>(
+    filter: FollowUpFilter,
+    auth_result: Result<Uuid, warp::Rejection>,
+    handler: RequestHandler<S>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let user_id = auth_result?;
+    match handler.list_follow_ups(ListFollowUpsRequest { user_id, filter }) {
+        Ok(resp) => Ok(warp::reply::json(&resp)),
         Err(e) => Err(warp::reject::custom(CustomError::unknown(format!(
             "Failure {:?}",
             e
@@ -197,147 +579,3526 @@ fn authenticate<T: AuthStore>(
     }
 }
 
-// This function receives a `Rejection` and tries to return a custom
-// value, otherwise simply passes the rejection along.
-async fn handle_custom_rejection(err: warp::Rejection) -> Result<impl Reply, warp::Rejection> {
-    if let Some(CustomError { error }) = err.find() {
-        error!("Handling Error - {:?}", error);
-        match error {
-            ErrorType::Unknown(message) => {
-                let code = StatusCode::INTERNAL_SERVER_ERROR;
-                let json_reply = warp::reply::json(&ErrorMessage {
-                    code: code.as_u16(),
-                    message: message.into(),
-                });
-                Ok(warp::reply::with_status(json_reply, code))
-            }
-        }
-    } else {
-        error!("Passing along unknown Warp Rejection - {:?}", err);
-        Err(err)
+async fn create_idea<S: BuddiesStore>(
+    mut request: CreateIdeaRequest,
+    auth_result: Result<Uuid, warp::Rejection>,
+    mut handler: RequestHandler<S>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    request.user_id = auth_result?;
+    match handler.create_idea(request) {
+        Ok(resp) => Ok(warp::reply::json(&resp)),
+        Err(e) => Err(warp::reject::custom(CustomError::from_service(e))),
     }
 }
 
-/// This function links the service to warp's route handling
-pub fn build_warp_routes<S: BuddiesStore, T: AuthStore>(
-    auth_handler: AuthHandler<T>,
-    handler: RequestHandler<S>,
-) -> BoxedFilter<(impl Reply,)> {
-    let cors = warp::cors()
-        .allow_any_origin()
-        .allow_headers(vec![
-            "Accept",
-            "Accept-Encoding",
-            "Access-Control-Request-Headers",
-            "Access-Contorl-Request-Method",
-            "Authorization",
-            "Connection",
-            "Content-Type",
-            "Host",
-            "Origin",
-            "Referer",
-            "Sec-Fetch-Dest",
-            "Sec-Fetch-Mode",
-            "User-Agent",
-        ])
-        .allow_methods(vec!["GET", "PUT", "POST"]);
+async fn update_idea<S: BuddiesStore>(
+    mut request: UpdateIdeaRequest,
+    auth_result: Result<Uuid, warp::Rejection>,
+    mut handler: RequestHandler<S>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    request.user_id = auth_result?;
+    match handler.update_idea(request) {
+        Ok(resp) => Ok(warp::reply::json(&resp)),
+        Err(e) => Err(warp::reject::custom(CustomError::from_service(e))),
+    }
+}
 
-    let auth_handler_filter = warp::any().map(move || auth_handler.clone());
-    let auth_filter = auth_handler_filter
-        .clone()
-        .and(warp::header("Authorization"))
-        .map(authenticate);
+async fn archive_idea<S: BuddiesStore>(
+    mut request: ArchiveIdeaRequest,
+    auth_result: Result<Uuid, warp::Rejection>,
+    mut handler: RequestHandler<S>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    request.user_id = auth_result?;
+    match handler.archive_idea(request) {
+        Ok(resp) => Ok(warp::reply::json(&resp)),
+        Err(e) => Err(warp::reject::custom(CustomError::from_service(e))),
+    }
+}
 
-    let handler_filter = warp::any().map(move || handler.clone());
+async fn create_webhook<S: BuddiesStore>(
+    mut request: CreateWebhookRequest,
+    auth_result: Result<Uuid, warp::Rejection>,
+    mut handler: RequestHandler<S>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    request.user_id = auth_result?;
+    match handler.create_webhook(request) {
+        Ok(resp) => Ok(warp::reply::json(&resp)),
+        Err(e) => Err(warp::reject::custom(CustomError::from_service(e))),
+    }
+}
 
-    let login = warp::post()
-        .and(warp::path("login"))
-        .and(warp::body::content_length_limit(1024 * 16))
-        .and(warp::body::json())
+async fn list_webhooks<S: BuddiesStore>(
+    auth_result: Result<Uuid, warp::Rejection>,
+    handler: RequestHandler<S>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let user_id = auth_result?;
+    match handler.list_webhooks(ListWebhooksRequest { user_id }) {
+        Ok(resp) => Ok(warp::reply::json(&resp)),
+        Err(e) => Err(warp::reject::custom(CustomError::from_service(e))),
+    }
+}
+
+async fn archive_webhook<S: BuddiesStore>(
+    mut request: ArchiveWebhookRequest,
+    auth_result: Result<Uuid, warp::Rejection>,
+    mut handler: RequestHandler<S>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    request.user_id = auth_result?;
+    match handler.archive_webhook(request) {
+        Ok(resp) => Ok(warp::reply::json(&resp)),
+        Err(e) => Err(warp::reject::custom(CustomError::from_service(e))),
+    }
+}
+
+async fn list_webhook_deliveries<S: BuddiesStore>(
+    webhook_id: Uuid,
+    auth_result: Result<Uuid, warp::Rejection>,
+    handler: RequestHandler<S>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let user_id = auth_result?;
+    match handler.list_webhook_deliveries(ListWebhookDeliveriesRequest {
+        user_id,
+        webhook_id,
+    }) {
+        Ok(resp) => Ok(warp::reply::json(&resp)),
+        Err(e) => Err(warp::reject::custom(CustomError::from_service(e))),
+    }
+}
+
+async fn create_personal_access_token<S: AuthStore>(
+    mut request: CreatePersonalAccessTokenRequest,
+    credentials: Result<AuthenticationResponse, warp::Rejection>,
+    mut handler: AuthHandler<S>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let credentials = credentials?;
+    // Otherwise a token could be traded for one that can do more
+    if let Some(scope) = request
+        .scopes
+        .iter()
+        .find(|scope| !credentials.scopes.contains(scope))
+    {
+        return Err(warp::reject::custom(CustomError::forbidden(format!(
+            "Can't give a token the {} scope without having it",
+            scope
+        ))));
+    }
+    request.user_id = credentials.user_uuid;
+    match handler.create_personal_access_token(request) {
+        Ok(resp) => Ok(warp::reply::json(&resp)),
+        Err(e) => Err(warp::reject::custom(CustomError::from_service(e))),
+    }
+}
+
+async fn list_personal_access_tokens<S: AuthStore>(
+    auth_result: Result<Uuid, warp::Rejection>,
+    handler: AuthHandler<S>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let user_id = auth_result?;
+    match handler.list_personal_access_tokens(ListPersonalAccessTokensRequest { user_id }) {
+        Ok(resp) => Ok(warp::reply::json(&resp)),
+        Err(e) => Err(warp::reject::custom(CustomError::from_service(e))),
+    }
+}
+
+async fn revoke_personal_access_token<S: AuthStore>(
+    mut request: RevokePersonalAccessTokenRequest,
+    auth_result: Result<Uuid, warp::Rejection>,
+    mut handler: AuthHandler<S>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    request.user_id = auth_result?;
+    match handler.revoke_personal_access_token(request) {
+        Ok(resp) => Ok(warp::reply::json(&resp)),
+        Err(e) => Err(warp::reject::custom(CustomError::from_service(e))),
+    }
+}
+
+/// Merge patches come in as `application/merge-patch+json`, so read the body ourselves
+fn parse_merge_patch(body: &[u8]) -> Result<serde_json::Value, warp::Rejection> {
+    serde_json::from_slice(body).map_err(|e| {
+        warp::reject::custom(CustomError::bad_request(format!(
+            "Unreadable merge patch {}",
+            e
+        )))
+    })
+}
+
+async fn patch_buddy<S: BuddiesStore>(
+    buddy_id: Uuid,
+    body: Bytes,
+    if_match: Option<String>,
+    credentials: Result<AuthenticationResponse, warp::Rejection>,
+    mut handler: RequestHandler<S>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let credentials = credentials?;
+    let user_id = credentials.user_uuid;
+    let request = PatchBuddyRequest {
+        user_id,
+        buddy_id,
+        patch: parse_merge_patch(&body)?,
+        version: parse_if_match(if_match)?,
+    };
+    match handler.patch_buddy(request) {
+        Ok(resp) => Ok(written(
+            &resp,
+            EntityKind::Buddy,
+            buddy_id,
+            resp.buddy.version,
+            &credentials.scopes,
+        )),
+        Err(e) => Err(warp::reject::custom(CustomError::from_write(
+            e,
+            &credentials.scopes,
+        ))),
+    }
+}
+
+async fn patch_interaction<S: BuddiesStore>(
+    interaction_id: Uuid,
+    body: Bytes,
+    if_match: Option<String>,
+    credentials: Result<AuthenticationResponse, warp::Rejection>,
+    mut handler: RequestHandler<S>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let credentials = credentials?;
+    let user_id = credentials.user_uuid;
+    let request = PatchInteractionRequest {
+        user_id,
+        interaction_id,
+        patch: parse_merge_patch(&body)?,
+        version: parse_if_match(if_match)?,
+    };
+    match handler.patch_interaction(request) {
+        Ok(resp) => Ok(written(
+            &resp,
+            EntityKind::Interaction,
+            interaction_id,
+            resp.interaction.version,
+            &credentials.scopes,
+        )),
+        Err(e) => Err(warp::reject::custom(CustomError::from_write(
+            e,
+            &credentials.scopes,
+        ))),
+    }
+}
+
+async fn get_buddy_history<S: BuddiesStore>(
+    buddy_id: Uuid,
+    auth_result: Result<Uuid, warp::Rejection>,
+    handler: RequestHandler<S>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let user_id = auth_result?;
+    match handler.get_buddy_history(GetHistoryRequest {
+        user_id,
+        entity_id: buddy_id,
+    }) {
+        Ok(resp) => Ok(warp::reply::json(&resp)),
+        Err(e) => Err(warp::reject::custom(CustomError::from_service(e))),
+    }
+}
+
+async fn revert_buddy<S: BuddiesStore>(
+    mut request: RevertBuddyRequest,
+    if_match: Option<String>,
+    credentials: Result<AuthenticationResponse, warp::Rejection>,
+    mut handler: RequestHandler<S>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let credentials = credentials?;
+    request.user_id = credentials.user_uuid;
+    if let Some(version) = parse_if_match(if_match)? {
+        request.version = Some(version);
+    }
+    match handler.revert_buddy(request) {
+        Ok(resp) => Ok(written(
+            &resp,
+            EntityKind::Buddy,
+            resp.buddy.id,
+            resp.buddy.version,
+            &credentials.scopes,
+        )),
+        Err(e) => Err(warp::reject::custom(CustomError::from_write(
+            e,
+            &credentials.scopes,
+        ))),
+    }
+}
+
+async fn get_interaction_history<S: BuddiesStore>(
+    interaction_id: Uuid,
+    auth_result: Result<Uuid, warp::Rejection>,
+    handler: RequestHandler<S>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let user_id = auth_result?;
+    match handler.get_interaction_history(GetHistoryRequest {
+        user_id,
+        entity_id: interaction_id,
+    }) {
+        Ok(resp) => Ok(warp::reply::json(&resp)),
+        Err(e) => Err(warp::reject::custom(CustomError::from_service(e))),
+    }
+}
+
+async fn revert_interaction<S: BuddiesStore>(
+    mut request: RevertInteractionRequest,
+    if_match: Option<String>,
+    credentials: Result<AuthenticationResponse, warp::Rejection>,
+    mut handler: RequestHandler<S>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let credentials = credentials?;
+    request.user_id = credentials.user_uuid;
+    if let Some(version) = parse_if_match(if_match)? {
+        request.version = Some(version);
+    }
+    match handler.revert_interaction(request) {
+        Ok(resp) => Ok(written(
+            &resp,
+            EntityKind::Interaction,
+            resp.interaction.id,
+            resp.interaction.version,
+            &credentials.scopes,
+        )),
+        Err(e) => Err(warp::reject::custom(CustomError::from_write(
+            e,
+            &credentials.scopes,
+        ))),
+    }
+}
+
+async fn find_buddies_near<S: BuddiesStore>(
+    near: NearFilter,
+    auth_result: Result<Uuid, warp::Rejection>,
+    handler: RequestHandler<S>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let user_id = auth_result?;
+    match handler.find_buddies_near(FindBuddiesNearRequest { user_id, near }) {
+        Ok(resp) => Ok(warp::reply::json(&resp)),
+        Err(e) => Err(warp::reject::custom(CustomError::from_service(e))),
+    }
+}
+
+async fn get_duplicates<S: BuddiesStore>(
+    auth_result: Result<Uuid, warp::Rejection>,
+    handler: RequestHandler<S>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let user_id = auth_result?;
+    match handler.get_duplicates(GetDuplicatesRequest { user_id }) {
+        Ok(resp) => Ok(warp::reply::json(&resp)),
+        Err(e) => Err(warp::reject::custom(CustomError::unknown(format!(
+            "Failure {:?}",
+            e
+        )))),
+    }
+}
+
+async fn merge_buddies<S: BuddiesStore>(
+    mut request: MergeBuddiesRequest,
+    credentials: Result<AuthenticationResponse, warp::Rejection>,
+    mut handler: RequestHandler<S>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let credentials = credentials?;
+    request.user_id = credentials.user_uuid;
+    match handler.merge_buddies(request) {
+        Ok(resp) => Ok(written(
+            &resp,
+            EntityKind::Buddy,
+            resp.buddy.id,
+            resp.buddy.version,
+            &credentials.scopes,
+        )),
+        Err(e) => Err(warp::reject::custom(CustomError::from_write(
+            e,
+            &credentials.scopes,
+        ))),
+    }
+}
+
+async fn export_vcard<S: BuddiesStore>(
+    buddy_id: Option<Uuid>,
+    auth_result: Result<Uuid, warp::Rejection>,
+    handler: RequestHandler<S>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let user_id = auth_result?;
+    match handler.export_vcard(ExportVCardRequest { user_id, buddy_id }) {
+        Ok(resp) => Ok(warp::reply::with_header(
+            resp.vcard,
+            "Content-Type",
+            "text/vcard; charset=utf-8",
+        )),
+        Err(e) => Err(warp::reject::custom(CustomError::from_service(e))),
+    }
+}
+
+async fn update_user<S: BuddiesStore>(
+    mut request: UpdateUserRequest,
+    auth_result: Result<Uuid, warp::Rejection>,
+    mut handler: RequestHandler<S>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    request.user_id = auth_result?;
+    match handler.update_user(request) {
+        Ok(resp) => Ok(warp::reply::json(&resp)),
+        Err(e) => Err(warp::reject::custom(CustomError::from_service(e))),
+    }
+}
+
+async fn get_digest<S: BuddiesStore>(
+    auth_result: Result<Uuid, warp::Rejection>,
+    handler: RequestHandler<S>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let user_id = auth_result?;
+    match handler.get_digest(GetDigestRequest { user_id }) {
+        Ok(resp) => Ok(warp::reply::json(&resp)),
+        Err(e) => Err(warp::reject::custom(CustomError::from_service(e))),
+    }
+}
+
+async fn create_calendar_token<S: BuddiesStore>(
+    auth_result: Result<Uuid, warp::Rejection>,
+    mut handler: RequestHandler<S>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let user_id = auth_result?;
+    match handler.create_calendar_token(CreateCalendarTokenRequest { user_id }) {
+        Ok(resp) => Ok(warp::reply::json(&resp)),
+        Err(e) => Err(warp::reject::custom(CustomError::unknown(format!(
+            "Failure {:?}",
+            e
+        )))),
+    }
+}
+
+async fn get_calendar<S: BuddiesStore>(
+    file_name: String,
+    handler: RequestHandler<S>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    // Calendar apps want a url that looks like a file
+    let token = match file_name.strip_suffix(".ics") {
+        Some(token) => token.to_string(),
+        None => return Err(warp::reject::not_found()),
+    };
+    match handler.get_calendar(GetCalendarRequest { token }) {
+        Ok(resp) => Ok(warp::reply::with_header(
+            resp.ics,
+            "Content-Type",
+            "text/calendar; charset=utf-8",
+        )),
+        Err(e) => Err(warp::reject::custom(CustomError::from_service(e))),
+    }
+}
+
+async fn list_interactions<S: BuddiesStore>(
+    filter: InteractionFilter,
+    auth_result: Result<Uuid, warp::Rejection>,
+    handler: RequestHandler<S>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let user_id = auth_result?;
+    match handler.list_interactions(ListInteractionsRequest { user_id, filter }) {
+        Ok(resp) => Ok(warp::reply::json(&resp)),
+        Err(e) => Err(warp::reject::custom(CustomError::unknown(format!(
+            "Failure {:?}",
+            e
+        )))),
+    }
+}
+
+/// Query parameters accepted by the sync endpoint
+#[derive(Deserialize, JsonSchema)]
+struct SyncQuery {
+    since: Option<String>,
+}
+
+async fn get_changes<S: BuddiesStore>(
+    query: SyncQuery,
+    auth_result: Result<Uuid, warp::Rejection>,
+    handler: RequestHandler<S>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let user_id = auth_result?;
+    match handler.get_changes(GetChangesRequest {
+        user_id,
+        since: query.since,
+    }) {
+        Ok(resp) => Ok(warp::reply::json(&resp)),
+        Err(e) => Err(warp::reject::custom(CustomError::from_service(e))),
+    }
+}
+
+async fn sync<S: BuddiesStore>(
+    mut request: SyncRequest,
+    credentials: Result<AuthenticationResponse, warp::Rejection>,
+    mut handler: RequestHandler<S>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    // Every mutation is applied as whoever is signed in
+    let credentials = credentials?;
+    request.user_id = credentials.user_uuid;
+    match handler.sync(request) {
+        Ok(mut resp) => {
+            for result in &mut resp.results {
+                result.current = readable(result.current.take(), &credentials.scopes);
+            }
+            Ok(warp::reply::json(&resp))
+        }
+        Err(e) => Err(warp::reject::custom(CustomError::from_service(e))),
+    }
+}
+
+async fn batch<S: BuddiesStore>(
+    mut request: BatchRequest,
+    credentials: Result<AuthenticationResponse, warp::Rejection>,
+    mut handler: RequestHandler<S>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    // Every operation is applied as whoever is signed in
+    let credentials = credentials?;
+    request.user_id = credentials.user_uuid;
+    match handler.batch(request) {
+        Ok(mut resp) => {
+            for result in &mut resp.results {
+                result.current = readable(result.current.take(), &credentials.scopes);
+            }
+            let failed = resp.failed_index.map(|index| resp.results[index].status);
+            let code = match failed {
+                None => StatusCode::OK,
+                Some(BatchStatus::Conflict) => StatusCode::CONFLICT,
+                Some(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            };
+            Ok(warp::reply::with_status(warp::reply::json(&resp), code))
+        }
+        Err(e) => Err(warp::reject::custom(CustomError::from_write(
+            e,
+            &credentials.scopes,
+        ))),
+    }
+}
+
+/// Read a v2 create body, which leaves the user out since it comes from the token
+fn parse_create_body<T: DeserializeOwned>(
+    mut body: serde_json::Value,
+    user_id: Uuid,
+) -> Result<T, warp::Rejection> {
+    match body.as_object_mut() {
+        Some(object) => {
+            object.insert("user_id".to_string(), json!(user_id));
+        }
+        None => {
+            return Err(warp::reject::custom(CustomError::bad_request(
+                "Expected a JSON object".to_string(),
+            )))
+        }
+    }
+    serde_json::from_value(body).map_err(|e| {
+        warp::reject::custom(CustomError::bad_request(format!(
+            "Unreadable request {}",
+            e
+        )))
+    })
+}
+
+async fn list_buddies_v2<S: BuddiesStore>(
+    auth_result: Result<Uuid, warp::Rejection>,
+    handler: RequestHandler<S>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let user_id = auth_result?;
+    match handler.list_buddies(ListBuddiesRequest { user_id }) {
+        Ok(resp) => Ok(warp::reply::json(&resp)),
+        Err(e) => Err(warp::reject::custom(CustomError::from_service(e))),
+    }
+}
+
+async fn create_buddy_v2<S: BuddiesStore>(
+    body: serde_json::Value,
+    idempotency_key: Option<String>,
+    credentials: Result<AuthenticationResponse, warp::Rejection>,
+    mut handler: RequestHandler<S>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let credentials = credentials?;
+    let user_id = credentials.user_uuid;
+    let mut request: CreateBuddyRequest = parse_create_body(body, user_id)?;
+    request.idempotency_key = idempotency_key;
+    match handler.create_buddy(request) {
+        Ok(resp) => {
+            let location = format!("/v2/buddies/{}", resp.buddy.id);
+            let version = resp.buddy.version;
+            let reply = written(
+                &resp.buddy,
+                EntityKind::Buddy,
+                resp.buddy.id,
+                version,
+                &credentials.scopes,
+            );
+            let reply = warp::reply::with_header(reply, "Location", location);
+            Ok(warp::reply::with_status(reply, StatusCode::CREATED))
+        }
+        Err(e) => Err(warp::reject::custom(CustomError::from_write(
+            e,
+            &credentials.scopes,
+        ))),
+    }
+}
+
+async fn get_buddy_v2<S: BuddiesStore>(
+    buddy_id: Uuid,
+    auth_result: Result<Uuid, warp::Rejection>,
+    handler: RequestHandler<S>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let user_id = auth_result?;
+    match handler.get_buddy(GetBuddyRequest { user_id, buddy_id }) {
+        Ok(buddy) => Ok(warp::reply::with_header(
+            warp::reply::json(&buddy),
+            "ETag",
+            etag(buddy.version),
+        )),
+        Err(e) => Err(warp::reject::custom(CustomError::from_service(e))),
+    }
+}
+
+async fn patch_buddy_v2<S: BuddiesStore>(
+    buddy_id: Uuid,
+    body: Bytes,
+    if_match: Option<String>,
+    credentials: Result<AuthenticationResponse, warp::Rejection>,
+    mut handler: RequestHandler<S>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let credentials = credentials?;
+    let user_id = credentials.user_uuid;
+    let request = PatchBuddyRequest {
+        user_id,
+        buddy_id,
+        patch: parse_merge_patch(&body)?,
+        version: parse_if_match(if_match)?,
+    };
+    match handler.patch_buddy(request) {
+        Ok(resp) => Ok(written(
+            &resp.buddy,
+            EntityKind::Buddy,
+            buddy_id,
+            resp.buddy.version,
+            &credentials.scopes,
+        )),
+        Err(e) => Err(warp::reject::custom(CustomError::from_write(
+            e,
+            &credentials.scopes,
+        ))),
+    }
+}
+
+async fn delete_buddy_v2<S: BuddiesStore>(
+    buddy_id: Uuid,
+    if_match: Option<String>,
+    credentials: Result<AuthenticationResponse, warp::Rejection>,
+    mut handler: RequestHandler<S>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let credentials = credentials?;
+    let user_id = credentials.user_uuid;
+    // Archiving something already archived is a 404, like any other request for it
+    handler
+        .get_buddy(GetBuddyRequest { user_id, buddy_id })
+        .map_err(|e| warp::reject::custom(CustomError::from_service(e)))?;
+    let request = ArchiveBuddyRequest {
+        id: buddy_id,
+        user_id,
+        version: parse_if_match(if_match)?,
+    };
+    match handler.archive_buddy(request) {
+        Ok(_) => Ok(StatusCode::NO_CONTENT),
+        Err(e) => Err(warp::reject::custom(CustomError::from_write(
+            e,
+            &credentials.scopes,
+        ))),
+    }
+}
+
+async fn list_interactions_v2<S: BuddiesStore>(
+    filter: InteractionFilter,
+    auth_result: Result<Uuid, warp::Rejection>,
+    handler: RequestHandler<S>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let user_id = auth_result?;
+    match handler.list_interactions(ListInteractionsRequest { user_id, filter }) {
+        Ok(resp) => Ok(warp::reply::json(&resp)),
+        Err(e) => Err(warp::reject::custom(CustomError::from_service(e))),
+    }
+}
+
+async fn create_interaction_v2<S: BuddiesStore>(
+    body: serde_json::Value,
+    idempotency_key: Option<String>,
+    credentials: Result<AuthenticationResponse, warp::Rejection>,
+    mut handler: RequestHandler<S>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let credentials = credentials?;
+    let user_id = credentials.user_uuid;
+    let mut request: CreateInteractionRequest = parse_create_body(body, user_id)?;
+    request.idempotency_key = idempotency_key;
+    match handler.create_interaction(request) {
+        Ok(resp) => {
+            let location = format!("/v2/interactions/{}", resp.interaction.id);
+            let version = resp.interaction.version;
+            let reply = written(
+                &resp.interaction,
+                EntityKind::Interaction,
+                resp.interaction.id,
+                version,
+                &credentials.scopes,
+            );
+            let reply = warp::reply::with_header(reply, "Location", location);
+            Ok(warp::reply::with_status(reply, StatusCode::CREATED))
+        }
+        Err(e) => Err(warp::reject::custom(CustomError::from_write(
+            e,
+            &credentials.scopes,
+        ))),
+    }
+}
+
+async fn get_interaction_v2<S: BuddiesStore>(
+    interaction_id: Uuid,
+    auth_result: Result<Uuid, warp::Rejection>,
+    handler: RequestHandler<S>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let user_id = auth_result?;
+    match handler.get_interaction(GetInteractionRequest {
+        user_id,
+        interaction_id,
+    }) {
+        Ok(interaction) => Ok(warp::reply::with_header(
+            warp::reply::json(&interaction),
+            "ETag",
+            etag(interaction.version),
+        )),
+        Err(e) => Err(warp::reject::custom(CustomError::from_service(e))),
+    }
+}
+
+async fn patch_interaction_v2<S: BuddiesStore>(
+    interaction_id: Uuid,
+    body: Bytes,
+    if_match: Option<String>,
+    credentials: Result<AuthenticationResponse, warp::Rejection>,
+    mut handler: RequestHandler<S>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let credentials = credentials?;
+    let user_id = credentials.user_uuid;
+    let request = PatchInteractionRequest {
+        user_id,
+        interaction_id,
+        patch: parse_merge_patch(&body)?,
+        version: parse_if_match(if_match)?,
+    };
+    match handler.patch_interaction(request) {
+        Ok(resp) => Ok(written(
+            &resp.interaction,
+            EntityKind::Interaction,
+            interaction_id,
+            resp.interaction.version,
+            &credentials.scopes,
+        )),
+        Err(e) => Err(warp::reject::custom(CustomError::from_write(
+            e,
+            &credentials.scopes,
+        ))),
+    }
+}
+
+async fn delete_interaction_v2<S: BuddiesStore>(
+    interaction_id: Uuid,
+    if_match: Option<String>,
+    credentials: Result<AuthenticationResponse, warp::Rejection>,
+    mut handler: RequestHandler<S>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let credentials = credentials?;
+    let user_id = credentials.user_uuid;
+    handler
+        .get_interaction(GetInteractionRequest {
+            user_id,
+            interaction_id,
+        })
+        .map_err(|e| warp::reject::custom(CustomError::from_service(e)))?;
+    let request = ArchiveInteractionRequest {
+        id: interaction_id,
+        user_id,
+        version: parse_if_match(if_match)?,
+    };
+    match handler.archive_interaction(request) {
+        Ok(_) => Ok(StatusCode::NO_CONTENT),
+        Err(e) => Err(warp::reject::custom(CustomError::from_write(
+            e,
+            &credentials.scopes,
+        ))),
+    }
+}
+
+async fn graphql<S: BuddiesStore>(
+    body: GraphQLBody,
+    schema: Arc<Schema>,
+    credentials: Result<AuthenticationResponse, warp::Rejection>,
+    handler: RequestHandler<S>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let credentials = credentials?;
+    let variables = match body.variables {
+        Some(variables) => Some(serde_json::from_value(variables).map_err(|e| {
+            warp::reject::custom(CustomError::bad_request(format!(
+                "Unreadable variables {}",
+                e
+            )))
+        })?),
+        None => None,
+    };
+    let request = juniper::http::GraphQLRequest::new(body.query, body.operation_name, variables);
+    let context = GraphQLContext {
+        user_id: credentials.user_uuid,
+        scopes: credentials.scopes,
+        service: Box::new(handler),
+    };
+    let response = request.execute(&schema, &context);
+    // Errors while resolving still come back as a 200 with an errors list
+    let code = if response.is_ok() {
+        StatusCode::OK
+    } else {
+        StatusCode::BAD_REQUEST
+    };
+    Ok(warp::reply::with_status(warp::reply::json(&response), code))
+}
+
+async fn events<S: BuddiesStore>(
+    auth_result: Result<Uuid, warp::Rejection>,
+    handler: RequestHandler<S>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let user_id = auth_result?;
+    let stream = handler.events.subscribe(user_id).map(|event| {
+        let name = match &event {
+            StreamEvent::Change(_) => "change",
+            StreamEvent::Resync { .. } => "resync",
+        };
+        Ok::<_, Infallible>((warp::sse::event(name), warp::sse::json(event)))
+    });
+    Ok(warp::sse::reply(warp::sse::keep_alive().stream(stream)))
+}
+
+/// Query parameters accepted by the stats endpoint
+#[derive(Deserialize, JsonSchema)]
+struct StatsQuery {
+    window_days: Option<i64>,
+}
+
+async fn get_stats<S: BuddiesStore>(
+    query: StatsQuery,
+    auth_result: Result<Uuid, warp::Rejection>,
+    handler: RequestHandler<S>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let user_id = auth_result?;
+    match handler.get_stats(GetStatsRequest {
+        user_id,
+        window_days: query.window_days,
+    }) {
+        Ok(resp) => Ok(warp::reply::json(&resp)),
+        Err(e) => Err(warp::reject::custom(CustomError::from_service(e))),
+    }
+}
+
+/// Who's calling, as long as their credential was given every scope in `required`
+fn authenticate<T: AuthStore>(
+    handler: AuthHandler<T>,
+    authorization_header: String,
+    required: &[Scope],
+) -> Result<AuthenticationResponse, warp::Rejection> {
+    let auth_header_components: Vec<&str> = authorization_header.split(' ').collect();
+
+    let jwt = match &auth_header_components[..] {
+        ["Bearer", jwt] => jwt.to_string(),
+        bad_form => {
+            return Err(warp::reject::custom(CustomError::unauthorized(format!(
+                "Malformed Authorization Header {:?}",
+                bad_form,
+            ))));
+        }
+    };
+    let response = match handler.authenticate(AuthenticationRequest { token: jwt }) {
+        Ok(response) => response,
+        Err(e) => {
+            let error = match e.downcast_ref::<UnauthorizedError>() {
+                Some(unauthorized) => CustomError::unauthorized(unauthorized.to_string()),
+                None => CustomError::unknown(format!("Failure {:?}", e)),
+            };
+            return Err(warp::reject::custom(error));
+        }
+    };
+    let missing: Vec<String> = required
+        .iter()
+        .filter(|scope| !response.scopes.contains(scope))
+        .map(|scope| scope.to_string())
+        .collect();
+    if !missing.is_empty() {
+        return Err(warp::reject::custom(CustomError::forbidden(format!(
+            "Missing scopes {}",
+            missing.join(", ")
+        ))));
+    }
+    Ok(response)
+}
+
+// This function receives a `Rejection` and tries to return a custom
+// value, otherwise simply passes the rejection along.
+async fn handle_custom_rejection(err: warp::Rejection) -> Result<Box<dyn Reply>, warp::Rejection> {
+    if let Some(CustomError { error }) = err.find() {
+        error!("Handling Error - {:?}", error);
+        match error {
+            ErrorType::Unknown(message) => {
+                let code = StatusCode::INTERNAL_SERVER_ERROR;
+                let json_reply = warp::reply::json(&ErrorMessage {
+                    code: code.as_u16(),
+                    message: message.into(),
+                });
+                Ok(Box::new(warp::reply::with_status(json_reply, code)))
+            }
+            ErrorType::BadRequest(message) => {
+                let code = StatusCode::BAD_REQUEST;
+                let json_reply = warp::reply::json(&ErrorMessage {
+                    code: code.as_u16(),
+                    message: message.into(),
+                });
+                Ok(Box::new(warp::reply::with_status(json_reply, code)))
+            }
+            ErrorType::NotFound(message) => {
+                let code = StatusCode::NOT_FOUND;
+                let json_reply = warp::reply::json(&ErrorMessage {
+                    code: code.as_u16(),
+                    message: message.into(),
+                });
+                Ok(Box::new(warp::reply::with_status(json_reply, code)))
+            }
+            ErrorType::Unauthorized(message) => {
+                let code = StatusCode::UNAUTHORIZED;
+                let json_reply = warp::reply::json(&ErrorMessage {
+                    code: code.as_u16(),
+                    message: message.into(),
+                });
+                Ok(Box::new(warp::reply::with_status(json_reply, code)))
+            }
+            ErrorType::Forbidden(message) => {
+                let code = StatusCode::FORBIDDEN;
+                let json_reply = warp::reply::json(&ErrorMessage {
+                    code: code.as_u16(),
+                    message: message.into(),
+                });
+                Ok(Box::new(warp::reply::with_status(json_reply, code)))
+            }
+            ErrorType::Unprocessable(message) => {
+                let code = StatusCode::UNPROCESSABLE_ENTITY;
+                let json_reply = warp::reply::json(&ErrorMessage {
+                    code: code.as_u16(),
+                    message: message.into(),
+                });
+                Ok(Box::new(warp::reply::with_status(json_reply, code)))
+            }
+            ErrorType::Conflict { version, current } => {
+                let code = StatusCode::CONFLICT;
+                let json_reply = warp::reply::json(&ConflictMessage {
+                    code: code.as_u16(),
+                    message: "Record has changed since it was read".to_string(),
+                    version: *version,
+                    current: current.as_deref().cloned(),
+                });
+                Ok(Box::new(warp::reply::with_header(
+                    warp::reply::with_status(json_reply, code),
+                    "ETag",
+                    etag(*version),
+                )))
+            }
+        }
+    } else {
+        error!("Passing along unknown Warp Rejection - {:?}", err);
+        Err(err)
+    }
+}
+
+/// Every route served by `build_warp_routes`, as documented at `/openapi.json`.
+/// A route added there needs an entry here too, and a test checks that the two agree.
+pub fn endpoints() -> Vec<Endpoint> {
+    vec![
+        // Accounts
+        Endpoint::post("/login", "auth", "Log in with an email and password")
+            .public()
+            .body::<LoginRequest>()
+            .returns::<LoginResponse>(),
+        Endpoint::post("/sign_up", "auth", "Create an account")
+            .public()
+            .body::<SignUpRequest>()
+            .returns::<SignUpResponse>(),
+        Endpoint::get("/user/{user_id}", "user", "Everything we have for a user")
+            .returns::<GetUserDataResponse>()
+            .scopes(&[Scope::Account, Scope::BuddiesRead, Scope::InteractionsRead]),
+        Endpoint::post("/user/update", "user", "Update a user's settings")
+            .body::<UpdateUserRequest>()
+            .returns::<UpdateUserResponse>()
+            .scopes(&[Scope::Account]),
+        Endpoint::post(
+            "/calendar/token",
+            "user",
+            "Create a secret calendar feed url",
+        )
+        .returns::<CreateCalendarTokenResponse>()
+        .scopes(&[Scope::Account]),
+        Endpoint::get(
+            "/calendar/{file_name}",
+            "user",
+            "Birthdays and dates as an iCalendar feed",
+        )
+        .public()
+        .returns_text("text/calendar"),
+        // Buddies
+        Endpoint::post("/buddy/create", "buddies", "Add a buddy")
+            .header("Idempotency-Key")
+            .body::<CreateBuddyRequest>()
+            .returns::<CreateBuddyResponse>()
+            .scopes(&[Scope::BuddiesWrite]),
+        Endpoint::post("/buddy/update", "buddies", "Update a buddy")
+            .header("If-Match")
+            .body::<UpdateBuddyRequest>()
+            .returns::<UpdateBuddyResponse>()
+            .scopes(&[Scope::BuddiesWrite]),
+        Endpoint::post("/buddy/archive", "buddies", "Archive a buddy")
+            .body::<ArchiveBuddyRequest>()
+            .returns::<ArchiveBuddyResponse>()
+            .scopes(&[Scope::BuddiesWrite]),
+        Endpoint::post(
+            "/buddy/apply_suggested_cadence",
+            "buddies",
+            "Use the cadence we suggest for a buddy",
+        )
+        .body::<ApplySuggestedCadenceRequest>()
+        .returns::<ApplySuggestedCadenceResponse>()
+        .scopes(&[Scope::BuddiesWrite]),
+        Endpoint::patch("/buddy/{buddy_id}", "buddies", "Merge patch a buddy")
+            .header("If-Match")
+            .merge_patch()
+            .returns::<PatchBuddyResponse>()
+            .scopes(&[Scope::BuddiesWrite]),
+        Endpoint::get(
+            "/buddy/{buddy_id}/history",
+            "buddies",
+            "Every revision of a buddy",
+        )
+        .returns::<GetHistoryResponse>()
+        .scopes(&[Scope::BuddiesRead]),
+        Endpoint::post(
+            "/buddy/revert",
+            "buddies",
+            "Put a buddy back to an earlier revision",
+        )
+        .header("If-Match")
+        .body::<RevertBuddyRequest>()
+        .returns::<RevertBuddyResponse>()
+        .scopes(&[Scope::BuddiesWrite]),
+        Endpoint::get(
+            "/buddy/{buddy_id}/graph",
+            "buddies",
+            "Everyone a buddy is connected to",
+        )
+        .returns::<GetBuddyGraphResponse>()
+        .scopes(&[Scope::BuddiesRead]),
+        Endpoint::get(
+            "/buddy/{buddy_id}/vcard",
+            "buddies",
+            "Export a buddy as a vCard",
+        )
+        .returns_text("text/vcard")
+        .scopes(&[Scope::BuddiesRead]),
+        Endpoint::get("/vcard", "buddies", "Export every buddy as vCards")
+            .returns_text("text/vcard")
+            .scopes(&[Scope::BuddiesRead]),
+        Endpoint::get("/buddies/near", "buddies", "Find buddies near a place")
+            .query::<NearFilter>()
+            .returns::<FindBuddiesNearResponse>()
+            .scopes(&[Scope::BuddiesRead]),
+        Endpoint::get(
+            "/buddies/duplicates",
+            "buddies",
+            "Find buddies that are likely the same person",
+        )
+        .returns::<GetDuplicatesResponse>()
+        .scopes(&[Scope::BuddiesRead]),
+        Endpoint::post("/buddy/merge", "buddies", "Merge one buddy into another")
+            .body::<MergeBuddiesRequest>()
+            .returns::<MergeBuddiesResponse>()
+            .scopes(&[Scope::BuddiesWrite, Scope::InteractionsWrite]),
+        // Interactions
+        Endpoint::post(
+            "/interaction/create",
+            "interactions",
+            "Record an interaction",
+        )
+        .header("Idempotency-Key")
+        .body::<CreateInteractionRequest>()
+        .returns::<CreateInteractionResponse>()
+        .scopes(&[Scope::InteractionsWrite]),
+        Endpoint::post(
+            "/interaction/update",
+            "interactions",
+            "Update an interaction",
+        )
+        .header("If-Match")
+        .body::<UpdateInteractionRequest>()
+        .returns::<UpdateInteractionResponse>()
+        .scopes(&[Scope::InteractionsWrite]),
+        Endpoint::post(
+            "/interaction/archive",
+            "interactions",
+            "Archive an interaction",
+        )
+        .body::<ArchiveInteractionRequest>()
+        .returns::<ArchiveInteractionResponse>()
+        .scopes(&[Scope::InteractionsWrite]),
+        Endpoint::get(
+            "/interactions",
+            "interactions",
+            "List interactions, most recent first",
+        )
+        .query::<InteractionFilter>()
+        .returns::<ListInteractionsResponse>()
+        .scopes(&[Scope::InteractionsRead]),
+        Endpoint::patch(
+            "/interaction/{interaction_id}",
+            "interactions",
+            "Merge patch an interaction",
+        )
+        .header("If-Match")
+        .merge_patch()
+        .returns::<PatchInteractionResponse>()
+        .scopes(&[Scope::InteractionsWrite]),
+        Endpoint::get(
+            "/interaction/{interaction_id}/history",
+            "interactions",
+            "Every revision of an interaction",
+        )
+        .returns::<GetHistoryResponse>()
+        .scopes(&[Scope::InteractionsRead]),
+        Endpoint::post(
+            "/interaction/revert",
+            "interactions",
+            "Put an interaction back to an earlier revision",
+        )
+        .header("If-Match")
+        .body::<RevertInteractionRequest>()
+        .returns::<RevertInteractionResponse>()
+        .scopes(&[Scope::InteractionsWrite]),
+        // Everything else we know about buddies
+        Endpoint::post(
+            "/relationship/create",
+            "relationships",
+            "Relate two buddies",
+        )
+        .body::<CreateRelationshipRequest>()
+        .returns::<CreateRelationshipResponse>()
+        .scopes(&[Scope::BuddiesWrite]),
+        Endpoint::post(
+            "/relationship/update",
+            "relationships",
+            "Update a relationship",
+        )
+        .body::<UpdateRelationshipRequest>()
+        .returns::<UpdateRelationshipResponse>()
+        .scopes(&[Scope::BuddiesWrite]),
+        Endpoint::post(
+            "/relationship/archive",
+            "relationships",
+            "Archive a relationship",
+        )
+        .body::<ArchiveRelationshipRequest>()
+        .returns::<ArchiveRelationshipResponse>()
+        .scopes(&[Scope::BuddiesWrite]),
+        Endpoint::post(
+            "/contact_method/create",
+            "contact methods",
+            "Add a contact method",
+        )
+        .body::<CreateContactMethodRequest>()
+        .returns::<CreateContactMethodResponse>()
+        .scopes(&[Scope::BuddiesWrite]),
+        Endpoint::post(
+            "/contact_method/update",
+            "contact methods",
+            "Update a contact method",
+        )
+        .body::<UpdateContactMethodRequest>()
+        .returns::<UpdateContactMethodResponse>()
+        .scopes(&[Scope::BuddiesWrite]),
+        Endpoint::post(
+            "/contact_method/archive",
+            "contact methods",
+            "Archive a contact method",
+        )
+        .body::<ArchiveContactMethodRequest>()
+        .returns::<ArchiveContactMethodResponse>()
+        .scopes(&[Scope::BuddiesWrite]),
+        Endpoint::post(
+            "/important_date/create",
+            "important dates",
+            "Add an important date",
+        )
+        .body::<CreateImportantDateRequest>()
+        .returns::<CreateImportantDateResponse>()
+        .scopes(&[Scope::BuddiesWrite]),
+        Endpoint::post(
+            "/important_date/update",
+            "important dates",
+            "Update an important date",
+        )
+        .body::<UpdateImportantDateRequest>()
+        .returns::<UpdateImportantDateResponse>()
+        .scopes(&[Scope::BuddiesWrite]),
+        Endpoint::post(
+            "/important_date/archive",
+            "important dates",
+            "Archive an important date",
+        )
+        .body::<ArchiveImportantDateRequest>()
+        .returns::<ArchiveImportantDateResponse>()
+        .scopes(&[Scope::BuddiesWrite]),
+        Endpoint::get(
+            "/dates/upcoming",
+            "important dates",
+            "Birthdays and dates coming up",
+        )
+        .query::<UpcomingDatesQuery>()
+        .returns::<GetUpcomingDatesResponse>()
+        .scopes(&[Scope::BuddiesRead]),
+        Endpoint::post("/follow_up/create", "follow ups", "Add a follow up")
+            .body::<CreateFollowUpRequest>()
+            .returns::<CreateFollowUpResponse>()
+            .scopes(&[Scope::InteractionsWrite]),
+        Endpoint::post("/follow_up/complete", "follow ups", "Complete a follow up")
+            .body::<CompleteFollowUpRequest>()
+            .returns::<CompleteFollowUpResponse>()
+            .scopes(&[Scope::InteractionsWrite]),
+        Endpoint::get("/follow_ups", "follow ups", "List follow ups")
+            .query::<FollowUpFilter>()
+            .returns::<ListFollowUpsResponse>()
+            .scopes(&[Scope::InteractionsRead]),
+        Endpoint::post("/idea/create", "ideas", "Add an idea")
+            .body::<CreateIdeaRequest>()
+            .returns::<CreateIdeaResponse>()
+            .scopes(&[Scope::BuddiesWrite]),
+        Endpoint::post("/idea/update", "ideas", "Update an idea")
+            .body::<UpdateIdeaRequest>()
+            .returns::<UpdateIdeaResponse>()
+            .scopes(&[Scope::BuddiesWrite]),
+        Endpoint::post("/idea/archive", "ideas", "Archive an idea")
+            .body::<ArchiveIdeaRequest>()
+            .returns::<ArchiveIdeaResponse>()
+            .scopes(&[Scope::BuddiesWrite]),
+        // Sync and reports
+        Endpoint::get("/sync", "sync", "Everything that changed since a cursor")
+            .query::<SyncQuery>()
+            .returns::<GetChangesResponse>()
+            .scopes(&[Scope::BuddiesRead, Scope::InteractionsRead]),
+        Endpoint::post("/sync", "sync", "Apply changes made offline")
+            .body::<SyncRequest>()
+            .returns::<SyncResponse>()
+            .scopes(&[Scope::BuddiesWrite, Scope::InteractionsWrite]),
+        Endpoint::post("/batch", "sync", "Apply changes all together or not at all")
+            .body::<BatchRequest>()
+            .returns::<BatchResponse>()
+            .scopes(&[Scope::BuddiesWrite, Scope::InteractionsWrite]),
+        Endpoint::get("/stats", "reports", "How often you keep in touch")
+            .query::<StatsQuery>()
+            .returns::<GetStatsResponse>()
+            .scopes(&[Scope::BuddiesRead, Scope::InteractionsRead]),
+        Endpoint::get("/digest", "reports", "Who to reach out to")
+            .returns::<GetDigestResponse>()
+            .scopes(&[Scope::BuddiesRead, Scope::InteractionsRead]),
+        // v2
+        Endpoint::get("/v2/buddies", "v2", "List buddies")
+            .returns::<ListBuddiesResponse>()
+            .scopes(&[Scope::BuddiesRead]),
+        Endpoint::post("/v2/buddies", "v2", "Add a buddy")
+            .header("Idempotency-Key")
+            .body::<CreateBuddyRequest>()
+            .returns_status::<Buddy>(201)
+            .scopes(&[Scope::BuddiesWrite]),
+        Endpoint::get("/v2/buddies/{buddy_id}", "v2", "Get a buddy")
+            .returns::<Buddy>()
+            .scopes(&[Scope::BuddiesRead]),
+        Endpoint::patch("/v2/buddies/{buddy_id}", "v2", "Merge patch a buddy")
+            .header("If-Match")
+            .merge_patch()
+            .returns::<Buddy>()
+            .scopes(&[Scope::BuddiesWrite]),
+        Endpoint::delete("/v2/buddies/{buddy_id}", "v2", "Archive a buddy")
+            .header("If-Match")
+            .scopes(&[Scope::BuddiesWrite]),
+        Endpoint::get("/v2/interactions", "v2", "List interactions")
+            .query::<InteractionFilter>()
+            .returns::<ListInteractionsResponse>()
+            .scopes(&[Scope::InteractionsRead]),
+        Endpoint::post("/v2/interactions", "v2", "Record an interaction")
+            .header("Idempotency-Key")
+            .body::<CreateInteractionRequest>()
+            .returns_status::<Interaction>(201)
+            .scopes(&[Scope::InteractionsWrite]),
+        Endpoint::get(
+            "/v2/interactions/{interaction_id}",
+            "v2",
+            "Get an interaction",
+        )
+        .returns::<Interaction>()
+        .scopes(&[Scope::InteractionsRead]),
+        Endpoint::patch(
+            "/v2/interactions/{interaction_id}",
+            "v2",
+            "Merge patch an interaction",
+        )
+        .header("If-Match")
+        .merge_patch()
+        .returns::<Interaction>()
+        .scopes(&[Scope::InteractionsWrite]),
+        Endpoint::delete(
+            "/v2/interactions/{interaction_id}",
+            "v2",
+            "Archive an interaction",
+        )
+        .header("If-Match")
+        .scopes(&[Scope::InteractionsWrite]),
+        Endpoint::post(
+            "/graphql",
+            "graphql",
+            "Query buddies and interactions with GraphQL",
+        )
+        .body::<GraphQLBody>()
+        .returns::<serde_json::Value>()
+        .scopes(&[Scope::BuddiesRead, Scope::InteractionsRead]),
+        // Webhooks
+        Endpoint::post("/webhook/create", "webhooks", "Register a webhook")
+            .body::<CreateWebhookRequest>()
+            .returns::<CreateWebhookResponse>()
+            .scopes(&[Scope::Account]),
+        Endpoint::post("/webhook/archive", "webhooks", "Stop sending to a webhook")
+            .body::<ArchiveWebhookRequest>()
+            .returns::<ArchiveWebhookResponse>()
+            .scopes(&[Scope::Account]),
+        Endpoint::get("/webhooks", "webhooks", "List webhooks")
+            .returns::<ListWebhooksResponse>()
+            .scopes(&[Scope::Account]),
+        Endpoint::get(
+            "/webhook/{webhook_id}/deliveries",
+            "webhooks",
+            "What was sent to a webhook and how it went",
+        )
+        .returns::<ListWebhookDeliveriesResponse>()
+        .scopes(&[Scope::Account]),
+        // Personal access tokens
+        Endpoint::post(
+            "/token/create",
+            "tokens",
+            "Create a personal access token. The token is only ever shown here.",
+        )
+        .body::<CreatePersonalAccessTokenRequest>()
+        .returns::<CreatePersonalAccessTokenResponse>()
+        .scopes(&[Scope::Account]),
+        Endpoint::post("/token/revoke", "tokens", "Revoke a personal access token")
+            .body::<RevokePersonalAccessTokenRequest>()
+            .returns::<RevokePersonalAccessTokenResponse>()
+            .scopes(&[Scope::Account]),
+        Endpoint::get(
+            "/tokens",
+            "tokens",
+            "List personal access tokens that still work",
+        )
+        .returns::<ListPersonalAccessTokensResponse>()
+        .scopes(&[Scope::Account]),
+        Endpoint::get(
+            "/events",
+            "events",
+            "Stream changes to buddies and interactions as server-sent events",
+        )
+        .returns_text("text/event-stream")
+        .scopes(&[Scope::BuddiesRead, Scope::InteractionsRead]),
+        // Docs
+        Endpoint::get("/openapi.json", "docs", "This document")
+            .public()
+            .returns_text("application/json"),
+        Endpoint::get("/docs", "docs", "Browse this document with Swagger UI")
+            .public()
+            .returns_text("text/html"),
+    ]
+}
+
+/// This function links the service to warp's route handling
+pub fn build_warp_routes<S: BuddiesStore, T: AuthStore>(
+    auth_handler: AuthHandler<T>,
+    handler: RequestHandler<S>,
+) -> BoxedFilter<(impl Reply,)> {
+    let cors = warp::cors()
+        .allow_any_origin()
+        .allow_headers(vec![
+            "Accept",
+            "Accept-Encoding",
+            "Access-Control-Request-Headers",
+            "Access-Contorl-Request-Method",
+            "Authorization",
+            "Connection",
+            "Content-Type",
+            "Host",
+            "Idempotency-Key",
+            "If-Match",
+            "Origin",
+            "Referer",
+            "Sec-Fetch-Dest",
+            "Sec-Fetch-Mode",
+            "User-Agent",
+        ])
+        .allow_methods(vec!["GET", "PUT", "POST", "PATCH", "DELETE"])
+        .expose_headers(vec!["ETag", "Location"]);
+
+    let auth_handler_filter = warp::any().map(move || auth_handler.clone());
+    // Every authenticated route says which scopes the caller needs
+    let credentials_filter = |required: &'static [Scope]| {
+        auth_handler_filter
+            .clone()
+            .and(warp::header("Authorization"))
+            .map(move |handler, authorization_header| {
+                authenticate(handler, authorization_header, required)
+            })
+    };
+    let auth_filter = |required: &'static [Scope]| {
+        credentials_filter(required).map(
+            |credentials: Result<AuthenticationResponse, warp::Rejection>| {
+                credentials.map(|credentials| credentials.user_uuid)
+            },
+        )
+    };
+
+    let handler_filter = warp::any().map(move || handler.clone());
+
+    let login = warp::post()
+        .and(warp::path("login"))
+        .and(warp::body::content_length_limit(1024 * 16))
+        .and(warp::body::json())
         .and(auth_handler_filter.clone())
         .and_then(login);
 
-    let sign_up = warp::post()
-        .and(warp::path("sign_up"))
+    let sign_up = warp::post()
+        .and(warp::path("sign_up"))
+        .and(warp::body::content_length_limit(1024 * 16))
+        .and(warp::body::json())
+        .and(auth_handler_filter.clone())
+        .and_then(sign_up);
+
+    let create_buddy = warp::post()
+        .and(warp::path("buddy"))
+        .and(warp::path("create"))
+        // Only accept bodies smaller than 16kb... (because warp said so)
+        // https://github.com/seanmonstar/warp/blob/master/examples/body.rs
+        .and(warp::body::content_length_limit(1024 * 16))
+        .and(warp::body::json())
+        .and(warp::header::optional::<String>("idempotency-key"))
+        .and(credentials_filter(&[Scope::BuddiesWrite]))
+        .and(handler_filter.clone())
+        .and_then(create_buddy);
+
+    let archive_buddy = warp::post()
+        .and(warp::path("buddy"))
+        .and(warp::path("archive"))
+        .and(warp::body::content_length_limit(1024 * 16))
+        .and(warp::body::json())
+        .and(credentials_filter(&[Scope::BuddiesWrite]))
+        .and(handler_filter.clone())
+        .and_then(archive_buddy);
+
+    let update_buddy = warp::post()
+        .and(warp::path("buddy"))
+        .and(warp::path("update"))
+        .and(warp::body::content_length_limit(1024 * 16))
+        .and(warp::body::json())
+        .and(warp::header::optional::<String>("if-match"))
+        .and(credentials_filter(&[Scope::BuddiesWrite]))
+        .and(handler_filter.clone())
+        .and_then(update_buddy);
+
+    let apply_suggested_cadence = warp::post()
+        .and(warp::path("buddy"))
+        .and(warp::path("apply_suggested_cadence"))
+        .and(warp::body::content_length_limit(1024 * 16))
+        .and(warp::body::json())
+        .and(auth_filter(&[Scope::BuddiesWrite]))
+        .and(handler_filter.clone())
+        .and_then(apply_suggested_cadence);
+
+    let create_interaction = warp::post()
+        .and(warp::path("interaction"))
+        .and(warp::path("create"))
+        .and(warp::body::content_length_limit(1024 * 16))
+        .and(warp::body::json())
+        .and(warp::header::optional::<String>("idempotency-key"))
+        .and(credentials_filter(&[Scope::InteractionsWrite]))
+        .and(handler_filter.clone())
+        .and_then(create_interaction);
+
+    let archive_interaction = warp::post()
+        .and(warp::path("interaction"))
+        .and(warp::path("archive"))
+        .and(warp::body::content_length_limit(1024 * 16))
+        .and(warp::body::json())
+        .and(credentials_filter(&[Scope::InteractionsWrite]))
+        .and(handler_filter.clone())
+        .and_then(archive_interaction);
+
+    let update_interaction = warp::post()
+        .and(warp::path("interaction"))
+        .and(warp::path("update"))
+        .and(warp::body::content_length_limit(1024 * 16))
+        .and(warp::body::json())
+        .and(warp::header::optional::<String>("if-match"))
+        .and(credentials_filter(&[Scope::InteractionsWrite]))
+        .and(handler_filter.clone())
+        .and_then(update_interaction);
+
+    let create_relationship = warp::post()
+        .and(warp::path("relationship"))
+        .and(warp::path("create"))
+        .and(warp::body::content_length_limit(1024 * 16))
+        .and(warp::body::json())
+        .and(auth_filter(&[Scope::BuddiesWrite]))
+        .and(handler_filter.clone())
+        .and_then(create_relationship);
+
+    let update_relationship = warp::post()
+        .and(warp::path("relationship"))
+        .and(warp::path("update"))
+        .and(warp::body::content_length_limit(1024 * 16))
+        .and(warp::body::json())
+        .and(auth_filter(&[Scope::BuddiesWrite]))
+        .and(handler_filter.clone())
+        .and_then(update_relationship);
+
+    let archive_relationship = warp::post()
+        .and(warp::path("relationship"))
+        .and(warp::path("archive"))
+        .and(warp::body::content_length_limit(1024 * 16))
+        .and(warp::body::json())
+        .and(auth_filter(&[Scope::BuddiesWrite]))
+        .and(handler_filter.clone())
+        .and_then(archive_relationship);
+
+    let get_buddy_graph = warp::get()
+        .and(warp::path("buddy"))
+        .and(warp::path::param::<Uuid>())
+        .and(warp::path("graph"))
+        .and(warp::path::end())
+        .and(auth_filter(&[Scope::BuddiesRead]))
+        .and(handler_filter.clone())
+        .and_then(get_buddy_graph);
+
+    let create_important_date = warp::post()
+        .and(warp::path("important_date"))
+        .and(warp::path("create"))
+        .and(warp::body::content_length_limit(1024 * 16))
+        .and(warp::body::json())
+        .and(auth_filter(&[Scope::BuddiesWrite]))
+        .and(handler_filter.clone())
+        .and_then(create_important_date);
+
+    let update_important_date = warp::post()
+        .and(warp::path("important_date"))
+        .and(warp::path("update"))
+        .and(warp::body::content_length_limit(1024 * 16))
+        .and(warp::body::json())
+        .and(auth_filter(&[Scope::BuddiesWrite]))
+        .and(handler_filter.clone())
+        .and_then(update_important_date);
+
+    let archive_important_date = warp::post()
+        .and(warp::path("important_date"))
+        .and(warp::path("archive"))
+        .and(warp::body::content_length_limit(1024 * 16))
+        .and(warp::body::json())
+        .and(auth_filter(&[Scope::BuddiesWrite]))
+        .and(handler_filter.clone())
+        .and_then(archive_important_date);
+
+    let get_upcoming_dates = warp::get()
+        .and(warp::path("dates"))
+        .and(warp::path("upcoming"))
+        .and(warp::path::end())
+        .and(warp::query::<UpcomingDatesQuery>())
+        .and(auth_filter(&[Scope::BuddiesRead]))
+        .and(handler_filter.clone())
+        .and_then(get_upcoming_dates);
+
+    let create_follow_up = warp::post()
+        .and(warp::path("follow_up"))
+        .and(warp::path("create"))
+        .and(warp::body::content_length_limit(1024 * 16))
+        .and(warp::body::json())
+        .and(auth_filter(&[Scope::InteractionsWrite]))
+        .and(handler_filter.clone())
+        .and_then(create_follow_up);
+
+    let complete_follow_up = warp::post()
+        .and(warp::path("follow_up"))
+        .and(warp::path("complete"))
+        .and(warp::body::content_length_limit(1024 * 16))
+        .and(warp::body::json())
+        .and(auth_filter(&[Scope::InteractionsWrite]))
+        .and(handler_filter.clone())
+        .and_then(complete_follow_up);
+
+    let list_follow_ups = warp::get()
+        .and(warp::path("follow_ups"))
+        .and(warp::path::end())
+        .and(warp::query::<FollowUpFilter>())
+        .and(auth_filter(&[Scope::InteractionsRead]))
+        .and(handler_filter.clone())
+        .and_then(list_follow_ups);
+
+    let create_idea = warp::post()
+        .and(warp::path("idea"))
+        .and(warp::path("create"))
+        .and(warp::body::content_length_limit(1024 * 16))
+        .and(warp::body::json())
+        .and(auth_filter(&[Scope::BuddiesWrite]))
+        .and(handler_filter.clone())
+        .and_then(create_idea);
+
+    let update_idea = warp::post()
+        .and(warp::path("idea"))
+        .and(warp::path("update"))
+        .and(warp::body::content_length_limit(1024 * 16))
+        .and(warp::body::json())
+        .and(auth_filter(&[Scope::BuddiesWrite]))
+        .and(handler_filter.clone())
+        .and_then(update_idea);
+
+    let archive_idea = warp::post()
+        .and(warp::path("idea"))
+        .and(warp::path("archive"))
+        .and(warp::body::content_length_limit(1024 * 16))
+        .and(warp::body::json())
+        .and(auth_filter(&[Scope::BuddiesWrite]))
+        .and(handler_filter.clone())
+        .and_then(archive_idea);
+
+    let patch_buddy = warp::patch()
+        .and(warp::path("buddy"))
+        .and(warp::path::param::<Uuid>())
+        .and(warp::path::end())
+        .and(warp::body::content_length_limit(1024 * 16))
+        .and(warp::body::bytes())
+        .and(warp::header::optional::<String>("if-match"))
+        .and(credentials_filter(&[Scope::BuddiesWrite]))
+        .and(handler_filter.clone())
+        .and_then(patch_buddy);
+
+    let patch_interaction = warp::patch()
+        .and(warp::path("interaction"))
+        .and(warp::path::param::<Uuid>())
+        .and(warp::path::end())
+        .and(warp::body::content_length_limit(1024 * 16))
+        .and(warp::body::bytes())
+        .and(warp::header::optional::<String>("if-match"))
+        .and(credentials_filter(&[Scope::InteractionsWrite]))
+        .and(handler_filter.clone())
+        .and_then(patch_interaction);
+
+    let get_buddy_history = warp::get()
+        .and(warp::path("buddy"))
+        .and(warp::path::param::<Uuid>())
+        .and(warp::path("history"))
+        .and(warp::path::end())
+        .and(auth_filter(&[Scope::BuddiesRead]))
+        .and(handler_filter.clone())
+        .and_then(get_buddy_history);
+
+    let revert_buddy = warp::post()
+        .and(warp::path("buddy"))
+        .and(warp::path("revert"))
+        .and(warp::body::content_length_limit(1024 * 16))
+        .and(warp::body::json())
+        .and(warp::header::optional::<String>("if-match"))
+        .and(credentials_filter(&[Scope::BuddiesWrite]))
+        .and(handler_filter.clone())
+        .and_then(revert_buddy);
+
+    let get_interaction_history = warp::get()
+        .and(warp::path("interaction"))
+        .and(warp::path::param::<Uuid>())
+        .and(warp::path("history"))
+        .and(warp::path::end())
+        .and(auth_filter(&[Scope::InteractionsRead]))
+        .and(handler_filter.clone())
+        .and_then(get_interaction_history);
+
+    let revert_interaction = warp::post()
+        .and(warp::path("interaction"))
+        .and(warp::path("revert"))
+        .and(warp::body::content_length_limit(1024 * 16))
+        .and(warp::body::json())
+        .and(warp::header::optional::<String>("if-match"))
+        .and(credentials_filter(&[Scope::InteractionsWrite]))
+        .and(handler_filter.clone())
+        .and_then(revert_interaction);
+
+    let find_buddies_near = warp::get()
+        .and(warp::path("buddies"))
+        .and(warp::path("near"))
+        .and(warp::path::end())
+        .and(warp::query::<NearFilter>())
+        .and(auth_filter(&[Scope::BuddiesRead]))
+        .and(handler_filter.clone())
+        .and_then(find_buddies_near);
+
+    let get_duplicates = warp::get()
+        .and(warp::path("buddies"))
+        .and(warp::path("duplicates"))
+        .and(warp::path::end())
+        .and(auth_filter(&[Scope::BuddiesRead]))
+        .and(handler_filter.clone())
+        .and_then(get_duplicates);
+
+    let merge_buddies = warp::post()
+        .and(warp::path("buddy"))
+        .and(warp::path("merge"))
+        .and(warp::path::end())
+        .and(warp::body::content_length_limit(1024 * 16))
+        .and(warp::body::json())
+        .and(credentials_filter(&[
+            Scope::BuddiesWrite,
+            Scope::InteractionsWrite,
+        ]))
+        .and(handler_filter.clone())
+        .and_then(merge_buddies);
+
+    let create_contact_method = warp::post()
+        .and(warp::path("contact_method"))
+        .and(warp::path("create"))
+        .and(warp::body::content_length_limit(1024 * 16))
+        .and(warp::body::json())
+        .and(auth_filter(&[Scope::BuddiesWrite]))
+        .and(handler_filter.clone())
+        .and_then(create_contact_method);
+
+    let update_contact_method = warp::post()
+        .and(warp::path("contact_method"))
+        .and(warp::path("update"))
+        .and(warp::body::content_length_limit(1024 * 16))
+        .and(warp::body::json())
+        .and(auth_filter(&[Scope::BuddiesWrite]))
+        .and(handler_filter.clone())
+        .and_then(update_contact_method);
+
+    let archive_contact_method = warp::post()
+        .and(warp::path("contact_method"))
+        .and(warp::path("archive"))
+        .and(warp::body::content_length_limit(1024 * 16))
+        .and(warp::body::json())
+        .and(auth_filter(&[Scope::BuddiesWrite]))
+        .and(handler_filter.clone())
+        .and_then(archive_contact_method);
+
+    let export_buddy_vcard = warp::get()
+        .and(warp::path("buddy"))
+        .and(warp::path::param::<Uuid>())
+        .and(warp::path("vcard"))
+        .and(warp::path::end())
+        .map(Some)
+        .and(auth_filter(&[Scope::BuddiesRead]))
+        .and(handler_filter.clone())
+        .and_then(export_vcard);
+
+    let export_all_vcards = warp::get()
+        .and(warp::path("vcard"))
+        .and(warp::path::end())
+        .map(|| None)
+        .and(auth_filter(&[Scope::BuddiesRead]))
+        .and(handler_filter.clone())
+        .and_then(export_vcard);
+
+    let list_interactions = warp::get()
+        .and(warp::path("interactions"))
+        .and(warp::path::end())
+        .and(warp::query::<InteractionFilter>())
+        .and(auth_filter(&[Scope::InteractionsRead]))
+        .and(handler_filter.clone())
+        .and_then(list_interactions);
+
+    let get_changes = warp::get()
+        .and(warp::path("sync"))
+        .and(warp::path::end())
+        .and(warp::query::<SyncQuery>())
+        .and(auth_filter(&[Scope::BuddiesRead, Scope::InteractionsRead]))
+        .and(handler_filter.clone())
+        .and_then(get_changes);
+
+    // Offline clients save up their changes, so allow a bigger body here
+    let sync = warp::post()
+        .and(warp::path("sync"))
+        .and(warp::path::end())
+        .and(warp::body::content_length_limit(1024 * 256))
+        .and(warp::body::json())
+        .and(credentials_filter(&[
+            Scope::BuddiesWrite,
+            Scope::InteractionsWrite,
+        ]))
+        .and(handler_filter.clone())
+        .and_then(sync);
+
+    let batch = warp::post()
+        .and(warp::path("batch"))
+        .and(warp::path::end())
+        .and(warp::body::content_length_limit(1024 * 256))
+        .and(warp::body::json())
+        .and(credentials_filter(&[
+            Scope::BuddiesWrite,
+            Scope::InteractionsWrite,
+        ]))
+        .and(handler_filter.clone())
+        .and_then(batch);
+
+    let get_stats = warp::get()
+        .and(warp::path("stats"))
+        .and(warp::path::end())
+        .and(warp::query::<StatsQuery>())
+        .and(auth_filter(&[Scope::BuddiesRead, Scope::InteractionsRead]))
+        .and(handler_filter.clone())
+        .and_then(get_stats);
+
+    let get_user_data = warp::get()
+        .and(warp::path("user"))
+        .and(warp::path::param::<Uuid>())
+        .and(auth_filter(&[
+            Scope::Account,
+            Scope::BuddiesRead,
+            Scope::InteractionsRead,
+        ]))
+        .and(handler_filter.clone())
+        .and_then(get_user_data);
+
+    let update_user = warp::post()
+        .and(warp::path("user"))
+        .and(warp::path("update"))
         .and(warp::body::content_length_limit(1024 * 16))
         .and(warp::body::json())
-        .and(auth_handler_filter.clone())
-        .and_then(sign_up);
+        .and(auth_filter(&[Scope::Account]))
+        .and(handler_filter.clone())
+        .and_then(update_user);
 
-    let create_buddy = warp::post()
-        .and(warp::path("buddy"))
+    let get_digest = warp::get()
+        .and(warp::path("digest"))
+        .and(warp::path::end())
+        .and(auth_filter(&[Scope::BuddiesRead, Scope::InteractionsRead]))
+        .and(handler_filter.clone())
+        .and_then(get_digest);
+
+    let create_calendar_token = warp::post()
+        .and(warp::path("calendar"))
+        .and(warp::path("token"))
+        .and(warp::path::end())
+        .and(auth_filter(&[Scope::Account]))
+        .and(handler_filter.clone())
+        .and_then(create_calendar_token);
+
+    // No auth header here, the secret token in the path is the credential
+    let get_calendar = warp::get()
+        .and(warp::path("calendar"))
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(handler_filter.clone())
+        .and_then(get_calendar);
+
+    let schema = Arc::new(graphql::schema());
+    let schema_filter = warp::any().map(move || schema.clone());
+    let graphql = warp::post()
+        .and(warp::path("graphql"))
+        .and(warp::path::end())
+        .and(warp::body::content_length_limit(1024 * 64))
+        .and(warp::body::json())
+        .and(schema_filter)
+        .and(credentials_filter(&[
+            Scope::BuddiesRead,
+            Scope::InteractionsRead,
+        ]))
+        .and(handler_filter.clone())
+        .and_then(graphql);
+
+    let create_webhook = warp::post()
+        .and(warp::path("webhook"))
         .and(warp::path("create"))
-        // Only accept bodies smaller than 16kb... (because warp said so)
-        // https://github.com/seanmonstar/warp/blob/master/examples/body.rs
         .and(warp::body::content_length_limit(1024 * 16))
         .and(warp::body::json())
-        .and(auth_filter.clone())
+        .and(auth_filter(&[Scope::Account]))
         .and(handler_filter.clone())
-        .and_then(create_buddy);
+        .and_then(create_webhook);
 
-    let archive_buddy = warp::post()
-        .and(warp::path("buddy"))
+    let archive_webhook = warp::post()
+        .and(warp::path("webhook"))
         .and(warp::path("archive"))
         .and(warp::body::content_length_limit(1024 * 16))
         .and(warp::body::json())
-        .and(auth_filter.clone())
+        .and(auth_filter(&[Scope::Account]))
         .and(handler_filter.clone())
-        .and_then(archive_buddy);
+        .and_then(archive_webhook);
 
-    let update_buddy = warp::post()
-        .and(warp::path("buddy"))
-        .and(warp::path("update"))
-        .and(warp::body::content_length_limit(1024 * 16))
-        .and(warp::body::json())
-        .and(auth_filter.clone())
+    let list_webhooks = warp::get()
+        .and(warp::path("webhooks"))
+        .and(warp::path::end())
+        .and(auth_filter(&[Scope::Account]))
         .and(handler_filter.clone())
-        .and_then(update_buddy);
+        .and_then(list_webhooks);
 
-    let create_interaction = warp::post()
-        .and(warp::path("interaction"))
+    let list_webhook_deliveries = warp::get()
+        .and(warp::path("webhook"))
+        .and(warp::path::param::<Uuid>())
+        .and(warp::path("deliveries"))
+        .and(warp::path::end())
+        .and(auth_filter(&[Scope::Account]))
+        .and(handler_filter.clone())
+        .and_then(list_webhook_deliveries);
+
+    let create_personal_access_token = warp::post()
+        .and(warp::path("token"))
         .and(warp::path("create"))
         .and(warp::body::content_length_limit(1024 * 16))
         .and(warp::body::json())
-        .and(auth_filter.clone())
+        .and(credentials_filter(&[Scope::Account]))
+        .and(auth_handler_filter.clone())
+        .and_then(create_personal_access_token);
+
+    let revoke_personal_access_token = warp::post()
+        .and(warp::path("token"))
+        .and(warp::path("revoke"))
+        .and(warp::body::content_length_limit(1024 * 16))
+        .and(warp::body::json())
+        .and(auth_filter(&[Scope::Account]))
+        .and(auth_handler_filter.clone())
+        .and_then(revoke_personal_access_token);
+
+    let list_personal_access_tokens = warp::get()
+        .and(warp::path("tokens"))
+        .and(warp::path::end())
+        .and(auth_filter(&[Scope::Account]))
+        .and(auth_handler_filter.clone())
+        .and_then(list_personal_access_tokens);
+
+    let events = warp::get()
+        .and(warp::path("events"))
+        .and(warp::path::end())
+        .and(auth_filter(&[Scope::BuddiesRead, Scope::InteractionsRead]))
         .and(handler_filter.clone())
-        .and_then(create_interaction);
+        .and_then(events);
 
-    let archive_interaction = warp::post()
-        .and(warp::path("interaction"))
-        .and(warp::path("archive"))
+    let spec = build_spec(&endpoints());
+    let openapi = warp::get()
+        .and(warp::path("openapi.json"))
+        .and(warp::path::end())
+        .map(move || warp::reply::json(&spec));
+
+    let docs = warp::get()
+        .and(warp::path("docs"))
+        .and(warp::path::end())
+        .map(|| warp::reply::html(SWAGGER_UI));
+
+    // The v2 surface, with ids in paths and the user taken from the token
+    let v2 = warp::path("v2");
+    let list_buddies_v2 = warp::get()
+        .and(v2)
+        .and(warp::path("buddies"))
+        .and(warp::path::end())
+        .and(auth_filter(&[Scope::BuddiesRead]))
+        .and(handler_filter.clone())
+        .and_then(list_buddies_v2);
+
+    let create_buddy_v2 = warp::post()
+        .and(v2)
+        .and(warp::path("buddies"))
+        .and(warp::path::end())
         .and(warp::body::content_length_limit(1024 * 16))
         .and(warp::body::json())
-        .and(auth_filter.clone())
+        .and(warp::header::optional::<String>("idempotency-key"))
+        .and(credentials_filter(&[Scope::BuddiesWrite]))
         .and(handler_filter.clone())
-        .and_then(archive_interaction);
+        .and_then(create_buddy_v2);
 
-    let update_interaction = warp::post()
-        .and(warp::path("interaction"))
-        .and(warp::path("update"))
+    let get_buddy_v2 = warp::get()
+        .and(v2)
+        .and(warp::path("buddies"))
+        .and(warp::path::param::<Uuid>())
+        .and(warp::path::end())
+        .and(auth_filter(&[Scope::BuddiesRead]))
+        .and(handler_filter.clone())
+        .and_then(get_buddy_v2);
+
+    let patch_buddy_v2 = warp::patch()
+        .and(v2)
+        .and(warp::path("buddies"))
+        .and(warp::path::param::<Uuid>())
+        .and(warp::path::end())
+        .and(warp::body::content_length_limit(1024 * 16))
+        .and(warp::body::bytes())
+        .and(warp::header::optional::<String>("if-match"))
+        .and(credentials_filter(&[Scope::BuddiesWrite]))
+        .and(handler_filter.clone())
+        .and_then(patch_buddy_v2);
+
+    let delete_buddy_v2 = warp::delete()
+        .and(v2)
+        .and(warp::path("buddies"))
+        .and(warp::path::param::<Uuid>())
+        .and(warp::path::end())
+        .and(warp::header::optional::<String>("if-match"))
+        .and(credentials_filter(&[Scope::BuddiesWrite]))
+        .and(handler_filter.clone())
+        .and_then(delete_buddy_v2);
+
+    let list_interactions_v2 = warp::get()
+        .and(v2)
+        .and(warp::path("interactions"))
+        .and(warp::path::end())
+        .and(warp::query::<InteractionFilter>())
+        .and(auth_filter(&[Scope::InteractionsRead]))
+        .and(handler_filter.clone())
+        .and_then(list_interactions_v2);
+
+    let create_interaction_v2 = warp::post()
+        .and(v2)
+        .and(warp::path("interactions"))
+        .and(warp::path::end())
         .and(warp::body::content_length_limit(1024 * 16))
         .and(warp::body::json())
-        .and(auth_filter.clone())
+        .and(warp::header::optional::<String>("idempotency-key"))
+        .and(credentials_filter(&[Scope::InteractionsWrite]))
         .and(handler_filter.clone())
-        .and_then(update_interaction);
+        .and_then(create_interaction_v2);
 
-    let get_user_data = warp::get()
-        .and(warp::path("user"))
+    let get_interaction_v2 = warp::get()
+        .and(v2)
+        .and(warp::path("interactions"))
         .and(warp::path::param::<Uuid>())
-        .and(auth_filter.clone())
+        .and(warp::path::end())
+        .and(auth_filter(&[Scope::InteractionsRead]))
         .and(handler_filter.clone())
-        .and_then(get_user_data);
+        .and_then(get_interaction_v2);
+
+    let patch_interaction_v2 = warp::patch()
+        .and(v2)
+        .and(warp::path("interactions"))
+        .and(warp::path::param::<Uuid>())
+        .and(warp::path::end())
+        .and(warp::body::content_length_limit(1024 * 16))
+        .and(warp::body::bytes())
+        .and(warp::header::optional::<String>("if-match"))
+        .and(credentials_filter(&[Scope::InteractionsWrite]))
+        .and(handler_filter.clone())
+        .and_then(patch_interaction_v2);
 
-    let routes = login
+    let delete_interaction_v2 = warp::delete()
+        .and(v2)
+        .and(warp::path("interactions"))
+        .and(warp::path::param::<Uuid>())
+        .and(warp::path::end())
+        .and(warp::header::optional::<String>("if-match"))
+        .and(credentials_filter(&[Scope::InteractionsWrite]))
+        .and(handler_filter.clone())
+        .and_then(delete_interaction_v2);
+
+    // Grouped and boxed so the combined filter doesn't blow the stack
+    let account_routes = login
         .or(sign_up)
-        .or(create_buddy)
+        .or(get_user_data)
+        .or(update_user)
+        .or(create_calendar_token)
+        .or(get_calendar)
+        .boxed();
+    let buddy_routes = create_buddy
         .or(update_buddy)
         .or(archive_buddy)
-        .or(create_interaction)
+        .or(apply_suggested_cadence)
+        .or(patch_buddy)
+        .or(get_buddy_history)
+        .or(revert_buddy)
+        .or(get_buddy_graph)
+        .or(export_buddy_vcard)
+        .or(export_all_vcards)
+        .or(find_buddies_near)
+        .or(get_duplicates)
+        .or(merge_buddies)
+        .boxed();
+    let interaction_routes = create_interaction
         .or(update_interaction)
         .or(archive_interaction)
-        .or(get_user_data)
+        .or(list_interactions)
+        .or(patch_interaction)
+        .or(get_interaction_history)
+        .or(revert_interaction)
+        .boxed();
+    let detail_routes = create_relationship
+        .or(update_relationship)
+        .or(archive_relationship)
+        .or(create_contact_method)
+        .or(update_contact_method)
+        .or(archive_contact_method)
+        .or(create_important_date)
+        .or(update_important_date)
+        .or(archive_important_date)
+        .or(get_upcoming_dates)
+        .or(create_follow_up)
+        .or(complete_follow_up)
+        .or(list_follow_ups)
+        .or(create_idea)
+        .or(update_idea)
+        .or(archive_idea)
+        .boxed();
+    let sync_routes = get_changes
+        .or(sync)
+        .or(batch)
+        .or(get_stats)
+        .or(get_digest)
+        .boxed();
+
+    let v2_routes = list_buddies_v2
+        .or(create_buddy_v2)
+        .or(get_buddy_v2)
+        .or(patch_buddy_v2)
+        .or(delete_buddy_v2)
+        .or(list_interactions_v2)
+        .or(create_interaction_v2)
+        .or(get_interaction_v2)
+        .or(patch_interaction_v2)
+        .or(delete_interaction_v2)
+        .boxed();
+
+    let webhook_routes = create_webhook
+        .or(archive_webhook)
+        .or(list_webhooks)
+        .or(list_webhook_deliveries)
+        .boxed();
+
+    let token_routes = create_personal_access_token
+        .or(revoke_personal_access_token)
+        .or(list_personal_access_tokens)
+        .boxed();
+
+    let routes = account_routes
+        .or(buddy_routes)
+        .or(interaction_routes)
+        .or(detail_routes)
+        .or(sync_routes)
+        .or(v2_routes)
+        .or(webhook_routes)
+        .or(token_routes)
+        .or(graphql)
+        .or(events)
+        .or(openapi)
+        .or(docs)
         .recover(handle_custom_rejection) // Added this and still am not getting what i think out of it...
         .with(cors)
         .boxed();
     routes
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::storage::MemoryBuddiesStore;
+    use crate::lib::testing;
+    use crate::lib::types::{CreateUserRequest, User};
+    use serde_json::Value;
+    use std::time::Duration;
+    use warp::hyper::body::Bytes;
+
+    /// The smallest value `schema` accepts, following references into the spec
+    fn example(spec: &Value, schema: &Value) -> Value {
+        if let Some(reference) = schema["$ref"].as_str() {
+            let name = reference.trim_start_matches("#/components/schemas/");
+            return example(spec, &spec["components"]["schemas"][name]);
+        }
+        if let Some(parts) = schema["allOf"].as_array() {
+            let mut merged = json!({});
+            for part in parts {
+                if let Value::Object(fields) = example(spec, part) {
+                    merged.as_object_mut().unwrap().extend(fields);
+                }
+            }
+            return merged;
+        }
+        for key in &["oneOf", "anyOf"] {
+            if let Some(options) = schema[*key].as_array() {
+                return example(spec, &options[0]);
+            }
+        }
+        if let Some(values) = schema["enum"].as_array() {
+            return values[0].clone();
+        }
+        match schema["type"].as_str() {
+            Some("object") => {
+                let mut fields = serde_json::Map::new();
+                for name in schema["required"].as_array().into_iter().flatten() {
+                    let name = name.as_str().unwrap();
+                    fields.insert(name.to_string(), example(spec, &schema["properties"][name]));
+                }
+                Value::Object(fields)
+            }
+            Some("array") => json!([]),
+            Some("string") if schema["format"] == "uuid" => json!(Uuid::new_v4()),
+            Some("string") => json!("example"),
+            Some("integer") => json!(1),
+            Some("number") => json!(1.0),
+            Some("boolean") => json!(false),
+            _ => Value::Null,
+        }
+    }
+
+    /// Call the operation the spec documents at `method` and `path`. Gives back `None` if
+    /// the response is still streaming after a second.
+    async fn call(
+        routes: &BoxedFilter<(impl Reply + 'static,)>,
+        spec: &Value,
+        method: &str,
+        path: &str,
+        user_id: Uuid,
+        token: Option<&str>,
+    ) -> Option<(StatusCode, Bytes)> {
+        let operation = &spec["paths"][path][method];
+        let uri = path
+            .split('/')
+            .map(|segment| match segment {
+                "{user_id}" => user_id.to_string(),
+                "{file_name}" => format!("{}.ics", Uuid::new_v4()),
+                _ if segment.starts_with('{') => Uuid::new_v4().to_string(),
+                _ => segment.to_string(),
+            })
+            .collect::<Vec<String>>()
+            .join("/");
+        let mut request = warp::test::request()
+            .method(&method.to_uppercase())
+            .path(&uri);
+        if let Some(token) = token {
+            request = request.header("authorization", format!("Bearer {}", token));
+        }
+        if let Some(content) = operation["requestBody"]["content"].as_object() {
+            let (content_type, media) = content.iter().next().unwrap();
+            let body = example(spec, &media["schema"]);
+            request = request
+                .header("content-type", content_type.as_str())
+                .body(body.to_string());
+        }
+        let response = tokio::time::timeout(Duration::from_secs(1), request.reply(routes))
+            .await
+            .ok()?;
+        Some((response.status(), response.body().clone()))
+    }
+
+    /// Whether a response came from one of our handlers, rather than warp turning the
+    /// request away because no route matched its path, method or body. GraphQL reports
+    /// its own errors.
+    fn handled((status, body): &(StatusCode, Bytes)) -> bool {
+        status.is_success()
+            || status.is_redirection()
+            || serde_json::from_slice::<Value>(body)
+                .map(|body| body["code"].is_u64() || body["errors"].is_array())
+                .unwrap_or(false)
+    }
+
+    #[tokio::test]
+    async fn every_documented_endpoint_is_routed_with_its_scopes() {
+        let store = MemoryBuddiesStore::new();
+        let routes = testing::routes(&store);
+        let spec = build_spec(&endpoints());
+        let user_id = Uuid::new_v4();
+        for (path, operations) in spec["paths"].as_object().unwrap() {
+            for (method, operation) in operations.as_object().unwrap() {
+                let documented = operation["security"][0]["bearer"].as_array();
+                let scopes = match documented {
+                    Some(scopes) => scopes
+                        .iter()
+                        .map(|scope| serde_json::from_value::<Scope>(scope.clone()).unwrap())
+                        .collect::<Vec<Scope>>(),
+                    None => {
+                        let response = call(&routes, &spec, method, path, user_id, None).await;
+                        assert!(
+                            response.as_ref().is_none_or(handled),
+                            "{} {} isn't routed: {:?}",
+                            method,
+                            path,
+                            response
+                        );
+                        continue;
+                    }
+                };
+
+                let others: Vec<Scope> = Scope::ALL
+                    .iter()
+                    .filter(|scope| !scopes.contains(scope))
+                    .cloned()
+                    .collect();
+                let token = testing::token(&store, user_id, &others);
+                let response = call(&routes, &spec, method, path, user_id, Some(&token)).await;
+                assert!(
+                    matches!(&response, Some((StatusCode::FORBIDDEN, _))),
+                    "{} {} doesn't require {:?}: {:?}",
+                    method,
+                    path,
+                    scopes,
+                    response
+                );
+
+                let token = testing::token(&store, user_id, &scopes);
+                let response = call(&routes, &spec, method, path, user_id, Some(&token)).await;
+                assert!(
+                    response.as_ref().is_none_or(
+                        |response| handled(response) && response.0 != StatusCode::FORBIDDEN
+                    ),
+                    "{} {} isn't routed with just {:?}: {:?}",
+                    method,
+                    path,
+                    scopes,
+                    response
+                );
+            }
+        }
+    }
+
+    /// Send `body` as JSON with `token`, giving back the status and the JSON response
+    async fn send(
+        routes: &BoxedFilter<(impl Reply + 'static,)>,
+        token: &str,
+        method: &str,
+        path: &str,
+        body: Option<Value>,
+    ) -> (StatusCode, Value) {
+        let mut request = warp::test::request()
+            .method(method)
+            .path(path)
+            .header("authorization", format!("Bearer {}", token));
+        if let Some(body) = body {
+            request = request.json(&body);
+        }
+        let response = request.reply(routes).await;
+        let body = serde_json::from_slice(response.body()).unwrap_or(Value::Null);
+        (response.status(), body)
+    }
+
+    #[tokio::test]
+    async fn revoked_personal_access_tokens_are_rejected() {
+        let store = MemoryBuddiesStore::new();
+        let routes = testing::routes(&store);
+        let user_id = Uuid::new_v4();
+        let jwt = testing::token(&store, user_id, Scope::ALL);
+        let (status, created) = send(
+            &routes,
+            &jwt,
+            "POST",
+            "/token/create",
+            Some(json!({ "user_id": user_id, "name": "script", "scopes": ["account"] })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{}", created);
+        let pat = created["token"].as_str().unwrap();
+        let (status, _) = send(&routes, pat, "GET", "/tokens", None).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let id = &created["personal_access_token"]["id"];
+        let (status, _) = send(
+            &routes,
+            &jwt,
+            "POST",
+            "/token/revoke",
+            Some(json!({ "user_id": user_id, "id": id })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, _) = send(&routes, pat, "GET", "/tokens", None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn tokens_cannot_grant_scopes_they_do_not_hold() {
+        let store = MemoryBuddiesStore::new();
+        let routes = testing::routes(&store);
+        let user_id = Uuid::new_v4();
+        let jwt = testing::token(&store, user_id, &[Scope::Account, Scope::BuddiesRead]);
+        let create =
+            |scopes: Value| Some(json!({ "user_id": user_id, "name": "script", "scopes": scopes }));
+
+        let (status, _) = send(
+            &routes,
+            &jwt,
+            "POST",
+            "/token/create",
+            create(json!(["buddies:write"])),
+        )
+        .await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        // Nor can a token made from it, even one that can make tokens
+        let (status, created) = send(
+            &routes,
+            &jwt,
+            "POST",
+            "/token/create",
+            create(json!(["account", "buddies:read"])),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{}", created);
+        let pat = created["token"].as_str().unwrap();
+        let (status, _) = send(
+            &routes,
+            pat,
+            "POST",
+            "/token/create",
+            create(json!(["account", "interactions:read"])),
+        )
+        .await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn request_bodies_cannot_act_for_another_user() {
+        let store = MemoryBuddiesStore::new();
+        let routes = testing::routes(&store);
+        let mut handler = RequestHandler::new(store.clone());
+        let (me, them) = (Uuid::new_v4(), Uuid::new_v4());
+        let their_buddy = handler
+            .create_buddy(CreateBuddyRequest {
+                user_id: them,
+                name: "Theirs".to_string(),
+                ..Default::default()
+            })
+            .unwrap()
+            .buddy
+            .id;
+        let their_interaction = handler
+            .create_interaction(CreateInteractionRequest {
+                user_id: them,
+                notes: "Theirs".to_string(),
+                participants: vec![their_buddy].into_iter().collect(),
+                ..Default::default()
+            })
+            .unwrap()
+            .interaction
+            .id;
+        let token = testing::token(&store, me, Scope::ALL);
+
+        // Creates are made for whoever is signed in
+        let (status, created) = send(
+            &routes,
+            &token,
+            "POST",
+            "/buddy/create",
+            Some(json!({ "user_id": them, "name": "Mine", "notes": "" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{}", created);
+        assert_eq!(created["buddy"]["user_id"], json!(me));
+        let (status, created) = send(
+            &routes,
+            &token,
+            "POST",
+            "/interaction/create",
+            Some(json!({ "user_id": them, "notes": "Mine", "participants": [] })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{}", created);
+        assert_eq!(created["interaction"]["user_id"], json!(me));
+
+        // Their records can't be changed by naming them in the body
+        for (path, body) in [
+            (
+                "/buddy/update",
+                json!({ "user_id": them, "buddy_id": their_buddy, "name": "Changed" }),
+            ),
+            (
+                "/buddy/archive",
+                json!({ "user_id": them, "id": their_buddy }),
+            ),
+            (
+                "/interaction/update",
+                json!({ "user_id": them, "interaction_id": their_interaction, "notes": "Changed" }),
+            ),
+            (
+                "/interaction/archive",
+                json!({ "user_id": them, "id": their_interaction }),
+            ),
+        ] {
+            let (status, response) = send(&routes, &token, "POST", path, Some(body)).await;
+            assert!(!status.is_success(), "{} {}: {}", path, status, response);
+        }
+        let buddy = handler
+            .get_buddy(GetBuddyRequest {
+                user_id: them,
+                buddy_id: their_buddy,
+            })
+            .unwrap();
+        assert_eq!((buddy.name.as_str(), buddy.version), ("Theirs", 1));
+        let interaction = handler
+            .get_interaction(GetInteractionRequest {
+                user_id: them,
+                interaction_id: their_interaction,
+            })
+            .unwrap();
+        assert_eq!(
+            (interaction.notes.as_str(), interaction.version),
+            ("Theirs", 1)
+        );
+
+        let (status, _) = send(&routes, &token, "GET", &format!("/user/{}", them), None).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn interactions_are_only_with_the_users_own_live_buddies() {
+        let store = MemoryBuddiesStore::new();
+        let routes = testing::routes(&store);
+        let mut handler = RequestHandler::new(store.clone());
+        let (me, them) = (Uuid::new_v4(), Uuid::new_v4());
+        let mut buddy = |user_id: Uuid| {
+            handler
+                .create_buddy(CreateBuddyRequest {
+                    user_id,
+                    name: "Buddy".to_string(),
+                    ..Default::default()
+                })
+                .unwrap()
+                .buddy
+                .id
+        };
+        let (mine, archived, theirs) = (buddy(me), buddy(me), buddy(them));
+        handler
+            .archive_buddy(ArchiveBuddyRequest {
+                user_id: me,
+                id: archived,
+                version: None,
+            })
+            .unwrap();
+        let token = testing::token(&store, me, Scope::ALL);
+        let create = |participants: Vec<Uuid>| {
+            Some(json!({ "user_id": me, "notes": "Lunch", "participants": participants }))
+        };
+
+        for participants in [vec![mine, theirs], vec![archived], vec![Uuid::new_v4()]] {
+            let (status, response) = send(
+                &routes,
+                &token,
+                "POST",
+                "/interaction/create",
+                create(participants),
+            )
+            .await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{}", response);
+        }
+        let (status, created) = send(
+            &routes,
+            &token,
+            "POST",
+            "/interaction/create",
+            create(vec![mine]),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{}", created);
+
+        let interaction_id = &created["interaction"]["id"];
+        let (status, response) = send(
+            &routes,
+            &token,
+            "POST",
+            "/interaction/update",
+            Some(json!({
+                "user_id": me,
+                "interaction_id": interaction_id,
+                "participants": [mine, theirs],
+            })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", response);
+        let (status, response) = send(
+            &routes,
+            &token,
+            "POST",
+            "/interaction/update",
+            Some(json!({
+                "user_id": me,
+                "interaction_id": interaction_id,
+                "participants": [mine],
+                "notes": "Long lunch",
+            })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{}", response);
+    }
+
+    #[tokio::test]
+    async fn bad_credentials_are_unauthorized_and_missing_scopes_forbidden() {
+        let store = MemoryBuddiesStore::new();
+        let routes = testing::routes(&store);
+        let user_id = Uuid::new_v4();
+        for authorization in [
+            "Bearer not-a-jwt".to_string(),
+            "Bearer bpat_unknown".to_string(),
+            format!("Token {}", testing::token(&store, user_id, Scope::ALL)),
+        ] {
+            let response = warp::test::request()
+                .method("GET")
+                .path("/v2/buddies")
+                .header("authorization", &authorization)
+                .reply(&routes)
+                .await;
+            assert_eq!(
+                response.status(),
+                StatusCode::UNAUTHORIZED,
+                "{}",
+                authorization
+            );
+        }
+
+        let token = testing::token(&store, user_id, &[Scope::InteractionsRead]);
+        let (status, _) = send(&routes, &token, "GET", "/v2/buddies", None).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let token = testing::token(&store, user_id, &[Scope::BuddiesRead]);
+        let (status, _) = send(&routes, &token, "GET", "/digest", None).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn write_only_tokens_only_get_ids_and_versions_back() {
+        let store = MemoryBuddiesStore::new();
+        let routes = testing::routes(&store);
+        let mut handler = RequestHandler::new(store.clone());
+        let user_id = Uuid::new_v4();
+        let buddy = handler
+            .create_buddy(CreateBuddyRequest {
+                user_id,
+                name: "Buddy".to_string(),
+                ..Default::default()
+            })
+            .unwrap()
+            .buddy;
+        let interaction = handler
+            .create_interaction(CreateInteractionRequest {
+                user_id,
+                notes: "Secret".to_string(),
+                participants: vec![buddy.id].into_iter().collect(),
+                ..Default::default()
+            })
+            .unwrap()
+            .interaction;
+        let write_only = testing::token(&store, user_id, &[Scope::InteractionsWrite]);
+        let shows_notes = |body: &Value| body.to_string().contains("Secret");
+
+        // Creating it again with its id replays the create
+        let (status, body) = send(
+            &routes,
+            &write_only,
+            "POST",
+            "/interaction/create",
+            Some(json!({ "user_id": user_id, "id": interaction.id, "notes": "", "participants": [] })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(body, json!({ "id": interaction.id, "version": 1 }));
+
+        for path in [
+            format!("/interaction/{}", interaction.id),
+            format!("/v2/interactions/{}", interaction.id),
+        ] {
+            let response = warp::test::request()
+                .method("PATCH")
+                .path(&path)
+                .header("authorization", format!("Bearer {}", write_only))
+                .header("content-type", "application/merge-patch+json")
+                .body(json!({ "date": "2021-01-01" }).to_string())
+                .reply(&routes)
+                .await;
+            let body: Value = serde_json::from_slice(response.body()).unwrap();
+            assert_eq!(response.status(), StatusCode::OK, "{}", body);
+            assert!(!shows_notes(&body), "{} gave back {}", path, body);
+            assert_eq!(body["id"], json!(interaction.id));
+
+            let response = warp::test::request()
+                .method("PATCH")
+                .path(&path)
+                .header("authorization", format!("Bearer {}", write_only))
+                .header("if-match", etag(1))
+                .header("content-type", "application/merge-patch+json")
+                .body(json!({ "date": "2021-01-02" }).to_string())
+                .reply(&routes)
+                .await;
+            let body: Value = serde_json::from_slice(response.body()).unwrap();
+            assert_eq!(response.status(), StatusCode::CONFLICT, "{}", body);
+            assert!(!shows_notes(&body), "{} gave back {}", path, body);
+            assert!(response.headers().contains_key("etag"));
+        }
+
+        // Holding the read scope too gets the whole interaction back
+        let read_write = testing::token(
+            &store,
+            user_id,
+            &[Scope::InteractionsRead, Scope::InteractionsWrite],
+        );
+        let (status, body) = send(
+            &routes,
+            &read_write,
+            "POST",
+            "/interaction/create",
+            Some(json!({ "user_id": user_id, "id": interaction.id, "notes": "", "participants": [] })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert!(shows_notes(&body), "{}", body);
+    }
+
+    /// Merge patch `path` with `token`, sending `if_match` as an If-Match if there is one.
+    /// Gives back the status, the ETag and the JSON response.
+    async fn patch(
+        routes: &BoxedFilter<(impl Reply + 'static,)>,
+        token: &str,
+        path: &str,
+        if_match: Option<i32>,
+        body: Value,
+    ) -> (StatusCode, Option<String>, Value) {
+        let mut request = warp::test::request()
+            .method("PATCH")
+            .path(path)
+            .header("authorization", format!("Bearer {}", token))
+            .header("content-type", "application/merge-patch+json")
+            .body(body.to_string());
+        if let Some(version) = if_match {
+            request = request.header("if-match", etag(version));
+        }
+        let response = request.reply(routes).await;
+        let tag = response
+            .headers()
+            .get("etag")
+            .map(|tag| tag.to_str().unwrap().to_string());
+        let body = serde_json::from_slice(response.body()).unwrap_or(Value::Null);
+        (response.status(), tag, body)
+    }
+
+    #[tokio::test]
+    async fn retried_creates_replay_and_reused_keys_are_unprocessable() {
+        let store = MemoryBuddiesStore::new();
+        let routes = testing::routes(&store);
+        let user_id = Uuid::new_v4();
+        let token = testing::token(&store, user_id, &[Scope::BuddiesRead, Scope::BuddiesWrite]);
+        let create = |name: &str| {
+            warp::test::request()
+                .method("POST")
+                .path("/buddy/create")
+                .header("authorization", format!("Bearer {}", token))
+                .header("idempotency-key", "retry-me")
+                .json(&json!({ "user_id": user_id, "name": name, "notes": "" }))
+                .reply(&routes)
+        };
+
+        let first = create("Ada").await;
+        assert_eq!(first.status(), StatusCode::OK);
+        let retry = create("Ada").await;
+        assert_eq!(retry.status(), StatusCode::OK);
+        assert_eq!(retry.body(), first.body());
+        assert_eq!(store.get_buddies(user_id).unwrap().len(), 1);
+
+        let reused = create("Grace").await;
+        assert_eq!(reused.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let buddies = store.get_buddies(user_id).unwrap();
+        assert_eq!(buddies.len(), 1);
+        assert!(buddies.values().all(|buddy| buddy.name == "Ada"));
+    }
+
+    #[tokio::test]
+    async fn unknown_and_replaced_calendar_tokens_are_not_found() {
+        let store = MemoryBuddiesStore::new();
+        let routes = testing::routes(&store);
+        let mut handler = RequestHandler::new(store.clone());
+        let user_id = Uuid::new_v4();
+        let user = User {
+            id: user_id,
+            email: "calendar@example.com".to_string(),
+            ..Default::default()
+        };
+        store
+            .clone()
+            .create_user(CreateUserRequest { user })
+            .unwrap();
+        let calendar = |token: String| {
+            warp::test::request()
+                .method("GET")
+                .path(&format!("/calendar/{}.ics", token))
+                .reply(&routes)
+        };
+
+        let replaced = handler
+            .create_calendar_token(CreateCalendarTokenRequest { user_id })
+            .unwrap()
+            .token;
+        let current = handler
+            .create_calendar_token(CreateCalendarTokenRequest { user_id })
+            .unwrap()
+            .token;
+
+        assert_eq!(calendar(current).await.status(), StatusCode::OK);
+        assert_eq!(calendar(replaced).await.status(), StatusCode::NOT_FOUND);
+        let unknown = Uuid::new_v4().to_simple().to_string();
+        assert_eq!(calendar(unknown).await.status(), StatusCode::NOT_FOUND);
+        let malformed = "not-a-token".to_string();
+        assert_eq!(calendar(malformed).await.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn malformed_contact_methods_are_bad_requests() {
+        let store = MemoryBuddiesStore::new();
+        let routes = testing::routes(&store);
+        let mut handler = RequestHandler::new(store.clone());
+        let user_id = Uuid::new_v4();
+        let buddy = handler
+            .create_buddy(CreateBuddyRequest {
+                user_id,
+                name: "Buddy".to_string(),
+                ..Default::default()
+            })
+            .unwrap()
+            .buddy;
+        let token = testing::token(&store, user_id, &[Scope::BuddiesWrite]);
+        let create = |kind: &str, value: &str| json!({ "user_id": user_id, "buddy_id": buddy.id, "kind": kind, "value": value });
+
+        for body in &[create("email", "not an email"), create("phone", "call me")] {
+            let (status, body) = send(
+                &routes,
+                &token,
+                "POST",
+                "/contact_method/create",
+                Some(body.clone()),
+            )
+            .await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{}", body);
+        }
+        let (status, body) = send(
+            &routes,
+            &token,
+            "POST",
+            "/contact_method/create",
+            Some(create("email", "jo@example.com")),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+    }
+
+    #[tokio::test]
+    async fn important_dates_need_a_real_date_and_a_live_buddy() {
+        let store = MemoryBuddiesStore::new();
+        let routes = testing::routes(&store);
+        let mut handler = RequestHandler::new(store.clone());
+        let (user_id, someone_else) = (Uuid::new_v4(), Uuid::new_v4());
+        let mut create_buddy = |user_id| {
+            handler
+                .create_buddy(CreateBuddyRequest {
+                    user_id,
+                    name: "Buddy".to_string(),
+                    ..Default::default()
+                })
+                .unwrap()
+                .buddy
+        };
+        let (buddy, archived, others) = (
+            create_buddy(user_id),
+            create_buddy(user_id),
+            create_buddy(someone_else),
+        );
+        handler
+            .archive_buddy(ArchiveBuddyRequest {
+                id: archived.id,
+                user_id,
+                version: None,
+            })
+            .unwrap();
+        let token = testing::token(&store, user_id, &[Scope::BuddiesWrite]);
+        let create = |buddy_id: Uuid, date: &str| {
+            let body = json!({
+                "user_id": user_id,
+                "buddy_id": buddy_id,
+                "kind": "anniversary",
+                "date": date,
+            });
+            send(
+                &routes,
+                &token,
+                "POST",
+                "/important_date/create",
+                Some(body),
+            )
+        };
+
+        for date in &["2021-02-30", "02/03/2021", "2021-2-3x"] {
+            let (status, body) = create(buddy.id, date).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{} {}", date, body);
+        }
+        for buddy_id in &[Uuid::new_v4(), archived.id, others.id] {
+            let (status, body) = create(*buddy_id, "2021-02-03").await;
+            assert_eq!(status, StatusCode::NOT_FOUND, "{}", body);
+        }
+        let (status, body) = create(buddy.id, "2021-02-03").await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(store.get_important_dates(user_id).unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn follow_ups_need_a_real_due_date_and_a_live_interaction() {
+        let store = MemoryBuddiesStore::new();
+        let routes = testing::routes(&store);
+        let mut handler = RequestHandler::new(store.clone());
+        let (user_id, someone_else) = (Uuid::new_v4(), Uuid::new_v4());
+        let mut create_interaction = |user_id| {
+            let buddy = handler
+                .create_buddy(CreateBuddyRequest {
+                    user_id,
+                    name: "Buddy".to_string(),
+                    ..Default::default()
+                })
+                .unwrap()
+                .buddy;
+            let interaction = handler
+                .create_interaction(CreateInteractionRequest {
+                    user_id,
+                    notes: "Lunch".to_string(),
+                    participants: vec![buddy.id].into_iter().collect(),
+                    ..Default::default()
+                })
+                .unwrap()
+                .interaction;
+            (buddy, interaction)
+        };
+        let (buddy, interaction) = create_interaction(user_id);
+        let (bystander, archived) = create_interaction(user_id);
+        let (_, others) = create_interaction(someone_else);
+        handler
+            .archive_interaction(ArchiveInteractionRequest {
+                id: archived.id,
+                user_id,
+                version: None,
+            })
+            .unwrap();
+        let token = testing::token(&store, user_id, &[Scope::InteractionsWrite]);
+        let create = |interaction_id: Uuid, buddy_id: Option<Uuid>, due: &str| {
+            let body = json!({
+                "user_id": user_id,
+                "interaction_id": interaction_id,
+                "buddy_id": buddy_id,
+                "notes": "Send the article",
+                "due": due,
+            });
+            send(&routes, &token, "POST", "/follow_up/create", Some(body))
+        };
+
+        for due in &["2021-13-01", "next week"] {
+            let (status, body) = create(interaction.id, None, due).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{} {}", due, body);
+        }
+        let (status, body) = create(interaction.id, Some(bystander.id), "2021-02-03").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", body);
+        for interaction_id in &[Uuid::new_v4(), archived.id, others.id] {
+            let (status, body) = create(*interaction_id, None, "2021-02-03").await;
+            assert_eq!(status, StatusCode::NOT_FOUND, "{}", body);
+        }
+        let (status, body) = create(interaction.id, Some(buddy.id), "2021-02-03").await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(store.get_follow_ups(user_id).unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn ideas_need_a_live_buddy_and_an_interaction_they_were_in() {
+        let store = MemoryBuddiesStore::new();
+        let routes = testing::routes(&store);
+        let mut handler = RequestHandler::new(store.clone());
+        let (user_id, someone_else) = (Uuid::new_v4(), Uuid::new_v4());
+        let mut create_buddy = |user_id| {
+            handler
+                .create_buddy(CreateBuddyRequest {
+                    user_id,
+                    name: "Buddy".to_string(),
+                    ..Default::default()
+                })
+                .unwrap()
+                .buddy
+        };
+        let (buddy, bystander, archived, others) = (
+            create_buddy(user_id),
+            create_buddy(user_id),
+            create_buddy(user_id),
+            create_buddy(someone_else),
+        );
+        let interaction = handler
+            .create_interaction(CreateInteractionRequest {
+                user_id,
+                notes: "Lunch".to_string(),
+                participants: vec![buddy.id].into_iter().collect(),
+                ..Default::default()
+            })
+            .unwrap()
+            .interaction;
+        handler
+            .archive_buddy(ArchiveBuddyRequest {
+                id: archived.id,
+                user_id,
+                version: None,
+            })
+            .unwrap();
+        let token = testing::token(&store, user_id, &[Scope::BuddiesWrite]);
+        let create = |buddy_id: Uuid, interaction_id: Option<Uuid>| {
+            let body = json!({
+                "user_id": user_id,
+                "buddy_id": buddy_id,
+                "kind": "gift",
+                "description": "A book",
+                "interaction_id": interaction_id,
+            });
+            send(&routes, &token, "POST", "/idea/create", Some(body))
+        };
+
+        for buddy_id in &[Uuid::new_v4(), archived.id, others.id] {
+            let (status, body) = create(*buddy_id, None).await;
+            assert_eq!(status, StatusCode::NOT_FOUND, "{}", body);
+        }
+        let (status, body) = create(buddy.id, Some(Uuid::new_v4())).await;
+        assert_eq!(status, StatusCode::NOT_FOUND, "{}", body);
+        let (status, body) = create(bystander.id, Some(interaction.id)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", body);
+        let (status, body) = create(buddy.id, Some(interaction.id)).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(store.get_ideas(user_id).unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn relationships_need_two_different_live_buddies() {
+        let store = MemoryBuddiesStore::new();
+        let routes = testing::routes(&store);
+        let mut handler = RequestHandler::new(store.clone());
+        let (user_id, someone_else) = (Uuid::new_v4(), Uuid::new_v4());
+        let mut create_buddy = |user_id| {
+            handler
+                .create_buddy(CreateBuddyRequest {
+                    user_id,
+                    name: "Buddy".to_string(),
+                    ..Default::default()
+                })
+                .unwrap()
+                .buddy
+        };
+        let (buddy, friend, archived, others) = (
+            create_buddy(user_id),
+            create_buddy(user_id),
+            create_buddy(user_id),
+            create_buddy(someone_else),
+        );
+        handler
+            .archive_buddy(ArchiveBuddyRequest {
+                id: archived.id,
+                user_id,
+                version: None,
+            })
+            .unwrap();
+        let token = testing::token(&store, user_id, &[Scope::BuddiesWrite]);
+        let create = |to_buddy_id: Uuid| {
+            let body = json!({
+                "user_id": user_id,
+                "from_buddy_id": buddy.id,
+                "to_buddy_id": to_buddy_id,
+                "kind": "sibling",
+                "bidirectional": true,
+            });
+            send(&routes, &token, "POST", "/relationship/create", Some(body))
+        };
+
+        let (status, body) = create(buddy.id).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", body);
+        for buddy_id in &[Uuid::new_v4(), archived.id, others.id] {
+            let (status, body) = create(*buddy_id).await;
+            assert_eq!(status, StatusCode::NOT_FOUND, "{}", body);
+        }
+        let (status, body) = create(friend.id).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(store.get_relationships(user_id).unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn invalid_coordinates_are_bad_requests() {
+        let store = MemoryBuddiesStore::new();
+        let routes = testing::routes(&store);
+        let user_id = Uuid::new_v4();
+        let token = testing::token(&store, user_id, &[Scope::BuddiesRead, Scope::BuddiesWrite]);
+
+        let location = json!({ "latitude": 91.0, "longitude": 0.0 });
+        let (status, body) = send(
+            &routes,
+            &token,
+            "POST",
+            "/buddy/create",
+            Some(json!({ "user_id": user_id, "name": "Jo", "notes": "", "location": location })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", body);
+        assert!(store.get_buddies(user_id).unwrap().is_empty());
+
+        for query in &["latitude=91&longitude=0", "latitude=10", "radius_km=5"] {
+            let path = format!("/buddies/near?{}", query);
+            let (status, body) = send(&routes, &token, "GET", &path, None).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{} {}", query, body);
+        }
+    }
+
+    /// Send a DELETE with an If-Match for `version`
+    async fn delete(
+        routes: &BoxedFilter<(impl Reply + 'static,)>,
+        token: &str,
+        path: &str,
+        if_match: i32,
+    ) -> StatusCode {
+        warp::test::request()
+            .method("DELETE")
+            .path(path)
+            .header("authorization", format!("Bearer {}", token))
+            .header("if-match", etag(if_match))
+            .reply(routes)
+            .await
+            .status()
+    }
+
+    #[tokio::test]
+    async fn v2_records_are_only_found_by_their_owner_and_stale_deletes_conflict() {
+        let store = MemoryBuddiesStore::new();
+        let routes = testing::routes(&store);
+        let mut handler = RequestHandler::new(store.clone());
+        let (owner, someone_else) = (Uuid::new_v4(), Uuid::new_v4());
+        let buddy = handler
+            .create_buddy(CreateBuddyRequest {
+                user_id: owner,
+                name: "Buddy".to_string(),
+                ..Default::default()
+            })
+            .unwrap()
+            .buddy;
+        let interaction = handler
+            .create_interaction(CreateInteractionRequest {
+                user_id: owner,
+                notes: "Lunch".to_string(),
+                participants: vec![buddy.id].into_iter().collect(),
+                ..Default::default()
+            })
+            .unwrap()
+            .interaction;
+        let every_scope = [
+            Scope::BuddiesRead,
+            Scope::BuddiesWrite,
+            Scope::InteractionsRead,
+            Scope::InteractionsWrite,
+        ];
+        let owners = testing::token(&store, owner, &every_scope);
+        let others = testing::token(&store, someone_else, &every_scope);
+        let paths = [
+            format!("/v2/buddies/{}", buddy.id),
+            format!("/v2/interactions/{}", interaction.id),
+        ];
+
+        for path in &paths {
+            let (status, body) = send(&routes, &others, "GET", path, None).await;
+            assert_eq!(status, StatusCode::NOT_FOUND, "{} {}", path, body);
+            let (status, _, body) = patch(&routes, &others, path, None, json!({})).await;
+            assert_eq!(status, StatusCode::NOT_FOUND, "{} {}", path, body);
+            assert_eq!(
+                delete(&routes, &others, path, 1).await,
+                StatusCode::NOT_FOUND
+            );
+        }
+
+        // Move both on a version, so deleting the first one is stale
+        for path in &paths {
+            let (status, _, body) =
+                patch(&routes, &owners, path, None, json!({ "notes": "Changed" })).await;
+            assert_eq!(status, StatusCode::OK, "{} {}", path, body);
+        }
+        for path in paths.iter().rev() {
+            assert_eq!(
+                delete(&routes, &owners, path, 1).await,
+                StatusCode::CONFLICT
+            );
+            let (status, body) = send(&routes, &owners, "GET", path, None).await;
+            assert_eq!(status, StatusCode::OK, "{} {}", path, body);
+            assert_eq!(body["version"], 2);
+        }
+        for path in paths.iter().rev() {
+            assert_eq!(
+                delete(&routes, &owners, path, 2).await,
+                StatusCode::NO_CONTENT
+            );
+            assert_eq!(
+                delete(&routes, &owners, path, 3).await,
+                StatusCode::NOT_FOUND
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn unreadable_sync_cursors_are_bad_requests() {
+        let store = MemoryBuddiesStore::new();
+        let routes = testing::routes(&store);
+        let token = testing::token(&store, Uuid::new_v4(), Scope::ALL);
+
+        let (status, body) = send(&routes, &token, "GET", "/sync?since=yesterday", None).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", body);
+        let (status, body) = send(&routes, &token, "GET", "/sync", None).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+    }
+
+    #[tokio::test]
+    async fn reverts_need_a_known_revision_and_the_current_version() {
+        let store = MemoryBuddiesStore::new();
+        let routes = testing::routes(&store);
+        let token = testing::token(&store, Uuid::new_v4(), Scope::ALL);
+        let (status, buddy) = send(
+            &routes,
+            &token,
+            "POST",
+            "/v2/buddies",
+            Some(json!({ "name": "Buddy", "notes": "" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED, "{}", buddy);
+        let path = format!("/v2/buddies/{}", buddy["id"].as_str().unwrap());
+        let (status, _, patched) =
+            patch(&routes, &token, &path, None, json!({ "notes": "New" })).await;
+        assert_eq!(status, StatusCode::OK, "{}", patched);
+        let path = format!("/buddy/{}/history", buddy["id"].as_str().unwrap());
+        let (status, history) = send(&routes, &token, "GET", &path, None).await;
+        assert_eq!(status, StatusCode::OK, "{}", history);
+        let created = &history["revisions"][0]["id"];
+
+        for path in &[
+            format!("/buddy/{}/history", Uuid::new_v4()),
+            format!("/interaction/{}/history", Uuid::new_v4()),
+        ] {
+            let (status, body) = send(&routes, &token, "GET", path, None).await;
+            assert_eq!(status, StatusCode::NOT_FOUND, "{} {}", path, body);
+        }
+        let revert = |revision_id: &Value| json!({ "user_id": Uuid::nil(), "buddy_id": buddy["id"], "revision_id": revision_id });
+        let (status, body) = send(
+            &routes,
+            &token,
+            "POST",
+            "/buddy/revert",
+            Some(revert(&json!(Uuid::new_v4()))),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND, "{}", body);
+        let (status, body) = send(
+            &routes,
+            &token,
+            "POST",
+            "/interaction/revert",
+            Some(json!({
+                "user_id": Uuid::nil(),
+                "interaction_id": Uuid::new_v4(),
+                "revision_id": Uuid::new_v4(),
+            })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND, "{}", body);
+
+        // Reverting a version someone has since changed would quietly undo their edit
+        let mut stale = revert(created);
+        stale["version"] = json!(1);
+        let (status, conflict) = send(&routes, &token, "POST", "/buddy/revert", Some(stale)).await;
+        assert_eq!(status, StatusCode::CONFLICT, "{}", conflict);
+        assert_eq!(conflict["current"]["notes"], json!("New"));
+        let response = warp::test::request()
+            .method("POST")
+            .path("/buddy/revert")
+            .header("authorization", format!("Bearer {}", token))
+            .header("if-match", etag(1))
+            .json(&revert(created))
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+
+        let mut fresh = revert(created);
+        fresh["version"] = json!(2);
+        let (status, reverted) = send(&routes, &token, "POST", "/buddy/revert", Some(fresh)).await;
+        assert_eq!(status, StatusCode::OK, "{}", reverted);
+        assert_eq!(reverted["buddy"]["notes"], json!(""));
+        assert_eq!(reverted["buddy"]["version"], json!(3));
+    }
+
+    #[tokio::test]
+    async fn webhooks_need_a_public_http_url() {
+        let store = MemoryBuddiesStore::new();
+        let routes = testing::routes(&store);
+        let user_id = Uuid::new_v4();
+        let token = testing::token(&store, user_id, &[Scope::Account]);
+
+        for url in &[
+            "not a url",
+            "ftp://example.com/hook",
+            "http://localhost/hook",
+            "http://10.0.0.1/hook",
+        ] {
+            let webhook = json!({ "user_id": user_id, "url": url });
+            let (status, body) =
+                send(&routes, &token, "POST", "/webhook/create", Some(webhook)).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{} {}", url, body);
+        }
+        assert!(store.get_webhooks(user_id).unwrap().is_empty());
+        let webhook = json!({ "user_id": user_id, "url": "https://example.com/hook" });
+        let (status, body) = send(&routes, &token, "POST", "/webhook/create", Some(webhook)).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+    }
+
+    #[tokio::test]
+    async fn tokens_need_a_name_and_a_scope() {
+        let store = MemoryBuddiesStore::new();
+        let routes = testing::routes(&store);
+        let user_id = Uuid::new_v4();
+        let jwt = testing::token(&store, user_id, Scope::ALL);
+
+        for (name, scopes) in &[(" ", json!(["account"])), ("script", json!([]))] {
+            let token = json!({ "user_id": user_id, "name": name, "scopes": scopes });
+            let (status, body) = send(&routes, &jwt, "POST", "/token/create", Some(token)).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{} {}", scopes, body);
+        }
+        let (status, listed) = send(&routes, &jwt, "GET", "/tokens", None).await;
+        assert_eq!(status, StatusCode::OK, "{}", listed);
+        assert_eq!(listed["personal_access_tokens"], json!([]));
+    }
+
+    #[tokio::test]
+    async fn merges_need_two_different_live_buddies() {
+        let store = MemoryBuddiesStore::new();
+        let routes = testing::routes(&store);
+        let token = testing::token(&store, Uuid::new_v4(), Scope::ALL);
+        let mut ids = Vec::new();
+        for name in &["Jo", "Joe", "Archived"] {
+            let buddy = json!({ "name": name, "notes": "" });
+            let (status, buddy) = send(&routes, &token, "POST", "/v2/buddies", Some(buddy)).await;
+            assert_eq!(status, StatusCode::CREATED, "{}", buddy);
+            ids.push(buddy["id"].clone());
+        }
+        let archive = json!({ "user_id": Uuid::nil(), "id": ids[2] });
+        let (status, body) = send(&routes, &token, "POST", "/buddy/archive", Some(archive)).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        let merge = |winner_id: &Value, loser_id: &Value| {
+            Some(json!({ "user_id": Uuid::nil(), "winner_id": winner_id, "loser_id": loser_id }))
+        };
+
+        let (status, body) = send(
+            &routes,
+            &token,
+            "POST",
+            "/buddy/merge",
+            merge(&ids[0], &ids[0]),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", body);
+        let unknown = json!(Uuid::new_v4());
+        for (winner_id, loser_id) in &[(&ids[0], &ids[2]), (&ids[2], &ids[0]), (&ids[0], &unknown)]
+        {
+            let (status, body) = send(
+                &routes,
+                &token,
+                "POST",
+                "/buddy/merge",
+                merge(winner_id, loser_id),
+            )
+            .await;
+            assert_eq!(
+                status,
+                StatusCode::NOT_FOUND,
+                "{} {} {}",
+                winner_id,
+                loser_id,
+                body
+            );
+        }
+        let (status, body) = send(
+            &routes,
+            &token,
+            "POST",
+            "/buddy/merge",
+            merge(&ids[0], &ids[1]),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+    }
+
+    #[tokio::test]
+    async fn vcards_of_unknown_buddies_are_not_found() {
+        let store = MemoryBuddiesStore::new();
+        let routes = testing::routes(&store);
+        let token = testing::token(&store, Uuid::new_v4(), &[Scope::BuddiesRead]);
+
+        let path = format!("/buddy/{}/vcard", Uuid::new_v4());
+        let (status, body) = send(&routes, &token, "GET", &path, None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND, "{}", body);
+    }
+
+    #[tokio::test]
+    async fn stats_windows_need_at_least_a_day() {
+        let store = MemoryBuddiesStore::new();
+        let routes = testing::routes(&store);
+        let token = testing::token(&store, Uuid::new_v4(), Scope::ALL);
+
+        let (status, body) = send(&routes, &token, "GET", "/stats?window_days=0", None).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", body);
+        let (status, body) = send(&routes, &token, "GET", "/stats?window_days=1", None).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+    }
+
+    #[tokio::test]
+    async fn digests_and_settings_of_unknown_users_are_not_found() {
+        let store = MemoryBuddiesStore::new();
+        let routes = testing::routes(&store);
+        let user_id = Uuid::new_v4();
+        let token = testing::token(&store, user_id, Scope::ALL);
+        let update = json!({ "user_id": user_id, "digest_frequency": "weekly" });
+
+        let (status, body) = send(&routes, &token, "GET", "/digest", None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND, "{}", body);
+        let (status, body) = send(
+            &routes,
+            &token,
+            "POST",
+            "/user/update",
+            Some(update.clone()),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND, "{}", body);
+
+        let user = User {
+            id: user_id,
+            email: "digest@example.com".to_string(),
+            ..Default::default()
+        };
+        store
+            .clone()
+            .create_user(CreateUserRequest { user })
+            .unwrap();
+        let (status, body) = send(&routes, &token, "GET", "/digest", None).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        let (status, body) = send(&routes, &token, "POST", "/user/update", Some(update)).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+    }
+
+    /// Writes based on an old version, given in the body or an If-Match, conflict and carry
+    /// the record as it stands. Writes that don't say which version they saw go through.
+    async fn check_stale_writes_conflict<S: BuddiesStore + AuthStore>(store: S) {
+        let routes = testing::routes(&store);
+        let user_id = Uuid::new_v4();
+        let token = testing::token(&store, user_id, Scope::ALL);
+        let (status, buddy) = send(
+            &routes,
+            &token,
+            "POST",
+            "/v2/buddies",
+            Some(json!({ "name": "Buddy", "notes": "" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED, "{}", buddy);
+        let update = |version: i32| {
+            Some(json!({
+                "user_id": user_id,
+                "buddy_id": buddy["id"],
+                "name": format!("Seen {}", version),
+                "version": version,
+            }))
+        };
+        let (status, updated) = send(&routes, &token, "POST", "/buddy/update", update(1)).await;
+        assert_eq!(status, StatusCode::OK, "{}", updated);
+        assert_eq!(updated["version"], json!(2));
+
+        let (status, conflict) = send(&routes, &token, "POST", "/buddy/update", update(1)).await;
+        assert_eq!(status, StatusCode::CONFLICT, "{}", conflict);
+        assert_eq!(conflict["version"], json!(2));
+        assert_eq!(conflict["current"]["name"], json!("Seen 1"));
+
+        let path = format!("/v2/buddies/{}", buddy["id"].as_str().unwrap());
+        let (status, tag, conflict) =
+            patch(&routes, &token, &path, Some(1), json!({ "notes": "Stale" })).await;
+        assert_eq!(status, StatusCode::CONFLICT, "{}", conflict);
+        assert_eq!(tag, Some(etag(2)));
+        assert_eq!(conflict["current"]["notes"], json!(""));
+        let (status, tag, patched) =
+            patch(&routes, &token, &path, None, json!({ "notes": "Fresh" })).await;
+        assert_eq!(status, StatusCode::OK, "{}", patched);
+        assert_eq!(tag, Some(etag(3)));
+        assert_eq!(patched["notes"], json!("Fresh"));
+
+        let (status, interaction) = send(
+            &routes,
+            &token,
+            "POST",
+            "/v2/interactions",
+            Some(json!({ "notes": "Lunch", "participants": [buddy["id"]] })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED, "{}", interaction);
+        let path = format!("/v2/interactions/{}", interaction["id"].as_str().unwrap());
+        let (status, tag, patched) = patch(
+            &routes,
+            &token,
+            &path,
+            Some(1),
+            json!({ "notes": "Dinner" }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{}", patched);
+        assert_eq!(tag, Some(etag(2)));
+        let (status, tag, conflict) =
+            patch(&routes, &token, &path, Some(1), json!({ "notes": "Stale" })).await;
+        assert_eq!(status, StatusCode::CONFLICT, "{}", conflict);
+        assert_eq!(tag, Some(etag(2)));
+        assert_eq!(conflict["current"]["notes"], json!("Dinner"));
+    }
+
+    #[tokio::test]
+    async fn null_clears_fields_and_left_out_keys_are_kept() {
+        let store = MemoryBuddiesStore::new();
+        let routes = testing::routes(&store);
+        let user_id = Uuid::new_v4();
+        let token = testing::token(&store, user_id, Scope::ALL);
+        let (status, buddy) = send(
+            &routes,
+            &token,
+            "POST",
+            "/v2/buddies",
+            Some(json!({ "name": "Buddy", "notes": "", "birthday": "1990-01-01", "cadence": { "secs": 86400, "nanos": 0 } })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED, "{}", buddy);
+        let path = format!("/v2/buddies/{}", buddy["id"].as_str().unwrap());
+
+        let (status, _, patched) =
+            patch(&routes, &token, &path, None, json!({ "birthday": null })).await;
+        assert_eq!(status, StatusCode::OK, "{}", patched);
+        assert_eq!(patched["birthday"], Value::Null);
+        assert_eq!(patched["cadence"], buddy["cadence"]);
+        assert_eq!(patched["name"], json!("Buddy"));
+
+        let (status, updated) = send(
+            &routes,
+            &token,
+            "POST",
+            "/buddy/update",
+            Some(json!({ "user_id": user_id, "buddy_id": buddy["id"], "cadence": null })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{}", updated);
+        let (_, current) = send(&routes, &token, "GET", &path, None).await;
+        assert_eq!(current["cadence"], Value::Null);
+        assert_eq!(current["name"], json!("Buddy"));
+
+        for body in [json!(["name"]), json!("Renamed"), json!({ "name": null })] {
+            let (status, _, response) = patch(&routes, &token, &path, None, body.clone()).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{}: {}", body, response);
+        }
+    }
+
+    #[tokio::test]
+    async fn stale_writes_conflict() {
+        check_stale_writes_conflict(MemoryBuddiesStore::new()).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn stale_writes_conflict_on_psql() {
+        check_stale_writes_conflict(testing::psql_store()).await;
+    }
+}
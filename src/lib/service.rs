@@ -1,37 +1,140 @@
+use crate::lib::analytics::{compute_stats, fill_suggested_cadences};
+use crate::lib::calendar::build_calendar;
+use crate::lib::digest::{
+    build_digest, follow_up_days_overdue, interaction_sort_key, render_html, render_text,
+    upcoming_dates,
+};
+use crate::lib::duplicates::{find_duplicates, merge_notes};
+use crate::lib::events::Events;
+use crate::lib::geo::{fill_local_times, find_near, local_time, validate_location};
+use crate::lib::graph::build_neighborhood;
+use crate::lib::patch::patched_fields;
 use crate::lib::storage::{AuthStore, BuddiesStore};
 use crate::lib::types::{
-    ArchiveBuddyRequest, ArchiveBuddyResponse, ArchiveInteractionRequest,
-    ArchiveInteractionResponse, AuthenticationRequest, AuthenticationResponse, Buddy,
-    CreateBuddyRequest, CreateBuddyResponse, CreateInteractionRequest, CreateInteractionResponse,
-    CreateUserRequest, Datestamp, GetUserDataRequest, GetUserDataResponse, Interaction,
-    LoginRequest, LoginResponse, PublicUser, SignUpRequest, SignUpResponse, Timestamp,
-    UpdateBuddyRequest, UpdateBuddyResponse, UpdateInteractionRequest, UpdateInteractionResponse,
-    User,
+    ApplySuggestedCadenceRequest, ApplySuggestedCadenceResponse, ArchiveBuddyRequest,
+    ArchiveBuddyResponse, ArchiveContactMethodRequest, ArchiveContactMethodResponse,
+    ArchiveIdeaRequest, ArchiveIdeaResponse, ArchiveImportantDateRequest,
+    ArchiveImportantDateResponse, ArchiveInteractionRequest, ArchiveInteractionResponse,
+    ArchiveRelationshipRequest, ArchiveRelationshipResponse, ArchiveWebhookRequest,
+    ArchiveWebhookResponse, AuthenticationRequest, AuthenticationResponse, BatchRequest,
+    BatchResponse, BatchResult, BatchStatus, Buddy, ChangeEvent, CompleteFollowUpRequest,
+    CompleteFollowUpResponse, ContactMethod, ContactMethodKind, CreateBuddyRequest,
+    CreateBuddyResponse, CreateCalendarTokenRequest, CreateCalendarTokenResponse,
+    CreateContactMethodRequest, CreateContactMethodResponse, CreateFollowUpRequest,
+    CreateFollowUpResponse, CreateIdeaRequest, CreateIdeaResponse, CreateImportantDateRequest,
+    CreateImportantDateResponse, CreateInteractionRequest, CreateInteractionResponse,
+    CreatePersonalAccessTokenRequest, CreatePersonalAccessTokenResponse, CreateRelationshipRequest,
+    CreateRelationshipResponse, CreateUserRequest, CreateWebhookRequest, CreateWebhookResponse,
+    CurrentRecord, Datestamp, DeliveryStatus, DigestFrequency, EntityKind, ExportVCardRequest,
+    ExportVCardResponse, FindBuddiesNearRequest, FindBuddiesNearResponse, FollowUp,
+    GetBuddyGraphRequest, GetBuddyGraphResponse, GetBuddyRequest, GetCalendarRequest,
+    GetCalendarResponse, GetChangesRequest, GetChangesResponse, GetDigestRequest,
+    GetDigestResponse, GetDuplicatesRequest, GetDuplicatesResponse, GetHistoryRequest,
+    GetHistoryResponse, GetInteractionRequest, GetStatsRequest, GetStatsResponse,
+    GetUpcomingDatesRequest, GetUpcomingDatesResponse, GetUserDataRequest, GetUserDataResponse,
+    GetUserRequest, Idea, IdempotencyKeyReusedError, IdempotencyRecord, ImportantDate,
+    ImportantDateKind, Interaction, InteractionFilter, InvalidRequestError, ListBuddiesRequest,
+    ListBuddiesResponse, ListFollowUpsRequest, ListFollowUpsResponse, ListInteractionsRequest,
+    ListInteractionsResponse, ListPersonalAccessTokensRequest, ListPersonalAccessTokensResponse,
+    ListWebhookDeliveriesRequest, ListWebhookDeliveriesResponse, ListWebhooksRequest,
+    ListWebhooksResponse, LoginRequest, LoginResponse, MergeBuddiesRequest, MergeBuddiesResponse,
+    Mutation, NotFoundError, PatchBuddyRequest, PatchBuddyResponse, PatchInteractionRequest,
+    PatchInteractionResponse, PersonalAccessToken, PublicUser, Relationship, RevertBuddyRequest,
+    RevertBuddyResponse, RevertInteractionRequest, RevertInteractionResponse, Revision,
+    RevisionAction, RevokePersonalAccessTokenRequest, RevokePersonalAccessTokenResponse, Scope,
+    SignUpRequest, SignUpResponse, StaleWriteError, SyncRequest, SyncResponse, SyncResult,
+    SyncStatus, Timestamp, Tombstone, UnauthorizedError, UpdateBuddyRequest, UpdateBuddyResponse,
+    UpdateContactMethodRequest, UpdateContactMethodResponse, UpdateIdeaRequest, UpdateIdeaResponse,
+    UpdateImportantDateRequest, UpdateImportantDateResponse, UpdateInteractionRequest,
+    UpdateInteractionResponse, UpdateRelationshipRequest, UpdateRelationshipResponse,
+    UpdateUserRequest, UpdateUserResponse, User, Webhook, WebhookDelivery,
 };
+use crate::lib::vcard::buddy_to_vcard;
+use crate::lib::webhooks::check_webhook_url;
 use anyhow::{anyhow, Context, Result};
 use bcrypt::{hash, verify, DEFAULT_COST};
-use chrono::{Duration, Local, NaiveDateTime};
+use chrono::{Duration, Local, NaiveDate, NaiveDateTime, Utc};
 use jsonwebtoken::{decode, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
 use std::convert::TryInto;
 use std::time::SystemTime;
+use url::Url;
 use uuid::Uuid;
 
 // y'know why not
 const JWT_EXPIRATION_HOURS: i64 = 72;
+const DEFAULT_STATS_WINDOW_DAYS: i64 = 365;
+/// How far ahead to look for upcoming dates when the caller doesn't say
+const DEFAULT_UPCOMING_DAYS: i64 = 30;
+/// How long we remember idempotency keys for
+const IDEMPOTENCY_WINDOW_SECS: u64 = 24 * 60 * 60;
+/// Buddy fields a merge patch can touch, and the ones it can't remove
+const PATCHABLE_BUDDY_FIELDS: &[&str] = &[
+    "name",
+    "notes",
+    "last_contacted",
+    "location",
+    "birthday",
+    "cadence",
+];
+const REQUIRED_BUDDY_FIELDS: &[&str] = &["name", "notes", "last_contacted"];
+/// Interaction fields a merge patch can touch, and the ones it can't remove
+const PATCHABLE_INTERACTION_FIELDS: &[&str] = &[
+    "notes",
+    "participants",
+    "date",
+    "kind",
+    "duration",
+    "location",
+    "initiator",
+];
+const REQUIRED_INTERACTION_FIELDS: &[&str] = &["notes", "participants"];
+/// Marks a bearer token as a personal access token rather than a JWT
+const PERSONAL_ACCESS_TOKEN_PREFIX: &str = "bpat_";
 
 pub trait AuthService: Send + Sync + Clone + 'static {
     fn login(&self, request: LoginRequest) -> Result<LoginResponse>;
     fn sign_up(&mut self, request: SignUpRequest) -> Result<SignUpResponse>;
     fn authenticate(&self, request: AuthenticationRequest) -> Result<AuthenticationResponse>;
+    // Personal access tokens
+    fn create_personal_access_token(
+        &mut self,
+        request: CreatePersonalAccessTokenRequest,
+    ) -> Result<CreatePersonalAccessTokenResponse>;
+    fn list_personal_access_tokens(
+        &self,
+        request: ListPersonalAccessTokensRequest,
+    ) -> Result<ListPersonalAccessTokensResponse>;
+    fn revoke_personal_access_token(
+        &mut self,
+        request: RevokePersonalAccessTokenRequest,
+    ) -> Result<RevokePersonalAccessTokenResponse>;
 }
 
 pub trait BuddiesService: Send + Sync + Clone + 'static {
     // Buddy CRUD
     fn create_buddy(&mut self, request: CreateBuddyRequest) -> Result<CreateBuddyResponse>;
     fn update_buddy(&mut self, request: UpdateBuddyRequest) -> Result<UpdateBuddyResponse>;
+    fn apply_suggested_cadence(
+        &mut self,
+        request: ApplySuggestedCadenceRequest,
+    ) -> Result<ApplySuggestedCadenceResponse>;
     fn archive_buddy(&mut self, request: ArchiveBuddyRequest) -> Result<ArchiveBuddyResponse>;
+    fn patch_buddy(&mut self, request: PatchBuddyRequest) -> Result<PatchBuddyResponse>;
+    fn get_buddy_history(&self, request: GetHistoryRequest) -> Result<GetHistoryResponse>;
+    fn revert_buddy(&mut self, request: RevertBuddyRequest) -> Result<RevertBuddyResponse>;
     fn get_user_data(&self, request: GetUserDataRequest) -> Result<GetUserDataResponse>;
+    fn list_buddies(&self, request: ListBuddiesRequest) -> Result<ListBuddiesResponse>;
+    fn get_buddy(&self, request: GetBuddyRequest) -> Result<Buddy>;
+
+    // Offline sync
+    fn get_changes(&self, request: GetChangesRequest) -> Result<GetChangesResponse>;
+    fn sync(&mut self, request: SyncRequest) -> Result<SyncResponse>;
+    fn batch(&mut self, request: BatchRequest) -> Result<BatchResponse>;
 
     // Interaction CRUD
     fn create_interaction(
@@ -46,17 +149,354 @@ pub trait BuddiesService: Send + Sync + Clone + 'static {
         &mut self,
         request: ArchiveInteractionRequest,
     ) -> Result<ArchiveInteractionResponse>;
+    fn patch_interaction(
+        &mut self,
+        request: PatchInteractionRequest,
+    ) -> Result<PatchInteractionResponse>;
+    fn get_interaction(&self, request: GetInteractionRequest) -> Result<Interaction>;
+    fn get_interaction_history(&self, request: GetHistoryRequest) -> Result<GetHistoryResponse>;
+    fn revert_interaction(
+        &mut self,
+        request: RevertInteractionRequest,
+    ) -> Result<RevertInteractionResponse>;
+    fn list_interactions(
+        &self,
+        request: ListInteractionsRequest,
+    ) -> Result<ListInteractionsResponse>;
+
+    // Relationship CRUD
+    fn create_relationship(
+        &mut self,
+        request: CreateRelationshipRequest,
+    ) -> Result<CreateRelationshipResponse>;
+    fn update_relationship(
+        &mut self,
+        request: UpdateRelationshipRequest,
+    ) -> Result<UpdateRelationshipResponse>;
+    fn archive_relationship(
+        &mut self,
+        request: ArchiveRelationshipRequest,
+    ) -> Result<ArchiveRelationshipResponse>;
+    fn get_buddy_graph(&self, request: GetBuddyGraphRequest) -> Result<GetBuddyGraphResponse>;
+    fn get_duplicates(&self, request: GetDuplicatesRequest) -> Result<GetDuplicatesResponse>;
+    fn merge_buddies(&mut self, request: MergeBuddiesRequest) -> Result<MergeBuddiesResponse>;
+    fn find_buddies_near(&self, request: FindBuddiesNearRequest)
+        -> Result<FindBuddiesNearResponse>;
+
+    // Contact method CRUD
+    fn create_contact_method(
+        &mut self,
+        request: CreateContactMethodRequest,
+    ) -> Result<CreateContactMethodResponse>;
+    fn update_contact_method(
+        &mut self,
+        request: UpdateContactMethodRequest,
+    ) -> Result<UpdateContactMethodResponse>;
+    fn archive_contact_method(
+        &mut self,
+        request: ArchiveContactMethodRequest,
+    ) -> Result<ArchiveContactMethodResponse>;
+    fn export_vcard(&self, request: ExportVCardRequest) -> Result<ExportVCardResponse>;
+
+    // Important date CRUD
+    fn create_important_date(
+        &mut self,
+        request: CreateImportantDateRequest,
+    ) -> Result<CreateImportantDateResponse>;
+    fn update_important_date(
+        &mut self,
+        request: UpdateImportantDateRequest,
+    ) -> Result<UpdateImportantDateResponse>;
+    fn archive_important_date(
+        &mut self,
+        request: ArchiveImportantDateRequest,
+    ) -> Result<ArchiveImportantDateResponse>;
+    fn get_upcoming_dates(
+        &self,
+        request: GetUpcomingDatesRequest,
+    ) -> Result<GetUpcomingDatesResponse>;
+
+    // Follow ups
+    fn create_follow_up(
+        &mut self,
+        request: CreateFollowUpRequest,
+    ) -> Result<CreateFollowUpResponse>;
+    fn complete_follow_up(
+        &mut self,
+        request: CompleteFollowUpRequest,
+    ) -> Result<CompleteFollowUpResponse>;
+    fn list_follow_ups(&self, request: ListFollowUpsRequest) -> Result<ListFollowUpsResponse>;
+
+    // Idea CRUD
+    fn create_idea(&mut self, request: CreateIdeaRequest) -> Result<CreateIdeaResponse>;
+    fn update_idea(&mut self, request: UpdateIdeaRequest) -> Result<UpdateIdeaResponse>;
+    fn archive_idea(&mut self, request: ArchiveIdeaRequest) -> Result<ArchiveIdeaResponse>;
+
+    // User settings
+    fn get_user(&self, request: GetUserRequest) -> Result<PublicUser>;
+    fn update_user(&mut self, request: UpdateUserRequest) -> Result<UpdateUserResponse>;
+
+    // Analytics
+    fn get_stats(&self, request: GetStatsRequest) -> Result<GetStatsResponse>;
+
+    // Digest
+    fn get_digest(&self, request: GetDigestRequest) -> Result<GetDigestResponse>;
+
+    // Calendar feed
+    fn create_calendar_token(
+        &mut self,
+        request: CreateCalendarTokenRequest,
+    ) -> Result<CreateCalendarTokenResponse>;
+    fn get_calendar(&self, request: GetCalendarRequest) -> Result<GetCalendarResponse>;
+
+    // Webhooks
+    fn create_webhook(&mut self, request: CreateWebhookRequest) -> Result<CreateWebhookResponse>;
+    fn list_webhooks(&self, request: ListWebhooksRequest) -> Result<ListWebhooksResponse>;
+    fn archive_webhook(&mut self, request: ArchiveWebhookRequest)
+        -> Result<ArchiveWebhookResponse>;
+    fn list_webhook_deliveries(
+        &self,
+        request: ListWebhookDeliveriesRequest,
+    ) -> Result<ListWebhookDeliveriesResponse>;
 }
 
 #[derive(Clone)]
 pub struct RequestHandler<S> {
     pub storage: S,
+    pub events: Events,
 }
 
 impl<S: BuddiesStore> RequestHandler<S> {
     pub fn new(storage: S) -> RequestHandler<S> {
-        RequestHandler { storage }
+        RequestHandler {
+            storage,
+            events: Events::new(),
+        }
+    }
+}
+
+/// The initiator of an interaction must be the user or one of the participants
+fn validate_initiator(
+    user_id: Uuid,
+    participants: &HashSet<Uuid>,
+    initiator: Option<Uuid>,
+) -> Result<()> {
+    match initiator {
+        Some(initiator) if initiator != user_id && !participants.contains(&initiator) => {
+            Err(InvalidRequestError {
+                message: format!(
+                    "Initiator {} is not a participant of the interaction",
+                    initiator
+                ),
+            }
+            .into())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Check that a contact method's value makes sense for its kind, returning it
+/// in the form we store it in
+fn validate_contact_value(kind: ContactMethodKind, value: &str) -> Result<String> {
+    let invalid = |message: String| -> anyhow::Error { InvalidRequestError { message }.into() };
+    let value = value.trim();
+    match kind {
+        ContactMethodKind::Phone => {
+            // People like to write numbers with separators, E.164 doesn't
+            let phone: String = value
+                .chars()
+                .filter(|c| !matches!(c, ' ' | '-' | '.' | '(' | ')'))
+                .collect();
+            let digits = phone.strip_prefix('+').unwrap_or("");
+            if digits.is_empty()
+                || digits.len() > 15
+                || digits.starts_with('0')
+                || !digits.chars().all(|c| c.is_ascii_digit())
+            {
+                return Err(invalid(format!("{} is not an E.164 phone number", value)));
+            }
+            Ok(phone)
+        }
+        ContactMethodKind::Email => {
+            let (local, domain) = match value.split_once('@') {
+                Some(parts) => parts,
+                None => return Err(invalid(format!("{} is not an email address", value))),
+            };
+            if local.is_empty()
+                || domain.is_empty()
+                || domain.contains('@')
+                || !domain.contains('.')
+                || domain.starts_with('.')
+                || domain.ends_with('.')
+                || value.chars().any(char::is_whitespace)
+            {
+                return Err(invalid(format!("{} is not an email address", value)));
+            }
+            Ok(value.to_string())
+        }
+        ContactMethodKind::Url => {
+            let url = Url::parse(value).map_err(|_| invalid(format!("{} is not a url", value)))?;
+            if !matches!(url.scheme(), "http" | "https") {
+                return Err(invalid(format!("{} is not a web url", value)));
+            }
+            Ok(url.to_string())
+        }
+        ContactMethodKind::Social => {
+            if value.is_empty() {
+                return Err(invalid("Social handles can't be empty".to_string()));
+            }
+            Ok(value.to_string())
+        }
+    }
+}
+
+/// A date sent in a request, which has to be a real yyyy-mm-dd date
+fn requested_date(date: &Datestamp) -> Result<NaiveDate> {
+    date.to_naive_date().map_err(|_| {
+        InvalidRequestError {
+            message: format!("{} is not a yyyy-mm-dd date", date.0),
+        }
+        .into()
+    })
+}
+
+/// Custom dates need a label to say what they are, and every date needs to be a real date
+fn validate_important_date(
+    kind: ImportantDateKind,
+    label: &Option<String>,
+    date: &Datestamp,
+) -> Result<()> {
+    requested_date(date)?;
+    if kind == ImportantDateKind::Custom && label.as_deref().unwrap_or("").is_empty() {
+        return Err(InvalidRequestError {
+            message: "Custom important dates need a label".to_string(),
+        }
+        .into());
+    }
+    Ok(())
+}
+
+/// Hang each contact method off of the buddy it belongs to
+fn attach_contact_methods(
+    buddies: &mut HashMap<Uuid, Buddy>,
+    contact_methods: HashMap<Uuid, ContactMethod>,
+) {
+    for contact_method in contact_methods.into_values() {
+        if let Some(buddy) = buddies.get_mut(&contact_method.buddy_id) {
+            buddy.contact_methods.push(contact_method);
+        }
+    }
+    for buddy in buddies.values_mut() {
+        buddy
+            .contact_methods
+            .sort_by_key(|method| method.create_timestamp.0);
+    }
+}
+
+fn build_revision<T: Serialize>(
+    user_id: Uuid,
+    entity_kind: EntityKind,
+    entity_id: Uuid,
+    action: RevisionAction,
+    before: Option<&T>,
+    after: Option<&T>,
+) -> Result<Revision> {
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)?
+        .as_secs();
+    Ok(Revision {
+        id: Uuid::new_v4(),
+        entity_kind,
+        entity_id,
+        action,
+        before: before
+            .map(serde_json::to_value)
+            .transpose()
+            .context("Snapshotting record before change")?,
+        after: after
+            .map(serde_json::to_value)
+            .transpose()
+            .context("Snapshotting record after change")?,
+        reverted_to: None,
+        create_timestamp: Timestamp(now),
+        user_id,
+    })
+}
+
+fn sync_result(id: Uuid, outcome: Result<i32>) -> SyncResult {
+    match outcome {
+        Ok(version) => SyncResult {
+            id,
+            status: SyncStatus::Applied,
+            version: Some(version),
+            current: None,
+            message: None,
+        },
+        Err(e) => match e.downcast_ref::<StaleWriteError>() {
+            Some(stale) => SyncResult {
+                id,
+                status: SyncStatus::Conflict,
+                version: Some(stale.current.version()),
+                current: Some(stale.current.clone()),
+                message: None,
+            },
+            None => SyncResult {
+                id,
+                status: SyncStatus::Rejected,
+                version: None,
+                current: None,
+                message: Some(format!("{:?}", e)),
+            },
+        },
+    }
+}
+
+fn batch_result(index: usize, id: Uuid, outcome: Result<i32>) -> BatchResult {
+    let result = sync_result(id, outcome);
+    BatchResult {
+        index,
+        id: Some(result.id),
+        status: match result.status {
+            SyncStatus::Applied => BatchStatus::Applied,
+            SyncStatus::Conflict => BatchStatus::Conflict,
+            SyncStatus::Rejected => BatchStatus::Rejected,
+        },
+        version: result.version,
+        current: result.current,
+        message: result.message,
+    }
+}
+
+fn filter_matches(filter: &InteractionFilter, interaction: &Interaction) -> bool {
+    if let Some(kind) = filter.kind {
+        if interaction.kind != Some(kind) {
+            return false;
+        }
+    }
+    if let Some(participant) = filter.participant {
+        if !interaction.participants.contains(&participant) {
+            return false;
+        }
+    }
+    if let Some(initiator) = filter.initiator {
+        if interaction.initiator != Some(initiator) {
+            return false;
+        }
+    }
+    // Datestamps are yyyy-mm-dd, so they sort the same as the dates they represent
+    if let Some(since) = &filter.since {
+        match &interaction.date {
+            Some(date) if date >= since => {}
+            _ => return false,
+        }
+    }
+    if let Some(until) = &filter.until {
+        match &interaction.date {
+            Some(date) if date <= until => {}
+            _ => return false,
+        }
     }
+    true
 }
 
 impl<S: AuthStore> AuthService for AuthHandler<S> {
@@ -66,10 +506,17 @@ impl<S: AuthStore> AuthService for AuthHandler<S> {
             return Err(anyhow!("Password mismatch - {:?}", e));
         }
         let user_uuid = user.id.clone();
+        let scopes = match request.scopes {
+            Some(scopes) if scopes.is_empty() => {
+                return Err(anyhow!("A JWT needs at least one scope"));
+            }
+            Some(scopes) => scopes,
+            None => Scope::ALL.to_vec(),
+        };
 
         Ok(LoginResponse {
             user: PublicUser::from(user),
-            jwt: self.create_jwt(user_uuid).context("Creating JWT")?,
+            jwt: self.create_jwt(user_uuid, scopes).context("Creating JWT")?,
         })
     }
     fn sign_up(&mut self, request: SignUpRequest) -> Result<SignUpResponse> {
@@ -86,6 +533,8 @@ impl<S: AuthStore> AuthService for AuthHandler<S> {
             id: user_id,
             email: request.email,
             password: password_hash,
+            digest_frequency: DigestFrequency::default(),
+            calendar_token: None,
             create_timestamp: Timestamp(now.clone()),
             last_update_timestamp: Timestamp(now),
         };
@@ -98,112 +547,1853 @@ impl<S: AuthStore> AuthService for AuthHandler<S> {
         Ok(SignUpResponse { user: public_user })
     }
     fn authenticate(&self, request: AuthenticationRequest) -> Result<AuthenticationResponse> {
+        if request.token.starts_with(PERSONAL_ACCESS_TOKEN_PREFIX) {
+            let token = self
+                .storage
+                .get_personal_access_token_by_hash(&hash_token(&request.token))
+                .context("Looking up personal access token")?
+                .ok_or_else(|| UnauthorizedError {
+                    message: "Unknown or revoked personal access token".to_string(),
+                })?;
+            return Ok(AuthenticationResponse {
+                user_uuid: token.user_id,
+                scopes: token.scopes,
+            });
+        }
         let claims = self
-            .decode_token(&request.json_web_token)
-            .context("Parsing JWT")?;
-        let user_uuid = claims.get_user_id().context("Parsing UUID")?;
-        Ok(AuthenticationResponse { user_uuid })
+            .decode_token(&request.token)
+            .map_err(|e| UnauthorizedError {
+                message: format!("{:#}", e),
+            })?;
+        let user_uuid = claims.get_user_id().map_err(|e| UnauthorizedError {
+            message: format!("{:#}", e),
+        })?;
+        Ok(AuthenticationResponse {
+            user_uuid,
+            scopes: claims.scopes,
+        })
     }
-}
-
-impl<S: BuddiesStore> BuddiesService for RequestHandler<S> {
-    fn create_buddy(&mut self, request: CreateBuddyRequest) -> Result<CreateBuddyResponse> {
-        let buddy_id = Uuid::new_v4();
+    fn create_personal_access_token(
+        &mut self,
+        request: CreatePersonalAccessTokenRequest,
+    ) -> Result<CreatePersonalAccessTokenResponse> {
+        if request.name.trim().is_empty() {
+            return Err(InvalidRequestError {
+                message: "A personal access token needs a name".to_string(),
+            }
+            .into());
+        }
+        if request.scopes.is_empty() {
+            return Err(InvalidRequestError {
+                message: "A personal access token needs at least one scope".to_string(),
+            }
+            .into());
+        }
         let now = SystemTime::now()
             .duration_since(SystemTime::UNIX_EPOCH)?
             .as_secs();
-        let date_time = NaiveDateTime::from_timestamp(now.try_into().unwrap(), 0);
-        let buddy = Buddy {
-            id: buddy_id,
+        let mut scopes = request.scopes;
+        scopes.sort();
+        scopes.dedup();
+        let token = format!(
+            "{}{}{}",
+            PERSONAL_ACCESS_TOKEN_PREFIX,
+            Uuid::new_v4().to_simple(),
+            Uuid::new_v4().to_simple()
+        );
+        let personal_access_token = PersonalAccessToken {
+            id: Uuid::new_v4(),
             name: request.name,
-            birthday: request.birthday,
-            cadence: request.cadence,
-            notes: request.notes,
-            location: request.location,
-            user_id: request.user_id,
-            last_contacted: Datestamp(format!("{}", date_time.date())),
+            scopes,
+            token_hash: hash_token(&token),
             create_timestamp: Timestamp(now),
-            last_update_timestamp: Timestamp(now),
-            delete_timestamp: None,
+            revoke_timestamp: None,
+            user_id: request.user_id,
         };
+        self.storage
+            .create_personal_access_token(personal_access_token.clone())
+            .context(format!(
+                "Creating personal access token with id {}",
+                personal_access_token.id
+            ))?;
+        Ok(CreatePersonalAccessTokenResponse {
+            personal_access_token,
+            token,
+        })
+    }
+    fn list_personal_access_tokens(
+        &self,
+        request: ListPersonalAccessTokensRequest,
+    ) -> Result<ListPersonalAccessTokensResponse> {
+        let mut personal_access_tokens: Vec<PersonalAccessToken> = self
+            .storage
+            .get_personal_access_tokens(request.user_id)
+            .context("getting personal access tokens")?
+            .into_values()
+            .collect();
+        personal_access_tokens.sort_by_key(|token| (token.create_timestamp.0, token.id));
+        Ok(ListPersonalAccessTokensResponse {
+            personal_access_tokens,
+        })
+    }
+    fn revoke_personal_access_token(
+        &mut self,
+        request: RevokePersonalAccessTokenRequest,
+    ) -> Result<RevokePersonalAccessTokenResponse> {
+        let tokens = self
+            .storage
+            .get_personal_access_tokens(request.user_id)
+            .context("getting personal access tokens")?;
+        if !tokens.contains_key(&request.id) {
+            return Err(NotFoundError {
+                kind: "personal access token",
+                id: request.id,
+            }
+            .into());
+        }
+        self.storage
+            .revoke_personal_access_token(request.id, request.user_id)
+            .context("Attempting to revoke personal access token")?;
+        Ok(RevokePersonalAccessTokenResponse {})
+    }
+}
 
+/// Personal access tokens are random enough that a plain hash is all they need at rest
+fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+impl<S: BuddiesStore> RequestHandler<S> {
+    fn find_user(&self, user_id: Uuid) -> Result<User> {
         self.storage
-            .create_buddy(buddy.clone())
-            .context(format!("Creating buddy with id {}", buddy_id))?;
+            .get_user_by_id(user_id)
+            .context("getting user")?
+            .ok_or_else(|| {
+                NotFoundError {
+                    kind: "user",
+                    id: user_id,
+                }
+                .into()
+            })
+    }
 
-        Ok(CreateBuddyResponse { buddy })
+    fn ensure_buddy_exists(&self, user_id: Uuid, buddy_id: Uuid) -> Result<()> {
+        let buddies = self
+            .storage
+            .get_buddies(user_id)
+            .context("getting buddies")?;
+        match buddies.get(&buddy_id) {
+            Some(buddy) if buddy.delete_timestamp.is_none() => Ok(()),
+            _ => Err(NotFoundError {
+                kind: "buddy",
+                id: buddy_id,
+            }
+            .into()),
+        }
     }
 
-    fn get_user_data(&self, request: GetUserDataRequest) -> Result<GetUserDataResponse> {
+    /// Interactions can only be with the user's own buddies, and not ones they've archived
+    fn ensure_participants_exist(&self, user_id: Uuid, participants: &HashSet<Uuid>) -> Result<()> {
         let buddies = self
             .storage
-            .get_buddies(request.user_id)
+            .get_buddies(user_id)
             .context("getting buddies")?;
+        for participant in participants {
+            match buddies.get(participant) {
+                Some(buddy) if buddy.delete_timestamp.is_none() => {}
+                _ => {
+                    return Err(InvalidRequestError {
+                        message: format!("No buddy found with id {}", participant),
+                    }
+                    .into())
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// One of the user's interactions that hasn't been archived
+    fn live_interaction(&self, user_id: Uuid, interaction_id: Uuid) -> Result<Interaction> {
+        self.storage
+            .get_interactions(user_id)
+            .context("getting interactions")?
+            .remove(&interaction_id)
+            .filter(|interaction| interaction.delete_timestamp.is_none())
+            .ok_or_else(|| {
+                NotFoundError {
+                    kind: "interaction",
+                    id: interaction_id,
+                }
+                .into()
+            })
+    }
+
+    fn ensure_buddy_participated(
+        &self,
+        user_id: Uuid,
+        buddy_id: Uuid,
+        interaction_id: Uuid,
+    ) -> Result<()> {
+        let interaction = self.live_interaction(user_id, interaction_id)?;
+        if interaction.participants.contains(&buddy_id) {
+            Ok(())
+        } else {
+            Err(InvalidRequestError {
+                message: format!(
+                    "Buddy {} did not participate in interaction {}",
+                    buddy_id, interaction_id
+                ),
+            }
+            .into())
+        }
+    }
+
+    fn find_buddy(&self, user_id: Uuid, buddy_id: Uuid) -> Result<Buddy> {
+        self.storage
+            .get_buddies(user_id)
+            .context("getting buddies")?
+            .remove(&buddy_id)
+            .ok_or_else(|| {
+                NotFoundError {
+                    kind: "buddy",
+                    id: buddy_id,
+                }
+                .into()
+            })
+    }
+
+    fn find_interaction(&self, user_id: Uuid, interaction_id: Uuid) -> Result<Interaction> {
+        self.storage
+            .get_interactions(user_id)
+            .context("getting interactions")?
+            .remove(&interaction_id)
+            .ok_or_else(|| {
+                NotFoundError {
+                    kind: "interaction",
+                    id: interaction_id,
+                }
+                .into()
+            })
+    }
+
+    /// The revision of a record to revert to, which must have left something behind
+    fn find_revision(
+        &self,
+        user_id: Uuid,
+        entity_id: Uuid,
+        revision_id: Uuid,
+    ) -> Result<serde_json::Value> {
+        self.storage
+            .get_revisions(user_id, entity_id)
+            .context("getting revisions")?
+            .into_iter()
+            .find(|revision| revision.id == revision_id)
+            .ok_or(NotFoundError {
+                kind: "revision",
+                id: revision_id,
+            })?
+            .after
+            .ok_or_else(|| {
+                InvalidRequestError {
+                    message: format!("Revision {} has nothing to revert to", revision_id),
+                }
+                .into()
+            })
+    }
+
+    /// Fold everything about `loser_id` into `winner_id` and archive the loser
+    fn merge_into(
+        &mut self,
+        user_id: Uuid,
+        winner_id: Uuid,
+        loser_id: Uuid,
+    ) -> Result<MergeBuddiesResponse> {
+        self.ensure_buddy_exists(user_id, winner_id)?;
+        self.ensure_buddy_exists(user_id, loser_id)?;
+        let winner = self.find_buddy(user_id, winner_id)?;
+        let loser = self.find_buddy(user_id, loser_id)?;
+
+        // Only fill in what the winner doesn't know yet
+        self.update_buddy(UpdateBuddyRequest {
+            user_id,
+            buddy_id: winner_id,
+            notes: Some(merge_notes(&winner.notes, &loser.notes)),
+            last_contacted: Some(
+                winner
+                    .last_contacted
+                    .clone()
+                    .max(loser.last_contacted.clone()),
+            ),
+            birthday: winner.birthday.is_none().then(|| loser.birthday.clone()),
+            cadence: winner.cadence.is_none().then_some(loser.cadence),
+            location: winner.location.is_none().then(|| loser.location.clone()),
+            version: Some(winner.version),
+            ..Default::default()
+        })
+        .context("Updating winner")?;
+
+        let mut interactions_updated = Vec::new();
+        let interactions = self
+            .storage
+            .get_interactions(user_id)
+            .context("getting interactions")?;
+        for interaction in interactions.into_values() {
+            if interaction.delete_timestamp.is_some()
+                || !interaction.participants.contains(&loser_id)
+            {
+                continue;
+            }
+            let mut participants = interaction.participants;
+            participants.remove(&loser_id);
+            participants.insert(winner_id);
+            let initiator = (interaction.initiator == Some(loser_id)).then_some(Some(winner_id));
+            self.update_interaction(UpdateInteractionRequest {
+                user_id,
+                interaction_id: interaction.id,
+                participants: Some(participants),
+                initiator,
+                version: Some(interaction.version),
+                ..Default::default()
+            })
+            .context(format!(
+                "Moving interaction {} to the winner",
+                interaction.id
+            ))?;
+            interactions_updated.push(interaction.id);
+        }
+
+        let contact_methods = self
+            .storage
+            .get_contact_methods(user_id)
+            .context("getting contact methods")?;
+        let live: Vec<&ContactMethod> = contact_methods
+            .values()
+            .filter(|contact_method| contact_method.delete_timestamp.is_none())
+            .collect();
+        // The rest move over with the loser's other records below
+        for contact_method in &live {
+            if contact_method.buddy_id != loser_id {
+                continue;
+            }
+            let winners = || {
+                live.iter().filter(|other| {
+                    other.buddy_id == winner_id && other.kind == contact_method.kind
+                })
+            };
+            if winners().any(|other| other.value.eq_ignore_ascii_case(&contact_method.value)) {
+                self.archive_contact_method(ArchiveContactMethodRequest {
+                    id: contact_method.id,
+                    user_id,
+                })
+                .context(format!(
+                    "Archiving contact method {} the winner already has",
+                    contact_method.id
+                ))?;
+            } else if contact_method.preferred && winners().any(|other| other.preferred) {
+                // Never take the winner's preferred contact method away
+                self.storage
+                    .update_contact_method(UpdateContactMethodRequest {
+                        user_id,
+                        contact_method_id: contact_method.id,
+                        preferred: Some(false),
+                        ..Default::default()
+                    })
+                    .context(format!("Unpreferring contact method {}", contact_method.id))?;
+            }
+        }
+
+        // Relationships between the two would leave the winner related to itself, and ones
+        // the winner already has would be doubled up, so those go rather than move
+        let relationships = self
+            .storage
+            .get_relationships(user_id)
+            .context("getting relationships")?;
+        let live: Vec<&Relationship> = relationships
+            .values()
+            .filter(|relationship| relationship.delete_timestamp.is_none())
+            .collect();
+        let moved = |id: Uuid| if id == loser_id { winner_id } else { id };
+        for relationship in &live {
+            if relationship.from_buddy_id != loser_id && relationship.to_buddy_id != loser_id {
+                continue;
+            }
+            let (from, to) = (
+                moved(relationship.from_buddy_id),
+                moved(relationship.to_buddy_id),
+            );
+            let duplicate = from == to
+                || live.iter().any(|existing| {
+                    let ends = (existing.from_buddy_id, existing.to_buddy_id);
+                    existing.kind == relationship.kind
+                        && (ends == (from, to)
+                            || (relationship.bidirectional || existing.bidirectional)
+                                && ends == (to, from))
+                });
+            if duplicate {
+                self.archive_relationship(ArchiveRelationshipRequest {
+                    id: relationship.id,
+                    user_id,
+                })
+                .context(format!("Archiving relationship {}", relationship.id))?;
+            }
+        }
+        self.storage
+            .move_buddy_records(user_id, loser_id, winner_id)
+            .context("Moving the loser's records to the winner")?;
+
+        self.archive_buddy(ArchiveBuddyRequest {
+            id: loser_id,
+            user_id,
+            version: Some(loser.version),
+        })
+        .context("Archiving loser")?;
+
+        Ok(MergeBuddiesResponse {
+            buddy: self.find_buddy(user_id, winner_id)?,
+            interactions_updated,
+        })
+    }
+
+    /// Run `f` in a transaction, so a change is saved along with its revision and webhook
+    /// deliveries or not at all. Its events are held back until the transaction commits.
+    fn atomically<T, F>(&mut self, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut RequestHandler<S>) -> Result<T>,
+    {
+        let events = self.events.deferred();
+        let result = self.storage.transaction(|storage| {
+            f(&mut RequestHandler {
+                storage: storage.clone(),
+                events: events.clone(),
+            })
+        })?;
+        self.events.flush(events);
+        Ok(result)
+    }
+
+    /// Save a revision and tell anyone listening about the change. Call it from inside
+    /// `atomically`, so the revision is kept only if the change is.
+    fn record_revision(&mut self, revision: Revision) -> Result<()> {
+        self.storage.create_revision(revision.clone())?;
+        self.queue_webhook_deliveries(&revision)
+            .context("queueing webhook deliveries")?;
+        self.events.publish(&revision);
+        Ok(())
+    }
+
+    /// Queue the change a revision records for each of the user's webhooks. The deliveries
+    /// are written in the change's transaction, so they're only sent if it sticks.
+    fn queue_webhook_deliveries(&mut self, revision: &Revision) -> Result<()> {
+        let webhooks = self.storage.get_webhooks(revision.user_id)?;
+        if webhooks.is_empty() {
+            return Ok(());
+        }
+        let payload = serde_json::to_value(ChangeEvent::from(revision))?;
+        let now = self.storage.now()?;
+        for webhook_id in webhooks.keys() {
+            self.storage.create_webhook_delivery(WebhookDelivery {
+                id: Uuid::new_v4(),
+                webhook_id: *webhook_id,
+                payload: payload.clone(),
+                status: DeliveryStatus::Pending,
+                attempts: 0,
+                next_attempt_timestamp: Some(Timestamp(now)),
+                response_status: None,
+                error: None,
+                create_timestamp: Timestamp(now),
+                last_update_timestamp: Timestamp(now),
+                user_id: revision.user_id,
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Run a create once per idempotency key, replaying its response to retries within the window
+    fn idempotent<T, F>(
+        &mut self,
+        user_id: Uuid,
+        operation: &str,
+        key: String,
+        request: Value,
+        create: F,
+    ) -> Result<T>
+    where
+        T: Serialize + DeserializeOwned,
+        F: FnOnce(&mut Self) -> Result<T>,
+    {
+        let now = self.storage.now()?;
+        // Looking the key up, creating and saving the key happen in one transaction. If a
+        // concurrent request with the same key got there first, saving the key fails on the
+        // unique index and everything is rolled back, so look again for its response.
+        let outcome = self.atomically(|handler| {
+            if let Some(replay) = handler.replay(user_id, operation, &key, &request, now)? {
+                return Ok(replay);
+            }
+            handler
+                .storage
+                .delete_idempotency_record(user_id, operation, &key)
+                .context("deleting expired idempotency record")?;
+            // Failures aren't remembered, so they can be retried
+            let response = create(handler)?;
+            handler
+                .storage
+                .save_idempotency_record(IdempotencyRecord {
+                    user_id,
+                    operation: operation.to_string(),
+                    key: key.clone(),
+                    request: request.clone(),
+                    response: serde_json::to_value(&response)?,
+                    create_timestamp: Timestamp(now),
+                })
+                .context("saving idempotency record")?;
+            Ok(response)
+        });
+        match outcome {
+            Ok(response) => Ok(response),
+            Err(e) => match self.replay(user_id, operation, &key, &request, now)? {
+                Some(replay) => Ok(replay),
+                None => Err(e),
+            },
+        }
+    }
+
+    /// The response saved for an idempotency key still in its window, if there is one
+    fn replay<T: DeserializeOwned>(
+        &self,
+        user_id: Uuid,
+        operation: &str,
+        key: &str,
+        request: &Value,
+        now: u64,
+    ) -> Result<Option<T>> {
+        let record = self
+            .storage
+            .get_idempotency_record(user_id, operation, key)
+            .context("getting idempotency record")?;
+        match record {
+            Some(record) if now < record.create_timestamp.0 + IDEMPOTENCY_WINDOW_SECS => {
+                if &record.request != request {
+                    return Err(IdempotencyKeyReusedError {
+                        key: key.to_string(),
+                    }
+                    .into());
+                }
+                serde_json::from_value(record.response)
+                    .map(Some)
+                    .context("Replaying response for idempotency key")
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Apply a single change, returning the version of the record it leaves behind. Creates
+    /// that already happened are let through, so retries are safe.
+    fn apply_mutation(&mut self, user_id: Uuid, mutation: Mutation) -> (Uuid, Result<i32>) {
+        match mutation {
+            Mutation::CreateBuddy(mut request) => {
+                request.user_id = user_id;
+                let id = *request.id.get_or_insert_with(Uuid::new_v4);
+                (
+                    id,
+                    self.create_buddy(request).map(|resp| resp.buddy.version),
+                )
+            }
+            Mutation::UpdateBuddy(mut request) => {
+                request.user_id = user_id;
+                let id = request.buddy_id;
+                (id, self.update_buddy(request).map(|resp| resp.version))
+            }
+            Mutation::ArchiveBuddy(mut request) => {
+                request.user_id = user_id;
+                let id = request.id;
+                let outcome = self
+                    .archive_buddy(request)
+                    .and_then(|_| self.find_buddy(user_id, id))
+                    .map(|buddy| buddy.version);
+                (id, outcome)
+            }
+            Mutation::CreateInteraction(mut request) => {
+                request.user_id = user_id;
+                let id = *request.id.get_or_insert_with(Uuid::new_v4);
+                (
+                    id,
+                    self.create_interaction(request)
+                        .map(|resp| resp.interaction.version),
+                )
+            }
+            Mutation::UpdateInteraction(mut request) => {
+                request.user_id = user_id;
+                let id = request.interaction_id;
+                (
+                    id,
+                    self.update_interaction(request).map(|resp| resp.version),
+                )
+            }
+            Mutation::ArchiveInteraction(mut request) => {
+                request.user_id = user_id;
+                let id = request.id;
+                let outcome = self
+                    .archive_interaction(request)
+                    .and_then(|_| self.find_interaction(user_id, id))
+                    .map(|interaction| interaction.version);
+                (id, outcome)
+            }
+        }
+    }
+
+    /// Buddies with everything we know about them filled in
+    fn get_full_buddies(&self, user_id: Uuid) -> Result<HashMap<Uuid, Buddy>> {
+        let mut buddies = self.get_buddies_with_important_dates(user_id)?;
+        let history = self
+            .storage
+            .get_contact_history(user_id, None, None)
+            .context("getting contact history")?;
+        fill_suggested_cadences(&mut buddies, &history).context("suggesting cadences")?;
+        let contact_methods = self
+            .storage
+            .get_contact_methods(user_id)
+            .context("getting contact methods")?;
+        attach_contact_methods(&mut buddies, contact_methods);
+        fill_local_times(&mut buddies, Utc::now()).context("getting local times")?;
+        Ok(buddies)
+    }
+
+    fn get_buddies_with_important_dates(&self, user_id: Uuid) -> Result<HashMap<Uuid, Buddy>> {
+        let mut buddies = self
+            .storage
+            .get_buddies(user_id)
+            .context("getting buddies")?;
+        let important_dates = self
+            .storage
+            .get_important_dates(user_id)
+            .context("getting important dates")?;
+        for important_date in important_dates.into_values() {
+            if let Some(buddy) = buddies.get_mut(&important_date.buddy_id) {
+                buddy.important_dates.push(important_date);
+            }
+        }
+        for buddy in buddies.values_mut() {
+            buddy.important_dates.sort_by(|a, b| a.date.cmp(&b.date));
+        }
+        Ok(buddies)
+    }
+
+    /// A buddy only has one preferred contact method of each kind, so make room for a new one
+    fn clear_preferred(&mut self, preferred: &ContactMethod) -> Result<()> {
+        let contact_methods = self
+            .storage
+            .get_contact_methods(preferred.user_id)
+            .context("getting contact methods")?;
+        for contact_method in contact_methods.values() {
+            if contact_method.id != preferred.id
+                && contact_method.buddy_id == preferred.buddy_id
+                && contact_method.kind == preferred.kind
+                && contact_method.preferred
+            {
+                self.storage
+                    .update_contact_method(UpdateContactMethodRequest {
+                        user_id: preferred.user_id,
+                        contact_method_id: contact_method.id,
+                        preferred: Some(false),
+                        ..UpdateContactMethodRequest::default()
+                    })
+                    .context(format!(
+                        "Clearing preferred contact method {}",
+                        contact_method.id
+                    ))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<S: BuddiesStore> BuddiesService for RequestHandler<S> {
+    fn create_buddy(&mut self, mut request: CreateBuddyRequest) -> Result<CreateBuddyResponse> {
+        if let Some(key) = request.idempotency_key.take() {
+            let fingerprint = serde_json::to_value(&request)?;
+            return self.idempotent(
+                request.user_id,
+                "buddy/create",
+                key,
+                fingerprint,
+                |handler| handler.create_buddy(request),
+            );
+        }
+        self.atomically(|handler| {
+            // Creating a buddy again with the id it was given replays the original create
+            if let Some(id) = request.id {
+                if let Ok(buddy) = handler.find_buddy(request.user_id, id) {
+                    return Ok(CreateBuddyResponse { buddy });
+                }
+            }
+            let buddy_id = request.id.unwrap_or_else(Uuid::new_v4);
+            let now = handler.storage.now()?;
+            let date_time = NaiveDateTime::from_timestamp(now.try_into().unwrap(), 0);
+            let location = request.location.map(validate_location).transpose()?;
+            let mut buddy = Buddy {
+                id: buddy_id,
+                name: request.name,
+                birthday: request.birthday,
+                cadence: request.cadence,
+                notes: request.notes,
+                location,
+                user_id: request.user_id,
+                last_contacted: Datestamp(format!("{}", date_time.date())),
+                create_timestamp: Timestamp(now),
+                last_update_timestamp: Timestamp(now),
+                delete_timestamp: None,
+                suggested_cadence: None,
+                contact_methods: Vec::new(),
+                local_time: None,
+                important_dates: Vec::new(),
+                version: 1,
+            };
+
+            handler
+                .storage
+                .create_buddy(buddy.clone())
+                .context(format!("Creating buddy with id {}", buddy_id))?;
+            let revision = build_revision(
+                request.user_id,
+                EntityKind::Buddy,
+                buddy_id,
+                RevisionAction::Create,
+                None,
+                Some(&buddy),
+            )?;
+            handler
+                .record_revision(revision)
+                .context("recording buddy revision")?;
+            if let Some(location) = &buddy.location {
+                buddy.local_time = local_time(location, Utc::now())?;
+            }
+
+            Ok(CreateBuddyResponse { buddy })
+        })
+    }
+
+    fn get_user_data(&self, request: GetUserDataRequest) -> Result<GetUserDataResponse> {
+        let buddies = self.get_full_buddies(request.user_id)?;
 
         let interactions = self
             .storage
             .get_interactions(request.user_id)
             .context("getting interactions")?;
 
+        let ideas = self
+            .storage
+            .get_ideas(request.user_id)
+            .context("getting ideas")?;
+
         Ok(GetUserDataResponse {
             buddies,
             interactions,
+            ideas,
         })
     }
+    fn list_buddies(&self, request: ListBuddiesRequest) -> Result<ListBuddiesResponse> {
+        let mut buddies: Vec<Buddy> = self
+            .get_full_buddies(request.user_id)?
+            .into_values()
+            .filter(|buddy| buddy.delete_timestamp.is_none())
+            .collect();
+        buddies.sort_by(|a, b| a.name.cmp(&b.name).then(a.id.cmp(&b.id)));
+        Ok(ListBuddiesResponse { buddies })
+    }
+    fn get_buddy(&self, request: GetBuddyRequest) -> Result<Buddy> {
+        match self
+            .get_full_buddies(request.user_id)?
+            .remove(&request.buddy_id)
+        {
+            Some(buddy) if buddy.delete_timestamp.is_none() => Ok(buddy),
+            _ => Err(NotFoundError {
+                kind: "buddy",
+                id: request.buddy_id,
+            }
+            .into()),
+        }
+    }
+    fn get_changes(&self, request: GetChangesRequest) -> Result<GetChangesResponse> {
+        // Taken before reading, so anything written while we read shows up next time
+        let cursor = self.storage.now()?;
+        let since: Option<u64> = match &request.since {
+            Some(since) => Some(since.parse().map_err(|_| InvalidRequestError {
+                message: format!("Unrecognized sync cursor {}", since),
+            })?),
+            None => None,
+        };
+        // A sync covers the seconds from its `since` up to but not including its cursor, so
+        // the next one starts where it stopped. Anything written in the second the cursor
+        // was taken is left for the next sync, rather than sent by both.
+        let changed = |timestamp: &Timestamp| {
+            timestamp.0 < cursor && since.is_none_or(|since| timestamp.0 >= since)
+        };
+
+        let mut changes = GetChangesResponse {
+            cursor: cursor.to_string(),
+            ..GetChangesResponse::default()
+        };
+        let buddies = self
+            .storage
+            .get_buddies(request.user_id)
+            .context("getting buddies")?;
+        for buddy in buddies.into_values() {
+            if !changed(&buddy.last_update_timestamp) {
+                continue;
+            }
+            match buddy.delete_timestamp {
+                // A client syncing from scratch has nothing to delete
+                Some(delete_timestamp) if since.is_some() => changes.tombstones.push(Tombstone {
+                    entity_kind: EntityKind::Buddy,
+                    id: buddy.id,
+                    delete_timestamp,
+                    version: buddy.version,
+                }),
+                Some(_) => {}
+                None => changes.buddies.push(buddy),
+            }
+        }
+        let interactions = self
+            .storage
+            .get_interactions(request.user_id)
+            .context("getting interactions")?;
+        for interaction in interactions.into_values() {
+            if !changed(&interaction.last_update_timestamp) {
+                continue;
+            }
+            match interaction.delete_timestamp {
+                Some(delete_timestamp) if since.is_some() => changes.tombstones.push(Tombstone {
+                    entity_kind: EntityKind::Interaction,
+                    id: interaction.id,
+                    delete_timestamp,
+                    version: interaction.version,
+                }),
+                Some(_) => {}
+                None => changes.interactions.push(interaction),
+            }
+        }
+        changes
+            .buddies
+            .sort_by_key(|buddy| (buddy.last_update_timestamp.0, buddy.id));
+        changes
+            .interactions
+            .sort_by_key(|interaction| (interaction.last_update_timestamp.0, interaction.id));
+        changes
+            .tombstones
+            .sort_by_key(|tombstone| (tombstone.delete_timestamp.0, tombstone.id));
+        Ok(changes)
+    }
+    fn sync(&mut self, request: SyncRequest) -> Result<SyncResponse> {
+        let mut results = Vec::new();
+        for mutation in request.mutations {
+            let (id, outcome) = self.apply_mutation(request.user_id, mutation);
+            results.push(sync_result(id, outcome));
+        }
+        Ok(SyncResponse { results })
+    }
+
+    fn batch(&mut self, request: BatchRequest) -> Result<BatchResponse> {
+        let user_id = request.user_id;
+        let operations = request.operations;
+        let mut results = Vec::new();
+        let mut failed_index = None;
+        let outcome = self.atomically(|handler| {
+            let mut operations = operations.into_iter().enumerate();
+            for (index, operation) in &mut operations {
+                let (id, outcome) = handler.apply_mutation(user_id, operation);
+                let result = batch_result(index, id, outcome);
+                let failed = result.status != BatchStatus::Applied;
+                results.push(result);
+                if failed {
+                    failed_index = Some(index);
+                    results.extend(operations.map(|(index, operation)| BatchResult {
+                        index,
+                        id: operation.record_id(),
+                        status: BatchStatus::Skipped,
+                        version: None,
+                        current: None,
+                        message: None,
+                    }));
+                    return Err(anyhow!("Operation {} failed", index));
+                }
+            }
+            Ok(())
+        });
+        match (outcome, failed_index) {
+            (Ok(()), _) => Ok(BatchResponse {
+                applied: true,
+                failed_index: None,
+                results,
+            }),
+            (Err(_), Some(failed_index)) => {
+                for result in results.iter_mut().take(failed_index) {
+                    result.status = BatchStatus::RolledBack;
+                    result.version = None;
+                }
+                Ok(BatchResponse {
+                    applied: false,
+                    failed_index: Some(failed_index),
+                    results,
+                })
+            }
+            (Err(e), None) => Err(e),
+        }
+    }
     fn archive_buddy(&mut self, request: ArchiveBuddyRequest) -> Result<ArchiveBuddyResponse> {
-        self.storage
-            .archive_buddy(request.id, request.user_id)
-            .context("Attempting to archive buddy")?;
-        Ok(ArchiveBuddyResponse {})
+        self.atomically(|handler| {
+            let before = handler.find_buddy(request.user_id, request.id)?;
+            if request.version.is_some() && request.version != Some(before.version) {
+                return Err(StaleWriteError {
+                    current: CurrentRecord::Buddy(Box::new(before)),
+                }
+                .into());
+            }
+            handler
+                .storage
+                .archive_buddy(request.id, request.user_id)
+                .context("Attempting to archive buddy")?;
+            let after = handler.find_buddy(request.user_id, request.id)?;
+            let revision = build_revision(
+                request.user_id,
+                EntityKind::Buddy,
+                request.id,
+                RevisionAction::Archive,
+                Some(&before),
+                Some(&after),
+            )?;
+            handler
+                .record_revision(revision)
+                .context("recording buddy revision")?;
+            Ok(ArchiveBuddyResponse {})
+        })
     }
     fn update_buddy(&mut self, request: UpdateBuddyRequest) -> Result<UpdateBuddyResponse> {
-        self.storage
-            .update_buddy(request)
-            .context("updating buddy")?;
-        Ok(UpdateBuddyResponse {})
+        self.atomically(|handler| {
+            let mut request = request;
+            if let Some(Some(location)) = request.location {
+                request.location = Some(Some(validate_location(location)?));
+            }
+            let user_id = request.user_id;
+            let buddy_id = request.buddy_id;
+            let before = handler.find_buddy(user_id, buddy_id)?;
+            handler
+                .storage
+                .update_buddy(request)
+                .context("updating buddy")?;
+            let after = handler.find_buddy(user_id, buddy_id)?;
+            let revision = build_revision(
+                user_id,
+                EntityKind::Buddy,
+                buddy_id,
+                RevisionAction::Update,
+                Some(&before),
+                Some(&after),
+            )?;
+            handler
+                .record_revision(revision)
+                .context("recording buddy revision")?;
+            Ok(UpdateBuddyResponse {
+                version: after.version,
+            })
+        })
+    }
+    fn patch_buddy(&mut self, request: PatchBuddyRequest) -> Result<PatchBuddyResponse> {
+        let current = self.find_buddy(request.user_id, request.buddy_id)?;
+        let mut update = patched_fields(
+            serde_json::to_value(&current).context("Reading buddy to patch")?,
+            &request.patch,
+            PATCHABLE_BUDDY_FIELDS,
+            REQUIRED_BUDDY_FIELDS,
+        )?;
+        update.insert("user_id".to_string(), json!(request.user_id));
+        update.insert("buddy_id".to_string(), json!(request.buddy_id));
+        // The patch was merged into what we just read, so don't write over anything newer
+        update.insert(
+            "version".to_string(),
+            json!(request.version.unwrap_or(current.version)),
+        );
+        let update: UpdateBuddyRequest =
+            serde_json::from_value(Value::Object(update)).map_err(|e| InvalidRequestError {
+                message: format!("Unreadable buddy patch {}", e),
+            })?;
+        self.update_buddy(update)?;
+        let buddy = self.find_buddy(request.user_id, request.buddy_id)?;
+        Ok(PatchBuddyResponse { buddy })
+    }
+    fn get_buddy_history(&self, request: GetHistoryRequest) -> Result<GetHistoryResponse> {
+        self.find_buddy(request.user_id, request.entity_id)?;
+        let revisions = self
+            .storage
+            .get_revisions(request.user_id, request.entity_id)
+            .context("getting buddy revisions")?;
+        Ok(GetHistoryResponse { revisions })
+    }
+    fn revert_buddy(&mut self, request: RevertBuddyRequest) -> Result<RevertBuddyResponse> {
+        self.atomically(|handler| {
+            let before = handler.find_buddy(request.user_id, request.buddy_id)?;
+            if request.version.is_some() && request.version != Some(before.version) {
+                return Err(StaleWriteError {
+                    current: CurrentRecord::Buddy(Box::new(before)),
+                }
+                .into());
+            }
+            let snapshot =
+                handler.find_revision(request.user_id, request.buddy_id, request.revision_id)?;
+            let mut buddy: Buddy =
+                serde_json::from_value(snapshot).context("Reading buddy from revision")?;
+            buddy.last_update_timestamp = Timestamp(handler.storage.now()?);
+            buddy.version = before.version + 1;
+            handler
+                .storage
+                .restore_buddy(buddy.clone())
+                .context(format!("Reverting buddy {}", request.buddy_id))?;
+            let mut revision = build_revision(
+                request.user_id,
+                EntityKind::Buddy,
+                request.buddy_id,
+                RevisionAction::Revert,
+                Some(&before),
+                Some(&buddy),
+            )?;
+            revision.reverted_to = Some(request.revision_id);
+            handler
+                .record_revision(revision)
+                .context("recording buddy revision")?;
+            Ok(RevertBuddyResponse { buddy })
+        })
+    }
+    fn apply_suggested_cadence(
+        &mut self,
+        request: ApplySuggestedCadenceRequest,
+    ) -> Result<ApplySuggestedCadenceResponse> {
+        // Every buddy gets their suggestion or none do
+        self.atomically(|handler| {
+            let mut buddies = handler
+                .storage
+                .get_buddies(request.user_id)
+                .context("getting buddies")?;
+            let history = handler
+                .storage
+                .get_contact_history(request.user_id, None, None)
+                .context("getting contact history")?;
+            fill_suggested_cadences(&mut buddies, &history).context("suggesting cadences")?;
+
+            let mut updated = HashMap::new();
+            for buddy in buddies.values() {
+                if let Some(buddy_ids) = &request.buddy_ids {
+                    if !buddy_ids.contains(&buddy.id) {
+                        continue;
+                    }
+                }
+                let cadence = match (buddy.delete_timestamp, buddy.suggested_cadence) {
+                    (None, Some(cadence)) => cadence,
+                    _ => continue,
+                };
+                handler
+                    .update_buddy(UpdateBuddyRequest {
+                        user_id: request.user_id,
+                        buddy_id: buddy.id,
+                        cadence: Some(Some(cadence)),
+                        ..UpdateBuddyRequest::default()
+                    })
+                    .context(format!("Applying suggested cadence to buddy {}", buddy.id))?;
+                updated.insert(buddy.id, cadence);
+            }
+            Ok(ApplySuggestedCadenceResponse { updated })
+        })
     }
     fn create_interaction(
         &mut self,
-        request: CreateInteractionRequest,
+        mut request: CreateInteractionRequest,
     ) -> Result<CreateInteractionResponse> {
-        let interaction_id = Uuid::new_v4();
-        let now = SystemTime::now()
-            .duration_since(SystemTime::UNIX_EPOCH)?
-            .as_secs();
-        let interaction = Interaction {
-            id: interaction_id,
-            notes: request.notes,
+        if let Some(key) = request.idempotency_key.take() {
+            let fingerprint = serde_json::to_value(&request)?;
+            return self.idempotent(
+                request.user_id,
+                "interaction/create",
+                key,
+                fingerprint,
+                |handler| handler.create_interaction(request),
+            );
+        }
+        self.atomically(|handler| {
+            // Creating an interaction again with the id it was given replays the original create
+            if let Some(id) = request.id {
+                if let Ok(interaction) = handler.find_interaction(request.user_id, id) {
+                    return Ok(CreateInteractionResponse { interaction });
+                }
+            }
+            handler.ensure_participants_exist(request.user_id, &request.participants)?;
+            validate_initiator(request.user_id, &request.participants, request.initiator)?;
+            let interaction_id = request.id.unwrap_or_else(Uuid::new_v4);
+            let now = handler.storage.now()?;
+            let interaction = Interaction {
+                id: interaction_id,
+                notes: request.notes,
+                user_id: request.user_id,
+                date: request.date,
+                participants: request.participants,
+                kind: request.kind,
+                duration: request.duration,
+                location: request.location,
+                initiator: request.initiator,
+                create_timestamp: Timestamp(now),
+                last_update_timestamp: Timestamp(now),
+                delete_timestamp: None,
+                version: 1,
+            };
+
+            handler
+                .storage
+                .create_interaction(interaction.clone())
+                .context(format!("Creating interaction with id {}", interaction_id))?;
+            let revision = build_revision(
+                interaction.user_id,
+                EntityKind::Interaction,
+                interaction_id,
+                RevisionAction::Create,
+                None,
+                Some(&interaction),
+            )?;
+            handler
+                .record_revision(revision)
+                .context("recording interaction revision")?;
+
+            Ok(CreateInteractionResponse { interaction })
+        })
+    }
+    fn update_interaction(
+        &mut self,
+        request: UpdateInteractionRequest,
+    ) -> Result<UpdateInteractionResponse> {
+        self.atomically(|handler| {
+            let user_id = request.user_id;
+            let interaction_id = request.interaction_id;
+            let before = handler.find_interaction(user_id, interaction_id)?;
+            if let Some(participants) = &request.participants {
+                handler.ensure_participants_exist(user_id, participants)?;
+            }
+            // Either half can change on its own, so check what the interaction will end up with
+            let participants = request
+                .participants
+                .as_ref()
+                .unwrap_or(&before.participants);
+            let initiator = request.initiator.unwrap_or(before.initiator);
+            validate_initiator(user_id, participants, initiator)?;
+            handler
+                .storage
+                .update_interaction(request)
+                .context("updating interaction")?;
+            let after = handler.find_interaction(user_id, interaction_id)?;
+            let revision = build_revision(
+                user_id,
+                EntityKind::Interaction,
+                interaction_id,
+                RevisionAction::Update,
+                Some(&before),
+                Some(&after),
+            )?;
+            handler
+                .record_revision(revision)
+                .context("recording interaction revision")?;
+            Ok(UpdateInteractionResponse {
+                version: after.version,
+            })
+        })
+    }
+    fn archive_interaction(
+        &mut self,
+        request: ArchiveInteractionRequest,
+    ) -> Result<ArchiveInteractionResponse> {
+        self.atomically(|handler| {
+            let before = handler.find_interaction(request.user_id, request.id)?;
+            if request.version.is_some() && request.version != Some(before.version) {
+                return Err(StaleWriteError {
+                    current: CurrentRecord::Interaction(Box::new(before)),
+                }
+                .into());
+            }
+            handler
+                .storage
+                .archive_interaction(request.id, request.user_id)
+                .context("Attempting to archive interaction")?;
+            let after = handler.find_interaction(request.user_id, request.id)?;
+            let revision = build_revision(
+                request.user_id,
+                EntityKind::Interaction,
+                request.id,
+                RevisionAction::Archive,
+                Some(&before),
+                Some(&after),
+            )?;
+            handler
+                .record_revision(revision)
+                .context("recording interaction revision")?;
+            Ok(ArchiveInteractionResponse {})
+        })
+    }
+    fn patch_interaction(
+        &mut self,
+        request: PatchInteractionRequest,
+    ) -> Result<PatchInteractionResponse> {
+        let current = self.find_interaction(request.user_id, request.interaction_id)?;
+        let mut update = patched_fields(
+            serde_json::to_value(&current).context("Reading interaction to patch")?,
+            &request.patch,
+            PATCHABLE_INTERACTION_FIELDS,
+            REQUIRED_INTERACTION_FIELDS,
+        )?;
+        update.insert("user_id".to_string(), json!(request.user_id));
+        update.insert("interaction_id".to_string(), json!(request.interaction_id));
+        // The patch was merged into what we just read, so don't write over anything newer
+        update.insert(
+            "version".to_string(),
+            json!(request.version.unwrap_or(current.version)),
+        );
+        let update: UpdateInteractionRequest = serde_json::from_value(Value::Object(update))
+            .map_err(|e| InvalidRequestError {
+                message: format!("Unreadable interaction patch {}", e),
+            })?;
+        self.update_interaction(update)?;
+        let interaction = self.find_interaction(request.user_id, request.interaction_id)?;
+        Ok(PatchInteractionResponse { interaction })
+    }
+    fn get_interaction(&self, request: GetInteractionRequest) -> Result<Interaction> {
+        let interaction = self.find_interaction(request.user_id, request.interaction_id)?;
+        if interaction.delete_timestamp.is_some() {
+            return Err(NotFoundError {
+                kind: "interaction",
+                id: request.interaction_id,
+            }
+            .into());
+        }
+        Ok(interaction)
+    }
+    fn get_interaction_history(&self, request: GetHistoryRequest) -> Result<GetHistoryResponse> {
+        self.find_interaction(request.user_id, request.entity_id)?;
+        let revisions = self
+            .storage
+            .get_revisions(request.user_id, request.entity_id)
+            .context("getting interaction revisions")?;
+        Ok(GetHistoryResponse { revisions })
+    }
+    fn revert_interaction(
+        &mut self,
+        request: RevertInteractionRequest,
+    ) -> Result<RevertInteractionResponse> {
+        self.atomically(|handler| {
+            let before = handler.find_interaction(request.user_id, request.interaction_id)?;
+            if request.version.is_some() && request.version != Some(before.version) {
+                return Err(StaleWriteError {
+                    current: CurrentRecord::Interaction(Box::new(before)),
+                }
+                .into());
+            }
+            let snapshot = handler.find_revision(
+                request.user_id,
+                request.interaction_id,
+                request.revision_id,
+            )?;
+            let mut interaction: Interaction =
+                serde_json::from_value(snapshot).context("Reading interaction from revision")?;
+            interaction.last_update_timestamp = Timestamp(handler.storage.now()?);
+            interaction.version = before.version + 1;
+            handler
+                .storage
+                .restore_interaction(interaction.clone())
+                .context(format!("Reverting interaction {}", request.interaction_id))?;
+            let mut revision = build_revision(
+                request.user_id,
+                EntityKind::Interaction,
+                request.interaction_id,
+                RevisionAction::Revert,
+                Some(&before),
+                Some(&interaction),
+            )?;
+            revision.reverted_to = Some(request.revision_id);
+            handler
+                .record_revision(revision)
+                .context("recording interaction revision")?;
+            Ok(RevertInteractionResponse { interaction })
+        })
+    }
+    fn list_interactions(
+        &self,
+        request: ListInteractionsRequest,
+    ) -> Result<ListInteractionsResponse> {
+        let interactions = self
+            .storage
+            .get_interactions(request.user_id)
+            .context("getting interactions")?;
+        let mut interactions: Vec<Interaction> = interactions
+            .into_values()
+            .filter(|interaction| interaction.delete_timestamp.is_none())
+            .filter(|interaction| filter_matches(&request.filter, interaction))
+            .collect();
+        interactions.sort_by(|a, b| interaction_sort_key(b).cmp(&interaction_sort_key(a)));
+        if let Some(limit) = request.filter.limit {
+            interactions.truncate(limit);
+        }
+        Ok(ListInteractionsResponse { interactions })
+    }
+    fn create_relationship(
+        &mut self,
+        request: CreateRelationshipRequest,
+    ) -> Result<CreateRelationshipResponse> {
+        if request.from_buddy_id == request.to_buddy_id {
+            return Err(InvalidRequestError {
+                message: "A buddy can't have a relationship with themselves".to_string(),
+            }
+            .into());
+        }
+        self.ensure_buddy_exists(request.user_id, request.from_buddy_id)?;
+        self.ensure_buddy_exists(request.user_id, request.to_buddy_id)?;
+
+        let relationship_id = Uuid::new_v4();
+        let now = self.storage.now()?;
+        let relationship = Relationship {
+            id: relationship_id,
+            from_buddy_id: request.from_buddy_id,
+            to_buddy_id: request.to_buddy_id,
+            kind: request.kind,
+            bidirectional: request.bidirectional,
+            create_timestamp: Timestamp(now),
+            last_update_timestamp: Timestamp(now),
+            delete_timestamp: None,
+            user_id: request.user_id,
+        };
+        self.storage
+            .create_relationship(relationship.clone())
+            .context(format!("Creating relationship with id {}", relationship_id))?;
+        Ok(CreateRelationshipResponse { relationship })
+    }
+    fn update_relationship(
+        &mut self,
+        request: UpdateRelationshipRequest,
+    ) -> Result<UpdateRelationshipResponse> {
+        self.storage
+            .update_relationship(request)
+            .context("updating relationship")?;
+        Ok(UpdateRelationshipResponse {})
+    }
+    fn archive_relationship(
+        &mut self,
+        request: ArchiveRelationshipRequest,
+    ) -> Result<ArchiveRelationshipResponse> {
+        self.storage
+            .archive_relationship(request.id, request.user_id)
+            .context("Attempting to archive relationship")?;
+        Ok(ArchiveRelationshipResponse {})
+    }
+    fn find_buddies_near(
+        &self,
+        request: FindBuddiesNearRequest,
+    ) -> Result<FindBuddiesNearResponse> {
+        let mut buddies = self
+            .storage
+            .get_buddies(request.user_id)
+            .context("getting buddies")?;
+        fill_local_times(&mut buddies, Utc::now()).context("getting local times")?;
+        let buddies = find_near(&buddies, &request.near)?;
+        Ok(FindBuddiesNearResponse { buddies })
+    }
+    fn get_buddy_graph(&self, request: GetBuddyGraphRequest) -> Result<GetBuddyGraphResponse> {
+        let buddies = self
+            .storage
+            .get_buddies(request.user_id)
+            .context("getting buddies")?;
+        let relationships = self
+            .storage
+            .get_relationships(request.user_id)
+            .context("getting relationships")?;
+        let interactions = self
+            .storage
+            .get_interactions(request.user_id)
+            .context("getting interactions")?;
+        let connections =
+            build_neighborhood(request.buddy_id, &buddies, &relationships, &interactions)
+                .context(format!("Building graph for buddy {}", request.buddy_id))?;
+        Ok(GetBuddyGraphResponse {
+            buddy_id: request.buddy_id,
+            connections,
+        })
+    }
+    fn get_duplicates(&self, request: GetDuplicatesRequest) -> Result<GetDuplicatesResponse> {
+        let buddies = self
+            .storage
+            .get_buddies(request.user_id)
+            .context("getting buddies")?;
+        let contact_methods = self
+            .storage
+            .get_contact_methods(request.user_id)
+            .context("getting contact methods")?;
+        Ok(GetDuplicatesResponse {
+            duplicates: find_duplicates(&buddies, &contact_methods),
+        })
+    }
+    fn merge_buddies(&mut self, request: MergeBuddiesRequest) -> Result<MergeBuddiesResponse> {
+        if request.winner_id == request.loser_id {
+            return Err(InvalidRequestError {
+                message: "Can't merge a buddy into itself".to_string(),
+            }
+            .into());
+        }
+        self.atomically(|handler| {
+            handler.merge_into(request.user_id, request.winner_id, request.loser_id)
+        })
+    }
+    fn create_contact_method(
+        &mut self,
+        request: CreateContactMethodRequest,
+    ) -> Result<CreateContactMethodResponse> {
+        self.ensure_buddy_exists(request.user_id, request.buddy_id)?;
+        let value = validate_contact_value(request.kind, &request.value)?;
+
+        let contact_method_id = Uuid::new_v4();
+        let now = self.storage.now()?;
+        let contact_method = ContactMethod {
+            id: contact_method_id,
+            buddy_id: request.buddy_id,
+            kind: request.kind,
+            value,
+            label: request.label,
+            preferred: request.preferred,
+            create_timestamp: Timestamp(now),
+            last_update_timestamp: Timestamp(now),
+            delete_timestamp: None,
             user_id: request.user_id,
+        };
+        if contact_method.preferred {
+            self.clear_preferred(&contact_method)?;
+        }
+        self.storage
+            .create_contact_method(contact_method.clone())
+            .context(format!(
+                "Creating contact method with id {}",
+                contact_method_id
+            ))?;
+        Ok(CreateContactMethodResponse { contact_method })
+    }
+    fn update_contact_method(
+        &mut self,
+        request: UpdateContactMethodRequest,
+    ) -> Result<UpdateContactMethodResponse> {
+        let mut request = request;
+        let existing = self
+            .storage
+            .get_contact_methods(request.user_id)
+            .context("getting contact methods")?
+            .remove(&request.contact_method_id)
+            .ok_or(NotFoundError {
+                kind: "contact method",
+                id: request.contact_method_id,
+            })?;
+        if let Some(value) = &request.value {
+            request.value = Some(validate_contact_value(existing.kind, value)?);
+        }
+        if request.preferred == Some(true) {
+            self.clear_preferred(&existing)?;
+        }
+        self.storage
+            .update_contact_method(request)
+            .context("updating contact method")?;
+        Ok(UpdateContactMethodResponse {})
+    }
+    fn archive_contact_method(
+        &mut self,
+        request: ArchiveContactMethodRequest,
+    ) -> Result<ArchiveContactMethodResponse> {
+        self.storage
+            .archive_contact_method(request.id, request.user_id)
+            .context("Attempting to archive contact method")?;
+        Ok(ArchiveContactMethodResponse {})
+    }
+    fn export_vcard(&self, request: ExportVCardRequest) -> Result<ExportVCardResponse> {
+        let mut buddies = self
+            .storage
+            .get_buddies(request.user_id)
+            .context("getting buddies")?;
+        let contact_methods = self
+            .storage
+            .get_contact_methods(request.user_id)
+            .context("getting contact methods")?;
+        attach_contact_methods(&mut buddies, contact_methods);
+
+        let mut buddies: Vec<&Buddy> = buddies
+            .values()
+            .filter(|buddy| buddy.delete_timestamp.is_none())
+            .filter(|buddy| request.buddy_id.is_none() || request.buddy_id == Some(buddy.id))
+            .collect();
+        if let Some(buddy_id) = request.buddy_id {
+            if buddies.is_empty() {
+                return Err(NotFoundError {
+                    kind: "buddy",
+                    id: buddy_id,
+                }
+                .into());
+            }
+        }
+        buddies.sort_by(|a, b| a.name.cmp(&b.name));
+        let vcard = buddies.into_iter().map(buddy_to_vcard).collect();
+        Ok(ExportVCardResponse { vcard })
+    }
+    fn create_important_date(
+        &mut self,
+        request: CreateImportantDateRequest,
+    ) -> Result<CreateImportantDateResponse> {
+        self.ensure_buddy_exists(request.user_id, request.buddy_id)?;
+        validate_important_date(request.kind, &request.label, &request.date)?;
+
+        let important_date_id = Uuid::new_v4();
+        let now = self.storage.now()?;
+        let important_date = ImportantDate {
+            id: important_date_id,
+            buddy_id: request.buddy_id,
+            kind: request.kind,
+            label: request.label,
             date: request.date,
-            participants: request.participants,
+            recurrence: request.recurrence,
             create_timestamp: Timestamp(now),
             last_update_timestamp: Timestamp(now),
             delete_timestamp: None,
+            user_id: request.user_id,
         };
+        self.storage
+            .create_important_date(important_date.clone())
+            .context(format!(
+                "Creating important date with id {}",
+                important_date_id
+            ))?;
+        Ok(CreateImportantDateResponse { important_date })
+    }
+    fn update_important_date(
+        &mut self,
+        request: UpdateImportantDateRequest,
+    ) -> Result<UpdateImportantDateResponse> {
+        let existing = self
+            .storage
+            .get_important_dates(request.user_id)
+            .context("getting important dates")?
+            .remove(&request.important_date_id)
+            .ok_or(NotFoundError {
+                kind: "important date",
+                id: request.important_date_id,
+            })?;
+        validate_important_date(
+            request.kind.unwrap_or(existing.kind),
+            &request.label.clone().or(existing.label),
+            request.date.as_ref().unwrap_or(&existing.date),
+        )?;
+        self.storage
+            .update_important_date(request)
+            .context("updating important date")?;
+        Ok(UpdateImportantDateResponse {})
+    }
+    fn archive_important_date(
+        &mut self,
+        request: ArchiveImportantDateRequest,
+    ) -> Result<ArchiveImportantDateResponse> {
+        self.storage
+            .archive_important_date(request.id, request.user_id)
+            .context("Attempting to archive important date")?;
+        Ok(ArchiveImportantDateResponse {})
+    }
+    fn get_upcoming_dates(
+        &self,
+        request: GetUpcomingDatesRequest,
+    ) -> Result<GetUpcomingDatesResponse> {
+        let buddies = self.get_buddies_with_important_dates(request.user_id)?;
+        let days = request.days.unwrap_or(DEFAULT_UPCOMING_DAYS);
+        let (birthdays, important_dates) =
+            upcoming_dates(&buddies, Local::today().naive_local(), days)
+                .context("finding upcoming dates")?;
+        Ok(GetUpcomingDatesResponse {
+            birthdays,
+            important_dates,
+        })
+    }
+    fn create_follow_up(
+        &mut self,
+        request: CreateFollowUpRequest,
+    ) -> Result<CreateFollowUpResponse> {
+        match request.buddy_id {
+            Some(buddy_id) => {
+                self.ensure_buddy_participated(request.user_id, buddy_id, request.interaction_id)?
+            }
+            None => {
+                self.live_interaction(request.user_id, request.interaction_id)?;
+            }
+        }
+        if let Some(due) = &request.due {
+            requested_date(due)?;
+        }
 
+        let follow_up_id = Uuid::new_v4();
+        let now = self.storage.now()?;
+        let follow_up = FollowUp {
+            id: follow_up_id,
+            interaction_id: request.interaction_id,
+            buddy_id: request.buddy_id,
+            notes: request.notes,
+            due: request.due,
+            done: false,
+            create_timestamp: Timestamp(now),
+            last_update_timestamp: Timestamp(now),
+            delete_timestamp: None,
+            user_id: request.user_id,
+        };
+        self.storage
+            .create_follow_up(follow_up.clone())
+            .context(format!("Creating follow up with id {}", follow_up_id))?;
+        Ok(CreateFollowUpResponse { follow_up })
+    }
+    fn complete_follow_up(
+        &mut self,
+        request: CompleteFollowUpRequest,
+    ) -> Result<CompleteFollowUpResponse> {
         self.storage
-            .create_interaction(interaction.clone())
-            .context(format!("Creating interaction with id {}", interaction_id))?;
+            .complete_follow_up(request.id, request.user_id)
+            .context("Attempting to complete follow up")?;
+        Ok(CompleteFollowUpResponse {})
+    }
+    fn list_follow_ups(&self, request: ListFollowUpsRequest) -> Result<ListFollowUpsResponse> {
+        let today = Local::today().naive_local();
+        let filter = request.filter;
+        let mut follow_ups = Vec::new();
+        for follow_up in self
+            .storage
+            .get_follow_ups(request.user_id)
+            .context("getting follow ups")?
+            .into_values()
+        {
+            if follow_up.done
+                || (filter.buddy.is_some() && follow_up.buddy_id != filter.buddy)
+                || (filter.interaction.is_some()
+                    && Some(follow_up.interaction_id) != filter.interaction)
+            {
+                continue;
+            }
+            if filter.overdue && follow_up_days_overdue(&follow_up, today)?.is_none() {
+                continue;
+            }
+            follow_ups.push(follow_up);
+        }
+        follow_ups.sort_by(|a, b| {
+            (a.due.is_none(), &a.due, a.create_timestamp.0).cmp(&(
+                b.due.is_none(),
+                &b.due,
+                b.create_timestamp.0,
+            ))
+        });
+        Ok(ListFollowUpsResponse { follow_ups })
+    }
+    fn create_idea(&mut self, request: CreateIdeaRequest) -> Result<CreateIdeaResponse> {
+        self.ensure_buddy_exists(request.user_id, request.buddy_id)?;
+        if let Some(interaction_id) = request.interaction_id {
+            self.ensure_buddy_participated(request.user_id, request.buddy_id, interaction_id)?;
+        }
 
-        Ok(CreateInteractionResponse { interaction })
+        let idea_id = Uuid::new_v4();
+        let now = self.storage.now()?;
+        let idea = Idea {
+            id: idea_id,
+            buddy_id: request.buddy_id,
+            kind: request.kind,
+            description: request.description,
+            status: request.status,
+            interaction_id: request.interaction_id,
+            create_timestamp: Timestamp(now),
+            last_update_timestamp: Timestamp(now),
+            delete_timestamp: None,
+            user_id: request.user_id,
+        };
+        self.storage
+            .create_idea(idea.clone())
+            .context(format!("Creating idea with id {}", idea_id))?;
+        Ok(CreateIdeaResponse { idea })
     }
-    fn update_interaction(
+    fn update_idea(&mut self, request: UpdateIdeaRequest) -> Result<UpdateIdeaResponse> {
+        if let Some(interaction_id) = request.interaction_id {
+            let idea = self
+                .storage
+                .get_ideas(request.user_id)
+                .context("getting ideas")?
+                .remove(&request.idea_id)
+                .ok_or(NotFoundError {
+                    kind: "idea",
+                    id: request.idea_id,
+                })?;
+            self.ensure_buddy_participated(request.user_id, idea.buddy_id, interaction_id)?;
+        }
+        self.storage.update_idea(request).context("updating idea")?;
+        Ok(UpdateIdeaResponse {})
+    }
+    fn archive_idea(&mut self, request: ArchiveIdeaRequest) -> Result<ArchiveIdeaResponse> {
+        self.storage
+            .archive_idea(request.id, request.user_id)
+            .context("Attempting to archive idea")?;
+        Ok(ArchiveIdeaResponse {})
+    }
+    fn get_user(&self, request: GetUserRequest) -> Result<PublicUser> {
+        let user = self.find_user(request.user_id)?;
+        Ok(PublicUser::from(user))
+    }
+    fn update_user(&mut self, request: UpdateUserRequest) -> Result<UpdateUserResponse> {
+        self.find_user(request.user_id)?;
+        self.storage.update_user(request).context("updating user")?;
+        Ok(UpdateUserResponse {})
+    }
+    fn get_stats(&self, request: GetStatsRequest) -> Result<GetStatsResponse> {
+        let window_days = request.window_days.unwrap_or(DEFAULT_STATS_WINDOW_DAYS);
+        if window_days < 1 {
+            return Err(InvalidRequestError {
+                message: format!("Stats window must be at least a day, not {}", window_days),
+            }
+            .into());
+        }
+        let today = Local::today().naive_local();
+        let window_start = today - Duration::days(window_days - 1);
+
+        let buddies = self
+            .storage
+            .get_buddies(request.user_id)
+            .context("getting buddies")?;
+        let history = self
+            .storage
+            .get_contact_history(
+                request.user_id,
+                Some(&Datestamp::from(window_start)),
+                Some(&Datestamp::from(today)),
+            )
+            .context("getting contact history")?;
+        let (aggregate, buddies) = compute_stats(&buddies, &history, window_start, today)
+            .context(format!("Computing stats for user {}", request.user_id))?;
+        Ok(GetStatsResponse {
+            window_start: Datestamp::from(window_start),
+            window_end: Datestamp::from(today),
+            aggregate,
+            buddies,
+        })
+    }
+    fn get_digest(&self, request: GetDigestRequest) -> Result<GetDigestResponse> {
+        let user = self.find_user(request.user_id)?;
+        let buddies = self.get_buddies_with_important_dates(request.user_id)?;
+        let interactions = self
+            .storage
+            .get_interactions(request.user_id)
+            .context("getting interactions")?;
+
+        let follow_ups = self
+            .storage
+            .get_follow_ups(request.user_id)
+            .context("getting follow ups")?;
+
+        let today = Local::today().naive_local();
+        let digest = build_digest(&user, &buddies, &interactions, &follow_ups, today)
+            .context(format!("Building digest for user {}", user.id))?;
+        let text = render_text(&digest)?;
+        let html = render_html(&digest)?;
+        Ok(GetDigestResponse { digest, text, html })
+    }
+    fn create_calendar_token(
         &mut self,
-        request: UpdateInteractionRequest,
-    ) -> Result<UpdateInteractionResponse> {
+        request: CreateCalendarTokenRequest,
+    ) -> Result<CreateCalendarTokenResponse> {
+        let token = Uuid::new_v4().to_simple().to_string();
         self.storage
-            .update_interaction(request)
-            .context("updating interaction")?;
-        Ok(UpdateInteractionResponse {})
+            .set_calendar_token(request.user_id, token.clone())
+            .context("setting calendar token")?;
+        Ok(CreateCalendarTokenResponse {
+            path: format!("/calendar/{}.ics", token),
+            token,
+        })
     }
-    fn archive_interaction(
+    fn get_calendar(&self, request: GetCalendarRequest) -> Result<GetCalendarResponse> {
+        let user = self
+            .storage
+            .get_user_by_calendar_token(&request.token)
+            .context("getting user for calendar")?
+            // Tokens are simple uuids, so one that isn't can't belong to anyone
+            .ok_or_else(|| NotFoundError {
+                kind: "calendar",
+                id: Uuid::parse_str(&request.token).unwrap_or_else(|_| Uuid::nil()),
+            })?;
+        let buddies = self.get_buddies_with_important_dates(user.id)?;
+        let ics = build_calendar(
+            &buddies,
+            Local::today().naive_local(),
+            Utc::now().naive_utc(),
+        )
+        .context(format!("Building calendar for user {}", user.id))?;
+        Ok(GetCalendarResponse { ics })
+    }
+    fn create_webhook(&mut self, request: CreateWebhookRequest) -> Result<CreateWebhookResponse> {
+        let url = Url::parse(&request.url).map_err(|_| InvalidRequestError {
+            message: format!("Webhook url {} is not a url", request.url),
+        })?;
+        if url.scheme() != "http" && url.scheme() != "https" {
+            return Err(InvalidRequestError {
+                message: format!("Webhook url {} must be http or https", request.url),
+            }
+            .into());
+        }
+        check_webhook_url(&url)?;
+        let now = self.storage.now()?;
+        let webhook = Webhook {
+            id: Uuid::new_v4(),
+            url: url.to_string(),
+            secret: Uuid::new_v4().to_simple().to_string(),
+            create_timestamp: Timestamp(now),
+            delete_timestamp: None,
+            user_id: request.user_id,
+        };
+        self.storage
+            .create_webhook(webhook.clone())
+            .context(format!("Creating webhook with id {}", webhook.id))?;
+        Ok(CreateWebhookResponse {
+            secret: webhook.secret.clone(),
+            webhook,
+        })
+    }
+    fn list_webhooks(&self, request: ListWebhooksRequest) -> Result<ListWebhooksResponse> {
+        let mut webhooks: Vec<Webhook> = self
+            .storage
+            .get_webhooks(request.user_id)
+            .context("getting webhooks")?
+            .into_values()
+            .collect();
+        webhooks.sort_by_key(|webhook| (webhook.create_timestamp.0, webhook.id));
+        Ok(ListWebhooksResponse { webhooks })
+    }
+    fn archive_webhook(
         &mut self,
-        request: ArchiveInteractionRequest,
-    ) -> Result<ArchiveInteractionResponse> {
+        request: ArchiveWebhookRequest,
+    ) -> Result<ArchiveWebhookResponse> {
+        let webhooks = self
+            .storage
+            .get_webhooks(request.user_id)
+            .context("getting webhooks")?;
+        if !webhooks.contains_key(&request.id) {
+            return Err(NotFoundError {
+                kind: "webhook",
+                id: request.id,
+            }
+            .into());
+        }
         self.storage
-            .archive_interaction(request.id, request.user_id)
-            .context("Attempting to archive interaction")?;
-        Ok(ArchiveInteractionResponse {})
+            .archive_webhook(request.id, request.user_id)
+            .context("Attempting to archive webhook")?;
+        Ok(ArchiveWebhookResponse {})
+    }
+    fn list_webhook_deliveries(
+        &self,
+        request: ListWebhookDeliveriesRequest,
+    ) -> Result<ListWebhookDeliveriesResponse> {
+        let deliveries = self
+            .storage
+            .get_webhook_deliveries(request.user_id, request.webhook_id)
+            .context("getting webhook deliveries")?;
+        Ok(ListWebhookDeliveriesResponse { deliveries })
     }
 }
 
@@ -211,13 +2401,21 @@ impl<S: BuddiesStore> BuddiesService for RequestHandler<S> {
 pub struct Claims {
     sub: String,
     exp: usize,
+    /// JWTs from before scopes existed could do anything, so they still can
+    #[serde(default = "all_scopes")]
+    scopes: Vec<Scope>,
+}
+
+fn all_scopes() -> Vec<Scope> {
+    Scope::ALL.to_vec()
 }
 
 impl Claims {
-    fn with_user_id(user_id: Uuid) -> Self {
+    fn with_user_id(user_id: Uuid, scopes: Vec<Scope>) -> Self {
         Claims {
             sub: user_id.to_string(),
             exp: (Local::now() + Duration::hours(JWT_EXPIRATION_HOURS)).timestamp() as usize,
+            scopes,
         }
     }
     fn get_user_id(&self) -> Result<Uuid> {
@@ -248,8 +2446,8 @@ impl<S: AuthStore> AuthHandler<S> {
         }
     }
 
-    pub fn create_jwt(&self, user_id: Uuid) -> Result<String> {
-        let claims = Claims::with_user_id(user_id);
+    pub fn create_jwt(&self, user_id: Uuid, scopes: Vec<Scope>) -> Result<String> {
+        let claims = Claims::with_user_id(user_id, scopes);
         let encoding_key = EncodingKey::from_rsa_pem(&self.secret).context("creating encoder")?;
         match encode(&Header::new(Algorithm::RS256), &claims, &encoding_key) {
             Ok(encoded) => Ok(encoded),
@@ -269,3 +2467,417 @@ impl<S: AuthStore> AuthHandler<S> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::storage::MemoryBuddiesStore;
+
+    fn create_buddy(handler: &mut RequestHandler<MemoryBuddiesStore>, user_id: Uuid) -> Buddy {
+        handler
+            .create_buddy(CreateBuddyRequest {
+                user_id,
+                name: "Buddy".to_string(),
+                ..Default::default()
+            })
+            .unwrap()
+            .buddy
+    }
+
+    fn create_interaction(
+        handler: &mut RequestHandler<MemoryBuddiesStore>,
+        user_id: Uuid,
+        buddy_id: Uuid,
+    ) -> Interaction {
+        handler
+            .create_interaction(CreateInteractionRequest {
+                user_id,
+                notes: "Lunch".to_string(),
+                participants: vec![buddy_id].into_iter().collect(),
+                ..Default::default()
+            })
+            .unwrap()
+            .interaction
+    }
+
+    fn is_not_found(result: Result<impl std::fmt::Debug>) -> bool {
+        match result {
+            Ok(_) => false,
+            Err(e) => e.downcast_ref::<NotFoundError>().is_some(),
+        }
+    }
+
+    fn changes(
+        handler: &RequestHandler<MemoryBuddiesStore>,
+        user_id: Uuid,
+        since: Option<&str>,
+    ) -> GetChangesResponse {
+        handler
+            .get_changes(GetChangesRequest {
+                user_id,
+                since: since.map(str::to_string),
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn syncs_send_each_change_once() {
+        let mut handler = RequestHandler::new(MemoryBuddiesStore::new());
+        handler.storage.set_time(1_000);
+        let user_id = Uuid::new_v4();
+        let kept = create_buddy(&mut handler, user_id);
+        let archived = create_buddy(&mut handler, user_id);
+        let interaction = create_interaction(&mut handler, user_id, archived.id);
+        handler.storage.set_time(1_001);
+
+        let first = changes(&handler, user_id, None);
+        let mut ids: Vec<Uuid> = first.buddies.iter().map(|buddy| buddy.id).collect();
+        ids.sort();
+        let mut expected = vec![kept.id, archived.id];
+        expected.sort();
+        assert_eq!(ids, expected);
+        assert_eq!(first.interactions.len(), 1);
+        assert!(first.tombstones.is_empty());
+        let again = changes(&handler, user_id, Some(&first.cursor));
+        assert!(again.buddies.is_empty() && again.interactions.is_empty());
+
+        handler
+            .update_buddy(UpdateBuddyRequest {
+                user_id,
+                buddy_id: kept.id,
+                notes: Some("Changed".to_string()),
+                ..Default::default()
+            })
+            .unwrap();
+        handler
+            .archive_interaction(ArchiveInteractionRequest {
+                id: interaction.id,
+                user_id,
+                version: None,
+            })
+            .unwrap();
+        handler
+            .archive_buddy(ArchiveBuddyRequest {
+                id: archived.id,
+                user_id,
+                version: None,
+            })
+            .unwrap();
+        handler.storage.set_time(1_002);
+
+        let second = changes(&handler, user_id, Some(&first.cursor));
+        assert_eq!(second.buddies.len(), 1);
+        assert_eq!(
+            (second.buddies[0].id, second.buddies[0].version),
+            (kept.id, 2)
+        );
+        assert!(second.interactions.is_empty());
+        let tombstones: Vec<(EntityKind, Uuid, i32)> = second
+            .tombstones
+            .iter()
+            .map(|tombstone| (tombstone.entity_kind, tombstone.id, tombstone.version))
+            .collect();
+        assert_eq!(tombstones.len(), 2);
+        assert!(tombstones.contains(&(EntityKind::Buddy, archived.id, 2)));
+        assert!(tombstones.contains(&(EntityKind::Interaction, interaction.id, 2)));
+        let third = changes(&handler, user_id, Some(&second.cursor));
+        assert!(third.buddies.is_empty() && third.tombstones.is_empty());
+
+        // A client starting over has nothing to delete
+        let fresh = changes(&handler, user_id, None);
+        assert_eq!(fresh.buddies.len(), 1);
+        assert!(fresh.interactions.is_empty() && fresh.tombstones.is_empty());
+
+        let cursors: Vec<u64> = [&first, &again, &second, &third, &fresh]
+            .iter()
+            .map(|changes| changes.cursor.parse().unwrap())
+            .collect();
+        assert!(
+            cursors.windows(2).all(|pair| pair[0] <= pair[1]),
+            "{:?}",
+            cursors
+        );
+    }
+
+    #[test]
+    fn changes_written_as_the_cursor_is_taken_come_next_time() {
+        let mut handler = RequestHandler::new(MemoryBuddiesStore::new());
+        handler.storage.set_time(1_000);
+        let user_id = Uuid::new_v4();
+        let first = changes(&handler, user_id, None);
+        let buddy = create_buddy(&mut handler, user_id);
+        // Until the second the buddy was written in is over, it isn't sent
+        let during = changes(&handler, user_id, Some(&first.cursor));
+        assert!(during.buddies.is_empty());
+
+        handler.storage.set_time(1_001);
+        let after = changes(&handler, user_id, Some(&during.cursor));
+        let ids: Vec<Uuid> = after.buddies.iter().map(|found| found.id).collect();
+        assert_eq!(ids, vec![buddy.id]);
+        assert!(changes(&handler, user_id, Some(&after.cursor))
+            .buddies
+            .is_empty());
+    }
+
+    #[test]
+    fn unreadable_cursors_are_invalid() {
+        let handler = RequestHandler::new(MemoryBuddiesStore::new());
+        let err = handler
+            .get_changes(GetChangesRequest {
+                user_id: Uuid::new_v4(),
+                since: Some("yesterday".to_string()),
+            })
+            .unwrap_err();
+        assert!(
+            err.downcast_ref::<InvalidRequestError>().is_some(),
+            "{:?}",
+            err
+        );
+    }
+
+    #[test]
+    fn archived_records_cannot_be_edited() {
+        let mut handler = RequestHandler::new(MemoryBuddiesStore::new());
+        let user_id = Uuid::new_v4();
+        let buddy = create_buddy(&mut handler, user_id);
+        let interaction = create_interaction(&mut handler, user_id, buddy.id);
+        handler
+            .archive_interaction(ArchiveInteractionRequest {
+                id: interaction.id,
+                user_id,
+                version: None,
+            })
+            .unwrap();
+        handler
+            .archive_buddy(ArchiveBuddyRequest {
+                id: buddy.id,
+                user_id,
+                version: None,
+            })
+            .unwrap();
+        let history = |handler: &RequestHandler<MemoryBuddiesStore>, entity_id| {
+            handler
+                .storage
+                .get_revisions(user_id, entity_id)
+                .unwrap()
+                .len()
+        };
+        let revisions = (
+            history(&handler, buddy.id),
+            history(&handler, interaction.id),
+        );
+
+        assert!(is_not_found(handler.update_buddy(UpdateBuddyRequest {
+            user_id,
+            buddy_id: buddy.id,
+            name: Some("Changed".to_string()),
+            ..Default::default()
+        })));
+        assert!(is_not_found(handler.update_interaction(
+            UpdateInteractionRequest {
+                user_id,
+                interaction_id: interaction.id,
+                notes: Some("Changed".to_string()),
+                ..Default::default()
+            }
+        )));
+        assert_eq!(
+            (
+                history(&handler, buddy.id),
+                history(&handler, interaction.id)
+            ),
+            revisions
+        );
+    }
+
+    #[test]
+    fn failed_batches_undo_earlier_operations_without_announcing_them() {
+        let mut handler = RequestHandler::new(MemoryBuddiesStore::new());
+        let user_id = Uuid::new_v4();
+        let buddy = create_buddy(&mut handler, user_id);
+        let mut listener = handler.events.listen();
+
+        let response = handler
+            .batch(BatchRequest {
+                user_id,
+                operations: vec![
+                    Mutation::UpdateBuddy(UpdateBuddyRequest {
+                        user_id,
+                        buddy_id: buddy.id,
+                        name: Some("Changed".to_string()),
+                        ..Default::default()
+                    }),
+                    Mutation::CreateBuddy(CreateBuddyRequest {
+                        user_id,
+                        name: "New".to_string(),
+                        ..Default::default()
+                    }),
+                    Mutation::UpdateBuddy(UpdateBuddyRequest {
+                        user_id,
+                        buddy_id: buddy.id,
+                        name: Some("Stale".to_string()),
+                        version: Some(buddy.version),
+                        ..Default::default()
+                    }),
+                ],
+            })
+            .unwrap();
+
+        assert!(!response.applied);
+        assert_eq!(response.failed_index, Some(2));
+        let statuses: Vec<BatchStatus> = response.results.iter().map(|r| r.status).collect();
+        assert_eq!(
+            statuses,
+            vec![
+                BatchStatus::RolledBack,
+                BatchStatus::RolledBack,
+                BatchStatus::Conflict
+            ]
+        );
+        let buddies = handler.storage.get_buddies(user_id).unwrap();
+        assert_eq!(buddies.len(), 1);
+        assert_eq!(buddies[&buddy.id].name, buddy.name);
+        assert_eq!(buddies[&buddy.id].version, buddy.version);
+        assert_eq!(
+            handler
+                .storage
+                .get_revisions(user_id, buddy.id)
+                .unwrap()
+                .len(),
+            1
+        );
+        assert!(listener.try_recv().is_err());
+    }
+
+    #[test]
+    fn malformed_contact_values_are_invalid_requests() {
+        assert_eq!(
+            validate_contact_value(ContactMethodKind::Phone, " +1 (555) 010-0000 ").unwrap(),
+            "+15550100000"
+        );
+        assert_eq!(
+            validate_contact_value(ContactMethodKind::Email, "jo@example.com").unwrap(),
+            "jo@example.com"
+        );
+        for (kind, value) in &[
+            (ContactMethodKind::Phone, "555 0100"),
+            (ContactMethodKind::Phone, "+0123"),
+            (ContactMethodKind::Phone, "+1234567890123456"),
+            (ContactMethodKind::Email, "jo.example.com"),
+            (ContactMethodKind::Email, "jo@example"),
+            (ContactMethodKind::Email, "jo@@example.com"),
+            (ContactMethodKind::Email, "j o@example.com"),
+            (ContactMethodKind::Url, "example.com"),
+            (ContactMethodKind::Url, "ftp://example.com"),
+            (ContactMethodKind::Social, "  "),
+        ] {
+            let err = validate_contact_value(*kind, value).unwrap_err();
+            assert!(
+                err.downcast_ref::<InvalidRequestError>().is_some(),
+                "{} {:?}",
+                value,
+                err
+            );
+        }
+    }
+
+    #[test]
+    fn merging_moves_the_losers_contact_methods_without_doubling_up() {
+        let mut handler = RequestHandler::new(MemoryBuddiesStore::new());
+        let user_id = Uuid::new_v4();
+        let (winner, loser) = (
+            create_buddy(&mut handler, user_id),
+            create_buddy(&mut handler, user_id),
+        );
+        let mut add = |buddy_id, kind, value: &str, preferred| {
+            handler
+                .create_contact_method(CreateContactMethodRequest {
+                    user_id,
+                    buddy_id,
+                    kind,
+                    value: value.to_string(),
+                    label: None,
+                    preferred,
+                })
+                .unwrap()
+                .contact_method
+        };
+        let winners_email = add(winner.id, ContactMethodKind::Email, "jo@example.com", false);
+        let winners_phone = add(winner.id, ContactMethodKind::Phone, "+15550100000", true);
+        let same_email = add(loser.id, ContactMethodKind::Email, "JO@example.com", false);
+        let other_phone = add(loser.id, ContactMethodKind::Phone, "+15550100001", true);
+        let url = add(
+            loser.id,
+            ContactMethodKind::Url,
+            "https://example.com",
+            true,
+        );
+
+        handler
+            .merge_buddies(MergeBuddiesRequest {
+                user_id,
+                winner_id: winner.id,
+                loser_id: loser.id,
+            })
+            .unwrap();
+
+        let contact_methods = handler.storage.get_contact_methods(user_id).unwrap();
+        assert!(!contact_methods.contains_key(&same_email.id));
+        let mut moved: Vec<(Uuid, Uuid, bool)> = contact_methods
+            .values()
+            .map(|method| (method.id, method.buddy_id, method.preferred))
+            .collect();
+        moved.sort();
+        let mut expected = vec![
+            (winners_email.id, winner.id, false),
+            (winners_phone.id, winner.id, true),
+            (other_phone.id, winner.id, false),
+            (url.id, winner.id, true),
+        ];
+        expected.sort();
+        assert_eq!(moved, expected);
+    }
+
+    #[test]
+    fn suggested_cadences_are_applied_together_and_announced_after() {
+        let mut handler = RequestHandler::new(MemoryBuddiesStore::new());
+        let user_id = Uuid::new_v4();
+        let buddies = vec![
+            create_buddy(&mut handler, user_id),
+            create_buddy(&mut handler, user_id),
+        ];
+        for buddy in &buddies {
+            for date in &["2021-01-01", "2021-01-08", "2021-01-15"] {
+                handler
+                    .create_interaction(CreateInteractionRequest {
+                        user_id,
+                        notes: "Lunch".to_string(),
+                        participants: vec![buddy.id].into_iter().collect(),
+                        date: Some(Datestamp(date.to_string())),
+                        ..Default::default()
+                    })
+                    .unwrap();
+            }
+        }
+        let mut listener = handler.events.listen();
+
+        let response = handler
+            .apply_suggested_cadence(ApplySuggestedCadenceRequest {
+                user_id,
+                buddy_ids: None,
+            })
+            .unwrap();
+
+        assert_eq!(response.updated.len(), 2);
+        let stored = handler.storage.get_buddies(user_id).unwrap();
+        for buddy in &buddies {
+            assert_eq!(stored[&buddy.id].cadence, Some(response.updated[&buddy.id]));
+            assert_eq!(stored[&buddy.id].version, buddy.version + 1);
+        }
+        let announced: HashSet<Uuid> = (0..2)
+            .map(|_| listener.try_recv().unwrap().entity_id)
+            .collect();
+        assert_eq!(announced, buddies.iter().map(|buddy| buddy.id).collect());
+        assert!(listener.try_recv().is_err());
+    }
+}
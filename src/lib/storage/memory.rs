@@ -1,33 +1,191 @@
 use super::traits::{AuthStore, BuddiesStore};
 use crate::lib::types::{
-    Buddy, CreateUserRequest, Interaction, LoginRequest, Timestamp, UpdateBuddyRequest,
-    UpdateInteractionRequest, User,
+    Buddy, ContactHistory, ContactMethod, CreateUserRequest, CurrentRecord, Datestamp,
+    DeliveryStatus, FollowUp, Idea, IdempotencyRecord, ImportantDate, Interaction, LoginRequest,
+    NotFoundError, PersonalAccessToken, Relationship, Revision, StaleWriteError, Timestamp,
+    UpdateBuddyRequest, UpdateContactMethodRequest, UpdateIdeaRequest, UpdateImportantDateRequest,
+    UpdateInteractionRequest, UpdateRelationshipRequest, UpdateUserRequest, User, Webhook,
+    WebhookDelivery,
 };
 use anyhow::{anyhow, Context, Result};
 use std::collections::HashMap;
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, LockResult, Mutex, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
 use std::time::SystemTime;
 use uuid::Uuid;
 
+/// The user, operation and key an idempotency record is stored under
+type IdempotencyKey = (Uuid, String, String);
+
+/// A table, along with a copy of it for each running transaction that has written to it.
+/// The copy is taken on a transaction's first write, so tables it doesn't touch are never
+/// copied, but one it does is copied whole.
+struct Table<T> {
+    rows: Arc<RwLock<T>>,
+    /// One per running transaction, innermost last, holding the rows from before it first
+    /// wrote to the table
+    snapshots: Arc<Mutex<Vec<Option<T>>>>,
+}
+
+impl<T: Default> Default for Table<T> {
+    fn default() -> Self {
+        Table {
+            rows: Arc::new(RwLock::new(T::default())),
+            snapshots: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+}
+
+impl<T> Clone for Table<T> {
+    fn clone(&self) -> Self {
+        Table {
+            rows: Arc::clone(&self.rows),
+            snapshots: Arc::clone(&self.snapshots),
+        }
+    }
+}
+
+impl<T: Clone> Table<T> {
+    fn read(&self) -> LockResult<RwLockReadGuard<'_, T>> {
+        self.rows.read()
+    }
+    /// Writes are saved for the innermost transaction before the first one it makes
+    fn write(&self) -> LockResult<RwLockWriteGuard<'_, T>> {
+        let rows = self.rows.write();
+        if let Ok(rows) = &rows {
+            if let Some(snapshot @ None) = self.snapshots.lock().unwrap().last_mut() {
+                *snapshot = Some((**rows).clone());
+            }
+        }
+        rows
+    }
+}
+
+/// Starting and ending transactions on a table, whatever it holds
+trait Journaled {
+    fn begin(&self);
+    /// Keeps the innermost transaction's writes. The rows from before them are the rows
+    /// from before the enclosing transaction too, if that one hasn't written yet.
+    fn commit(&self);
+    /// Puts back the rows from before the innermost transaction's writes
+    fn roll_back(&self);
+}
+
+impl<T: Clone + Send> Journaled for Table<T> {
+    fn begin(&self) {
+        self.snapshots.lock().unwrap().push(None);
+    }
+    fn commit(&self) {
+        let mut snapshots = self.snapshots.lock().unwrap();
+        let snapshot = snapshots.pop().flatten();
+        if let Some(enclosing @ None) = snapshots.last_mut() {
+            *enclosing = snapshot;
+        }
+    }
+    fn roll_back(&self) {
+        let snapshot = self.snapshots.lock().unwrap().pop().flatten();
+        if let Some(snapshot) = snapshot {
+            *self.rows.write().unwrap() = snapshot;
+        }
+    }
+}
+
 /// In memory storage for testing
 #[derive(Clone)]
 pub struct MemoryBuddiesStore {
     /// Represents a "buddies" table
-    buddy_storage: Arc<RwLock<HashMap<Uuid, Buddy>>>,
+    buddy_storage: Table<HashMap<Uuid, Buddy>>,
     /// Represents an "interactions" table
-    interaction_storage: Arc<RwLock<HashMap<Uuid, Interaction>>>,
+    interaction_storage: Table<HashMap<Uuid, Interaction>>,
+    /// Represents a "buddy_relationships" table
+    relationship_storage: Table<HashMap<Uuid, Relationship>>,
+    /// Represents a "contact_methods" table
+    contact_method_storage: Table<HashMap<Uuid, ContactMethod>>,
+    /// Represents an "important_dates" table
+    important_date_storage: Table<HashMap<Uuid, ImportantDate>>,
+    /// Represents a "follow_ups" table
+    follow_up_storage: Table<HashMap<Uuid, FollowUp>>,
+    /// Represents an "ideas" table
+    idea_storage: Table<HashMap<Uuid, Idea>>,
+    /// Represents a "revisions" table, in insertion order
+    revision_storage: Table<Vec<Revision>>,
+    /// Represents an "idempotency_keys" table, keyed on user, operation and key
+    idempotency_storage: Table<HashMap<IdempotencyKey, IdempotencyRecord>>,
+    /// Represents a "webhooks" table
+    webhook_storage: Table<HashMap<Uuid, Webhook>>,
+    /// Represents a "webhook_deliveries" table, in insertion order
+    webhook_delivery_storage: Table<Vec<WebhookDelivery>>,
     /// Represents an "users" table
-    user_storage: Arc<RwLock<HashMap<String, User>>>,
+    user_storage: Table<HashMap<String, User>>,
+    /// Represents a "personal_access_tokens" table
+    personal_access_token_storage: Table<HashMap<Uuid, PersonalAccessToken>>,
+    /// Writes hold this shared and transactions hold it exclusively, so nothing else writes
+    /// while a transaction that might be rolled back is running
+    write_lock: Arc<RwLock<()>>,
+    /// Set on the copy of the store a transaction hands to its closure, which already holds
+    /// `write_lock` exclusively
+    in_transaction: bool,
+    /// The time every timestamp is taken from when a test has pinned it, otherwise the
+    /// system clock is used
+    clock: Arc<Mutex<Option<u64>>>,
 }
 
 impl MemoryBuddiesStore {
     pub fn new() -> Self {
         Self {
-            buddy_storage: Arc::new(RwLock::new(HashMap::new())),
-            interaction_storage: Arc::new(RwLock::new(HashMap::new())),
-            user_storage: Arc::new(RwLock::new(HashMap::new())),
+            buddy_storage: Table::default(),
+            interaction_storage: Table::default(),
+            relationship_storage: Table::default(),
+            contact_method_storage: Table::default(),
+            important_date_storage: Table::default(),
+            follow_up_storage: Table::default(),
+            idea_storage: Table::default(),
+            revision_storage: Table::default(),
+            idempotency_storage: Table::default(),
+            webhook_storage: Table::default(),
+            webhook_delivery_storage: Table::default(),
+            user_storage: Table::default(),
+            personal_access_token_storage: Table::default(),
+            write_lock: Arc::new(RwLock::new(())),
+            in_transaction: false,
+            clock: Arc::default(),
         }
     }
+    /// Pins the clock at `now` seconds since the epoch, for this store and every clone of it
+    #[cfg(test)]
+    pub fn set_time(&self, now: u64) {
+        *self.clock.lock().unwrap() = Some(now);
+    }
+    /// Waits for any running transaction to finish and keeps new ones out until the guard
+    /// is dropped. Inside a transaction the lock is already held, so there's nothing to take.
+    fn shared_write(&self) -> Option<RwLockReadGuard<'_, ()>> {
+        if self.in_transaction {
+            None
+        } else {
+            Some(
+                self.write_lock
+                    .read()
+                    .unwrap_or_else(PoisonError::into_inner),
+            )
+        }
+    }
+    /// Every table, for starting and ending transactions
+    fn tables(&self) -> [&dyn Journaled; 13] {
+        [
+            &self.buddy_storage,
+            &self.interaction_storage,
+            &self.relationship_storage,
+            &self.contact_method_storage,
+            &self.important_date_storage,
+            &self.follow_up_storage,
+            &self.idea_storage,
+            &self.revision_storage,
+            &self.idempotency_storage,
+            &self.webhook_storage,
+            &self.webhook_delivery_storage,
+            &self.user_storage,
+            &self.personal_access_token_storage,
+        ]
+    }
     pub fn get_buddy(&self, buddy_id: &Uuid) -> Result<Buddy> {
         self.buddy_storage
             .read()
@@ -44,6 +202,104 @@ impl MemoryBuddiesStore {
             .context(format!("Looking for note with id {}", interaction_id))
             .map(|x| x.clone())
     }
+    pub fn get_relationship(&self, relationship_id: &Uuid, user_id: Uuid) -> Result<Relationship> {
+        self.relationship_storage
+            .read()
+            .unwrap()
+            .get(relationship_id)
+            .filter(|relationship| relationship.user_id == user_id)
+            .cloned()
+            .ok_or_else(|| {
+                NotFoundError {
+                    kind: "relationship",
+                    id: *relationship_id,
+                }
+                .into()
+            })
+    }
+    pub fn get_contact_method(
+        &self,
+        contact_method_id: &Uuid,
+        user_id: Uuid,
+    ) -> Result<ContactMethod> {
+        self.contact_method_storage
+            .read()
+            .unwrap()
+            .get(contact_method_id)
+            .filter(|contact_method| contact_method.user_id == user_id)
+            .cloned()
+            .ok_or_else(|| {
+                NotFoundError {
+                    kind: "contact method",
+                    id: *contact_method_id,
+                }
+                .into()
+            })
+    }
+    pub fn get_important_date(
+        &self,
+        important_date_id: &Uuid,
+        user_id: Uuid,
+    ) -> Result<ImportantDate> {
+        self.important_date_storage
+            .read()
+            .unwrap()
+            .get(important_date_id)
+            .filter(|important_date| important_date.user_id == user_id)
+            .cloned()
+            .ok_or_else(|| {
+                NotFoundError {
+                    kind: "important date",
+                    id: *important_date_id,
+                }
+                .into()
+            })
+    }
+    pub fn get_follow_up(&self, follow_up_id: &Uuid, user_id: Uuid) -> Result<FollowUp> {
+        self.follow_up_storage
+            .read()
+            .unwrap()
+            .get(follow_up_id)
+            .filter(|follow_up| follow_up.user_id == user_id)
+            .cloned()
+            .ok_or_else(|| {
+                NotFoundError {
+                    kind: "follow up",
+                    id: *follow_up_id,
+                }
+                .into()
+            })
+    }
+    pub fn get_idea(&self, idea_id: &Uuid, user_id: Uuid) -> Result<Idea> {
+        self.idea_storage
+            .read()
+            .unwrap()
+            .get(idea_id)
+            .filter(|idea| idea.user_id == user_id)
+            .cloned()
+            .ok_or_else(|| {
+                NotFoundError {
+                    kind: "idea",
+                    id: *idea_id,
+                }
+                .into()
+            })
+    }
+    pub fn get_webhook(&self, webhook_id: &Uuid, user_id: Uuid) -> Result<Webhook> {
+        self.webhook_storage
+            .read()
+            .unwrap()
+            .get(webhook_id)
+            .filter(|webhook| webhook.user_id == user_id)
+            .cloned()
+            .ok_or_else(|| {
+                NotFoundError {
+                    kind: "webhook",
+                    id: *webhook_id,
+                }
+                .into()
+            })
+    }
     pub fn find_user(&self, email: &String) -> Option<User> {
         self.user_storage
             .read()
@@ -54,26 +310,40 @@ impl MemoryBuddiesStore {
 }
 
 impl BuddiesStore for MemoryBuddiesStore {
+    fn now(&self) -> Result<u64> {
+        match *self.clock.lock().unwrap() {
+            Some(now) => Ok(now),
+            None => Ok(SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)?
+                .as_secs()),
+        }
+    }
     fn create_buddy(&mut self, buddy: Buddy) -> Result<()> {
-        self.buddy_storage
-            .write()
-            .unwrap()
-            .insert(buddy.id.clone(), buddy.clone());
+        let _writing = self.shared_write();
+        let mut buddy_storage = self.buddy_storage.write().unwrap();
+        // Clients can pick their own ids, so don't let them write over someone else's buddy
+        if buddy_storage.contains_key(&buddy.id) {
+            return Err(anyhow!("Buddy {} already exists", buddy.id));
+        }
+        buddy_storage.insert(buddy.id, buddy.clone());
         Ok(())
     }
     fn create_interaction(&mut self, interaction: Interaction) -> Result<()> {
-        self.interaction_storage
-            .write()
-            .unwrap()
-            .insert(interaction.id.clone(), interaction.clone());
+        let _writing = self.shared_write();
+        let mut interaction_storage = self.interaction_storage.write().unwrap();
+        if interaction_storage.contains_key(&interaction.id) {
+            return Err(anyhow!("Interaction {} already exists", interaction.id));
+        }
+        interaction_storage.insert(interaction.id, interaction.clone());
         Ok(())
     }
     fn get_buddies(&self, user_id: Uuid) -> Result<HashMap<Uuid, Buddy>> {
         let mut users_buddies = HashMap::new();
         let storage = self.buddy_storage.read().unwrap();
         for buddy in storage.values() {
-            if buddy.user_id == user_id && buddy.delete_timestamp.is_none() {
-                users_buddies.insert(buddy.id.clone(), buddy.clone());
+            // Archived buddies are included, like they are in psql
+            if buddy.user_id == user_id {
+                users_buddies.insert(buddy.id, buddy.clone());
             }
         }
         Ok(users_buddies)
@@ -82,38 +352,97 @@ impl BuddiesStore for MemoryBuddiesStore {
         let mut users_interactions = HashMap::new();
         let storage = self.interaction_storage.read().unwrap();
         for interaction in storage.values() {
-            if interaction.user_id == user_id && interaction.delete_timestamp.is_none() {
-                users_interactions.insert(interaction.id.clone(), interaction.clone());
+            if interaction.user_id == user_id {
+                users_interactions.insert(interaction.id, interaction.clone());
             }
         }
         Ok(users_interactions)
     }
-    fn archive_buddy(&mut self, id: Uuid, _user_id: Uuid) -> Result<()> {
-        let now = SystemTime::now()
-            .duration_since(SystemTime::UNIX_EPOCH)?
-            .as_secs();
-        let mut buddy = self.get_buddy(&id).context("getting note to update")?;
+    fn get_contact_history(
+        &self,
+        user_id: Uuid,
+        since: Option<&Datestamp>,
+        until: Option<&Datestamp>,
+    ) -> Result<ContactHistory> {
+        let mut history = ContactHistory::default();
+        let storage = self.interaction_storage.read().unwrap();
+        for interaction in storage.values() {
+            if interaction.user_id != user_id || interaction.delete_timestamp.is_some() {
+                continue;
+            }
+            let date = match &interaction.date {
+                Some(date)
+                    if since.is_none_or(|since| date >= since)
+                        && until.is_none_or(|until| date <= until) =>
+                {
+                    date
+                }
+                _ => continue,
+            };
+            history.total_interactions += 1;
+            for participant in &interaction.participants {
+                history
+                    .contact_dates
+                    .entry(*participant)
+                    .or_insert_with(Vec::new)
+                    .push(date.clone());
+            }
+        }
+        for dates in history.contact_dates.values_mut() {
+            dates.sort();
+        }
+        Ok(history)
+    }
+    fn archive_buddy(&mut self, id: Uuid, user_id: Uuid) -> Result<()> {
+        let _writing = self.shared_write();
+        let now = self.now()?;
+        let mut buddy = self
+            .get_buddy(&id)
+            .ok()
+            .filter(|buddy| buddy.user_id == user_id)
+            .context(format!("Looking for buddy with id {}", id))?;
         buddy.delete_timestamp = Some(Timestamp(now));
+        buddy.last_update_timestamp = Timestamp(now);
+        buddy.version += 1;
+        self.buddy_storage.write().unwrap().insert(id, buddy);
         Ok(())
     }
 
-    fn archive_interaction(&mut self, id: Uuid, _user_id: Uuid) -> Result<()> {
-        let now = SystemTime::now()
-            .duration_since(SystemTime::UNIX_EPOCH)?
-            .as_secs();
+    fn archive_interaction(&mut self, id: Uuid, user_id: Uuid) -> Result<()> {
+        let _writing = self.shared_write();
+        let now = self.now()?;
         let mut interaction = self
             .get_interaction(&id)
-            .context("getting note to update")?;
+            .ok()
+            .filter(|interaction| interaction.user_id == user_id)
+            .context(format!("Looking for interaction with id {}", id))?;
         interaction.delete_timestamp = Some(Timestamp(now));
+        interaction.last_update_timestamp = Timestamp(now);
+        interaction.version += 1;
+        self.interaction_storage
+            .write()
+            .unwrap()
+            .insert(id, interaction);
         Ok(())
     }
     fn update_buddy(&mut self, request: UpdateBuddyRequest) -> Result<()> {
-        let now = SystemTime::now()
-            .duration_since(SystemTime::UNIX_EPOCH)?
-            .as_secs();
-        let mut buddy = self
-            .get_buddy(&request.buddy_id)
-            .context("getting note to update")?;
+        let _writing = self.shared_write();
+        let now = self.now()?;
+        // Hold the lock from the version check through to the write
+        let mut buddy_storage = self.buddy_storage.write().unwrap();
+        let buddy = buddy_storage
+            .get_mut(&request.buddy_id)
+            .filter(|buddy| buddy.user_id == request.user_id && buddy.delete_timestamp.is_none())
+            .ok_or(NotFoundError {
+                kind: "buddy",
+                id: request.buddy_id,
+            })?;
+        if request.version.is_some() && request.version != Some(buddy.version) {
+            return Err(StaleWriteError {
+                current: CurrentRecord::Buddy(Box::new(buddy.clone())),
+            }
+            .into());
+        }
         if let Some(name) = request.name {
             buddy.name = name;
         }
@@ -124,34 +453,584 @@ impl BuddiesStore for MemoryBuddiesStore {
             buddy.last_contacted = last_contacted;
         }
         if let Some(location) = request.location {
-            buddy.location = Some(location);
+            buddy.location = location;
+        }
+        if let Some(birthday) = request.birthday {
+            buddy.birthday = birthday;
+        }
+        if let Some(cadence) = request.cadence {
+            buddy.cadence = cadence;
         }
         buddy.last_update_timestamp = Timestamp(now);
+        buddy.version += 1;
         Ok(())
     }
     fn update_interaction(&mut self, request: UpdateInteractionRequest) -> Result<()> {
-        let now = SystemTime::now()
-            .duration_since(SystemTime::UNIX_EPOCH)?
-            .as_secs();
-        let mut interaction = self
-            .get_interaction(&request.interaction_id)
-            .context("getting note to update")?;
+        let _writing = self.shared_write();
+        let now = self.now()?;
+        // Hold the lock from the version check through to the write
+        let mut interaction_storage = self.interaction_storage.write().unwrap();
+        let interaction = interaction_storage
+            .get_mut(&request.interaction_id)
+            .filter(|interaction| {
+                interaction.user_id == request.user_id && interaction.delete_timestamp.is_none()
+            })
+            .ok_or(NotFoundError {
+                kind: "interaction",
+                id: request.interaction_id,
+            })?;
+        if request.version.is_some() && request.version != Some(interaction.version) {
+            return Err(StaleWriteError {
+                current: CurrentRecord::Interaction(Box::new(interaction.clone())),
+            }
+            .into());
+        }
         interaction.last_update_timestamp = Timestamp(now);
+        interaction.version += 1;
         if let Some(notes) = request.notes {
             interaction.notes = notes;
         }
         if let Some(date) = request.date {
-            interaction.date = Some(date);
+            interaction.date = date;
         }
         if let Some(participants) = request.participants {
             interaction.participants = participants;
         }
+        if let Some(kind) = request.kind {
+            interaction.kind = kind;
+        }
+        if let Some(duration) = request.duration {
+            interaction.duration = duration;
+        }
+        if let Some(location) = request.location {
+            interaction.location = location;
+        }
+        if let Some(initiator) = request.initiator {
+            interaction.initiator = initiator;
+        }
+        Ok(())
+    }
+    fn create_relationship(&mut self, relationship: Relationship) -> Result<()> {
+        let _writing = self.shared_write();
+        self.relationship_storage
+            .write()
+            .unwrap()
+            .insert(relationship.id, relationship);
+        Ok(())
+    }
+    fn update_relationship(&mut self, request: UpdateRelationshipRequest) -> Result<()> {
+        let _writing = self.shared_write();
+        let now = self.now()?;
+        let mut relationship = self
+            .get_relationship(&request.relationship_id, request.user_id)
+            .context("getting relationship to update")?;
+        if let Some(kind) = request.kind {
+            relationship.kind = kind;
+        }
+        if let Some(bidirectional) = request.bidirectional {
+            relationship.bidirectional = bidirectional;
+        }
+        relationship.last_update_timestamp = Timestamp(now);
+        self.relationship_storage
+            .write()
+            .unwrap()
+            .insert(relationship.id, relationship);
+        Ok(())
+    }
+    fn archive_relationship(&mut self, id: Uuid, user_id: Uuid) -> Result<()> {
+        let _writing = self.shared_write();
+        let now = self.now()?;
+        let mut relationship = self
+            .get_relationship(&id, user_id)
+            .context("getting relationship to archive")?;
+        relationship.delete_timestamp = Some(Timestamp(now));
+        relationship.last_update_timestamp = Timestamp(now);
+        self.relationship_storage
+            .write()
+            .unwrap()
+            .insert(id, relationship);
+        Ok(())
+    }
+    fn get_relationships(&self, user_id: Uuid) -> Result<HashMap<Uuid, Relationship>> {
+        let mut users_relationships = HashMap::new();
+        let storage = self.relationship_storage.read().unwrap();
+        for relationship in storage.values() {
+            if relationship.user_id == user_id && relationship.delete_timestamp.is_none() {
+                users_relationships.insert(relationship.id, relationship.clone());
+            }
+        }
+        Ok(users_relationships)
+    }
+    fn create_contact_method(&mut self, contact_method: ContactMethod) -> Result<()> {
+        let _writing = self.shared_write();
+        self.contact_method_storage
+            .write()
+            .unwrap()
+            .insert(contact_method.id, contact_method);
         Ok(())
     }
+    fn update_contact_method(&mut self, request: UpdateContactMethodRequest) -> Result<()> {
+        let _writing = self.shared_write();
+        let now = self.now()?;
+        let mut contact_method = self
+            .get_contact_method(&request.contact_method_id, request.user_id)
+            .context("getting contact method to update")?;
+        if let Some(value) = request.value {
+            contact_method.value = value;
+        }
+        if let Some(label) = request.label {
+            contact_method.label = Some(label);
+        }
+        if let Some(preferred) = request.preferred {
+            contact_method.preferred = preferred;
+        }
+        contact_method.last_update_timestamp = Timestamp(now);
+        self.contact_method_storage
+            .write()
+            .unwrap()
+            .insert(contact_method.id, contact_method);
+        Ok(())
+    }
+    fn archive_contact_method(&mut self, id: Uuid, user_id: Uuid) -> Result<()> {
+        let _writing = self.shared_write();
+        let now = self.now()?;
+        let mut contact_method = self
+            .get_contact_method(&id, user_id)
+            .context("getting contact method to archive")?;
+        contact_method.delete_timestamp = Some(Timestamp(now));
+        contact_method.last_update_timestamp = Timestamp(now);
+        self.contact_method_storage
+            .write()
+            .unwrap()
+            .insert(id, contact_method);
+        Ok(())
+    }
+    fn get_contact_methods(&self, user_id: Uuid) -> Result<HashMap<Uuid, ContactMethod>> {
+        let mut users_contact_methods = HashMap::new();
+        let storage = self.contact_method_storage.read().unwrap();
+        for contact_method in storage.values() {
+            if contact_method.user_id == user_id && contact_method.delete_timestamp.is_none() {
+                users_contact_methods.insert(contact_method.id, contact_method.clone());
+            }
+        }
+        Ok(users_contact_methods)
+    }
+    fn create_important_date(&mut self, important_date: ImportantDate) -> Result<()> {
+        let _writing = self.shared_write();
+        self.important_date_storage
+            .write()
+            .unwrap()
+            .insert(important_date.id, important_date);
+        Ok(())
+    }
+    fn update_important_date(&mut self, request: UpdateImportantDateRequest) -> Result<()> {
+        let _writing = self.shared_write();
+        let now = self.now()?;
+        let mut important_date = self
+            .get_important_date(&request.important_date_id, request.user_id)
+            .context("getting important date to update")?;
+        if let Some(kind) = request.kind {
+            important_date.kind = kind;
+        }
+        if let Some(label) = request.label {
+            important_date.label = Some(label);
+        }
+        if let Some(date) = request.date {
+            important_date.date = date;
+        }
+        if let Some(recurrence) = request.recurrence {
+            important_date.recurrence = recurrence;
+        }
+        important_date.last_update_timestamp = Timestamp(now);
+        self.important_date_storage
+            .write()
+            .unwrap()
+            .insert(important_date.id, important_date);
+        Ok(())
+    }
+    fn archive_important_date(&mut self, id: Uuid, user_id: Uuid) -> Result<()> {
+        let _writing = self.shared_write();
+        let now = self.now()?;
+        let mut important_date = self
+            .get_important_date(&id, user_id)
+            .context("getting important date to archive")?;
+        important_date.delete_timestamp = Some(Timestamp(now));
+        important_date.last_update_timestamp = Timestamp(now);
+        self.important_date_storage
+            .write()
+            .unwrap()
+            .insert(id, important_date);
+        Ok(())
+    }
+    fn get_important_dates(&self, user_id: Uuid) -> Result<HashMap<Uuid, ImportantDate>> {
+        let mut users_important_dates = HashMap::new();
+        let storage = self.important_date_storage.read().unwrap();
+        for important_date in storage.values() {
+            if important_date.user_id == user_id && important_date.delete_timestamp.is_none() {
+                users_important_dates.insert(important_date.id, important_date.clone());
+            }
+        }
+        Ok(users_important_dates)
+    }
+    fn create_follow_up(&mut self, follow_up: FollowUp) -> Result<()> {
+        let _writing = self.shared_write();
+        self.follow_up_storage
+            .write()
+            .unwrap()
+            .insert(follow_up.id, follow_up);
+        Ok(())
+    }
+    fn complete_follow_up(&mut self, id: Uuid, user_id: Uuid) -> Result<()> {
+        let _writing = self.shared_write();
+        let now = self.now()?;
+        let mut follow_up = self
+            .get_follow_up(&id, user_id)
+            .context("getting follow up to complete")?;
+        follow_up.done = true;
+        follow_up.last_update_timestamp = Timestamp(now);
+        self.follow_up_storage
+            .write()
+            .unwrap()
+            .insert(id, follow_up);
+        Ok(())
+    }
+    fn get_follow_ups(&self, user_id: Uuid) -> Result<HashMap<Uuid, FollowUp>> {
+        let mut users_follow_ups = HashMap::new();
+        let storage = self.follow_up_storage.read().unwrap();
+        for follow_up in storage.values() {
+            if follow_up.user_id == user_id && follow_up.delete_timestamp.is_none() {
+                users_follow_ups.insert(follow_up.id, follow_up.clone());
+            }
+        }
+        Ok(users_follow_ups)
+    }
+    fn create_idea(&mut self, idea: Idea) -> Result<()> {
+        let _writing = self.shared_write();
+        self.idea_storage.write().unwrap().insert(idea.id, idea);
+        Ok(())
+    }
+    fn update_idea(&mut self, request: UpdateIdeaRequest) -> Result<()> {
+        let _writing = self.shared_write();
+        let now = self.now()?;
+        let mut idea = self
+            .get_idea(&request.idea_id, request.user_id)
+            .context("getting idea to update")?;
+        if let Some(kind) = request.kind {
+            idea.kind = kind;
+        }
+        if let Some(description) = request.description {
+            idea.description = description;
+        }
+        if let Some(status) = request.status {
+            idea.status = status;
+        }
+        if let Some(interaction_id) = request.interaction_id {
+            idea.interaction_id = Some(interaction_id);
+        }
+        idea.last_update_timestamp = Timestamp(now);
+        self.idea_storage.write().unwrap().insert(idea.id, idea);
+        Ok(())
+    }
+    fn archive_idea(&mut self, id: Uuid, user_id: Uuid) -> Result<()> {
+        let _writing = self.shared_write();
+        let now = self.now()?;
+        let mut idea = self
+            .get_idea(&id, user_id)
+            .context("getting idea to archive")?;
+        idea.delete_timestamp = Some(Timestamp(now));
+        idea.last_update_timestamp = Timestamp(now);
+        self.idea_storage.write().unwrap().insert(id, idea);
+        Ok(())
+    }
+    fn get_ideas(&self, user_id: Uuid) -> Result<HashMap<Uuid, Idea>> {
+        let mut users_ideas = HashMap::new();
+        let storage = self.idea_storage.read().unwrap();
+        for idea in storage.values() {
+            if idea.user_id == user_id && idea.delete_timestamp.is_none() {
+                users_ideas.insert(idea.id, idea.clone());
+            }
+        }
+        Ok(users_ideas)
+    }
+    fn move_buddy_records(
+        &mut self,
+        user_id: Uuid,
+        from_buddy_id: Uuid,
+        to_buddy_id: Uuid,
+    ) -> Result<()> {
+        let _writing = self.shared_write();
+        let now = Timestamp(self.now()?);
+        for relationship in self.relationship_storage.write().unwrap().values_mut() {
+            if relationship.user_id != user_id || relationship.delete_timestamp.is_some() {
+                continue;
+            }
+            for end in [
+                &mut relationship.from_buddy_id,
+                &mut relationship.to_buddy_id,
+            ] {
+                if *end == from_buddy_id {
+                    *end = to_buddy_id;
+                    relationship.last_update_timestamp = now;
+                }
+            }
+        }
+        for contact_method in self.contact_method_storage.write().unwrap().values_mut() {
+            if contact_method.user_id == user_id
+                && contact_method.delete_timestamp.is_none()
+                && contact_method.buddy_id == from_buddy_id
+            {
+                contact_method.buddy_id = to_buddy_id;
+                contact_method.last_update_timestamp = now;
+            }
+        }
+        for important_date in self.important_date_storage.write().unwrap().values_mut() {
+            if important_date.user_id == user_id
+                && important_date.delete_timestamp.is_none()
+                && important_date.buddy_id == from_buddy_id
+            {
+                important_date.buddy_id = to_buddy_id;
+                important_date.last_update_timestamp = now;
+            }
+        }
+        for follow_up in self.follow_up_storage.write().unwrap().values_mut() {
+            if follow_up.user_id == user_id
+                && follow_up.delete_timestamp.is_none()
+                && follow_up.buddy_id == Some(from_buddy_id)
+            {
+                follow_up.buddy_id = Some(to_buddy_id);
+                follow_up.last_update_timestamp = now;
+            }
+        }
+        for idea in self.idea_storage.write().unwrap().values_mut() {
+            if idea.user_id == user_id
+                && idea.delete_timestamp.is_none()
+                && idea.buddy_id == from_buddy_id
+            {
+                idea.buddy_id = to_buddy_id;
+                idea.last_update_timestamp = now;
+            }
+        }
+        Ok(())
+    }
+    fn create_revision(&mut self, revision: Revision) -> Result<()> {
+        let _writing = self.shared_write();
+        self.revision_storage.write().unwrap().push(revision);
+        Ok(())
+    }
+    fn get_revisions(&self, user_id: Uuid, entity_id: Uuid) -> Result<Vec<Revision>> {
+        Ok(self
+            .revision_storage
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|revision| revision.user_id == user_id && revision.entity_id == entity_id)
+            .cloned()
+            .collect())
+    }
+    fn restore_buddy(&mut self, buddy: Buddy) -> Result<()> {
+        let _writing = self.shared_write();
+        self.get_buddy(&buddy.id)
+            .context("getting buddy to restore")?;
+        self.buddy_storage.write().unwrap().insert(buddy.id, buddy);
+        Ok(())
+    }
+    fn restore_interaction(&mut self, interaction: Interaction) -> Result<()> {
+        let _writing = self.shared_write();
+        self.get_interaction(&interaction.id)
+            .context("getting interaction to restore")?;
+        self.interaction_storage
+            .write()
+            .unwrap()
+            .insert(interaction.id, interaction);
+        Ok(())
+    }
+    fn get_idempotency_record(
+        &self,
+        user_id: Uuid,
+        operation: &str,
+        key: &str,
+    ) -> Result<Option<IdempotencyRecord>> {
+        Ok(self
+            .idempotency_storage
+            .read()
+            .unwrap()
+            .get(&(user_id, operation.to_string(), key.to_string()))
+            .cloned())
+    }
+    fn save_idempotency_record(&mut self, record: IdempotencyRecord) -> Result<()> {
+        let _writing = self.shared_write();
+        let mut storage = self.idempotency_storage.write().unwrap();
+        let key = (record.user_id, record.operation.clone(), record.key.clone());
+        if storage.contains_key(&key) {
+            return Err(anyhow!("Idempotency key {} already used", record.key));
+        }
+        storage.insert(key, record);
+        Ok(())
+    }
+    fn delete_idempotency_record(
+        &mut self,
+        user_id: Uuid,
+        operation: &str,
+        key: &str,
+    ) -> Result<()> {
+        let _writing = self.shared_write();
+        self.idempotency_storage.write().unwrap().remove(&(
+            user_id,
+            operation.to_string(),
+            key.to_string(),
+        ));
+        Ok(())
+    }
+    fn create_webhook(&mut self, webhook: Webhook) -> Result<()> {
+        let _writing = self.shared_write();
+        self.webhook_storage
+            .write()
+            .unwrap()
+            .insert(webhook.id, webhook);
+        Ok(())
+    }
+    fn archive_webhook(&mut self, id: Uuid, user_id: Uuid) -> Result<()> {
+        let _writing = self.shared_write();
+        let now = self.now()?;
+        let mut webhook = self
+            .get_webhook(&id, user_id)
+            .context("getting webhook to archive")?;
+        webhook.delete_timestamp = Some(Timestamp(now));
+        self.webhook_storage.write().unwrap().insert(id, webhook);
+        Ok(())
+    }
+    fn get_webhooks(&self, user_id: Uuid) -> Result<HashMap<Uuid, Webhook>> {
+        let mut users_webhooks = HashMap::new();
+        let storage = self.webhook_storage.read().unwrap();
+        for webhook in storage.values() {
+            if webhook.user_id == user_id && webhook.delete_timestamp.is_none() {
+                users_webhooks.insert(webhook.id, webhook.clone());
+            }
+        }
+        Ok(users_webhooks)
+    }
+    fn create_webhook_delivery(&mut self, delivery: WebhookDelivery) -> Result<()> {
+        let _writing = self.shared_write();
+        self.webhook_delivery_storage
+            .write()
+            .unwrap()
+            .push(delivery);
+        Ok(())
+    }
+    fn update_webhook_delivery(&mut self, delivery: WebhookDelivery) -> Result<()> {
+        let _writing = self.shared_write();
+        let mut storage = self.webhook_delivery_storage.write().unwrap();
+        let existing = storage
+            .iter_mut()
+            .find(|existing| existing.id == delivery.id)
+            .context(format!(
+                "Looking for webhook delivery with id {}",
+                delivery.id
+            ))?;
+        *existing = delivery;
+        Ok(())
+    }
+    fn get_webhook_deliveries(
+        &self,
+        user_id: Uuid,
+        webhook_id: Uuid,
+    ) -> Result<Vec<WebhookDelivery>> {
+        Ok(self
+            .webhook_delivery_storage
+            .read()
+            .unwrap()
+            .iter()
+            .rev()
+            .filter(|delivery| delivery.user_id == user_id && delivery.webhook_id == webhook_id)
+            .cloned()
+            .collect())
+    }
+    fn get_due_webhook_deliveries(&self, now: Timestamp) -> Result<Vec<WebhookDelivery>> {
+        Ok(self
+            .webhook_delivery_storage
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|delivery| {
+                delivery.status == DeliveryStatus::Pending
+                    && delivery.next_attempt_timestamp.unwrap_or(now).0 <= now.0
+            })
+            .cloned()
+            .collect())
+    }
+    /// Runs `f` with every other write shut out and puts the tables it wrote to back the way
+    /// they were if it fails. Nested transactions roll back only their own writes.
+    fn transaction<T, F>(&mut self, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut Self) -> Result<T>,
+    {
+        if self.in_transaction {
+            for table in self.tables() {
+                table.begin();
+            }
+            let result = f(self);
+            for table in self.tables() {
+                if result.is_ok() {
+                    table.commit();
+                } else {
+                    table.roll_back();
+                }
+            }
+            return result;
+        }
+        let write_lock = Arc::clone(&self.write_lock);
+        let _exclusive = write_lock.write().unwrap_or_else(PoisonError::into_inner);
+        let mut store = Self {
+            in_transaction: true,
+            ..self.clone()
+        };
+        store.transaction(f)
+    }
+    fn get_user_by_id(&self, user_id: Uuid) -> Result<Option<User>> {
+        Ok(self
+            .user_storage
+            .read()
+            .unwrap()
+            .values()
+            .find(|user| user.id == user_id)
+            .cloned())
+    }
+    fn update_user(&mut self, request: UpdateUserRequest) -> Result<()> {
+        let _writing = self.shared_write();
+        let mut storage = self.user_storage.write().unwrap();
+        let user = storage
+            .values_mut()
+            .find(|user| user.id == request.user_id)
+            .context(format!("Looking for user with id {}", request.user_id))?;
+        if let Some(digest_frequency) = request.digest_frequency {
+            user.digest_frequency = digest_frequency;
+        }
+        Ok(())
+    }
+    fn set_calendar_token(&mut self, user_id: Uuid, token: String) -> Result<()> {
+        let _writing = self.shared_write();
+        let mut storage = self.user_storage.write().unwrap();
+        let user = storage
+            .values_mut()
+            .find(|user| user.id == user_id)
+            .context(format!("Looking for user with id {}", user_id))?;
+        user.calendar_token = Some(token);
+        Ok(())
+    }
+    fn get_user_by_calendar_token(&self, token: &str) -> Result<Option<User>> {
+        Ok(self
+            .user_storage
+            .read()
+            .unwrap()
+            .values()
+            .find(|user| user.calendar_token.as_deref() == Some(token))
+            .cloned())
+    }
 }
 
 impl AuthStore for MemoryBuddiesStore {
     fn create_user(&mut self, request: CreateUserRequest) -> Result<()> {
+        let _writing = self.shared_write();
         match self.find_user(&request.user.email) {
             Some(..) => {
                 return Err(anyhow!("User already exists"));
@@ -171,4 +1050,209 @@ impl AuthStore for MemoryBuddiesStore {
             None => return Err(anyhow!("No user found for email {}", request.email)),
         }
     }
+    fn create_personal_access_token(&mut self, token: PersonalAccessToken) -> Result<()> {
+        let _writing = self.shared_write();
+        self.personal_access_token_storage
+            .write()
+            .unwrap()
+            .insert(token.id, token);
+        Ok(())
+    }
+    fn revoke_personal_access_token(&mut self, id: Uuid, user_id: Uuid) -> Result<()> {
+        let _writing = self.shared_write();
+        let now = self.now()?;
+        let mut storage = self.personal_access_token_storage.write().unwrap();
+        let token = storage
+            .get_mut(&id)
+            .filter(|token| token.user_id == user_id)
+            .ok_or(NotFoundError {
+                kind: "personal access token",
+                id,
+            })?;
+        token.revoke_timestamp = Some(Timestamp(now));
+        Ok(())
+    }
+    fn get_personal_access_tokens(
+        &self,
+        user_id: Uuid,
+    ) -> Result<HashMap<Uuid, PersonalAccessToken>> {
+        let mut users_tokens = HashMap::new();
+        let storage = self.personal_access_token_storage.read().unwrap();
+        for token in storage.values() {
+            if token.user_id == user_id && token.revoke_timestamp.is_none() {
+                users_tokens.insert(token.id, token.clone());
+            }
+        }
+        Ok(users_tokens)
+    }
+    fn get_personal_access_token_by_hash(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<PersonalAccessToken>> {
+        Ok(self
+            .personal_access_token_storage
+            .read()
+            .unwrap()
+            .values()
+            .find(|token| token.token_hash == token_hash && token.revoke_timestamp.is_none())
+            .cloned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::types::{IdeaKind, IdeaStatus, RelationshipKind};
+
+    #[test]
+    fn webhooks_can_only_be_archived_by_their_owner() {
+        let mut store = MemoryBuddiesStore::new();
+        let (owner, someone_else) = (Uuid::new_v4(), Uuid::new_v4());
+        let webhook = Webhook {
+            id: Uuid::new_v4(),
+            url: "https://example.com/hook".to_string(),
+            secret: "secret".to_string(),
+            create_timestamp: Timestamp(0),
+            delete_timestamp: None,
+            user_id: owner,
+        };
+        store.create_webhook(webhook.clone()).unwrap();
+
+        let err = store.archive_webhook(webhook.id, someone_else).unwrap_err();
+        assert!(err.downcast_ref::<NotFoundError>().is_some(), "{:?}", err);
+        assert!(store.get_webhooks(owner).unwrap().contains_key(&webhook.id));
+
+        store.archive_webhook(webhook.id, owner).unwrap();
+        assert!(store.get_webhooks(owner).unwrap().is_empty());
+    }
+
+    #[test]
+    fn personal_access_tokens_can_only_be_revoked_by_their_owner() {
+        let mut store = MemoryBuddiesStore::new();
+        let (owner, someone_else) = (Uuid::new_v4(), Uuid::new_v4());
+        let token = PersonalAccessToken {
+            id: Uuid::new_v4(),
+            name: "script".to_string(),
+            scopes: Vec::new(),
+            token_hash: "hash".to_string(),
+            create_timestamp: Timestamp(0),
+            revoke_timestamp: None,
+            user_id: owner,
+        };
+        store.create_personal_access_token(token.clone()).unwrap();
+
+        let err = store
+            .revoke_personal_access_token(token.id, someone_else)
+            .unwrap_err();
+        assert!(err.downcast_ref::<NotFoundError>().is_some(), "{:?}", err);
+        assert!(store
+            .get_personal_access_token_by_hash("hash")
+            .unwrap()
+            .is_some());
+
+        store.revoke_personal_access_token(token.id, owner).unwrap();
+        assert!(store
+            .get_personal_access_token_by_hash("hash")
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn moving_buddy_records_only_touches_the_users_live_records() {
+        let mut store = MemoryBuddiesStore::new();
+        let (user, someone_else) = (Uuid::new_v4(), Uuid::new_v4());
+        let (loser, winner, friend) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        let relationship = |user_id, delete_timestamp| Relationship {
+            id: Uuid::new_v4(),
+            from_buddy_id: friend,
+            to_buddy_id: loser,
+            kind: RelationshipKind::IntroducedBy,
+            bidirectional: false,
+            create_timestamp: Timestamp(0),
+            last_update_timestamp: Timestamp(0),
+            delete_timestamp,
+            user_id,
+        };
+        let (live, archived, others) = (
+            relationship(user, None),
+            relationship(user, Some(Timestamp(0))),
+            relationship(someone_else, None),
+        );
+        for relationship in [&live, &archived, &others] {
+            store.create_relationship(relationship.clone()).unwrap();
+        }
+        let idea = Idea {
+            id: Uuid::new_v4(),
+            buddy_id: loser,
+            kind: IdeaKind::Gift,
+            description: "A book".to_string(),
+            status: IdeaStatus::Idea,
+            interaction_id: None,
+            create_timestamp: Timestamp(0),
+            last_update_timestamp: Timestamp(0),
+            delete_timestamp: None,
+            user_id: user,
+        };
+        store.create_idea(idea.clone()).unwrap();
+
+        store.move_buddy_records(user, loser, winner).unwrap();
+
+        let relationships = store.relationship_storage.read().unwrap().clone();
+        assert_eq!(relationships[&live.id].to_buddy_id, winner);
+        assert_eq!(relationships[&live.id].from_buddy_id, friend);
+        assert_eq!(relationships[&archived.id].to_buddy_id, loser);
+        assert_eq!(relationships[&others.id].to_buddy_id, loser);
+        assert_eq!(store.get_ideas(user).unwrap()[&idea.id].buddy_id, winner);
+    }
+
+    #[test]
+    fn failed_nested_transactions_only_undo_their_own_writes() {
+        let mut store = MemoryBuddiesStore::new();
+        let user_id = Uuid::new_v4();
+        let buddy = |name: &str| Buddy {
+            id: Uuid::new_v4(),
+            name: name.to_string(),
+            birthday: None,
+            cadence: None,
+            notes: String::new(),
+            location: None,
+            last_contacted: Datestamp("2020-01-01".to_string()),
+            create_timestamp: Timestamp(0),
+            last_update_timestamp: Timestamp(0),
+            delete_timestamp: None,
+            user_id,
+            suggested_cadence: None,
+            contact_methods: Vec::new(),
+            local_time: None,
+            important_dates: Vec::new(),
+            version: 1,
+        };
+        let (before, outer, inner) = (buddy("Before"), buddy("Outer"), buddy("Inner"));
+        store.create_buddy(before.clone()).unwrap();
+
+        store
+            .transaction(|store| {
+                store.create_buddy(outer.clone())?;
+                let failed: Result<()> = store.transaction(|store| {
+                    store.create_buddy(inner.clone())?;
+                    store.archive_buddy(before.id, user_id)?;
+                    Err(anyhow!("Changed my mind"))
+                });
+                assert!(failed.is_err());
+                Ok(())
+            })
+            .unwrap();
+        let failed: Result<()> = store.transaction(|store| {
+            store.archive_buddy(outer.id, user_id)?;
+            Err(anyhow!("Changed my mind"))
+        });
+        assert!(failed.is_err());
+
+        let buddies = store.get_buddies(user_id).unwrap();
+        let mut names: Vec<&str> = buddies.values().map(|b| b.name.as_str()).collect();
+        names.sort();
+        assert_eq!(names, vec!["Before", "Outer"]);
+        assert!(buddies.values().all(|b| b.delete_timestamp.is_none()));
+        assert!(store.buddy_storage.snapshots.lock().unwrap().is_empty());
+    }
 }
@@ -1,12 +1,20 @@
-use super::schema::{buddies, interactions, users};
+use super::schema::{
+    buddies, buddy_relationships, contact_methods, follow_ups, ideas, idempotency_keys,
+    important_dates, interactions, personal_access_tokens, revisions, users, webhook_deliveries,
+    webhooks,
+};
 use crate::lib::types::{
-    Buddy, Datestamp, Interaction, Location, Timestamp, UpdateBuddyRequest,
-    UpdateInteractionRequest, User,
+    Buddy, ContactMethod, Datestamp, FollowUp, Idea, IdempotencyRecord, ImportantDate, Interaction,
+    Location, PersonalAccessToken, Relationship, Revision, Scope, Timestamp, UpdateBuddyRequest,
+    UpdateContactMethodRequest, UpdateIdeaRequest, UpdateImportantDateRequest,
+    UpdateInteractionRequest, UpdateRelationshipRequest, UpdateUserRequest, User, Webhook,
+    WebhookDelivery,
 };
 use anyhow::{anyhow, Context, Result};
+use diesel::sql_types::{Array, Text};
 use std::collections::HashSet;
 use std::convert::TryFrom;
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
 use uuid::Uuid;
 
 /// Our DB representation of a buddy
@@ -18,12 +26,64 @@ pub struct DBBuddy {
     pub notes: String,
     pub last_contacted: String,
     pub birthday: Option<String>,
-    // TODO - Cadence
     pub location: Option<String>,
     pub create_timestamp: String,
     pub last_update_timestamp: String,
     pub delete_timestamp: Option<String>,
     pub user_uuid: String,
+    pub cadence_seconds: Option<i64>,
+    pub city: Option<String>,
+    pub region: Option<String>,
+    pub country_code: Option<String>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    pub timezone: Option<String>,
+    pub version: i32,
+}
+
+/// A buddy's location as it is spread across their columns
+#[derive(Default)]
+pub struct DBLocation {
+    pub location: Option<String>,
+    pub city: Option<String>,
+    pub region: Option<String>,
+    pub country_code: Option<String>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    pub timezone: Option<String>,
+}
+
+impl From<Location> for DBLocation {
+    fn from(location: Location) -> Self {
+        DBLocation {
+            location: location.text,
+            city: location.city,
+            region: location.region,
+            country_code: location.country_code,
+            latitude: location.latitude,
+            longitude: location.longitude,
+            timezone: location.timezone,
+        }
+    }
+}
+
+impl From<DBLocation> for Option<Location> {
+    fn from(location: DBLocation) -> Self {
+        let location = Location {
+            text: location.location,
+            city: location.city,
+            region: location.region,
+            country_code: location.country_code,
+            latitude: location.latitude,
+            longitude: location.longitude,
+            timezone: location.timezone,
+        };
+        if location == Location::default() {
+            None
+        } else {
+            Some(location)
+        }
+    }
 }
 
 impl TryFrom<DBBuddy> for Buddy {
@@ -57,14 +117,32 @@ impl TryFrom<DBBuddy> for Buddy {
                     .context("parsing last update timestamp")?,
             ),
             delete_timestamp,
-            location: buddy.location.map(Location),
-            cadence: None,
+            location: DBLocation {
+                location: buddy.location,
+                city: buddy.city,
+                region: buddy.region,
+                country_code: buddy.country_code,
+                latitude: buddy.latitude,
+                longitude: buddy.longitude,
+                timezone: buddy.timezone,
+            }
+            .into(),
+            cadence: buddy
+                .cadence_seconds
+                .map(|secs| Duration::from_secs(secs as u64)),
+            suggested_cadence: None,
+            contact_methods: Vec::new(),
+            local_time: None,
+            important_dates: Vec::new(),
+            version: buddy.version,
         })
     }
 }
 
-#[derive(Insertable)]
+/// Also used as a changeset to overwrite every column when restoring a buddy
+#[derive(Insertable, AsChangeset)]
 #[table_name = "buddies"]
+#[changeset_options(treat_none_as_null = "true")]
 pub struct NewBuddy {
     pub uuid: String,
     pub name: String,
@@ -75,8 +153,18 @@ pub struct NewBuddy {
     pub create_timestamp: String,
     pub last_update_timestamp: String,
     pub user_uuid: String,
+    pub cadence_seconds: Option<i64>,
+    pub city: Option<String>,
+    pub region: Option<String>,
+    pub country_code: Option<String>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    pub timezone: Option<String>,
+    pub version: i32,
 }
 
+/// Nullable columns are `Some(None)` to clear them. A new location replaces every location
+/// column, clearing the parts it doesn't have.
 #[derive(AsChangeset, Default)]
 #[table_name = "buddies"]
 pub struct DBUpdateBuddy {
@@ -84,9 +172,16 @@ pub struct DBUpdateBuddy {
     pub name: Option<String>,
     pub notes: Option<String>,
     pub last_contacted: Option<String>,
-    pub location: Option<String>,
-    pub birthday: Option<String>,
+    pub location: Option<Option<String>>,
+    pub birthday: Option<Option<String>>,
     pub delete_timestamp: Option<String>,
+    pub cadence_seconds: Option<Option<i64>>,
+    pub city: Option<Option<String>>,
+    pub region: Option<Option<String>>,
+    pub country_code: Option<Option<String>>,
+    pub latitude: Option<Option<f64>>,
+    pub longitude: Option<Option<f64>>,
+    pub timezone: Option<Option<String>>,
 }
 
 impl DBUpdateBuddy {
@@ -104,21 +199,33 @@ impl DBUpdateBuddy {
         let now = SystemTime::now()
             .duration_since(SystemTime::UNIX_EPOCH)?
             .as_secs();
-        Ok(Self {
+        let mut update = Self {
             name: request.name,
             notes: request.notes,
-            birthday: request.birthday.map(|x| x.0),
+            birthday: request.birthday.map(|x| x.map(|x| x.0)),
             last_contacted: request.last_contacted.map(|x| x.0),
-            location: request.location.map(|x| x.0),
+            cadence_seconds: request.cadence.map(|x| x.map(|x| x.as_secs() as i64)),
             last_update_timestamp: format!("{}", now),
             ..DBUpdateBuddy::default()
-        })
+        };
+        if let Some(location) = request.location {
+            let location = location.map(DBLocation::from).unwrap_or_default();
+            update.location = Some(location.location);
+            update.city = Some(location.city);
+            update.region = Some(location.region);
+            update.country_code = Some(location.country_code);
+            update.latitude = Some(location.latitude);
+            update.longitude = Some(location.longitude);
+            update.timezone = Some(location.timezone);
+        }
+        Ok(update)
     }
 }
 
 impl TryFrom<Buddy> for NewBuddy {
     type Error = anyhow::Error;
     fn try_from(buddy: Buddy) -> Result<Self, Self::Error> {
+        let location = buddy.location.map(DBLocation::from).unwrap_or_default();
         Ok(NewBuddy {
             uuid: buddy.id.to_string(),
             name: buddy.name,
@@ -127,8 +234,16 @@ impl TryFrom<Buddy> for NewBuddy {
             create_timestamp: buddy.create_timestamp.0.to_string(),
             last_update_timestamp: buddy.last_update_timestamp.0.to_string(),
             birthday: buddy.birthday.map(|b| b.0),
-            location: buddy.location.map(|b| b.0),
+            location: location.location,
             user_uuid: buddy.user_id.to_string(),
+            cadence_seconds: buddy.cadence.map(|c| c.as_secs() as i64),
+            city: location.city,
+            region: location.region,
+            country_code: location.country_code,
+            latitude: location.latitude,
+            longitude: location.longitude,
+            timezone: location.timezone,
+            version: buddy.version,
         })
     }
 }
@@ -145,10 +260,17 @@ pub struct DBInteraction {
     pub last_update_timestamp: String,
     pub delete_timestamp: Option<String>,
     pub user_uuid: String,
+    pub kind: Option<String>,
+    pub duration_seconds: Option<i64>,
+    pub location: Option<String>,
+    pub initiator: Option<String>,
+    pub version: i32,
 }
 
-#[derive(Insertable)]
+/// Also used as a changeset to overwrite every column when restoring an interaction
+#[derive(Insertable, AsChangeset)]
 #[table_name = "interactions"]
+#[changeset_options(treat_none_as_null = "true")]
 pub struct NewInteraction {
     pub uuid: String,
     pub notes: String,
@@ -157,16 +279,26 @@ pub struct NewInteraction {
     pub create_timestamp: String,
     pub last_update_timestamp: String,
     pub user_uuid: String,
+    pub kind: Option<String>,
+    pub duration_seconds: Option<i64>,
+    pub location: Option<String>,
+    pub initiator: Option<String>,
+    pub version: i32,
 }
 
+/// Nullable columns are `Some(None)` to clear them
 #[derive(AsChangeset, Default)]
 #[table_name = "interactions"]
 pub struct DBUpdateInteraction {
     pub last_update_timestamp: String,
     pub notes: Option<String>,
-    pub date: Option<String>,
+    pub date: Option<Option<String>>,
     pub participants: Option<Vec<String>>,
     pub delete_timestamp: Option<String>,
+    pub kind: Option<Option<String>>,
+    pub duration_seconds: Option<Option<i64>>,
+    pub location: Option<Option<String>>,
+    pub initiator: Option<Option<String>>,
 }
 
 impl DBUpdateInteraction {
@@ -198,10 +330,14 @@ impl DBUpdateInteraction {
 
         Ok(Self {
             notes: request.notes,
-            date: request.date.map(|x| x.0),
+            date: request.date.map(|x| x.map(|x| x.0)),
             participants,
             last_update_timestamp: format!("{}", now),
             delete_timestamp: None,
+            kind: request.kind.map(|x| x.map(|x| x.to_string())),
+            duration_seconds: request.duration.map(|x| x.map(|x| x.as_secs() as i64)),
+            location: request.location,
+            initiator: request.initiator.map(|x| x.map(|x| x.to_string())),
         })
     }
 }
@@ -222,6 +358,11 @@ impl TryFrom<Interaction> for NewInteraction {
             create_timestamp: interaction.create_timestamp.0.to_string(),
             last_update_timestamp: interaction.last_update_timestamp.0.to_string(),
             user_uuid: interaction.user_id.to_string(),
+            kind: interaction.kind.map(|k| k.to_string()),
+            duration_seconds: interaction.duration.map(|d| d.as_secs() as i64),
+            location: interaction.location,
+            initiator: interaction.initiator.map(|i| i.to_string()),
+            version: interaction.version,
         })
     }
 }
@@ -250,11 +391,27 @@ impl TryFrom<DBInteraction> for Interaction {
             }
             participants.insert(p_uuid);
         }
+        let kind = match interaction.kind {
+            Some(kind) => Some(kind.parse().context("Parsing interaction kind")?),
+            None => None,
+        };
+        let initiator = match interaction.initiator {
+            Some(initiator) => {
+                Some(Uuid::parse_str(&initiator).context("Parsing interaction initiator")?)
+            }
+            None => None,
+        };
         Ok(Interaction {
             id,
             notes: interaction.notes,
             participants,
             date: interaction.date.map(Datestamp),
+            kind,
+            duration: interaction
+                .duration_seconds
+                .map(|secs| Duration::from_secs(secs as u64)),
+            location: interaction.location,
+            initiator,
             create_timestamp: Timestamp(
                 interaction
                     .create_timestamp
@@ -269,47 +426,1084 @@ impl TryFrom<DBInteraction> for Interaction {
             ),
             delete_timestamp,
             user_id,
+            version: interaction.version,
         })
     }
 }
 
-#[derive(Queryable, Debug)]
-pub struct DBUser {
+/// Our DB repr of a relationship between two buddies
+#[derive(Queryable)]
+pub struct DBRelationship {
     pub id: i32,
-    pub email: String,
-    pub password: String,
+    pub uuid: String,
+    pub from_buddy_uuid: String,
+    pub to_buddy_uuid: String,
+    pub kind: String,
+    pub bidirectional: bool,
+    pub create_timestamp: String,
+    pub last_update_timestamp: String,
+    pub delete_timestamp: Option<String>,
+    pub user_uuid: String,
+}
+
+impl TryFrom<DBRelationship> for Relationship {
+    type Error = anyhow::Error;
+
+    fn try_from(relationship: DBRelationship) -> Result<Self, Self::Error> {
+        let delete_timestamp = match relationship.delete_timestamp {
+            Some(x) => Some(Timestamp(x.parse().context("Parsing delete timestamp")?)),
+            None => None,
+        };
+        Ok(Relationship {
+            id: Uuid::parse_str(&relationship.uuid).context("Parsing relationship id")?,
+            from_buddy_id: Uuid::parse_str(&relationship.from_buddy_uuid)
+                .context("Parsing relationship's from buddy id")?,
+            to_buddy_id: Uuid::parse_str(&relationship.to_buddy_uuid)
+                .context("Parsing relationship's to buddy id")?,
+            kind: relationship
+                .kind
+                .parse()
+                .context("Parsing relationship kind")?,
+            bidirectional: relationship.bidirectional,
+            create_timestamp: Timestamp(
+                relationship
+                    .create_timestamp
+                    .parse()
+                    .context("parsing create timestamp")?,
+            ),
+            last_update_timestamp: Timestamp(
+                relationship
+                    .last_update_timestamp
+                    .parse()
+                    .context("parsing last_update timestamp")?,
+            ),
+            delete_timestamp,
+            user_id: Uuid::parse_str(&relationship.user_uuid)
+                .context("parsing relationship's user id")?,
+        })
+    }
+}
+
+#[derive(Insertable)]
+#[table_name = "buddy_relationships"]
+pub struct NewRelationship {
+    pub uuid: String,
+    pub from_buddy_uuid: String,
+    pub to_buddy_uuid: String,
+    pub kind: String,
+    pub bidirectional: bool,
+    pub create_timestamp: String,
+    pub last_update_timestamp: String,
     pub user_uuid: String,
+}
+
+impl From<Relationship> for NewRelationship {
+    fn from(relationship: Relationship) -> Self {
+        NewRelationship {
+            uuid: relationship.id.to_string(),
+            from_buddy_uuid: relationship.from_buddy_id.to_string(),
+            to_buddy_uuid: relationship.to_buddy_id.to_string(),
+            kind: relationship.kind.to_string(),
+            bidirectional: relationship.bidirectional,
+            create_timestamp: relationship.create_timestamp.0.to_string(),
+            last_update_timestamp: relationship.last_update_timestamp.0.to_string(),
+            user_uuid: relationship.user_id.to_string(),
+        }
+    }
+}
+
+#[derive(AsChangeset, Default)]
+#[table_name = "buddy_relationships"]
+pub struct DBUpdateRelationship {
+    pub last_update_timestamp: String,
+    pub kind: Option<String>,
+    pub bidirectional: Option<bool>,
+    pub delete_timestamp: Option<String>,
+}
+
+impl DBUpdateRelationship {
+    pub fn archive() -> Result<Self> {
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)?
+            .as_secs();
+        Ok(Self {
+            last_update_timestamp: format!("{}", now),
+            delete_timestamp: Some(format!("{}", now)),
+            ..DBUpdateRelationship::default()
+        })
+    }
+
+    pub fn update(request: UpdateRelationshipRequest) -> Result<Self> {
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)?
+            .as_secs();
+        Ok(Self {
+            last_update_timestamp: format!("{}", now),
+            kind: request.kind.map(|x| x.to_string()),
+            bidirectional: request.bidirectional,
+            ..DBUpdateRelationship::default()
+        })
+    }
+}
+
+/// Our DB repr of a way to contact a buddy
+#[derive(Queryable)]
+pub struct DBContactMethod {
+    pub id: i32,
+    pub uuid: String,
+    pub buddy_uuid: String,
+    pub kind: String,
+    pub value: String,
+    pub label: Option<String>,
+    pub preferred: bool,
     pub create_timestamp: String,
+    pub last_update_timestamp: String,
+    pub delete_timestamp: Option<String>,
+    pub user_uuid: String,
 }
 
-impl TryFrom<DBUser> for User {
+impl TryFrom<DBContactMethod> for ContactMethod {
     type Error = anyhow::Error;
 
-    fn try_from(user: DBUser) -> Result<Self, Self::Error> {
-        let id = Uuid::parse_str(&user.user_uuid).context("Parsing user id")?;
+    fn try_from(contact_method: DBContactMethod) -> Result<Self, Self::Error> {
+        let delete_timestamp = match contact_method.delete_timestamp {
+            Some(x) => Some(Timestamp(x.parse().context("Parsing delete timestamp")?)),
+            None => None,
+        };
+        Ok(ContactMethod {
+            id: Uuid::parse_str(&contact_method.uuid).context("Parsing contact method id")?,
+            buddy_id: Uuid::parse_str(&contact_method.buddy_uuid)
+                .context("Parsing contact method's buddy id")?,
+            kind: contact_method
+                .kind
+                .parse()
+                .context("Parsing contact method kind")?,
+            value: contact_method.value,
+            label: contact_method.label,
+            preferred: contact_method.preferred,
+            create_timestamp: Timestamp(
+                contact_method
+                    .create_timestamp
+                    .parse()
+                    .context("parsing create timestamp")?,
+            ),
+            last_update_timestamp: Timestamp(
+                contact_method
+                    .last_update_timestamp
+                    .parse()
+                    .context("parsing last_update timestamp")?,
+            ),
+            delete_timestamp,
+            user_id: Uuid::parse_str(&contact_method.user_uuid)
+                .context("parsing contact method's user id")?,
+        })
+    }
+}
+
+#[derive(Insertable)]
+#[table_name = "contact_methods"]
+pub struct NewContactMethod {
+    pub uuid: String,
+    pub buddy_uuid: String,
+    pub kind: String,
+    pub value: String,
+    pub label: Option<String>,
+    pub preferred: bool,
+    pub create_timestamp: String,
+    pub last_update_timestamp: String,
+    pub user_uuid: String,
+}
+
+impl From<ContactMethod> for NewContactMethod {
+    fn from(contact_method: ContactMethod) -> Self {
+        NewContactMethod {
+            uuid: contact_method.id.to_string(),
+            buddy_uuid: contact_method.buddy_id.to_string(),
+            kind: contact_method.kind.to_string(),
+            value: contact_method.value,
+            label: contact_method.label,
+            preferred: contact_method.preferred,
+            create_timestamp: contact_method.create_timestamp.0.to_string(),
+            last_update_timestamp: contact_method.last_update_timestamp.0.to_string(),
+            user_uuid: contact_method.user_id.to_string(),
+        }
+    }
+}
+
+#[derive(AsChangeset, Default)]
+#[table_name = "contact_methods"]
+pub struct DBUpdateContactMethod {
+    pub last_update_timestamp: String,
+    pub value: Option<String>,
+    pub label: Option<String>,
+    pub preferred: Option<bool>,
+    pub delete_timestamp: Option<String>,
+}
+
+impl DBUpdateContactMethod {
+    pub fn archive() -> Result<Self> {
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)?
+            .as_secs();
         Ok(Self {
-            id,
-            email: user.email,
-            password: user.password,
+            last_update_timestamp: format!("{}", now),
+            delete_timestamp: Some(format!("{}", now)),
+            ..DBUpdateContactMethod::default()
+        })
+    }
+
+    pub fn update(request: UpdateContactMethodRequest) -> Result<Self> {
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)?
+            .as_secs();
+        Ok(Self {
+            last_update_timestamp: format!("{}", now),
+            value: request.value,
+            label: request.label,
+            preferred: request.preferred,
+            ..DBUpdateContactMethod::default()
+        })
+    }
+}
+
+/// Our DB repr of an important date
+#[derive(Queryable)]
+pub struct DBImportantDate {
+    pub id: i32,
+    pub uuid: String,
+    pub buddy_uuid: String,
+    pub kind: String,
+    pub label: Option<String>,
+    pub date: String,
+    pub recurrence: String,
+    pub create_timestamp: String,
+    pub last_update_timestamp: String,
+    pub delete_timestamp: Option<String>,
+    pub user_uuid: String,
+}
+
+impl TryFrom<DBImportantDate> for ImportantDate {
+    type Error = anyhow::Error;
+
+    fn try_from(important_date: DBImportantDate) -> Result<Self, Self::Error> {
+        let delete_timestamp = match important_date.delete_timestamp {
+            Some(x) => Some(Timestamp(x.parse().context("Parsing delete timestamp")?)),
+            None => None,
+        };
+        Ok(ImportantDate {
+            id: Uuid::parse_str(&important_date.uuid).context("Parsing important date id")?,
+            buddy_id: Uuid::parse_str(&important_date.buddy_uuid)
+                .context("Parsing important date's buddy id")?,
+            kind: important_date
+                .kind
+                .parse()
+                .context("Parsing important date kind")?,
+            label: important_date.label,
+            date: Datestamp(important_date.date),
+            recurrence: important_date
+                .recurrence
+                .parse()
+                .context("Parsing important date recurrence")?,
             create_timestamp: Timestamp(
-                user.create_timestamp
+                important_date
+                    .create_timestamp
                     .parse()
                     .context("parsing create timestamp")?,
             ),
             last_update_timestamp: Timestamp(
-                user.create_timestamp
+                important_date
+                    .last_update_timestamp
                     .parse()
                     .context("parsing last_update timestamp")?,
             ),
+            delete_timestamp,
+            user_id: Uuid::parse_str(&important_date.user_uuid)
+                .context("parsing important date's user id")?,
         })
     }
 }
 
 #[derive(Insertable)]
-#[table_name = "users"]
-pub struct NewUser {
-    pub email: String,
-    pub password: String,
-    pub user_id: String,
+#[table_name = "important_dates"]
+pub struct NewImportantDate {
+    pub uuid: String,
+    pub buddy_uuid: String,
+    pub kind: String,
+    pub label: Option<String>,
+    pub date: String,
+    pub recurrence: String,
+    pub create_timestamp: String,
+    pub last_update_timestamp: String,
+    pub user_uuid: String,
+}
+
+impl From<ImportantDate> for NewImportantDate {
+    fn from(important_date: ImportantDate) -> Self {
+        NewImportantDate {
+            uuid: important_date.id.to_string(),
+            buddy_uuid: important_date.buddy_id.to_string(),
+            kind: important_date.kind.to_string(),
+            label: important_date.label,
+            date: important_date.date.0,
+            recurrence: important_date.recurrence.to_string(),
+            create_timestamp: important_date.create_timestamp.0.to_string(),
+            last_update_timestamp: important_date.last_update_timestamp.0.to_string(),
+            user_uuid: important_date.user_id.to_string(),
+        }
+    }
+}
+
+#[derive(AsChangeset, Default)]
+#[table_name = "important_dates"]
+pub struct DBUpdateImportantDate {
+    pub last_update_timestamp: String,
+    pub kind: Option<String>,
+    pub label: Option<String>,
+    pub date: Option<String>,
+    pub recurrence: Option<String>,
+    pub delete_timestamp: Option<String>,
+}
+
+impl DBUpdateImportantDate {
+    pub fn archive() -> Result<Self> {
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)?
+            .as_secs();
+        Ok(Self {
+            last_update_timestamp: format!("{}", now),
+            delete_timestamp: Some(format!("{}", now)),
+            ..DBUpdateImportantDate::default()
+        })
+    }
+
+    pub fn update(request: UpdateImportantDateRequest) -> Result<Self> {
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)?
+            .as_secs();
+        Ok(Self {
+            last_update_timestamp: format!("{}", now),
+            kind: request.kind.map(|kind| kind.to_string()),
+            label: request.label,
+            date: request.date.map(|date| date.0),
+            recurrence: request.recurrence.map(|recurrence| recurrence.to_string()),
+            ..DBUpdateImportantDate::default()
+        })
+    }
+}
+
+/// Our DB repr of a follow up
+#[derive(Queryable)]
+pub struct DBFollowUp {
+    pub id: i32,
+    pub uuid: String,
+    pub interaction_uuid: String,
+    pub buddy_uuid: Option<String>,
+    pub notes: String,
+    pub due: Option<String>,
+    pub done: bool,
+    pub create_timestamp: String,
+    pub last_update_timestamp: String,
+    pub delete_timestamp: Option<String>,
+    pub user_uuid: String,
+}
+
+impl TryFrom<DBFollowUp> for FollowUp {
+    type Error = anyhow::Error;
+
+    fn try_from(follow_up: DBFollowUp) -> Result<Self, Self::Error> {
+        let delete_timestamp = match follow_up.delete_timestamp {
+            Some(x) => Some(Timestamp(x.parse().context("Parsing delete timestamp")?)),
+            None => None,
+        };
+        let buddy_id = match follow_up.buddy_uuid {
+            Some(buddy_uuid) => {
+                Some(Uuid::parse_str(&buddy_uuid).context("Parsing follow up's buddy id")?)
+            }
+            None => None,
+        };
+        Ok(FollowUp {
+            id: Uuid::parse_str(&follow_up.uuid).context("Parsing follow up id")?,
+            interaction_id: Uuid::parse_str(&follow_up.interaction_uuid)
+                .context("Parsing follow up's interaction id")?,
+            buddy_id,
+            notes: follow_up.notes,
+            due: follow_up.due.map(Datestamp),
+            done: follow_up.done,
+            create_timestamp: Timestamp(
+                follow_up
+                    .create_timestamp
+                    .parse()
+                    .context("parsing create timestamp")?,
+            ),
+            last_update_timestamp: Timestamp(
+                follow_up
+                    .last_update_timestamp
+                    .parse()
+                    .context("parsing last_update timestamp")?,
+            ),
+            delete_timestamp,
+            user_id: Uuid::parse_str(&follow_up.user_uuid)
+                .context("parsing follow up's user id")?,
+        })
+    }
+}
+
+#[derive(Insertable)]
+#[table_name = "follow_ups"]
+pub struct NewFollowUp {
+    pub uuid: String,
+    pub interaction_uuid: String,
+    pub buddy_uuid: Option<String>,
+    pub notes: String,
+    pub due: Option<String>,
+    pub done: bool,
     pub create_timestamp: String,
+    pub last_update_timestamp: String,
+    pub user_uuid: String,
+}
+
+impl From<FollowUp> for NewFollowUp {
+    fn from(follow_up: FollowUp) -> Self {
+        NewFollowUp {
+            uuid: follow_up.id.to_string(),
+            interaction_uuid: follow_up.interaction_id.to_string(),
+            buddy_uuid: follow_up.buddy_id.map(|id| id.to_string()),
+            notes: follow_up.notes,
+            due: follow_up.due.map(|due| due.0),
+            done: follow_up.done,
+            create_timestamp: follow_up.create_timestamp.0.to_string(),
+            last_update_timestamp: follow_up.last_update_timestamp.0.to_string(),
+            user_uuid: follow_up.user_id.to_string(),
+        }
+    }
+}
+
+#[derive(AsChangeset, Default)]
+#[table_name = "follow_ups"]
+pub struct DBUpdateFollowUp {
+    pub last_update_timestamp: String,
+    pub done: Option<bool>,
+    pub delete_timestamp: Option<String>,
+}
+
+impl DBUpdateFollowUp {
+    pub fn complete() -> Result<Self> {
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)?
+            .as_secs();
+        Ok(Self {
+            last_update_timestamp: format!("{}", now),
+            done: Some(true),
+            ..DBUpdateFollowUp::default()
+        })
+    }
+}
+
+/// Our DB repr of an idea
+#[derive(Queryable)]
+pub struct DBIdea {
+    pub id: i32,
+    pub uuid: String,
+    pub buddy_uuid: String,
+    pub kind: String,
+    pub description: String,
+    pub status: String,
+    pub interaction_uuid: Option<String>,
+    pub create_timestamp: String,
+    pub last_update_timestamp: String,
+    pub delete_timestamp: Option<String>,
+    pub user_uuid: String,
+}
+
+impl TryFrom<DBIdea> for Idea {
+    type Error = anyhow::Error;
+
+    fn try_from(idea: DBIdea) -> Result<Self, Self::Error> {
+        let delete_timestamp = match idea.delete_timestamp {
+            Some(x) => Some(Timestamp(x.parse().context("Parsing delete timestamp")?)),
+            None => None,
+        };
+        let interaction_id = match idea.interaction_uuid {
+            Some(interaction_uuid) => {
+                Some(Uuid::parse_str(&interaction_uuid).context("Parsing idea's interaction id")?)
+            }
+            None => None,
+        };
+        Ok(Idea {
+            id: Uuid::parse_str(&idea.uuid).context("Parsing idea id")?,
+            buddy_id: Uuid::parse_str(&idea.buddy_uuid).context("Parsing idea's buddy id")?,
+            kind: idea.kind.parse().context("Parsing idea kind")?,
+            description: idea.description,
+            status: idea.status.parse().context("Parsing idea status")?,
+            interaction_id,
+            create_timestamp: Timestamp(
+                idea.create_timestamp
+                    .parse()
+                    .context("parsing create timestamp")?,
+            ),
+            last_update_timestamp: Timestamp(
+                idea.last_update_timestamp
+                    .parse()
+                    .context("parsing last_update timestamp")?,
+            ),
+            delete_timestamp,
+            user_id: Uuid::parse_str(&idea.user_uuid).context("parsing idea's user id")?,
+        })
+    }
+}
+
+#[derive(Insertable)]
+#[table_name = "ideas"]
+pub struct NewIdea {
+    pub uuid: String,
+    pub buddy_uuid: String,
+    pub kind: String,
+    pub description: String,
+    pub status: String,
+    pub interaction_uuid: Option<String>,
+    pub create_timestamp: String,
+    pub last_update_timestamp: String,
+    pub user_uuid: String,
+}
+
+impl From<Idea> for NewIdea {
+    fn from(idea: Idea) -> Self {
+        NewIdea {
+            uuid: idea.id.to_string(),
+            buddy_uuid: idea.buddy_id.to_string(),
+            kind: idea.kind.to_string(),
+            description: idea.description,
+            status: idea.status.to_string(),
+            interaction_uuid: idea.interaction_id.map(|id| id.to_string()),
+            create_timestamp: idea.create_timestamp.0.to_string(),
+            last_update_timestamp: idea.last_update_timestamp.0.to_string(),
+            user_uuid: idea.user_id.to_string(),
+        }
+    }
+}
+
+#[derive(AsChangeset, Default)]
+#[table_name = "ideas"]
+pub struct DBUpdateIdea {
+    pub last_update_timestamp: String,
+    pub kind: Option<String>,
+    pub description: Option<String>,
+    pub status: Option<String>,
+    pub interaction_uuid: Option<String>,
+    pub delete_timestamp: Option<String>,
+}
+
+impl DBUpdateIdea {
+    pub fn archive() -> Result<Self> {
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)?
+            .as_secs();
+        Ok(Self {
+            last_update_timestamp: format!("{}", now),
+            delete_timestamp: Some(format!("{}", now)),
+            ..DBUpdateIdea::default()
+        })
+    }
+
+    pub fn update(request: UpdateIdeaRequest) -> Result<Self> {
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)?
+            .as_secs();
+        Ok(Self {
+            last_update_timestamp: format!("{}", now),
+            kind: request.kind.map(|kind| kind.to_string()),
+            description: request.description,
+            status: request.status.map(|status| status.to_string()),
+            interaction_uuid: request.interaction_id.map(|id| id.to_string()),
+            ..DBUpdateIdea::default()
+        })
+    }
+}
+
+/// Our DB repr of a revision. Snapshots are stored as JSON.
+#[derive(Queryable)]
+pub struct DBRevision {
+    pub id: i32,
+    pub uuid: String,
+    pub entity_kind: String,
+    pub entity_uuid: String,
+    pub action: String,
+    pub before: Option<String>,
+    pub after: Option<String>,
+    pub reverted_to_uuid: Option<String>,
+    pub create_timestamp: String,
+    pub user_uuid: String,
+}
+
+impl TryFrom<DBRevision> for Revision {
+    type Error = anyhow::Error;
+
+    fn try_from(revision: DBRevision) -> Result<Self, Self::Error> {
+        let snapshot = |json: Option<String>| -> Result<Option<serde_json::Value>> {
+            match json {
+                Some(json) => Ok(Some(
+                    serde_json::from_str(&json).context("Parsing revision snapshot")?,
+                )),
+                None => Ok(None),
+            }
+        };
+        let reverted_to = match revision.reverted_to_uuid {
+            Some(uuid) => Some(Uuid::parse_str(&uuid).context("Parsing reverted to id")?),
+            None => None,
+        };
+        Ok(Revision {
+            id: Uuid::parse_str(&revision.uuid).context("Parsing revision id")?,
+            entity_kind: revision
+                .entity_kind
+                .parse()
+                .context("Parsing revision entity kind")?,
+            entity_id: Uuid::parse_str(&revision.entity_uuid)
+                .context("Parsing revision entity id")?,
+            action: revision.action.parse().context("Parsing revision action")?,
+            before: snapshot(revision.before)?,
+            after: snapshot(revision.after)?,
+            reverted_to,
+            create_timestamp: Timestamp(
+                revision
+                    .create_timestamp
+                    .parse()
+                    .context("parsing create timestamp")?,
+            ),
+            user_id: Uuid::parse_str(&revision.user_uuid).context("parsing revision's user id")?,
+        })
+    }
+}
+
+#[derive(Insertable)]
+#[table_name = "revisions"]
+pub struct NewRevision {
+    pub uuid: String,
+    pub entity_kind: String,
+    pub entity_uuid: String,
+    pub action: String,
+    pub before: Option<String>,
+    pub after: Option<String>,
+    pub reverted_to_uuid: Option<String>,
+    pub create_timestamp: String,
+    pub user_uuid: String,
+}
+
+impl From<Revision> for NewRevision {
+    fn from(revision: Revision) -> Self {
+        NewRevision {
+            uuid: revision.id.to_string(),
+            entity_kind: revision.entity_kind.to_string(),
+            entity_uuid: revision.entity_id.to_string(),
+            action: revision.action.to_string(),
+            before: revision.before.map(|before| before.to_string()),
+            after: revision.after.map(|after| after.to_string()),
+            reverted_to_uuid: revision.reverted_to.map(|id| id.to_string()),
+            create_timestamp: revision.create_timestamp.0.to_string(),
+            user_uuid: revision.user_id.to_string(),
+        }
+    }
+}
+
+/// Our DB repr of an idempotency record. Requests and responses are stored as JSON.
+#[derive(Queryable)]
+pub struct DBIdempotencyRecord {
+    pub id: i32,
+    pub user_uuid: String,
+    pub operation: String,
+    pub key: String,
+    pub request: String,
+    pub response: String,
+    pub create_timestamp: String,
+}
+
+impl TryFrom<DBIdempotencyRecord> for IdempotencyRecord {
+    type Error = anyhow::Error;
+
+    fn try_from(record: DBIdempotencyRecord) -> Result<Self, Self::Error> {
+        Ok(IdempotencyRecord {
+            user_id: Uuid::parse_str(&record.user_uuid)
+                .context("parsing idempotency record's user id")?,
+            operation: record.operation,
+            key: record.key,
+            request: serde_json::from_str(&record.request)
+                .context("Parsing idempotency record request")?,
+            response: serde_json::from_str(&record.response)
+                .context("Parsing idempotency record response")?,
+            create_timestamp: Timestamp(
+                record
+                    .create_timestamp
+                    .parse()
+                    .context("parsing create timestamp")?,
+            ),
+        })
+    }
+}
+
+#[derive(Insertable)]
+#[table_name = "idempotency_keys"]
+pub struct NewIdempotencyRecord {
+    pub user_uuid: String,
+    pub operation: String,
+    pub key: String,
+    pub request: String,
+    pub response: String,
+    pub create_timestamp: String,
+}
+
+impl From<IdempotencyRecord> for NewIdempotencyRecord {
+    fn from(record: IdempotencyRecord) -> Self {
+        NewIdempotencyRecord {
+            user_uuid: record.user_id.to_string(),
+            operation: record.operation,
+            key: record.key,
+            request: record.request.to_string(),
+            response: record.response.to_string(),
+            create_timestamp: record.create_timestamp.0.to_string(),
+        }
+    }
+}
+
+/// Our DB repr of a webhook
+#[derive(Queryable)]
+pub struct DBWebhook {
+    pub id: i32,
+    pub uuid: String,
+    pub url: String,
+    pub secret: String,
+    pub create_timestamp: String,
+    pub delete_timestamp: Option<String>,
+    pub user_uuid: String,
+}
+
+impl TryFrom<DBWebhook> for Webhook {
+    type Error = anyhow::Error;
+
+    fn try_from(webhook: DBWebhook) -> Result<Self, Self::Error> {
+        let delete_timestamp = match webhook.delete_timestamp {
+            Some(timestamp) => Some(Timestamp(
+                timestamp.parse().context("parsing delete timestamp")?,
+            )),
+            None => None,
+        };
+        Ok(Webhook {
+            id: Uuid::parse_str(&webhook.uuid).context("Parsing webhook id")?,
+            url: webhook.url,
+            secret: webhook.secret,
+            create_timestamp: Timestamp(
+                webhook
+                    .create_timestamp
+                    .parse()
+                    .context("parsing create timestamp")?,
+            ),
+            delete_timestamp,
+            user_id: Uuid::parse_str(&webhook.user_uuid).context("parsing webhook's user id")?,
+        })
+    }
+}
+
+#[derive(Insertable)]
+#[table_name = "webhooks"]
+pub struct NewWebhook {
+    pub uuid: String,
+    pub url: String,
+    pub secret: String,
+    pub create_timestamp: String,
+    pub delete_timestamp: Option<String>,
+    pub user_uuid: String,
+}
+
+impl From<Webhook> for NewWebhook {
+    fn from(webhook: Webhook) -> Self {
+        NewWebhook {
+            uuid: webhook.id.to_string(),
+            url: webhook.url,
+            secret: webhook.secret,
+            create_timestamp: webhook.create_timestamp.0.to_string(),
+            delete_timestamp: webhook.delete_timestamp.map(|x| x.0.to_string()),
+            user_uuid: webhook.user_id.to_string(),
+        }
+    }
+}
+
+#[derive(AsChangeset)]
+#[table_name = "webhooks"]
+pub struct DBArchiveWebhook {
+    pub delete_timestamp: String,
+}
+
+impl DBArchiveWebhook {
+    pub fn new() -> Result<Self> {
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)?
+            .as_secs();
+        Ok(Self {
+            delete_timestamp: format!("{}", now),
+        })
+    }
+}
+
+/// Our DB repr of a webhook delivery. The payload is stored as JSON.
+#[derive(Queryable)]
+pub struct DBWebhookDelivery {
+    pub id: i32,
+    pub uuid: String,
+    pub webhook_uuid: String,
+    pub payload: String,
+    pub status: String,
+    pub attempts: i32,
+    pub next_attempt_timestamp: Option<String>,
+    pub response_status: Option<i32>,
+    pub error: Option<String>,
+    pub create_timestamp: String,
+    pub last_update_timestamp: String,
+    pub user_uuid: String,
+}
+
+impl TryFrom<DBWebhookDelivery> for WebhookDelivery {
+    type Error = anyhow::Error;
+
+    fn try_from(delivery: DBWebhookDelivery) -> Result<Self, Self::Error> {
+        let next_attempt_timestamp = match delivery.next_attempt_timestamp {
+            Some(timestamp) => Some(Timestamp(
+                timestamp
+                    .parse()
+                    .context("parsing next attempt timestamp")?,
+            )),
+            None => None,
+        };
+        Ok(WebhookDelivery {
+            id: Uuid::parse_str(&delivery.uuid).context("Parsing webhook delivery id")?,
+            webhook_id: Uuid::parse_str(&delivery.webhook_uuid)
+                .context("Parsing webhook delivery's webhook id")?,
+            payload: serde_json::from_str(&delivery.payload)
+                .context("Parsing webhook delivery payload")?,
+            status: delivery
+                .status
+                .parse()
+                .context("Parsing webhook delivery status")?,
+            attempts: delivery.attempts,
+            next_attempt_timestamp,
+            response_status: delivery.response_status,
+            error: delivery.error,
+            create_timestamp: Timestamp(
+                delivery
+                    .create_timestamp
+                    .parse()
+                    .context("parsing create timestamp")?,
+            ),
+            last_update_timestamp: Timestamp(
+                delivery
+                    .last_update_timestamp
+                    .parse()
+                    .context("parsing last update timestamp")?,
+            ),
+            user_id: Uuid::parse_str(&delivery.user_uuid)
+                .context("parsing webhook delivery's user id")?,
+        })
+    }
+}
+
+#[derive(Insertable, AsChangeset)]
+#[table_name = "webhook_deliveries"]
+#[changeset_options(treat_none_as_null = "true")]
+pub struct NewWebhookDelivery {
+    pub uuid: String,
+    pub webhook_uuid: String,
+    pub payload: String,
+    pub status: String,
+    pub attempts: i32,
+    pub next_attempt_timestamp: Option<String>,
+    pub response_status: Option<i32>,
+    pub error: Option<String>,
+    pub create_timestamp: String,
+    pub last_update_timestamp: String,
+    pub user_uuid: String,
+}
+
+impl From<WebhookDelivery> for NewWebhookDelivery {
+    fn from(delivery: WebhookDelivery) -> Self {
+        NewWebhookDelivery {
+            uuid: delivery.id.to_string(),
+            webhook_uuid: delivery.webhook_id.to_string(),
+            payload: delivery.payload.to_string(),
+            status: delivery.status.to_string(),
+            attempts: delivery.attempts,
+            next_attempt_timestamp: delivery.next_attempt_timestamp.map(|x| x.0.to_string()),
+            response_status: delivery.response_status,
+            error: delivery.error,
+            create_timestamp: delivery.create_timestamp.0.to_string(),
+            last_update_timestamp: delivery.last_update_timestamp.0.to_string(),
+            user_uuid: delivery.user_id.to_string(),
+        }
+    }
+}
+
+/// Our DB repr of a personal access token. Only the token's hash is kept.
+#[derive(Queryable)]
+pub struct DBPersonalAccessToken {
+    pub id: i32,
+    pub uuid: String,
+    pub name: String,
+    pub scopes: Vec<String>,
+    pub token_hash: String,
+    pub create_timestamp: String,
+    pub revoke_timestamp: Option<String>,
+    pub user_uuid: String,
+}
+
+impl TryFrom<DBPersonalAccessToken> for PersonalAccessToken {
+    type Error = anyhow::Error;
+
+    fn try_from(token: DBPersonalAccessToken) -> Result<Self, Self::Error> {
+        let revoke_timestamp = match token.revoke_timestamp {
+            Some(timestamp) => Some(Timestamp(
+                timestamp.parse().context("parsing revoke timestamp")?,
+            )),
+            None => None,
+        };
+        let scopes = token
+            .scopes
+            .iter()
+            .map(|scope| scope.parse())
+            .collect::<Result<Vec<Scope>>>()
+            .context("Parsing personal access token scopes")?;
+        Ok(PersonalAccessToken {
+            id: Uuid::parse_str(&token.uuid).context("Parsing personal access token id")?,
+            name: token.name,
+            scopes,
+            token_hash: token.token_hash,
+            create_timestamp: Timestamp(
+                token
+                    .create_timestamp
+                    .parse()
+                    .context("parsing create timestamp")?,
+            ),
+            revoke_timestamp,
+            user_id: Uuid::parse_str(&token.user_uuid)
+                .context("parsing personal access token's user id")?,
+        })
+    }
+}
+
+#[derive(Insertable)]
+#[table_name = "personal_access_tokens"]
+pub struct NewPersonalAccessToken {
+    pub uuid: String,
+    pub name: String,
+    pub scopes: Vec<String>,
+    pub token_hash: String,
+    pub create_timestamp: String,
+    pub revoke_timestamp: Option<String>,
+    pub user_uuid: String,
+}
+
+impl From<PersonalAccessToken> for NewPersonalAccessToken {
+    fn from(token: PersonalAccessToken) -> Self {
+        NewPersonalAccessToken {
+            uuid: token.id.to_string(),
+            name: token.name,
+            scopes: token.scopes.iter().map(|scope| scope.to_string()).collect(),
+            token_hash: token.token_hash,
+            create_timestamp: token.create_timestamp.0.to_string(),
+            revoke_timestamp: token.revoke_timestamp.map(|x| x.0.to_string()),
+            user_uuid: token.user_id.to_string(),
+        }
+    }
+}
+
+#[derive(AsChangeset)]
+#[table_name = "personal_access_tokens"]
+pub struct DBRevokePersonalAccessToken {
+    pub revoke_timestamp: String,
+}
+
+impl DBRevokePersonalAccessToken {
+    pub fn new() -> Result<Self> {
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)?
+            .as_secs();
+        Ok(Self {
+            revoke_timestamp: format!("{}", now),
+        })
+    }
+}
+
+/// Every date a single buddy was contacted, aggregated by the DB
+#[derive(QueryableByName)]
+pub struct DBContactDates {
+    #[sql_type = "Text"]
+    pub participant: String,
+    #[sql_type = "Array<Text>"]
+    pub dates: Vec<String>,
+}
+
+#[derive(Queryable, Debug)]
+pub struct DBUser {
+    pub id: i32,
+    pub email: String,
+    pub password: String,
+    pub user_uuid: String,
+    pub create_timestamp: String,
+    pub digest_frequency: String,
+    pub calendar_token: Option<String>,
+}
+
+impl TryFrom<DBUser> for User {
+    type Error = anyhow::Error;
+
+    fn try_from(user: DBUser) -> Result<Self, Self::Error> {
+        let id = Uuid::parse_str(&user.user_uuid).context("Parsing user id")?;
+        Ok(Self {
+            id,
+            email: user.email,
+            password: user.password,
+            digest_frequency: user
+                .digest_frequency
+                .parse()
+                .context("parsing digest frequency")?,
+            calendar_token: user.calendar_token,
+            create_timestamp: Timestamp(
+                user.create_timestamp
+                    .parse()
+                    .context("parsing create timestamp")?,
+            ),
+            last_update_timestamp: Timestamp(
+                user.create_timestamp
+                    .parse()
+                    .context("parsing last_update timestamp")?,
+            ),
+        })
+    }
+}
+
+#[derive(Insertable)]
+#[table_name = "users"]
+pub struct NewUser {
+    pub email: String,
+    pub password: String,
+    pub user_id: String,
+    pub create_timestamp: String,
+    pub digest_frequency: String,
+}
+
+#[derive(AsChangeset, Default)]
+#[table_name = "users"]
+pub struct DBUpdateUser {
+    pub digest_frequency: Option<String>,
+    pub calendar_token: Option<String>,
+}
+
+impl DBUpdateUser {
+    pub fn calendar_token(token: String) -> Self {
+        Self {
+            calendar_token: Some(token),
+            ..DBUpdateUser::default()
+        }
+    }
+    pub fn update(request: UpdateUserRequest) -> Self {
+        Self {
+            digest_frequency: request.digest_frequency.map(|x| x.to_string()),
+            ..DBUpdateUser::default()
+        }
+    }
 }
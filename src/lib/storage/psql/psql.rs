@@ -1,19 +1,37 @@
 use super::models::{
-    DBBuddy, DBInteraction, DBUpdateBuddy, DBUpdateInteraction, DBUser, NewBuddy, NewInteraction,
-    NewUser,
+    DBArchiveWebhook, DBBuddy, DBContactDates, DBContactMethod, DBFollowUp, DBIdea,
+    DBIdempotencyRecord, DBImportantDate, DBInteraction, DBPersonalAccessToken, DBRelationship,
+    DBRevision, DBRevokePersonalAccessToken, DBUpdateBuddy, DBUpdateContactMethod,
+    DBUpdateFollowUp, DBUpdateIdea, DBUpdateImportantDate, DBUpdateInteraction,
+    DBUpdateRelationship, DBUpdateUser, DBUser, DBWebhook, DBWebhookDelivery, NewBuddy,
+    NewContactMethod, NewFollowUp, NewIdea, NewIdempotencyRecord, NewImportantDate, NewInteraction,
+    NewPersonalAccessToken, NewRelationship, NewRevision, NewUser, NewWebhook, NewWebhookDelivery,
+};
+use super::schema::{
+    buddies, buddy_relationships, contact_methods, follow_ups, ideas, idempotency_keys,
+    important_dates, interactions, personal_access_tokens, revisions, users, webhook_deliveries,
+    webhooks,
 };
-use super::schema::{buddies, interactions, users};
 use crate::lib::storage::traits::{AuthStore, BuddiesStore};
 use crate::lib::types::{
-    Buddy, CreateUserRequest, Interaction, LoginRequest, UpdateBuddyRequest,
-    UpdateInteractionRequest, User,
+    Buddy, ContactHistory, ContactMethod, CreateUserRequest, CurrentRecord, Datestamp,
+    DeliveryStatus, FollowUp, Idea, IdempotencyRecord, ImportantDate, Interaction, LoginRequest,
+    NotFoundError, PersonalAccessToken, Relationship, Revision, StaleWriteError, Timestamp,
+    UpdateBuddyRequest, UpdateContactMethodRequest, UpdateIdeaRequest, UpdateImportantDateRequest,
+    UpdateInteractionRequest, UpdateRelationshipRequest, UpdateUserRequest, User, Webhook,
+    WebhookDelivery,
 };
 use anyhow::{anyhow, Context, Result};
+use diesel::connection::TransactionManager;
 use diesel::pg::PgConnection;
 use diesel::r2d2::{ConnectionManager, Pool, PoolError, PooledConnection};
-use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
+use diesel::sql_types::{Nullable, Text};
+use diesel::{Connection, ExpressionMethods, OptionalExtension, QueryDsl, RunQueryDsl};
 use std::collections::HashMap;
 use std::convert::TryFrom;
+use std::ops::Deref;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::SystemTime;
 use uuid::Uuid;
 
 pub type DBPool = Pool<ConnectionManager<PgConnection>>;
@@ -24,19 +42,46 @@ pub fn create_pool(psql_str: &str) -> std::result::Result<DBPool, PoolError> {
     Pool::builder().build(manager)
 }
 
+/// A connection from the pool, or the one a transaction is running on
+pub enum DBConnRef<'a> {
+    Pooled(DBCon),
+    Transaction(MutexGuard<'a, DBCon>),
+}
+
+impl Deref for DBConnRef<'_> {
+    type Target = PgConnection;
+
+    fn deref(&self) -> &PgConnection {
+        match self {
+            DBConnRef::Pooled(conn) => conn,
+            DBConnRef::Transaction(conn) => conn,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct PsqlBuddiesStore {
     db_pool: DBPool,
+    /// Set on the copy of the store handed to a transaction, so every query goes through it
+    transaction_conn: Option<Arc<Mutex<DBCon>>>,
 }
 
 impl PsqlBuddiesStore {
     pub fn new(psql_str: &str) -> PsqlBuddiesStore {
         let db_pool = create_pool(psql_str).expect("Could not connect to database");
-        PsqlBuddiesStore { db_pool }
+        PsqlBuddiesStore {
+            db_pool,
+            transaction_conn: None,
+        }
     }
 
-    pub fn get_db_conn(&self) -> Result<DBCon, PoolError> {
-        self.db_pool.get()
+    /// Callers should let go of the connection before calling other store methods, as a
+    /// transaction only has the one.
+    pub fn get_db_conn(&self) -> Result<DBConnRef<'_>, PoolError> {
+        match &self.transaction_conn {
+            Some(conn) => Ok(DBConnRef::Transaction(conn.lock().unwrap())),
+            None => Ok(DBConnRef::Pooled(self.db_pool.get()?)),
+        }
     }
 }
 
@@ -48,11 +93,12 @@ impl AuthStore for PsqlBuddiesStore {
             password: request.user.password,
             user_id: request.user.id.to_string(),
             create_timestamp: request.user.create_timestamp.0.to_string(),
+            digest_frequency: request.user.digest_frequency.to_string(),
         };
 
         diesel::insert_into(users::table)
             .values(&new_user_request)
-            .execute(&conn)
+            .execute(&*conn)
             .context(format!(
                 "Attempting to create insert statement for user with uuid {}",
                 new_user_request.user_id
@@ -64,7 +110,7 @@ impl AuthStore for PsqlBuddiesStore {
         let conn = self.get_db_conn()?;
         let db_users = users::dsl::users
             .filter(users::dsl::email.eq(&request.email))
-            .load::<DBUser>(&conn)
+            .load::<DBUser>(&*conn)
             .context(format!("Looking for user {} with email", request.email))?;
 
         if db_users.len() != 1 {
@@ -78,6 +124,81 @@ impl AuthStore for PsqlBuddiesStore {
             Ok(User::try_from(user).context("Converting user back from DB")?)
         }
     }
+    fn create_personal_access_token(&mut self, token: PersonalAccessToken) -> Result<()> {
+        let conn = self.get_db_conn()?;
+        let token_uuid = token.id;
+        let new_token_request = NewPersonalAccessToken::from(token);
+        diesel::insert_into(personal_access_tokens::table)
+            .values(&new_token_request)
+            .execute(&*conn)
+            .context(format!(
+                "Error attempting to persist personal access token in db with uuid {}",
+                token_uuid
+            ))?;
+        Ok(())
+    }
+    fn revoke_personal_access_token(&mut self, id: Uuid, user_id: Uuid) -> Result<()> {
+        let conn = self.get_db_conn()?;
+        let update = DBRevokePersonalAccessToken::new()
+            .context("Creating revoke personal access token request")?;
+        let updated = diesel::update(
+            personal_access_tokens::dsl::personal_access_tokens
+                .filter(personal_access_tokens::dsl::uuid.eq(id.to_string()))
+                .filter(personal_access_tokens::dsl::user_uuid.eq(user_id.to_string())),
+        )
+        .set(&update)
+        .execute(&*conn)
+        .context(format!("Revoking personal access token {} {}", id, user_id))?;
+        if updated == 0 {
+            return Err(NotFoundError {
+                kind: "personal access token",
+                id,
+            }
+            .into());
+        }
+        Ok(())
+    }
+    fn get_personal_access_tokens(
+        &self,
+        user_id: Uuid,
+    ) -> Result<HashMap<Uuid, PersonalAccessToken>> {
+        let user_id_string = user_id.to_string();
+        let conn = self.get_db_conn()?;
+        let db_tokens = personal_access_tokens::dsl::personal_access_tokens
+            .filter(personal_access_tokens::dsl::user_uuid.eq(&user_id_string))
+            .filter(personal_access_tokens::dsl::revoke_timestamp.is_null())
+            .load::<DBPersonalAccessToken>(&*conn)
+            .context(format!(
+                "Looking for personal access tokens of user {}",
+                user_id_string
+            ))?;
+        let mut resulting_map = HashMap::new();
+        for db_token in db_tokens {
+            let token = PersonalAccessToken::try_from(db_token).context(format!(
+                "Reading personal access tokens for {}",
+                user_id_string
+            ))?;
+            resulting_map.insert(token.id, token);
+        }
+        Ok(resulting_map)
+    }
+    fn get_personal_access_token_by_hash(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<PersonalAccessToken>> {
+        let conn = self.get_db_conn()?;
+        let db_token = personal_access_tokens::dsl::personal_access_tokens
+            .filter(personal_access_tokens::dsl::token_hash.eq(token_hash))
+            .filter(personal_access_tokens::dsl::revoke_timestamp.is_null())
+            .first::<DBPersonalAccessToken>(&*conn)
+            .optional()
+            .context("Looking for personal access token")?;
+        db_token
+            .map(|db_token| {
+                PersonalAccessToken::try_from(db_token).context("Reading personal access token")
+            })
+            .transpose()
+    }
 }
 
 impl BuddiesStore for PsqlBuddiesStore {
@@ -90,7 +211,7 @@ impl BuddiesStore for PsqlBuddiesStore {
         ))?;
         diesel::insert_into(buddies::table)
             .values(&new_buddy_request)
-            .execute(&conn)
+            .execute(&*conn)
             .context(format!(
                 "Error attempting to persist buddy in db with uuid {}",
                 buddy_uuid
@@ -106,7 +227,7 @@ impl BuddiesStore for PsqlBuddiesStore {
         ))?;
         diesel::insert_into(interactions::table)
             .values(&new_interaction_request)
-            .execute(&conn)
+            .execute(&*conn)
             .context(format!(
                 "Error attempting to persist interaction in db with uuid {}",
                 interaction_uuid
@@ -119,7 +240,7 @@ impl BuddiesStore for PsqlBuddiesStore {
         let conn = self.get_db_conn()?;
         let db_buddies = buddies::dsl::buddies
             .filter(buddies::dsl::user_uuid.eq(&user_id_string))
-            .load::<DBBuddy>(&conn)
+            .load::<DBBuddy>(&*conn)
             .context(format!("Looking for user {}", user_id_string))?;
         let mut resulting_map = HashMap::new();
         let buddies: Vec<Buddy> = db_buddies
@@ -138,7 +259,7 @@ impl BuddiesStore for PsqlBuddiesStore {
         let conn = self.get_db_conn()?;
         let db_interactions = interactions::dsl::interactions
             .filter(interactions::dsl::user_uuid.eq(&user_id_string))
-            .load::<DBInteraction>(&conn)
+            .load::<DBInteraction>(&*conn)
             .context(format!("Looking for user {}", user_id_string))?;
         let mut resulting_map = HashMap::new();
         let interactions: Vec<Interaction> = db_interactions
@@ -152,6 +273,59 @@ impl BuddiesStore for PsqlBuddiesStore {
         }
         Ok(resulting_map)
     }
+    fn get_contact_history(
+        &self,
+        user_id: Uuid,
+        since: Option<&Datestamp>,
+        until: Option<&Datestamp>,
+    ) -> Result<ContactHistory> {
+        let user_id_string = user_id.to_string();
+        let since = since.map(|since| since.0.clone());
+        let until = until.map(|until| until.0.clone());
+        let conn = self.get_db_conn()?;
+        let mut count_query = interactions::dsl::interactions
+            .filter(interactions::dsl::user_uuid.eq(&user_id_string))
+            .filter(interactions::dsl::delete_timestamp.is_null())
+            .filter(interactions::dsl::date.is_not_null())
+            .into_boxed();
+        if let Some(since) = &since {
+            count_query = count_query.filter(interactions::dsl::date.ge(since));
+        }
+        if let Some(until) = &until {
+            count_query = count_query.filter(interactions::dsl::date.le(until));
+        }
+        let total_interactions = count_query
+            .count()
+            .get_result::<i64>(&*conn)
+            .context(format!("Counting interactions for {}", user_id_string))?;
+        // Dates are stored as yyyy-mm-dd, so comparing and ordering them as text works
+        let db_contact_dates = diesel::sql_query(
+            "SELECT participant, array_agg(date ORDER BY date) AS dates \
+             FROM interactions, unnest(participants) AS participant \
+             WHERE user_uuid = $1 AND delete_timestamp IS NULL AND date IS NOT NULL \
+             AND ($2 IS NULL OR date >= $2) AND ($3 IS NULL OR date <= $3) \
+             GROUP BY participant",
+        )
+        .bind::<Text, _>(&user_id_string)
+        .bind::<Nullable<Text>, _>(&since)
+        .bind::<Nullable<Text>, _>(&until)
+        .load::<DBContactDates>(&*conn)
+        .context(format!("Aggregating contact dates for {}", user_id_string))?;
+
+        let mut contact_dates = HashMap::new();
+        for db_dates in db_contact_dates {
+            let buddy_id = Uuid::parse_str(&db_dates.participant)
+                .context("Parsing uuid for participant of interaction")?;
+            contact_dates.insert(
+                buddy_id,
+                db_dates.dates.into_iter().map(Datestamp).collect(),
+            );
+        }
+        Ok(ContactHistory {
+            total_interactions: total_interactions as usize,
+            contact_dates,
+        })
+    }
     fn archive_buddy(&mut self, id: Uuid, user_id: Uuid) -> Result<()> {
         let conn = self.get_db_conn()?;
         let update = DBUpdateBuddy::archive().context("Creating archive buddy request")?;
@@ -160,8 +334,8 @@ impl BuddiesStore for PsqlBuddiesStore {
                 .filter(buddies::dsl::uuid.eq(id.to_string()))
                 .filter(buddies::dsl::user_uuid.eq(user_id.to_string())),
         )
-        .set(&update)
-        .execute(&conn)
+        .set((&update, buddies::dsl::version.eq(buddies::dsl::version + 1)))
+        .execute(&*conn)
         .context(format!("Archiving buddy {} {}", id, user_id))?;
         Ok(())
     }
@@ -174,8 +348,11 @@ impl BuddiesStore for PsqlBuddiesStore {
                 .filter(interactions::dsl::uuid.eq(id.to_string()))
                 .filter(interactions::dsl::user_uuid.eq(user_id.to_string())),
         )
-        .set(&update)
-        .execute(&conn)
+        .set((
+            &update,
+            interactions::dsl::version.eq(interactions::dsl::version + 1),
+        ))
+        .execute(&*conn)
         .context(format!("Archiving interaction {} {}", id, user_id))?;
         Ok(())
     }
@@ -185,15 +362,34 @@ impl BuddiesStore for PsqlBuddiesStore {
         // portion of the updatebuddyrequest
         let buddy_id = request.buddy_id.clone();
         let user_id = request.user_id.clone();
+        let expected_version = request.version;
         let update = DBUpdateBuddy::update(request).context("Creating update buddy request")?;
-        diesel::update(
-            buddies::dsl::buddies
-                .filter(buddies::dsl::uuid.eq(buddy_id.to_string()))
-                .filter(buddies::dsl::user_uuid.eq(user_id.to_string())),
-        )
-        .set(&update)
-        .execute(&conn)
+        let target = buddies::dsl::buddies
+            .filter(buddies::dsl::uuid.eq(buddy_id.to_string()))
+            .filter(buddies::dsl::user_uuid.eq(user_id.to_string()))
+            .filter(buddies::dsl::delete_timestamp.is_null());
+        let changes = (&update, buddies::dsl::version.eq(buddies::dsl::version + 1));
+        let updated = match expected_version {
+            Some(version) => diesel::update(target.filter(buddies::dsl::version.eq(version)))
+                .set(changes)
+                .execute(&*conn),
+            None => diesel::update(target).set(changes).execute(&*conn),
+        }
         .context(format!("Updating buddy {} {}", buddy_id, user_id))?;
+        drop(conn);
+        if updated == 0 {
+            return Err(match self.get_buddies(user_id)?.remove(&buddy_id) {
+                Some(buddy) if buddy.delete_timestamp.is_none() => StaleWriteError {
+                    current: CurrentRecord::Buddy(Box::new(buddy)),
+                }
+                .into(),
+                _ => NotFoundError {
+                    kind: "buddy",
+                    id: buddy_id,
+                }
+                .into(),
+            });
+        }
         Ok(())
     }
     fn update_interaction(&mut self, request: UpdateInteractionRequest) -> Result<()> {
@@ -202,19 +398,805 @@ impl BuddiesStore for PsqlBuddiesStore {
         // portion of the updateinteractionrequest
         let interaction_id = request.interaction_id.clone();
         let user_id = request.user_id.clone();
+        let expected_version = request.version;
         let update =
             DBUpdateInteraction::update(request).context("Creating update interaction request")?;
+        let target = interactions::dsl::interactions
+            .filter(interactions::dsl::uuid.eq(interaction_id.to_string()))
+            .filter(interactions::dsl::user_uuid.eq(user_id.to_string()))
+            .filter(interactions::dsl::delete_timestamp.is_null());
+        let changes = (
+            &update,
+            interactions::dsl::version.eq(interactions::dsl::version + 1),
+        );
+        let updated = match expected_version {
+            Some(version) => diesel::update(target.filter(interactions::dsl::version.eq(version)))
+                .set(changes)
+                .execute(&*conn),
+            None => diesel::update(target).set(changes).execute(&*conn),
+        }
+        .context(format!(
+            "Updating interaction {} {}",
+            interaction_id, user_id
+        ))?;
+        drop(conn);
+        if updated == 0 {
+            return Err(
+                match self.get_interactions(user_id)?.remove(&interaction_id) {
+                    Some(interaction) if interaction.delete_timestamp.is_none() => {
+                        StaleWriteError {
+                            current: CurrentRecord::Interaction(Box::new(interaction)),
+                        }
+                        .into()
+                    }
+                    _ => NotFoundError {
+                        kind: "interaction",
+                        id: interaction_id,
+                    }
+                    .into(),
+                },
+            );
+        }
+        Ok(())
+    }
+    fn create_relationship(&mut self, relationship: Relationship) -> Result<()> {
+        let conn = self.get_db_conn()?;
+        let relationship_uuid = relationship.id;
+        let new_relationship_request = NewRelationship::from(relationship);
+        diesel::insert_into(buddy_relationships::table)
+            .values(&new_relationship_request)
+            .execute(&*conn)
+            .context(format!(
+                "Error attempting to persist relationship in db with uuid {}",
+                relationship_uuid
+            ))?;
+        Ok(())
+    }
+    fn update_relationship(&mut self, request: UpdateRelationshipRequest) -> Result<()> {
+        let conn = self.get_db_conn()?;
+        let relationship_id = request.relationship_id;
+        let user_id = request.user_id;
+        let update = DBUpdateRelationship::update(request)
+            .context("Creating update relationship request")?;
+        let updated = diesel::update(
+            buddy_relationships::dsl::buddy_relationships
+                .filter(buddy_relationships::dsl::uuid.eq(relationship_id.to_string()))
+                .filter(buddy_relationships::dsl::user_uuid.eq(user_id.to_string())),
+        )
+        .set(&update)
+        .execute(&*conn)
+        .context(format!(
+            "Updating relationship {} {}",
+            relationship_id, user_id
+        ))?;
+        if updated == 0 {
+            return Err(NotFoundError {
+                kind: "relationship",
+                id: relationship_id,
+            }
+            .into());
+        }
+        Ok(())
+    }
+    fn archive_relationship(&mut self, id: Uuid, user_id: Uuid) -> Result<()> {
+        let conn = self.get_db_conn()?;
+        let update =
+            DBUpdateRelationship::archive().context("Creating archive relationship request")?;
+        let updated = diesel::update(
+            buddy_relationships::dsl::buddy_relationships
+                .filter(buddy_relationships::dsl::uuid.eq(id.to_string()))
+                .filter(buddy_relationships::dsl::user_uuid.eq(user_id.to_string())),
+        )
+        .set(&update)
+        .execute(&*conn)
+        .context(format!("Archiving relationship {} {}", id, user_id))?;
+        if updated == 0 {
+            return Err(NotFoundError {
+                kind: "relationship",
+                id,
+            }
+            .into());
+        }
+        Ok(())
+    }
+    fn get_relationships(&self, user_id: Uuid) -> Result<HashMap<Uuid, Relationship>> {
+        let user_id_string = user_id.to_string();
+        let conn = self.get_db_conn()?;
+        let db_relationships = buddy_relationships::dsl::buddy_relationships
+            .filter(buddy_relationships::dsl::user_uuid.eq(&user_id_string))
+            .filter(buddy_relationships::dsl::delete_timestamp.is_null())
+            .load::<DBRelationship>(&*conn)
+            .context(format!(
+                "Looking for relationships of user {}",
+                user_id_string
+            ))?;
+        let mut resulting_map = HashMap::new();
+        for db_relationship in db_relationships {
+            let relationship = Relationship::try_from(db_relationship)
+                .context(format!("Reading relationships for {}", user_id_string))?;
+            resulting_map.insert(relationship.id, relationship);
+        }
+        Ok(resulting_map)
+    }
+    fn create_contact_method(&mut self, contact_method: ContactMethod) -> Result<()> {
+        let conn = self.get_db_conn()?;
+        let contact_method_uuid = contact_method.id;
+        let new_contact_method_request = NewContactMethod::from(contact_method);
+        diesel::insert_into(contact_methods::table)
+            .values(&new_contact_method_request)
+            .execute(&*conn)
+            .context(format!(
+                "Error attempting to persist contact method in db with uuid {}",
+                contact_method_uuid
+            ))?;
+        Ok(())
+    }
+    fn update_contact_method(&mut self, request: UpdateContactMethodRequest) -> Result<()> {
+        let conn = self.get_db_conn()?;
+        let contact_method_id = request.contact_method_id;
+        let user_id = request.user_id;
+        let update = DBUpdateContactMethod::update(request)
+            .context("Creating update contact method request")?;
+        let updated = diesel::update(
+            contact_methods::dsl::contact_methods
+                .filter(contact_methods::dsl::uuid.eq(contact_method_id.to_string()))
+                .filter(contact_methods::dsl::user_uuid.eq(user_id.to_string())),
+        )
+        .set(&update)
+        .execute(&*conn)
+        .context(format!(
+            "Updating contact method {} {}",
+            contact_method_id, user_id
+        ))?;
+        if updated == 0 {
+            return Err(NotFoundError {
+                kind: "contact method",
+                id: contact_method_id,
+            }
+            .into());
+        }
+        Ok(())
+    }
+    fn archive_contact_method(&mut self, id: Uuid, user_id: Uuid) -> Result<()> {
+        let conn = self.get_db_conn()?;
+        let update =
+            DBUpdateContactMethod::archive().context("Creating archive contact method request")?;
+        let updated = diesel::update(
+            contact_methods::dsl::contact_methods
+                .filter(contact_methods::dsl::uuid.eq(id.to_string()))
+                .filter(contact_methods::dsl::user_uuid.eq(user_id.to_string())),
+        )
+        .set(&update)
+        .execute(&*conn)
+        .context(format!("Archiving contact method {} {}", id, user_id))?;
+        if updated == 0 {
+            return Err(NotFoundError {
+                kind: "contact method",
+                id,
+            }
+            .into());
+        }
+        Ok(())
+    }
+    fn get_contact_methods(&self, user_id: Uuid) -> Result<HashMap<Uuid, ContactMethod>> {
+        let user_id_string = user_id.to_string();
+        let conn = self.get_db_conn()?;
+        let db_contact_methods = contact_methods::dsl::contact_methods
+            .filter(contact_methods::dsl::user_uuid.eq(&user_id_string))
+            .filter(contact_methods::dsl::delete_timestamp.is_null())
+            .load::<DBContactMethod>(&*conn)
+            .context(format!(
+                "Looking for contact methods of user {}",
+                user_id_string
+            ))?;
+        let mut resulting_map = HashMap::new();
+        for db_contact_method in db_contact_methods {
+            let contact_method = ContactMethod::try_from(db_contact_method)
+                .context(format!("Reading contact methods for {}", user_id_string))?;
+            resulting_map.insert(contact_method.id, contact_method);
+        }
+        Ok(resulting_map)
+    }
+    fn create_important_date(&mut self, important_date: ImportantDate) -> Result<()> {
+        let conn = self.get_db_conn()?;
+        let important_date_uuid = important_date.id;
+        let new_important_date_request = NewImportantDate::from(important_date);
+        diesel::insert_into(important_dates::table)
+            .values(&new_important_date_request)
+            .execute(&*conn)
+            .context(format!(
+                "Error attempting to persist important date in db with uuid {}",
+                important_date_uuid
+            ))?;
+        Ok(())
+    }
+    fn update_important_date(&mut self, request: UpdateImportantDateRequest) -> Result<()> {
+        let conn = self.get_db_conn()?;
+        let important_date_id = request.important_date_id;
+        let user_id = request.user_id;
+        let update = DBUpdateImportantDate::update(request)
+            .context("Creating update important date request")?;
+        let updated = diesel::update(
+            important_dates::dsl::important_dates
+                .filter(important_dates::dsl::uuid.eq(important_date_id.to_string()))
+                .filter(important_dates::dsl::user_uuid.eq(user_id.to_string())),
+        )
+        .set(&update)
+        .execute(&*conn)
+        .context(format!(
+            "Updating important date {} {}",
+            important_date_id, user_id
+        ))?;
+        if updated == 0 {
+            return Err(NotFoundError {
+                kind: "important date",
+                id: important_date_id,
+            }
+            .into());
+        }
+        Ok(())
+    }
+    fn archive_important_date(&mut self, id: Uuid, user_id: Uuid) -> Result<()> {
+        let conn = self.get_db_conn()?;
+        let update =
+            DBUpdateImportantDate::archive().context("Creating archive important date request")?;
+        let updated = diesel::update(
+            important_dates::dsl::important_dates
+                .filter(important_dates::dsl::uuid.eq(id.to_string()))
+                .filter(important_dates::dsl::user_uuid.eq(user_id.to_string())),
+        )
+        .set(&update)
+        .execute(&*conn)
+        .context(format!("Archiving important date {} {}", id, user_id))?;
+        if updated == 0 {
+            return Err(NotFoundError {
+                kind: "important date",
+                id,
+            }
+            .into());
+        }
+        Ok(())
+    }
+    fn get_important_dates(&self, user_id: Uuid) -> Result<HashMap<Uuid, ImportantDate>> {
+        let user_id_string = user_id.to_string();
+        let conn = self.get_db_conn()?;
+        let db_important_dates = important_dates::dsl::important_dates
+            .filter(important_dates::dsl::user_uuid.eq(&user_id_string))
+            .filter(important_dates::dsl::delete_timestamp.is_null())
+            .load::<DBImportantDate>(&*conn)
+            .context(format!(
+                "Looking for important dates of user {}",
+                user_id_string
+            ))?;
+        let mut resulting_map = HashMap::new();
+        for db_important_date in db_important_dates {
+            let important_date = ImportantDate::try_from(db_important_date)
+                .context(format!("Reading important dates for {}", user_id_string))?;
+            resulting_map.insert(important_date.id, important_date);
+        }
+        Ok(resulting_map)
+    }
+    fn create_follow_up(&mut self, follow_up: FollowUp) -> Result<()> {
+        let conn = self.get_db_conn()?;
+        let follow_up_uuid = follow_up.id;
+        let new_follow_up_request = NewFollowUp::from(follow_up);
+        diesel::insert_into(follow_ups::table)
+            .values(&new_follow_up_request)
+            .execute(&*conn)
+            .context(format!(
+                "Error attempting to persist follow up in db with uuid {}",
+                follow_up_uuid
+            ))?;
+        Ok(())
+    }
+    fn complete_follow_up(&mut self, id: Uuid, user_id: Uuid) -> Result<()> {
+        let conn = self.get_db_conn()?;
+        let update = DBUpdateFollowUp::complete().context("Creating complete follow up request")?;
+        let updated = diesel::update(
+            follow_ups::dsl::follow_ups
+                .filter(follow_ups::dsl::uuid.eq(id.to_string()))
+                .filter(follow_ups::dsl::user_uuid.eq(user_id.to_string())),
+        )
+        .set(&update)
+        .execute(&*conn)
+        .context(format!("Completing follow up {} {}", id, user_id))?;
+        if updated == 0 {
+            return Err(NotFoundError {
+                kind: "follow up",
+                id,
+            }
+            .into());
+        }
+        Ok(())
+    }
+    fn get_follow_ups(&self, user_id: Uuid) -> Result<HashMap<Uuid, FollowUp>> {
+        let user_id_string = user_id.to_string();
+        let conn = self.get_db_conn()?;
+        let db_follow_ups = follow_ups::dsl::follow_ups
+            .filter(follow_ups::dsl::user_uuid.eq(&user_id_string))
+            .filter(follow_ups::dsl::delete_timestamp.is_null())
+            .load::<DBFollowUp>(&*conn)
+            .context(format!("Looking for follow ups of user {}", user_id_string))?;
+        let mut resulting_map = HashMap::new();
+        for db_follow_up in db_follow_ups {
+            let follow_up = FollowUp::try_from(db_follow_up)
+                .context(format!("Reading follow ups for {}", user_id_string))?;
+            resulting_map.insert(follow_up.id, follow_up);
+        }
+        Ok(resulting_map)
+    }
+    fn create_idea(&mut self, idea: Idea) -> Result<()> {
+        let conn = self.get_db_conn()?;
+        let idea_uuid = idea.id;
+        let new_idea_request = NewIdea::from(idea);
+        diesel::insert_into(ideas::table)
+            .values(&new_idea_request)
+            .execute(&*conn)
+            .context(format!(
+                "Error attempting to persist idea in db with uuid {}",
+                idea_uuid
+            ))?;
+        Ok(())
+    }
+    fn update_idea(&mut self, request: UpdateIdeaRequest) -> Result<()> {
+        let conn = self.get_db_conn()?;
+        let idea_id = request.idea_id;
+        let user_id = request.user_id;
+        let update = DBUpdateIdea::update(request).context("Creating update idea request")?;
+        let updated = diesel::update(
+            ideas::dsl::ideas
+                .filter(ideas::dsl::uuid.eq(idea_id.to_string()))
+                .filter(ideas::dsl::user_uuid.eq(user_id.to_string())),
+        )
+        .set(&update)
+        .execute(&*conn)
+        .context(format!("Updating idea {} {}", idea_id, user_id))?;
+        if updated == 0 {
+            return Err(NotFoundError {
+                kind: "idea",
+                id: idea_id,
+            }
+            .into());
+        }
+        Ok(())
+    }
+    fn archive_idea(&mut self, id: Uuid, user_id: Uuid) -> Result<()> {
+        let conn = self.get_db_conn()?;
+        let update = DBUpdateIdea::archive().context("Creating archive idea request")?;
+        let updated = diesel::update(
+            ideas::dsl::ideas
+                .filter(ideas::dsl::uuid.eq(id.to_string()))
+                .filter(ideas::dsl::user_uuid.eq(user_id.to_string())),
+        )
+        .set(&update)
+        .execute(&*conn)
+        .context(format!("Archiving idea {} {}", id, user_id))?;
+        if updated == 0 {
+            return Err(NotFoundError { kind: "idea", id }.into());
+        }
+        Ok(())
+    }
+    fn get_ideas(&self, user_id: Uuid) -> Result<HashMap<Uuid, Idea>> {
+        let user_id_string = user_id.to_string();
+        let conn = self.get_db_conn()?;
+        let db_ideas = ideas::dsl::ideas
+            .filter(ideas::dsl::user_uuid.eq(&user_id_string))
+            .filter(ideas::dsl::delete_timestamp.is_null())
+            .load::<DBIdea>(&*conn)
+            .context(format!("Looking for ideas of user {}", user_id_string))?;
+        let mut resulting_map = HashMap::new();
+        for db_idea in db_ideas {
+            let idea =
+                Idea::try_from(db_idea).context(format!("Reading ideas for {}", user_id_string))?;
+            resulting_map.insert(idea.id, idea);
+        }
+        Ok(resulting_map)
+    }
+    fn move_buddy_records(
+        &mut self,
+        user_id: Uuid,
+        from_buddy_id: Uuid,
+        to_buddy_id: Uuid,
+    ) -> Result<()> {
+        let conn = self.get_db_conn()?;
+        let now = format!(
+            "{}",
+            SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)?
+                .as_secs()
+        );
+        let (user, from, to) = (
+            user_id.to_string(),
+            from_buddy_id.to_string(),
+            to_buddy_id.to_string(),
+        );
+        let context = format!("Moving records from buddy {} to {}", from, to);
+        let relationships = buddy_relationships::dsl::buddy_relationships
+            .filter(buddy_relationships::dsl::user_uuid.eq(&user))
+            .filter(buddy_relationships::dsl::delete_timestamp.is_null());
+        diesel::update(relationships.filter(buddy_relationships::dsl::from_buddy_uuid.eq(&from)))
+            .set((
+                buddy_relationships::dsl::from_buddy_uuid.eq(&to),
+                buddy_relationships::dsl::last_update_timestamp.eq(&now),
+            ))
+            .execute(&*conn)
+            .context(context.clone())?;
+        diesel::update(relationships.filter(buddy_relationships::dsl::to_buddy_uuid.eq(&from)))
+            .set((
+                buddy_relationships::dsl::to_buddy_uuid.eq(&to),
+                buddy_relationships::dsl::last_update_timestamp.eq(&now),
+            ))
+            .execute(&*conn)
+            .context(context.clone())?;
+        diesel::update(
+            contact_methods::dsl::contact_methods
+                .filter(contact_methods::dsl::user_uuid.eq(&user))
+                .filter(contact_methods::dsl::delete_timestamp.is_null())
+                .filter(contact_methods::dsl::buddy_uuid.eq(&from)),
+        )
+        .set((
+            contact_methods::dsl::buddy_uuid.eq(&to),
+            contact_methods::dsl::last_update_timestamp.eq(&now),
+        ))
+        .execute(&*conn)
+        .context(context.clone())?;
+        diesel::update(
+            important_dates::dsl::important_dates
+                .filter(important_dates::dsl::user_uuid.eq(&user))
+                .filter(important_dates::dsl::delete_timestamp.is_null())
+                .filter(important_dates::dsl::buddy_uuid.eq(&from)),
+        )
+        .set((
+            important_dates::dsl::buddy_uuid.eq(&to),
+            important_dates::dsl::last_update_timestamp.eq(&now),
+        ))
+        .execute(&*conn)
+        .context(context.clone())?;
+        diesel::update(
+            follow_ups::dsl::follow_ups
+                .filter(follow_ups::dsl::user_uuid.eq(&user))
+                .filter(follow_ups::dsl::delete_timestamp.is_null())
+                .filter(follow_ups::dsl::buddy_uuid.eq(&from)),
+        )
+        .set((
+            follow_ups::dsl::buddy_uuid.eq(&to),
+            follow_ups::dsl::last_update_timestamp.eq(&now),
+        ))
+        .execute(&*conn)
+        .context(context.clone())?;
+        diesel::update(
+            ideas::dsl::ideas
+                .filter(ideas::dsl::user_uuid.eq(&user))
+                .filter(ideas::dsl::delete_timestamp.is_null())
+                .filter(ideas::dsl::buddy_uuid.eq(&from)),
+        )
+        .set((
+            ideas::dsl::buddy_uuid.eq(&to),
+            ideas::dsl::last_update_timestamp.eq(&now),
+        ))
+        .execute(&*conn)
+        .context(context)?;
+        Ok(())
+    }
+    fn create_revision(&mut self, revision: Revision) -> Result<()> {
+        let conn = self.get_db_conn()?;
+        let revision_uuid = revision.id;
+        let new_revision_request = NewRevision::from(revision);
+        diesel::insert_into(revisions::table)
+            .values(&new_revision_request)
+            .execute(&*conn)
+            .context(format!(
+                "Error attempting to persist revision in db with uuid {}",
+                revision_uuid
+            ))?;
+        Ok(())
+    }
+    fn get_revisions(&self, user_id: Uuid, entity_id: Uuid) -> Result<Vec<Revision>> {
+        let conn = self.get_db_conn()?;
+        let db_revisions = revisions::dsl::revisions
+            .filter(revisions::dsl::user_uuid.eq(user_id.to_string()))
+            .filter(revisions::dsl::entity_uuid.eq(entity_id.to_string()))
+            .order(revisions::dsl::id.asc())
+            .load::<DBRevision>(&*conn)
+            .context(format!("Looking for revisions of {}", entity_id))?;
+        db_revisions
+            .into_iter()
+            .map(|db_revision| {
+                Revision::try_from(db_revision)
+                    .context(format!("Reading revisions of {}", entity_id))
+            })
+            .collect()
+    }
+    fn restore_buddy(&mut self, buddy: Buddy) -> Result<()> {
+        let conn = self.get_db_conn()?;
+        let buddy_id = buddy.id;
+        let user_id = buddy.user_id;
+        let delete_timestamp = buddy.delete_timestamp.map(|x| x.0.to_string());
+        let restored = NewBuddy::try_from(buddy).context("Creating restore buddy request")?;
+        diesel::update(
+            buddies::dsl::buddies
+                .filter(buddies::dsl::uuid.eq(buddy_id.to_string()))
+                .filter(buddies::dsl::user_uuid.eq(user_id.to_string())),
+        )
+        .set((
+            &restored,
+            buddies::dsl::delete_timestamp.eq(delete_timestamp),
+        ))
+        .execute(&*conn)
+        .context(format!("Restoring buddy {} {}", buddy_id, user_id))?;
+        Ok(())
+    }
+    fn restore_interaction(&mut self, interaction: Interaction) -> Result<()> {
+        let conn = self.get_db_conn()?;
+        let interaction_id = interaction.id;
+        let user_id = interaction.user_id;
+        let delete_timestamp = interaction.delete_timestamp.map(|x| x.0.to_string());
+        let restored = NewInteraction::try_from(interaction)
+            .context("Creating restore interaction request")?;
         diesel::update(
             interactions::dsl::interactions
                 .filter(interactions::dsl::uuid.eq(interaction_id.to_string()))
                 .filter(interactions::dsl::user_uuid.eq(user_id.to_string())),
         )
-        .set(&update)
-        .execute(&conn)
+        .set((
+            &restored,
+            interactions::dsl::delete_timestamp.eq(delete_timestamp),
+        ))
+        .execute(&*conn)
         .context(format!(
-            "Updating interaction {} {}",
+            "Restoring interaction {} {}",
             interaction_id, user_id
         ))?;
         Ok(())
     }
+    fn get_idempotency_record(
+        &self,
+        user_id: Uuid,
+        operation: &str,
+        key: &str,
+    ) -> Result<Option<IdempotencyRecord>> {
+        let conn = self.get_db_conn()?;
+        let db_record = idempotency_keys::dsl::idempotency_keys
+            .filter(idempotency_keys::dsl::user_uuid.eq(user_id.to_string()))
+            .filter(idempotency_keys::dsl::operation.eq(operation))
+            .filter(idempotency_keys::dsl::key.eq(key))
+            .first::<DBIdempotencyRecord>(&*conn)
+            .optional()
+            .context(format!("Looking for idempotency key {}", key))?;
+        db_record.map(IdempotencyRecord::try_from).transpose()
+    }
+    fn save_idempotency_record(&mut self, record: IdempotencyRecord) -> Result<()> {
+        let conn = self.get_db_conn()?;
+        let new_record = NewIdempotencyRecord::from(record);
+        diesel::insert_into(idempotency_keys::table)
+            .values(&new_record)
+            .execute(&*conn)
+            .context(format!(
+                "Error attempting to persist idempotency key {}",
+                new_record.key
+            ))?;
+        Ok(())
+    }
+    fn delete_idempotency_record(
+        &mut self,
+        user_id: Uuid,
+        operation: &str,
+        key: &str,
+    ) -> Result<()> {
+        let conn = self.get_db_conn()?;
+        diesel::delete(
+            idempotency_keys::dsl::idempotency_keys
+                .filter(idempotency_keys::dsl::user_uuid.eq(user_id.to_string()))
+                .filter(idempotency_keys::dsl::operation.eq(operation))
+                .filter(idempotency_keys::dsl::key.eq(key)),
+        )
+        .execute(&*conn)
+        .context(format!(
+            "Error attempting to delete idempotency key {}",
+            key
+        ))?;
+        Ok(())
+    }
+    fn create_webhook(&mut self, webhook: Webhook) -> Result<()> {
+        let conn = self.get_db_conn()?;
+        let webhook_uuid = webhook.id;
+        let new_webhook_request = NewWebhook::from(webhook);
+        diesel::insert_into(webhooks::table)
+            .values(&new_webhook_request)
+            .execute(&*conn)
+            .context(format!(
+                "Error attempting to persist webhook in db with uuid {}",
+                webhook_uuid
+            ))?;
+        Ok(())
+    }
+    fn archive_webhook(&mut self, id: Uuid, user_id: Uuid) -> Result<()> {
+        let conn = self.get_db_conn()?;
+        let update = DBArchiveWebhook::new().context("Creating archive webhook request")?;
+        let updated = diesel::update(
+            webhooks::dsl::webhooks
+                .filter(webhooks::dsl::uuid.eq(id.to_string()))
+                .filter(webhooks::dsl::user_uuid.eq(user_id.to_string())),
+        )
+        .set(&update)
+        .execute(&*conn)
+        .context(format!("Archiving webhook {} {}", id, user_id))?;
+        if updated == 0 {
+            return Err(NotFoundError {
+                kind: "webhook",
+                id,
+            }
+            .into());
+        }
+        Ok(())
+    }
+    fn get_webhooks(&self, user_id: Uuid) -> Result<HashMap<Uuid, Webhook>> {
+        let user_id_string = user_id.to_string();
+        let conn = self.get_db_conn()?;
+        let db_webhooks = webhooks::dsl::webhooks
+            .filter(webhooks::dsl::user_uuid.eq(&user_id_string))
+            .filter(webhooks::dsl::delete_timestamp.is_null())
+            .load::<DBWebhook>(&*conn)
+            .context(format!("Looking for webhooks of user {}", user_id_string))?;
+        let mut resulting_map = HashMap::new();
+        for db_webhook in db_webhooks {
+            let webhook = Webhook::try_from(db_webhook)
+                .context(format!("Reading webhooks for {}", user_id_string))?;
+            resulting_map.insert(webhook.id, webhook);
+        }
+        Ok(resulting_map)
+    }
+    fn create_webhook_delivery(&mut self, delivery: WebhookDelivery) -> Result<()> {
+        let conn = self.get_db_conn()?;
+        let delivery_uuid = delivery.id;
+        let new_delivery_request = NewWebhookDelivery::from(delivery);
+        diesel::insert_into(webhook_deliveries::table)
+            .values(&new_delivery_request)
+            .execute(&*conn)
+            .context(format!(
+                "Error attempting to persist webhook delivery in db with uuid {}",
+                delivery_uuid
+            ))?;
+        Ok(())
+    }
+    fn update_webhook_delivery(&mut self, delivery: WebhookDelivery) -> Result<()> {
+        let conn = self.get_db_conn()?;
+        let delivery_uuid = delivery.id;
+        let update = NewWebhookDelivery::from(delivery);
+        diesel::update(
+            webhook_deliveries::dsl::webhook_deliveries
+                .filter(webhook_deliveries::dsl::uuid.eq(delivery_uuid.to_string())),
+        )
+        .set(&update)
+        .execute(&*conn)
+        .context(format!("Updating webhook delivery {}", delivery_uuid))?;
+        Ok(())
+    }
+    fn get_webhook_deliveries(
+        &self,
+        user_id: Uuid,
+        webhook_id: Uuid,
+    ) -> Result<Vec<WebhookDelivery>> {
+        let conn = self.get_db_conn()?;
+        let db_deliveries = webhook_deliveries::dsl::webhook_deliveries
+            .filter(webhook_deliveries::dsl::user_uuid.eq(user_id.to_string()))
+            .filter(webhook_deliveries::dsl::webhook_uuid.eq(webhook_id.to_string()))
+            .order(webhook_deliveries::dsl::id.desc())
+            .load::<DBWebhookDelivery>(&*conn)
+            .context(format!("Looking for deliveries to webhook {}", webhook_id))?;
+        db_deliveries
+            .into_iter()
+            .map(|db_delivery| {
+                WebhookDelivery::try_from(db_delivery)
+                    .context(format!("Reading deliveries to webhook {}", webhook_id))
+            })
+            .collect()
+    }
+    fn get_due_webhook_deliveries(&self, now: Timestamp) -> Result<Vec<WebhookDelivery>> {
+        let conn = self.get_db_conn()?;
+        let db_deliveries = webhook_deliveries::dsl::webhook_deliveries
+            .filter(webhook_deliveries::dsl::status.eq(DeliveryStatus::Pending.to_string()))
+            .order(webhook_deliveries::dsl::id.asc())
+            .load::<DBWebhookDelivery>(&*conn)
+            .context("Looking for pending webhook deliveries")?;
+        let mut due = Vec::new();
+        for db_delivery in db_deliveries {
+            let delivery = WebhookDelivery::try_from(db_delivery)
+                .context("Reading pending webhook deliveries")?;
+            // Timestamps are strings in the DB, so compare them here
+            if delivery.next_attempt_timestamp.unwrap_or(now).0 <= now.0 {
+                due.push(delivery);
+            }
+        }
+        Ok(due)
+    }
+    fn transaction<T, F>(&mut self, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut Self) -> Result<T>,
+    {
+        // Nested transactions share the outer one's connection and become savepoints
+        let outermost = self.transaction_conn.is_none();
+        let conn = match &self.transaction_conn {
+            Some(conn) => conn.clone(),
+            None => Arc::new(Mutex::new(self.db_pool.get()?)),
+        };
+        let mut store = PsqlBuddiesStore {
+            db_pool: self.db_pool.clone(),
+            transaction_conn: Some(conn.clone()),
+        };
+        {
+            let conn = store.get_db_conn()?;
+            conn.transaction_manager()
+                .begin_transaction(&*conn)
+                .context("Beginning transaction")?;
+            // Reads in the transaction are what revisions are built from, so a row that
+            // changes underneath us should fail the write rather than record the wrong history
+            if outermost {
+                diesel::sql_query("SET TRANSACTION ISOLATION LEVEL REPEATABLE READ")
+                    .execute(&*conn)
+                    .context("Setting transaction isolation")?;
+            }
+        }
+        let result = f(&mut store);
+        let conn = store.get_db_conn()?;
+        match result {
+            Ok(value) => {
+                conn.transaction_manager()
+                    .commit_transaction(&*conn)
+                    .context("Committing transaction")?;
+                Ok(value)
+            }
+            Err(e) => {
+                conn.transaction_manager()
+                    .rollback_transaction(&*conn)
+                    .context("Rolling back transaction")?;
+                Err(e)
+            }
+        }
+    }
+    fn get_user_by_id(&self, user_id: Uuid) -> Result<Option<User>> {
+        let conn = self.get_db_conn()?;
+        let db_user = users::dsl::users
+            .filter(users::dsl::user_id.eq(user_id.to_string()))
+            .first::<DBUser>(&*conn)
+            .optional()
+            .context(format!("Looking for user with id {}", user_id))?;
+        db_user
+            .map(|db_user| User::try_from(db_user).context("Converting user back from DB"))
+            .transpose()
+    }
+    fn update_user(&mut self, request: UpdateUserRequest) -> Result<()> {
+        // Diesel refuses to run an update with nothing to set
+        if request.digest_frequency.is_none() {
+            return Ok(());
+        }
+        let conn = self.get_db_conn()?;
+        let user_id = request.user_id;
+        let update = DBUpdateUser::update(request);
+        diesel::update(users::dsl::users.filter(users::dsl::user_id.eq(user_id.to_string())))
+            .set(&update)
+            .execute(&*conn)
+            .context(format!("Updating user {}", user_id))?;
+        Ok(())
+    }
+    fn set_calendar_token(&mut self, user_id: Uuid, token: String) -> Result<()> {
+        let conn = self.get_db_conn()?;
+        let update = DBUpdateUser::calendar_token(token);
+        diesel::update(users::dsl::users.filter(users::dsl::user_id.eq(user_id.to_string())))
+            .set(&update)
+            .execute(&*conn)
+            .context(format!("Setting calendar token for user {}", user_id))?;
+        Ok(())
+    }
+    fn get_user_by_calendar_token(&self, token: &str) -> Result<Option<User>> {
+        let conn = self.get_db_conn()?;
+        let db_user = users::dsl::users
+            .filter(users::dsl::calendar_token.eq(token))
+            .first::<DBUser>(&*conn)
+            .optional()
+            .context("Looking for user with calendar token")?;
+        db_user
+            .map(|db_user| User::try_from(db_user).context("Converting user back from DB"))
+            .transpose()
+    }
 }
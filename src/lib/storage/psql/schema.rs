@@ -11,6 +11,105 @@ table! {
         last_update_timestamp -> Varchar,
         delete_timestamp -> Nullable<Varchar>,
         user_uuid -> Varchar,
+        cadence_seconds -> Nullable<Int8>,
+        city -> Nullable<Varchar>,
+        region -> Nullable<Varchar>,
+        country_code -> Nullable<Varchar>,
+        latitude -> Nullable<Float8>,
+        longitude -> Nullable<Float8>,
+        timezone -> Nullable<Varchar>,
+        version -> Int4,
+    }
+}
+
+table! {
+    buddy_relationships (id) {
+        id -> Int4,
+        uuid -> Varchar,
+        from_buddy_uuid -> Varchar,
+        to_buddy_uuid -> Varchar,
+        kind -> Varchar,
+        bidirectional -> Bool,
+        create_timestamp -> Varchar,
+        last_update_timestamp -> Varchar,
+        delete_timestamp -> Nullable<Varchar>,
+        user_uuid -> Varchar,
+    }
+}
+
+table! {
+    contact_methods (id) {
+        id -> Int4,
+        uuid -> Varchar,
+        buddy_uuid -> Varchar,
+        kind -> Varchar,
+        value -> Varchar,
+        label -> Nullable<Varchar>,
+        preferred -> Bool,
+        create_timestamp -> Varchar,
+        last_update_timestamp -> Varchar,
+        delete_timestamp -> Nullable<Varchar>,
+        user_uuid -> Varchar,
+    }
+}
+
+table! {
+    follow_ups (id) {
+        id -> Int4,
+        uuid -> Varchar,
+        interaction_uuid -> Varchar,
+        buddy_uuid -> Nullable<Varchar>,
+        notes -> Text,
+        due -> Nullable<Varchar>,
+        done -> Bool,
+        create_timestamp -> Varchar,
+        last_update_timestamp -> Varchar,
+        delete_timestamp -> Nullable<Varchar>,
+        user_uuid -> Varchar,
+    }
+}
+
+table! {
+    ideas (id) {
+        id -> Int4,
+        uuid -> Varchar,
+        buddy_uuid -> Varchar,
+        kind -> Varchar,
+        description -> Text,
+        status -> Varchar,
+        interaction_uuid -> Nullable<Varchar>,
+        create_timestamp -> Varchar,
+        last_update_timestamp -> Varchar,
+        delete_timestamp -> Nullable<Varchar>,
+        user_uuid -> Varchar,
+    }
+}
+
+table! {
+    idempotency_keys (id) {
+        id -> Int4,
+        user_uuid -> Varchar,
+        operation -> Varchar,
+        key -> Varchar,
+        request -> Text,
+        response -> Text,
+        create_timestamp -> Varchar,
+    }
+}
+
+table! {
+    important_dates (id) {
+        id -> Int4,
+        uuid -> Varchar,
+        buddy_uuid -> Varchar,
+        kind -> Varchar,
+        label -> Nullable<Varchar>,
+        date -> Varchar,
+        recurrence -> Varchar,
+        create_timestamp -> Varchar,
+        last_update_timestamp -> Varchar,
+        delete_timestamp -> Nullable<Varchar>,
+        user_uuid -> Varchar,
     }
 }
 
@@ -25,6 +124,39 @@ table! {
         last_update_timestamp -> Varchar,
         delete_timestamp -> Nullable<Varchar>,
         user_uuid -> Varchar,
+        kind -> Nullable<Varchar>,
+        duration_seconds -> Nullable<Int8>,
+        location -> Nullable<Varchar>,
+        initiator -> Nullable<Varchar>,
+        version -> Int4,
+    }
+}
+
+table! {
+    personal_access_tokens (id) {
+        id -> Int4,
+        uuid -> Varchar,
+        name -> Varchar,
+        scopes -> Array<Text>,
+        token_hash -> Varchar,
+        create_timestamp -> Varchar,
+        revoke_timestamp -> Nullable<Varchar>,
+        user_uuid -> Varchar,
+    }
+}
+
+table! {
+    revisions (id) {
+        id -> Int4,
+        uuid -> Varchar,
+        entity_kind -> Varchar,
+        entity_uuid -> Varchar,
+        action -> Varchar,
+        before -> Nullable<Text>,
+        after -> Nullable<Text>,
+        reverted_to_uuid -> Nullable<Varchar>,
+        create_timestamp -> Varchar,
+        user_uuid -> Varchar,
     }
 }
 
@@ -35,11 +167,52 @@ table! {
         password -> Varchar,
         user_id -> Varchar,
         create_timestamp -> Varchar,
+        digest_frequency -> Varchar,
+        calendar_token -> Nullable<Varchar>,
+    }
+}
+
+table! {
+    webhook_deliveries (id) {
+        id -> Int4,
+        uuid -> Varchar,
+        webhook_uuid -> Varchar,
+        payload -> Text,
+        status -> Varchar,
+        attempts -> Int4,
+        next_attempt_timestamp -> Nullable<Varchar>,
+        response_status -> Nullable<Int4>,
+        error -> Nullable<Text>,
+        create_timestamp -> Varchar,
+        last_update_timestamp -> Varchar,
+        user_uuid -> Varchar,
+    }
+}
+
+table! {
+    webhooks (id) {
+        id -> Int4,
+        uuid -> Varchar,
+        url -> Varchar,
+        secret -> Varchar,
+        create_timestamp -> Varchar,
+        delete_timestamp -> Nullable<Varchar>,
+        user_uuid -> Varchar,
     }
 }
 
 allow_tables_to_appear_in_same_query!(
     buddies,
+    buddy_relationships,
+    contact_methods,
+    follow_ups,
+    idempotency_keys,
+    ideas,
+    important_dates,
     interactions,
+    personal_access_tokens,
+    revisions,
     users,
+    webhook_deliveries,
+    webhooks,
 );
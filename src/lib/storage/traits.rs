@@ -1,23 +1,126 @@
 use crate::lib::types::{
-    Buddy, CreateUserRequest, Interaction, LoginRequest, UpdateBuddyRequest,
-    UpdateInteractionRequest, User,
+    Buddy, ContactHistory, ContactMethod, CreateUserRequest, Datestamp, FollowUp, Idea,
+    IdempotencyRecord, ImportantDate, Interaction, LoginRequest, PersonalAccessToken, Relationship,
+    Revision, Timestamp, UpdateBuddyRequest, UpdateContactMethodRequest, UpdateIdeaRequest,
+    UpdateImportantDateRequest, UpdateInteractionRequest, UpdateRelationshipRequest,
+    UpdateUserRequest, User, Webhook, WebhookDelivery,
 };
 use anyhow::Result;
 use std::collections::HashMap;
+use std::time::SystemTime;
 use uuid::Uuid;
 
 pub trait BuddiesStore: Send + Sync + Clone + 'static {
+    /// The time written into timestamps and sync cursors, in seconds since the epoch
+    fn now(&self) -> Result<u64> {
+        Ok(SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)?
+            .as_secs())
+    }
     fn create_buddy(&mut self, buddy: Buddy) -> Result<()>;
     fn archive_buddy(&mut self, id: Uuid, user_id: Uuid) -> Result<()>;
     fn create_interaction(&mut self, interaction: Interaction) -> Result<()>;
     fn archive_interaction(&mut self, id: Uuid, user_id: Uuid) -> Result<()>;
     fn get_buddies(&self, user_id: Uuid) -> Result<HashMap<Uuid, Buddy>>;
     fn get_interactions(&self, user_id: Uuid) -> Result<HashMap<Uuid, Interaction>>;
+    /// When each buddy was contacted between `since` and `until`, inclusive, ignoring
+    /// archived interactions. Either end is left open if it isn't given.
+    fn get_contact_history(
+        &self,
+        user_id: Uuid,
+        since: Option<&Datestamp>,
+        until: Option<&Datestamp>,
+    ) -> Result<ContactHistory>;
     fn update_buddy(&mut self, request: UpdateBuddyRequest) -> Result<()>;
     fn update_interaction(&mut self, request: UpdateInteractionRequest) -> Result<()>;
+    fn create_relationship(&mut self, relationship: Relationship) -> Result<()>;
+    fn update_relationship(&mut self, request: UpdateRelationshipRequest) -> Result<()>;
+    fn archive_relationship(&mut self, id: Uuid, user_id: Uuid) -> Result<()>;
+    fn get_relationships(&self, user_id: Uuid) -> Result<HashMap<Uuid, Relationship>>;
+    fn create_contact_method(&mut self, contact_method: ContactMethod) -> Result<()>;
+    fn update_contact_method(&mut self, request: UpdateContactMethodRequest) -> Result<()>;
+    fn archive_contact_method(&mut self, id: Uuid, user_id: Uuid) -> Result<()>;
+    fn get_contact_methods(&self, user_id: Uuid) -> Result<HashMap<Uuid, ContactMethod>>;
+    fn create_important_date(&mut self, important_date: ImportantDate) -> Result<()>;
+    fn update_important_date(&mut self, request: UpdateImportantDateRequest) -> Result<()>;
+    fn archive_important_date(&mut self, id: Uuid, user_id: Uuid) -> Result<()>;
+    fn get_important_dates(&self, user_id: Uuid) -> Result<HashMap<Uuid, ImportantDate>>;
+    fn create_follow_up(&mut self, follow_up: FollowUp) -> Result<()>;
+    fn complete_follow_up(&mut self, id: Uuid, user_id: Uuid) -> Result<()>;
+    fn get_follow_ups(&self, user_id: Uuid) -> Result<HashMap<Uuid, FollowUp>>;
+    fn create_idea(&mut self, idea: Idea) -> Result<()>;
+    fn update_idea(&mut self, request: UpdateIdeaRequest) -> Result<()>;
+    fn archive_idea(&mut self, id: Uuid, user_id: Uuid) -> Result<()>;
+    fn get_ideas(&self, user_id: Uuid) -> Result<HashMap<Uuid, Idea>>;
+    /// Hand a buddy's live relationships, contact methods, important dates, follow ups and
+    /// ideas to another buddy
+    fn move_buddy_records(
+        &mut self,
+        user_id: Uuid,
+        from_buddy_id: Uuid,
+        to_buddy_id: Uuid,
+    ) -> Result<()>;
+    fn create_revision(&mut self, revision: Revision) -> Result<()>;
+    /// Every revision of a buddy or interaction, oldest first
+    fn get_revisions(&self, user_id: Uuid, entity_id: Uuid) -> Result<Vec<Revision>>;
+    /// Overwrite a buddy with an earlier version of itself
+    fn restore_buddy(&mut self, buddy: Buddy) -> Result<()>;
+    /// Overwrite an interaction with an earlier version of itself
+    fn restore_interaction(&mut self, interaction: Interaction) -> Result<()>;
+    /// The response to an earlier create made with this idempotency key, if there was one
+    fn get_idempotency_record(
+        &self,
+        user_id: Uuid,
+        operation: &str,
+        key: &str,
+    ) -> Result<Option<IdempotencyRecord>>;
+    /// Remember the response to a create. Fails if there's already a record for the key.
+    fn save_idempotency_record(&mut self, record: IdempotencyRecord) -> Result<()>;
+    /// Forget an expired idempotency key so it can be used again
+    fn delete_idempotency_record(
+        &mut self,
+        user_id: Uuid,
+        operation: &str,
+        key: &str,
+    ) -> Result<()>;
+    fn create_webhook(&mut self, webhook: Webhook) -> Result<()>;
+    fn archive_webhook(&mut self, id: Uuid, user_id: Uuid) -> Result<()>;
+    fn get_webhooks(&self, user_id: Uuid) -> Result<HashMap<Uuid, Webhook>>;
+    fn create_webhook_delivery(&mut self, delivery: WebhookDelivery) -> Result<()>;
+    /// Overwrite a delivery with how its latest attempt went
+    fn update_webhook_delivery(&mut self, delivery: WebhookDelivery) -> Result<()>;
+    /// Every delivery to a webhook, newest first
+    fn get_webhook_deliveries(
+        &self,
+        user_id: Uuid,
+        webhook_id: Uuid,
+    ) -> Result<Vec<WebhookDelivery>>;
+    /// Pending deliveries for any user that are due to be tried by `now`, oldest first
+    fn get_due_webhook_deliveries(&self, now: Timestamp) -> Result<Vec<WebhookDelivery>>;
+    /// Run `f` against the store so that either all of its writes happen or none of them do
+    fn transaction<T, F>(&mut self, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut Self) -> Result<T>;
+    fn get_user_by_id(&self, user_id: Uuid) -> Result<Option<User>>;
+    fn update_user(&mut self, request: UpdateUserRequest) -> Result<()>;
+    fn set_calendar_token(&mut self, user_id: Uuid, token: String) -> Result<()>;
+    /// The user with this calendar token, if there is one
+    fn get_user_by_calendar_token(&self, token: &str) -> Result<Option<User>>;
 }
 
 pub trait AuthStore: Send + Sync + Clone + 'static {
     fn create_user(&mut self, request: CreateUserRequest) -> Result<()>;
     fn get_user(&self, request: &LoginRequest) -> Result<User>;
+    fn create_personal_access_token(&mut self, token: PersonalAccessToken) -> Result<()>;
+    fn revoke_personal_access_token(&mut self, id: Uuid, user_id: Uuid) -> Result<()>;
+    /// Tokens that haven't been revoked
+    fn get_personal_access_tokens(
+        &self,
+        user_id: Uuid,
+    ) -> Result<HashMap<Uuid, PersonalAccessToken>>;
+    /// The unrevoked token with this hash, if there is one
+    fn get_personal_access_token_by_hash(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<PersonalAccessToken>>;
 }
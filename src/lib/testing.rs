@@ -0,0 +1,60 @@
+//! Helpers for tests that go through the routes with a memory store, or with a psql store
+//! for the tests that check both
+
+use crate::lib::routes::build_warp_routes;
+use crate::lib::service::{AuthHandler, RequestHandler};
+use crate::lib::storage::{AuthStore, BuddiesStore, PsqlBuddiesStore};
+use crate::lib::types::{Buddy, Datestamp, Scope, Timestamp};
+use uuid::Uuid;
+use warp::filters::BoxedFilter;
+use warp::Reply;
+
+/// A key pair made for the tests, so tokens can be signed without any setup
+const PRIVATE_KEY: &[u8] = include_bytes!("../../tests/fixtures/private.pem");
+const PUBLIC_KEY: &[u8] = include_bytes!("../../tests/fixtures/public.pem");
+
+pub fn auth_handler<S: AuthStore>(store: &S) -> AuthHandler<S> {
+    AuthHandler::new(store.clone(), PRIVATE_KEY.to_vec(), PUBLIC_KEY.to_vec()).unwrap()
+}
+
+/// Every route, backed by `store`
+pub fn routes<S: BuddiesStore + AuthStore>(store: &S) -> BoxedFilter<(impl Reply,)> {
+    build_warp_routes(auth_handler(store), RequestHandler::new(store.clone()))
+}
+
+/// A buddy called `name` with nothing else set, for tests to fill in what they need
+pub fn buddy(name: &str) -> Buddy {
+    Buddy {
+        id: Uuid::new_v4(),
+        name: name.to_string(),
+        birthday: None,
+        cadence: None,
+        notes: String::new(),
+        location: None,
+        last_contacted: Datestamp("2021-01-01".to_string()),
+        create_timestamp: Timestamp(0),
+        last_update_timestamp: Timestamp(0),
+        delete_timestamp: None,
+        user_id: Uuid::nil(),
+        suggested_cadence: None,
+        contact_methods: Vec::new(),
+        local_time: None,
+        important_dates: Vec::new(),
+        version: 1,
+    }
+}
+
+/// A signed-in token for `user_id` holding just `scopes`
+pub fn token<S: AuthStore>(store: &S, user_id: Uuid, scopes: &[Scope]) -> String {
+    auth_handler(store)
+        .create_jwt(user_id, scopes.to_vec())
+        .unwrap()
+}
+
+/// A store on the migrated database at `TEST_DATABASE_URL`. Tests using it are ignored by
+/// default, run them with `cargo test -- --ignored`.
+pub fn psql_store() -> PsqlBuddiesStore {
+    let url = std::env::var("TEST_DATABASE_URL")
+        .expect("TEST_DATABASE_URL should point at a migrated database");
+    PsqlBuddiesStore::new(&url)
+}
@@ -1,18 +1,528 @@
-use serde::{Deserialize, Serialize};
+use anyhow::{anyhow, Context, Result};
+use chrono::NaiveDate;
+use schemars::JsonSchema;
+use serde::{Deserialize, Deserializer, Serialize};
 use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::str::FromStr;
 use std::time::Duration;
 use uuid::Uuid;
 
-#[derive(Debug, Clone, Default, Deserialize, Queryable, Serialize, PartialEq, Eq, Hash)]
-pub struct Location(pub String);
-#[derive(Debug, Clone, Copy, Default, Deserialize, Queryable, Serialize, PartialEq, Eq, Hash)]
+/// Where someone is. Clients may send a plain string instead of the structured
+/// form, which we make sense of as best we can and keep around in `text`.
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Serialize, PartialEq)]
+#[serde(from = "LocationInput")]
+pub struct Location {
+    /// The location as it was originally written
+    pub text: Option<String>,
+    pub city: Option<String>,
+    /// A state, province or similar
+    pub region: Option<String>,
+    /// ISO 3166-1 alpha-2 country code
+    pub country_code: Option<String>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    /// IANA timezone name, like America/Los_Angeles
+    pub timezone: Option<String>,
+}
+
+impl Location {
+    /// Read a free text location of the form "City", "City, Region" or "City, Region, CC".
+    /// Anything else is only kept as text. The structured_location migration does the same.
+    pub fn from_text(text: String) -> Self {
+        let parts: Vec<Option<String>> = text
+            .split(',')
+            .map(|part| part.trim())
+            .map(|part| {
+                if part.is_empty() {
+                    None
+                } else {
+                    Some(part.to_string())
+                }
+            })
+            .collect();
+        let mut location = Location::default();
+        if parts.len() <= 3 {
+            location.city = parts.first().cloned().flatten();
+            location.region = parts.get(1).cloned().flatten();
+            location.country_code = parts
+                .get(2)
+                .cloned()
+                .flatten()
+                .filter(|code| code.len() == 2 && code.chars().all(|c| c.is_ascii_alphabetic()))
+                .map(|code| code.to_ascii_uppercase());
+        }
+        location.text = Some(text);
+        location
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let parts: Vec<&str> = [&self.city, &self.region, &self.country_code]
+            .iter()
+            .filter_map(|part| part.as_deref())
+            .collect();
+        match &self.text {
+            Some(text) if parts.is_empty() => write!(f, "{}", text),
+            _ => write!(f, "{}", parts.join(", ")),
+        }
+    }
+}
+
+/// The structured form of a location
+#[derive(Deserialize, JsonSchema)]
+struct LocationFields {
+    text: Option<String>,
+    city: Option<String>,
+    region: Option<String>,
+    country_code: Option<String>,
+    latitude: Option<f64>,
+    longitude: Option<f64>,
+    timezone: Option<String>,
+}
+
+/// Locations used to be free text, and clients may still send them that way
+#[derive(Deserialize, JsonSchema)]
+#[serde(untagged)]
+enum LocationInput {
+    Text(String),
+    Fields(LocationFields),
+}
+
+impl From<LocationInput> for Location {
+    fn from(input: LocationInput) -> Self {
+        match input {
+            LocationInput::Text(text) => Location::from_text(text),
+            LocationInput::Fields(fields) => Location {
+                text: fields.text,
+                city: fields.city,
+                region: fields.region,
+                country_code: fields.country_code,
+                latitude: fields.latitude,
+                longitude: fields.longitude,
+                timezone: fields.timezone,
+            },
+        }
+    }
+}
+
+/// The time where a buddy is
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Serialize)]
+pub struct LocalTime {
+    /// RFC 3339 time in the buddy's timezone
+    pub time: String,
+    /// The buddy's offset from UTC
+    pub utc_offset_seconds: i32,
+    /// Whether it's a reasonable hour to reach out
+    pub reasonable_hour: bool,
+}
+
+#[derive(
+    Debug, Clone, Copy, Default, Deserialize, JsonSchema, Queryable, Serialize, PartialEq, Eq, Hash,
+)]
 pub struct Timestamp(pub u64);
 
 /// Display of NaiveDate yyyy-mm-dd
-#[derive(Debug, Clone, Default, Deserialize, Queryable, Serialize, PartialEq, Eq, Hash)]
+#[derive(
+    Debug,
+    Clone,
+    Default,
+    Deserialize,
+    JsonSchema,
+    Queryable,
+    Serialize,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+)]
 pub struct Datestamp(pub String);
 
-#[derive(Debug, Clone, Queryable, Serialize, Deserialize)]
+impl Datestamp {
+    pub fn to_naive_date(&self) -> Result<NaiveDate> {
+        NaiveDate::parse_from_str(&self.0, "%Y-%m-%d")
+            .context(format!("Parsing datestamp {}", self.0))
+    }
+}
+
+impl From<NaiveDate> for Datestamp {
+    fn from(date: NaiveDate) -> Self {
+        Datestamp(format!("{}", date))
+    }
+}
+
+/// How often a user would like to receive their digest
+#[derive(Debug, Clone, Copy, Default, Deserialize, JsonSchema, Serialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum DigestFrequency {
+    #[default]
+    Never,
+    Daily,
+    Weekly,
+}
+
+impl DigestFrequency {
+    /// How many days ahead a digest should look for upcoming events
+    pub fn lookahead_days(&self) -> i64 {
+        match self {
+            DigestFrequency::Daily => 1,
+            DigestFrequency::Weekly | DigestFrequency::Never => 7,
+        }
+    }
+}
+
+impl fmt::Display for DigestFrequency {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            DigestFrequency::Never => "never",
+            DigestFrequency::Daily => "daily",
+            DigestFrequency::Weekly => "weekly",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl FromStr for DigestFrequency {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "never" => Ok(DigestFrequency::Never),
+            "daily" => Ok(DigestFrequency::Daily),
+            "weekly" => Ok(DigestFrequency::Weekly),
+            other => Err(anyhow!("Unknown digest frequency {}", other)),
+        }
+    }
+}
+
+/// The way in which an interaction happened
+#[derive(Debug, Clone, Copy, Deserialize, JsonSchema, Serialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum InteractionKind {
+    Call,
+    Text,
+    InPerson,
+    Video,
+    Letter,
+}
+
+impl fmt::Display for InteractionKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            InteractionKind::Call => "call",
+            InteractionKind::Text => "text",
+            InteractionKind::InPerson => "in_person",
+            InteractionKind::Video => "video",
+            InteractionKind::Letter => "letter",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl FromStr for InteractionKind {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "call" => Ok(InteractionKind::Call),
+            "text" => Ok(InteractionKind::Text),
+            "in_person" => Ok(InteractionKind::InPerson),
+            "video" => Ok(InteractionKind::Video),
+            "letter" => Ok(InteractionKind::Letter),
+            other => Err(anyhow!("Unknown interaction kind {}", other)),
+        }
+    }
+}
+
+/// How two buddies know each other
+#[derive(Debug, Clone, Copy, Deserialize, JsonSchema, Serialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum RelationshipKind {
+    Partner,
+    Sibling,
+    Coworker,
+    /// The `from` buddy was introduced by the `to` buddy
+    IntroducedBy,
+}
+
+impl fmt::Display for RelationshipKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            RelationshipKind::Partner => "partner",
+            RelationshipKind::Sibling => "sibling",
+            RelationshipKind::Coworker => "coworker",
+            RelationshipKind::IntroducedBy => "introduced_by",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl FromStr for RelationshipKind {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "partner" => Ok(RelationshipKind::Partner),
+            "sibling" => Ok(RelationshipKind::Sibling),
+            "coworker" => Ok(RelationshipKind::Coworker),
+            "introduced_by" => Ok(RelationshipKind::IntroducedBy),
+            other => Err(anyhow!("Unknown relationship kind {}", other)),
+        }
+    }
+}
+
+/// The kind of a way to get in touch with a buddy
+#[derive(Debug, Clone, Copy, Deserialize, JsonSchema, Serialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum ContactMethodKind {
+    /// An E.164 phone number, like +14155552671
+    Phone,
+    Email,
+    Url,
+    /// A handle on some social network. The label says which.
+    Social,
+}
+
+impl fmt::Display for ContactMethodKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            ContactMethodKind::Phone => "phone",
+            ContactMethodKind::Email => "email",
+            ContactMethodKind::Url => "url",
+            ContactMethodKind::Social => "social",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl FromStr for ContactMethodKind {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "phone" => Ok(ContactMethodKind::Phone),
+            "email" => Ok(ContactMethodKind::Email),
+            "url" => Ok(ContactMethodKind::Url),
+            "social" => Ok(ContactMethodKind::Social),
+            other => Err(anyhow!("Unknown contact method kind {}", other)),
+        }
+    }
+}
+
+/// What an important date celebrates or remembers
+#[derive(Debug, Clone, Copy, Deserialize, JsonSchema, Serialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportantDateKind {
+    Anniversary,
+    ChildBirthday,
+    Memorial,
+    /// Anything else. The label says what.
+    Custom,
+}
+
+impl ImportantDateKind {
+    /// How to describe a date of this kind when it has no label
+    pub fn description(&self) -> &'static str {
+        match self {
+            ImportantDateKind::Anniversary => "Anniversary",
+            ImportantDateKind::ChildBirthday => "Kid's birthday",
+            ImportantDateKind::Memorial => "Memorial",
+            ImportantDateKind::Custom => "Important date",
+        }
+    }
+}
+
+impl fmt::Display for ImportantDateKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            ImportantDateKind::Anniversary => "anniversary",
+            ImportantDateKind::ChildBirthday => "child_birthday",
+            ImportantDateKind::Memorial => "memorial",
+            ImportantDateKind::Custom => "custom",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl FromStr for ImportantDateKind {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "anniversary" => Ok(ImportantDateKind::Anniversary),
+            "child_birthday" => Ok(ImportantDateKind::ChildBirthday),
+            "memorial" => Ok(ImportantDateKind::Memorial),
+            "custom" => Ok(ImportantDateKind::Custom),
+            other => Err(anyhow!("Unknown important date kind {}", other)),
+        }
+    }
+}
+
+/// How often an important date comes around
+#[derive(Debug, Clone, Copy, Default, Deserialize, JsonSchema, Serialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Recurrence {
+    #[default]
+    Yearly,
+    Once,
+}
+
+impl fmt::Display for Recurrence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            Recurrence::Yearly => "yearly",
+            Recurrence::Once => "once",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl FromStr for Recurrence {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "yearly" => Ok(Recurrence::Yearly),
+            "once" => Ok(Recurrence::Once),
+            other => Err(anyhow!("Unknown recurrence {}", other)),
+        }
+    }
+}
+
+/// What sort of thing an idea is
+#[derive(Debug, Clone, Copy, Deserialize, JsonSchema, Serialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum IdeaKind {
+    Gift,
+    /// Something to bring up next time you talk
+    Topic,
+    /// A restaurant or somewhere else to go together
+    Place,
+    Other,
+}
+
+impl fmt::Display for IdeaKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            IdeaKind::Gift => "gift",
+            IdeaKind::Topic => "topic",
+            IdeaKind::Place => "place",
+            IdeaKind::Other => "other",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl FromStr for IdeaKind {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "gift" => Ok(IdeaKind::Gift),
+            "topic" => Ok(IdeaKind::Topic),
+            "place" => Ok(IdeaKind::Place),
+            "other" => Ok(IdeaKind::Other),
+            other => Err(anyhow!("Unknown idea kind {}", other)),
+        }
+    }
+}
+
+/// How far along an idea is
+#[derive(Debug, Clone, Copy, Default, Deserialize, JsonSchema, Serialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum IdeaStatus {
+    /// Just a thought so far
+    #[default]
+    Idea,
+    Planned,
+    /// Given, brought up or visited
+    Given,
+}
+
+impl fmt::Display for IdeaStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            IdeaStatus::Idea => "idea",
+            IdeaStatus::Planned => "planned",
+            IdeaStatus::Given => "given",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl FromStr for IdeaStatus {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "idea" => Ok(IdeaStatus::Idea),
+            "planned" => Ok(IdeaStatus::Planned),
+            "given" => Ok(IdeaStatus::Given),
+            other => Err(anyhow!("Unknown idea status {}", other)),
+        }
+    }
+}
+
+/// The kinds of records we keep a history of
+#[derive(Debug, Clone, Copy, Deserialize, JsonSchema, Serialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum EntityKind {
+    Buddy,
+    Interaction,
+}
+
+impl fmt::Display for EntityKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            EntityKind::Buddy => "buddy",
+            EntityKind::Interaction => "interaction",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl FromStr for EntityKind {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "buddy" => Ok(EntityKind::Buddy),
+            "interaction" => Ok(EntityKind::Interaction),
+            other => Err(anyhow!("Unknown entity kind {}", other)),
+        }
+    }
+}
+
+/// What happened to a record in a revision
+#[derive(Debug, Clone, Copy, Deserialize, JsonSchema, Serialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum RevisionAction {
+    Create,
+    Update,
+    Archive,
+    /// Put back the way it was after an earlier revision
+    Revert,
+}
+
+impl fmt::Display for RevisionAction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            RevisionAction::Create => "create",
+            RevisionAction::Update => "update",
+            RevisionAction::Archive => "archive",
+            RevisionAction::Revert => "revert",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl FromStr for RevisionAction {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "create" => Ok(RevisionAction::Create),
+            "update" => Ok(RevisionAction::Update),
+            "archive" => Ok(RevisionAction::Archive),
+            "revert" => Ok(RevisionAction::Revert),
+            other => Err(anyhow!("Unknown revision action {}", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Queryable, Serialize, Deserialize, JsonSchema)]
 pub struct Buddy {
     /// A unique id for your buddy
     pub id: Uuid,
@@ -36,9 +546,24 @@ pub struct Buddy {
     pub delete_timestamp: Option<Timestamp>,
     /// The id of whoever's buddy this is
     pub user_id: Uuid,
+    /// A cadence learned from past interactions, for buddies without one
+    #[serde(default)]
+    pub suggested_cadence: Option<Duration>,
+    /// Ways to get in touch with your buddy
+    #[serde(default)]
+    pub contact_methods: Vec<ContactMethod>,
+    /// The time where your buddy is, if we know their timezone
+    #[serde(default)]
+    pub local_time: Option<LocalTime>,
+    /// Dates worth remembering besides their birthday
+    #[serde(default)]
+    pub important_dates: Vec<ImportantDate>,
+    /// Goes up by one on every change, so stale writes can be caught
+    #[serde(default)]
+    pub version: i32,
 }
 
-#[derive(Debug, Clone, Queryable, Serialize, Deserialize)]
+#[derive(Debug, Clone, Queryable, Serialize, Deserialize, JsonSchema)]
 pub struct Interaction {
     pub id: Uuid,
     /// General notes about the interaction
@@ -47,6 +572,14 @@ pub struct Interaction {
     pub participants: HashSet<Uuid>,
     /// The date in which this happened
     pub date: Option<Datestamp>,
+    /// How the interaction happened
+    pub kind: Option<InteractionKind>,
+    /// How long the interaction lasted
+    pub duration: Option<Duration>,
+    /// Where the interaction happened
+    pub location: Option<String>,
+    /// Who reached out. Either the user's id or one of the participants
+    pub initiator: Option<Uuid>,
     /// The time in which this interaction was registered in the DB
     pub create_timestamp: Timestamp,
     /// The last time this record was updated
@@ -54,19 +587,155 @@ pub struct Interaction {
     /// The time in which this interaction was deleted
     pub delete_timestamp: Option<Timestamp>,
     pub user_id: Uuid,
+    /// Goes up by one on every change, so stale writes can be caught
+    #[serde(default)]
+    pub version: i32,
 }
-#[derive(Debug, Clone, Default, Deserialize, Queryable, Serialize)]
+/// A way to get in touch with a buddy
+#[derive(Debug, Clone, Queryable, Serialize, Deserialize, JsonSchema)]
+pub struct ContactMethod {
+    pub id: Uuid,
+    pub buddy_id: Uuid,
+    pub kind: ContactMethodKind,
+    /// The phone number, email address, url or handle
+    pub value: String,
+    /// A human readable label, like "work" or "instagram"
+    pub label: Option<String>,
+    /// Whether this is the buddy's preferred contact method of its kind
+    pub preferred: bool,
+    /// The time in which this contact method was registered in the DB
+    pub create_timestamp: Timestamp,
+    /// The last time this record was updated
+    pub last_update_timestamp: Timestamp,
+    /// The time in which this contact method was deleted
+    pub delete_timestamp: Option<Timestamp>,
+    pub user_id: Uuid,
+}
+
+/// A date worth remembering for a buddy, besides their birthday
+#[derive(Debug, Clone, Queryable, Serialize, Deserialize, JsonSchema)]
+pub struct ImportantDate {
+    pub id: Uuid,
+    pub buddy_id: Uuid,
+    pub kind: ImportantDateKind,
+    /// A human readable label, like "Wedding anniversary" or "Sam's birthday"
+    pub label: Option<String>,
+    /// When it first happened, or when it happens for one-off dates
+    pub date: Datestamp,
+    pub recurrence: Recurrence,
+    /// The time in which this important date was registered in the DB
+    pub create_timestamp: Timestamp,
+    /// The last time this record was updated
+    pub last_update_timestamp: Timestamp,
+    /// The time in which this important date was deleted
+    pub delete_timestamp: Option<Timestamp>,
+    pub user_id: Uuid,
+}
+
+impl ImportantDate {
+    pub fn description(&self) -> String {
+        match &self.label {
+            Some(label) => label.clone(),
+            None => self.kind.description().to_string(),
+        }
+    }
+}
+
+/// Something promised during an interaction, like sending an article along
+#[derive(Debug, Clone, Queryable, Serialize, Deserialize, JsonSchema)]
+pub struct FollowUp {
+    pub id: Uuid,
+    /// The interaction where this was promised
+    pub interaction_id: Uuid,
+    /// The buddy this is for, if it's for anyone in particular
+    pub buddy_id: Option<Uuid>,
+    /// What needs doing
+    pub notes: String,
+    /// When this should be done by
+    pub due: Option<Datestamp>,
+    pub done: bool,
+    /// The time in which this follow up was registered in the DB
+    pub create_timestamp: Timestamp,
+    /// The last time this record was updated
+    pub last_update_timestamp: Timestamp,
+    /// The time in which this follow up was deleted
+    pub delete_timestamp: Option<Timestamp>,
+    pub user_id: Uuid,
+}
+
+/// A gift idea, a topic to bring up or a place to try with a buddy
+#[derive(Debug, Clone, Queryable, Serialize, Deserialize, JsonSchema)]
+pub struct Idea {
+    pub id: Uuid,
+    pub buddy_id: Uuid,
+    pub kind: IdeaKind,
+    /// What the idea is
+    pub description: String,
+    pub status: IdeaStatus,
+    /// The interaction where the idea was used
+    pub interaction_id: Option<Uuid>,
+    /// The time in which this idea was registered in the DB
+    pub create_timestamp: Timestamp,
+    /// The last time this record was updated
+    pub last_update_timestamp: Timestamp,
+    /// The time in which this idea was deleted
+    pub delete_timestamp: Option<Timestamp>,
+    pub user_id: Uuid,
+}
+
+/// One change to a buddy or interaction. Revisions are never updated or deleted.
+#[derive(Debug, Clone, Queryable, Serialize, Deserialize, JsonSchema)]
+pub struct Revision {
+    pub id: Uuid,
+    pub entity_kind: EntityKind,
+    pub entity_id: Uuid,
+    pub action: RevisionAction,
+    /// The record before the change. Missing for creates.
+    pub before: Option<serde_json::Value>,
+    /// The record after the change
+    pub after: Option<serde_json::Value>,
+    /// For reverts, the revision that was reverted to
+    pub reverted_to: Option<Uuid>,
+    /// The time in which this change was made
+    pub create_timestamp: Timestamp,
+    pub user_id: Uuid,
+}
+
+/// A typed edge between two of a user's buddies
+#[derive(Debug, Clone, Queryable, Serialize, Deserialize, JsonSchema)]
+pub struct Relationship {
+    pub id: Uuid,
+    pub from_buddy_id: Uuid,
+    pub to_buddy_id: Uuid,
+    pub kind: RelationshipKind,
+    /// Whether the relationship reads the same in both directions
+    pub bidirectional: bool,
+    /// The time in which this relationship was registered in the DB
+    pub create_timestamp: Timestamp,
+    /// The last time this record was updated
+    pub last_update_timestamp: Timestamp,
+    /// The time in which this relationship was deleted
+    pub delete_timestamp: Option<Timestamp>,
+    pub user_id: Uuid,
+}
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Queryable, Serialize)]
 pub struct ArchiveBuddyRequest {
     pub id: Uuid,
     pub user_id: Uuid,
+    /// The version of the buddy this archive was based on. Stale archives are rejected.
+    #[serde(default)]
+    pub version: Option<i32>,
 }
-#[derive(Debug, Clone, Default, Deserialize, Queryable, Serialize)]
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Queryable, Serialize)]
 pub struct ArchiveInteractionRequest {
     pub id: Uuid,
     pub user_id: Uuid,
+    /// The version of the interaction this archive was based on. Stale archives are rejected.
+    #[serde(default)]
+    pub version: Option<i32>,
 }
 
-#[derive(Debug, Clone, Default, Deserialize, Queryable, Serialize)]
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Queryable, Serialize)]
 pub struct CreateBuddyRequest {
     /// The id of the user who is asking to register a buddy
     pub user_id: Uuid,
@@ -80,9 +749,16 @@ pub struct CreateBuddyRequest {
     pub notes: String,
     /// Where your buddy is
     pub location: Option<Location>,
+    /// An id the client came up with, for buddies created offline
+    #[serde(default)]
+    pub id: Option<Uuid>,
+    /// Retries with the same key get the original response instead of a second buddy.
+    /// Usually sent as the Idempotency-Key header.
+    #[serde(default, skip_serializing)]
+    pub idempotency_key: Option<String>,
 }
 
-#[derive(Debug, Clone, Default, Deserialize, Queryable, Serialize)]
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Queryable, Serialize)]
 pub struct CreateInteractionRequest {
     /// The id of the user who is asking to create an interaction
     pub user_id: Uuid,
@@ -92,84 +768,633 @@ pub struct CreateInteractionRequest {
     pub participants: HashSet<Uuid>,
     /// The date in which this happened
     pub date: Option<Datestamp>,
+    /// How the interaction happened
+    pub kind: Option<InteractionKind>,
+    /// How long the interaction lasted
+    pub duration: Option<Duration>,
+    /// Where the interaction happened
+    pub location: Option<String>,
+    /// Who reached out. Either the user's id or one of the participants
+    pub initiator: Option<Uuid>,
+    /// An id the client came up with, for interactions created offline
+    #[serde(default)]
+    pub id: Option<Uuid>,
+    /// Retries with the same key get the original response instead of a second interaction.
+    /// Usually sent as the Idempotency-Key header.
+    #[serde(default, skip_serializing)]
+    pub idempotency_key: Option<String>,
 }
-#[derive(Debug, Clone, Default, Deserialize, Queryable, Serialize)]
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Queryable, Serialize)]
 pub struct GetUserDataRequest {
     pub user_id: Uuid,
 }
-#[derive(Debug, Clone, Default, Deserialize, Queryable, Serialize)]
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Serialize)]
+pub struct GetUserRequest {
+    pub user_id: Uuid,
+}
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Serialize)]
+pub struct ListBuddiesRequest {
+    pub user_id: Uuid,
+}
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Serialize)]
+pub struct ListBuddiesResponse {
+    /// Every buddy that hasn't been archived, by name
+    pub buddies: Vec<Buddy>,
+}
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Serialize)]
+pub struct GetBuddyRequest {
+    pub user_id: Uuid,
+    pub buddy_id: Uuid,
+}
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Serialize)]
+pub struct GetInteractionRequest {
+    pub user_id: Uuid,
+    pub interaction_id: Uuid,
+}
+
+/// Deserialize a field that was sent as null to `Some(None)`, so it can be told apart from a
+/// field that was left out altogether. Pair with `#[serde(default)]`.
+fn double_option<'de, T, D>(deserializer: D) -> std::result::Result<Option<Option<T>>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Queryable, Serialize)]
 pub struct UpdateBuddyRequest {
     pub user_id: Uuid,
     pub buddy_id: Uuid,
     pub name: Option<String>,
     pub notes: Option<String>,
     pub last_contacted: Option<Datestamp>,
-    pub location: Option<Location>,
-    pub birthday: Option<Datestamp>,
+    /// Left out to keep the location, null to clear it
+    #[serde(default, deserialize_with = "double_option")]
+    pub location: Option<Option<Location>>,
+    /// Left out to keep the birthday, null to clear it
+    #[serde(default, deserialize_with = "double_option")]
+    pub birthday: Option<Option<Datestamp>>,
+    /// Left out to keep the cadence, null to clear it
+    #[serde(default, deserialize_with = "double_option")]
+    pub cadence: Option<Option<Duration>>,
+    /// The version of the buddy this update was based on. Stale updates are rejected.
+    pub version: Option<i32>,
 }
-#[derive(Debug, Clone, Default, Deserialize, Queryable, Serialize)]
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Queryable, Serialize)]
 pub struct UpdateInteractionRequest {
     pub user_id: Uuid,
     pub interaction_id: Uuid,
     pub notes: Option<String>,
-    pub date: Option<Datestamp>,
     pub participants: Option<HashSet<Uuid>>,
+    /// For the optional fields below, left out keeps the value and null clears it
+    #[serde(default, deserialize_with = "double_option")]
+    pub date: Option<Option<Datestamp>>,
+    #[serde(default, deserialize_with = "double_option")]
+    pub kind: Option<Option<InteractionKind>>,
+    #[serde(default, deserialize_with = "double_option")]
+    pub duration: Option<Option<Duration>>,
+    #[serde(default, deserialize_with = "double_option")]
+    pub location: Option<Option<String>>,
+    #[serde(default, deserialize_with = "double_option")]
+    pub initiator: Option<Option<Uuid>>,
+    /// The version of the interaction this update was based on. Stale updates are rejected.
+    pub version: Option<i32>,
+}
+
+/// A JSON Merge Patch (RFC 7396) of a buddy
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Queryable, Serialize)]
+pub struct PatchBuddyRequest {
+    pub user_id: Uuid,
+    pub buddy_id: Uuid,
+    pub patch: serde_json::Value,
+    /// The version of the buddy the patch was based on
+    pub version: Option<i32>,
+}
+#[derive(Debug, Clone, Deserialize, JsonSchema, Queryable, Serialize)]
+pub struct PatchBuddyResponse {
+    /// The buddy after the patch
+    pub buddy: Buddy,
+}
+/// A JSON Merge Patch (RFC 7396) of an interaction
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Queryable, Serialize)]
+pub struct PatchInteractionRequest {
+    pub user_id: Uuid,
+    pub interaction_id: Uuid,
+    pub patch: serde_json::Value,
+    /// The version of the interaction the patch was based on
+    pub version: Option<i32>,
+}
+#[derive(Debug, Clone, Deserialize, JsonSchema, Queryable, Serialize)]
+pub struct PatchInteractionResponse {
+    /// The interaction after the patch
+    pub interaction: Interaction,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Queryable, Serialize)]
+pub struct GetChangesRequest {
+    pub user_id: Uuid,
+    /// A cursor from an earlier sync. Everything is returned without one.
+    pub since: Option<String>,
+}
+/// A record that has been archived since the cursor
+#[derive(Debug, Clone, Deserialize, JsonSchema, Serialize)]
+pub struct Tombstone {
+    pub entity_kind: EntityKind,
+    pub id: Uuid,
+    pub delete_timestamp: Timestamp,
+    pub version: i32,
+}
+/// A buddy or interaction that just changed, as pushed to `/events`
+#[derive(Debug, Clone, Deserialize, JsonSchema, Serialize)]
+pub struct ChangeEvent {
+    /// The revision that recorded the change
+    pub revision_id: Uuid,
+    pub entity_kind: EntityKind,
+    pub entity_id: Uuid,
+    pub action: RevisionAction,
+    /// The record as it stands after the change
+    pub record: Option<serde_json::Value>,
+    pub create_timestamp: Timestamp,
+    pub user_id: Uuid,
+}
+impl From<&Revision> for ChangeEvent {
+    fn from(revision: &Revision) -> Self {
+        ChangeEvent {
+            revision_id: revision.id,
+            entity_kind: revision.entity_kind,
+            entity_id: revision.entity_id,
+            action: revision.action,
+            record: revision.after.clone(),
+            create_timestamp: revision.create_timestamp,
+            user_id: revision.user_id,
+        }
+    }
+}
+/// What `/events` sends down the stream
+#[derive(Debug, Clone, Deserialize, JsonSchema, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum StreamEvent {
+    Change(ChangeEvent),
+    /// Some changes were dropped because the client fell behind, so it should refetch
+    Resync {
+        missed: u64,
+    },
+}
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Queryable, Serialize)]
+pub struct GetChangesResponse {
+    /// Pass this back as `since` on the next sync
+    pub cursor: String,
+    /// Buddies created or changed since the cursor
+    pub buddies: Vec<Buddy>,
+    /// Interactions created or changed since the cursor
+    pub interactions: Vec<Interaction>,
+    /// Buddies and interactions archived since the cursor
+    pub tombstones: Vec<Tombstone>,
+}
+
+/// A create, update or archive of a buddy or interaction, as sent to sync and batch.
+/// Creates can carry an id the client gave the record.
+#[derive(Debug, Clone, Deserialize, JsonSchema, Serialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Mutation {
+    CreateBuddy(CreateBuddyRequest),
+    UpdateBuddy(UpdateBuddyRequest),
+    ArchiveBuddy(ArchiveBuddyRequest),
+    CreateInteraction(CreateInteractionRequest),
+    UpdateInteraction(UpdateInteractionRequest),
+    ArchiveInteraction(ArchiveInteractionRequest),
+}
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Serialize)]
+pub struct SyncRequest {
+    pub user_id: Uuid,
+    /// Applied in order. One failing doesn't stop the rest.
+    pub mutations: Vec<Mutation>,
+}
+#[derive(Debug, Clone, Copy, Deserialize, JsonSchema, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncStatus {
+    Applied,
+    /// The record changed on the server since the version the client had
+    Conflict,
+    Rejected,
+}
+/// What came of a single mutation
+#[derive(Debug, Clone, Deserialize, JsonSchema, Serialize)]
+pub struct SyncResult {
+    /// The buddy or interaction the mutation was for
+    pub id: Uuid,
+    pub status: SyncStatus,
+    /// The version of the record after the mutation was applied
+    pub version: Option<i32>,
+    /// For conflicts, the record as it stands on the server
+    pub current: Option<CurrentRecord>,
+    /// Why a mutation was rejected
+    pub message: Option<String>,
+}
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Serialize)]
+pub struct SyncResponse {
+    /// One result per mutation, in the same order
+    pub results: Vec<SyncResult>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Serialize)]
+pub struct BatchRequest {
+    pub user_id: Uuid,
+    /// Applied in order. If any of them fail, none of them are applied.
+    pub operations: Vec<Mutation>,
+}
+#[derive(Debug, Clone, Copy, Deserialize, JsonSchema, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchStatus {
+    Applied,
+    /// Went through, but was undone because a later operation failed
+    RolledBack,
+    /// The record changed on the server since the version the client had
+    Conflict,
+    Rejected,
+    /// Not tried because an earlier operation failed
+    Skipped,
+}
+/// What came of a single operation
+#[derive(Debug, Clone, Deserialize, JsonSchema, Serialize)]
+pub struct BatchResult {
+    /// Where the operation was in the request
+    pub index: usize,
+    /// The buddy or interaction the operation was for. Left out for skipped creates that
+    /// didn't bring their own id.
+    pub id: Option<Uuid>,
+    pub status: BatchStatus,
+    /// The version of the record after the operation, if it was applied
+    pub version: Option<i32>,
+    /// For conflicts, the record as it stands on the server
+    pub current: Option<CurrentRecord>,
+    /// Why an operation was rejected
+    pub message: Option<String>,
+}
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Serialize)]
+pub struct BatchResponse {
+    /// Whether every operation was applied. If not, none of them were.
+    pub applied: bool,
+    /// The operation that failed, if one did
+    pub failed_index: Option<usize>,
+    /// One result per operation, in the same order
+    pub results: Vec<BatchResult>,
+}
+
+/// A create we've already done, remembered so that retries get the same response
+#[derive(Debug, Clone, Deserialize, JsonSchema, Serialize)]
+pub struct IdempotencyRecord {
+    pub user_id: Uuid,
+    /// Which kind of create the key was used for
+    pub operation: String,
+    /// The Idempotency-Key the client sent
+    pub key: String,
+    /// The original request, so the key can't be reused for something else
+    pub request: serde_json::Value,
+    pub response: serde_json::Value,
+    pub create_timestamp: Timestamp,
+}
+
+/// Something a credential is allowed to do
+#[derive(
+    Debug, Clone, Copy, Deserialize, JsonSchema, Serialize, PartialEq, Eq, Hash, PartialOrd, Ord,
+)]
+pub enum Scope {
+    /// See buddies and everything kept about them
+    #[serde(rename = "buddies:read")]
+    BuddiesRead,
+    /// Add and change buddies. Without `buddies:read`, writes only give back ids and
+    /// versions.
+    #[serde(rename = "buddies:write")]
+    BuddiesWrite,
+    /// See interactions and their follow ups
+    #[serde(rename = "interactions:read")]
+    InteractionsRead,
+    /// Log and change interactions. Without `interactions:read`, writes only give back ids
+    /// and versions.
+    #[serde(rename = "interactions:write")]
+    InteractionsWrite,
+    /// Settings, webhooks and tokens. Webhooks hear about every change and tokens can be
+    /// made with any scope the caller has, so this is best kept for credentials you'd
+    /// trust with everything else too.
+    #[serde(rename = "account")]
+    Account,
+}
+
+impl Scope {
+    /// Everything, which is what logging in with a password gets you
+    pub const ALL: &'static [Scope] = &[
+        Scope::BuddiesRead,
+        Scope::BuddiesWrite,
+        Scope::InteractionsRead,
+        Scope::InteractionsWrite,
+        Scope::Account,
+    ];
+
+    /// The scope needed to see records of `kind`
+    pub fn read(kind: EntityKind) -> Scope {
+        match kind {
+            EntityKind::Buddy => Scope::BuddiesRead,
+            EntityKind::Interaction => Scope::InteractionsRead,
+        }
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            Scope::BuddiesRead => "buddies:read",
+            Scope::BuddiesWrite => "buddies:write",
+            Scope::InteractionsRead => "interactions:read",
+            Scope::InteractionsWrite => "interactions:write",
+            Scope::Account => "account",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl FromStr for Scope {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "buddies:read" => Ok(Scope::BuddiesRead),
+            "buddies:write" => Ok(Scope::BuddiesWrite),
+            "interactions:read" => Ok(Scope::InteractionsRead),
+            "interactions:write" => Ok(Scope::InteractionsWrite),
+            "account" => Ok(Scope::Account),
+            other => Err(anyhow!("Unknown scope {}", other)),
+        }
+    }
+}
+
+/// A long lived credential for scripts, sent as a Bearer token just like a JWT
+#[derive(Debug, Clone, Deserialize, JsonSchema, Serialize)]
+pub struct PersonalAccessToken {
+    pub id: Uuid,
+    /// What the token is for, like "cron backup"
+    pub name: String,
+    pub scopes: Vec<Scope>,
+    /// Hex SHA-256 of the token. The token itself is only shown when it's created.
+    #[serde(skip_serializing)]
+    pub token_hash: String,
+    pub create_timestamp: Timestamp,
+    pub revoke_timestamp: Option<Timestamp>,
+    pub user_id: Uuid,
+}
+
+/// A URL that gets a signed POST whenever one of the user's buddies or interactions changes
+#[derive(Debug, Clone, Deserialize, JsonSchema, Serialize)]
+pub struct Webhook {
+    pub id: Uuid,
+    pub url: String,
+    /// Key for the HMAC signature on each delivery. Only shown when the webhook is created.
+    #[serde(skip_serializing)]
+    pub secret: String,
+    pub create_timestamp: Timestamp,
+    pub delete_timestamp: Option<Timestamp>,
+    pub user_id: Uuid,
+}
+
+/// Where a webhook delivery is at
+#[derive(Debug, Clone, Copy, Deserialize, JsonSchema, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryStatus {
+    /// Waiting to be sent, or to be retried
+    Pending,
+    Delivered,
+    /// Out of retries
+    Failed,
+}
+
+impl fmt::Display for DeliveryStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            DeliveryStatus::Pending => "pending",
+            DeliveryStatus::Delivered => "delivered",
+            DeliveryStatus::Failed => "failed",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl FromStr for DeliveryStatus {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "pending" => Ok(DeliveryStatus::Pending),
+            "delivered" => Ok(DeliveryStatus::Delivered),
+            "failed" => Ok(DeliveryStatus::Failed),
+            other => Err(anyhow!("Unknown delivery status {}", other)),
+        }
+    }
+}
+
+/// One change sent, or to be sent, to a webhook
+#[derive(Debug, Clone, Deserialize, JsonSchema, Serialize)]
+pub struct WebhookDelivery {
+    pub id: Uuid,
+    pub webhook_id: Uuid,
+    /// The JSON body that is POSTed
+    pub payload: serde_json::Value,
+    pub status: DeliveryStatus,
+    /// How many times we've tried to send it
+    pub attempts: i32,
+    /// When to try next, for pending deliveries
+    pub next_attempt_timestamp: Option<Timestamp>,
+    /// The status code of the last response, if we got one
+    pub response_status: Option<i32>,
+    /// Why the last attempt failed
+    pub error: Option<String>,
+    pub create_timestamp: Timestamp,
+    pub last_update_timestamp: Timestamp,
+    pub user_id: Uuid,
+}
+
+/// The record as it stands, for a write that was based on an older version of it
+#[derive(Debug, Clone, Deserialize, JsonSchema, Serialize)]
+#[serde(untagged)]
+pub enum CurrentRecord {
+    Buddy(Box<Buddy>),
+    Interaction(Box<Interaction>),
+}
+
+impl Mutation {
+    /// The buddy or interaction this is for, if known before it's applied
+    pub fn record_id(&self) -> Option<Uuid> {
+        match self {
+            Mutation::CreateBuddy(request) => request.id,
+            Mutation::UpdateBuddy(request) => Some(request.buddy_id),
+            Mutation::ArchiveBuddy(request) => Some(request.id),
+            Mutation::CreateInteraction(request) => request.id,
+            Mutation::UpdateInteraction(request) => Some(request.interaction_id),
+            Mutation::ArchiveInteraction(request) => Some(request.id),
+        }
+    }
+}
+
+impl CurrentRecord {
+    pub fn version(&self) -> i32 {
+        match self {
+            CurrentRecord::Buddy(buddy) => buddy.version,
+            CurrentRecord::Interaction(interaction) => interaction.version,
+        }
+    }
+
+    pub fn kind(&self) -> EntityKind {
+        match self {
+            CurrentRecord::Buddy(_) => EntityKind::Buddy,
+            CurrentRecord::Interaction(_) => EntityKind::Interaction,
+        }
+    }
+}
+
+/// Returned by the stores when someone else changed a record since the version a write
+/// was based on
+#[derive(Debug)]
+pub struct StaleWriteError {
+    pub current: CurrentRecord,
+}
+
+impl fmt::Display for StaleWriteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Record has changed since it was read, it is now at version {}",
+            self.current.version()
+        )
+    }
+}
+
+impl std::error::Error for StaleWriteError {}
+
+/// Returned when a record doesn't exist, or belongs to someone else
+#[derive(Debug)]
+pub struct NotFoundError {
+    /// What was looked for, like "buddy" or "contact method"
+    pub kind: &'static str,
+    pub id: Uuid,
+}
+
+impl fmt::Display for NotFoundError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "No {} found with id {}", self.kind, self.id)
+    }
+}
+
+impl std::error::Error for NotFoundError {}
+
+/// Returned when a request doesn't make sense, like an interaction with someone else's buddy
+#[derive(Debug)]
+pub struct InvalidRequestError {
+    pub message: String,
+}
+
+impl fmt::Display for InvalidRequestError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for InvalidRequestError {}
+
+/// Returned when a credential is malformed, expired, revoked or unknown
+#[derive(Debug)]
+pub struct UnauthorizedError {
+    pub message: String,
+}
+
+impl fmt::Display for UnauthorizedError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for UnauthorizedError {}
+
+/// Returned when an idempotency key is reused with a different request
+#[derive(Debug)]
+pub struct IdempotencyKeyReusedError {
+    pub key: String,
+}
+
+impl fmt::Display for IdempotencyKeyReusedError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Idempotency key {} was already used for a different request",
+            self.key
+        )
+    }
 }
 
-#[derive(Debug, Clone, Default, Deserialize, Queryable, Serialize)]
+impl std::error::Error for IdempotencyKeyReusedError {}
+
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Queryable, Serialize)]
 pub struct ArchiveBuddyResponse {}
-#[derive(Debug, Clone, Default, Deserialize, Queryable, Serialize)]
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Queryable, Serialize)]
 pub struct ArchiveInteractionResponse {}
-#[derive(Debug, Clone, Deserialize, Queryable, Serialize)]
+#[derive(Debug, Clone, Deserialize, JsonSchema, Queryable, Serialize)]
 pub struct CreateBuddyResponse {
     /// The buddy you just created
     pub buddy: Buddy,
 }
-#[derive(Debug, Clone, Deserialize, Queryable, Serialize)]
+#[derive(Debug, Clone, Deserialize, JsonSchema, Queryable, Serialize)]
 pub struct CreateInteractionResponse {
     pub interaction: Interaction,
 }
-#[derive(Debug, Clone, Deserialize, Queryable, Serialize)]
+#[derive(Debug, Clone, Deserialize, JsonSchema, Queryable, Serialize)]
 pub struct GetUserDataResponse {
     /// Map from buddy_id to buddy object
     pub buddies: HashMap<Uuid, Buddy>,
     pub interactions: HashMap<Uuid, Interaction>,
+    /// Map from idea_id to idea object
+    pub ideas: HashMap<Uuid, Idea>,
 }
 
-#[derive(Debug, Clone, Deserialize, Queryable, Serialize)]
+#[derive(Debug, Clone, Deserialize, JsonSchema, Queryable, Serialize)]
 pub struct LoginRequest {
     pub email: String,
     pub password: String,
+    /// What the JWT may be used for. Everything when left out.
+    #[serde(default)]
+    pub scopes: Option<Vec<Scope>>,
 }
 
-#[derive(Debug, Clone, Deserialize, Queryable, Serialize)]
+#[derive(Debug, Clone, Deserialize, JsonSchema, Queryable, Serialize)]
 pub struct SignUpRequest {
     pub email: String,
     pub password: String,
 }
 
-#[derive(Debug, Clone, Default, Deserialize, Queryable, Serialize)]
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Queryable, Serialize)]
 pub struct User {
     pub id: Uuid,
     pub email: String,
     pub password: String,
+    /// How often this user would like to receive a digest
+    pub digest_frequency: DigestFrequency,
+    /// Secret token used to subscribe to this user's calendar feed
+    pub calendar_token: Option<String>,
     /// The time in which this User was registered in the DB
     pub create_timestamp: Timestamp,
     /// The last time this record was updated
     pub last_update_timestamp: Timestamp,
 }
 
-#[derive(Debug, Clone, Default, Deserialize, Queryable, Serialize)]
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Queryable, Serialize)]
 pub struct PublicUser {
     pub id: Uuid,
     pub email: String,
+    /// How often this user would like to receive a digest
+    pub digest_frequency: DigestFrequency,
     /// The time in which this User was registered in the DB
     pub create_timestamp: Timestamp,
     /// The last time this record was updated
     pub last_update_timestamp: Timestamp,
 }
 
-#[derive(Debug, Clone, Default, Deserialize, Queryable, Serialize)]
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Queryable, Serialize)]
 pub struct CreateUserRequest {
     pub user: User,
 }
@@ -179,13 +1404,14 @@ impl From<User> for PublicUser {
         PublicUser {
             id: item.id,
             email: item.email,
+            digest_frequency: item.digest_frequency,
             last_update_timestamp: item.last_update_timestamp,
             create_timestamp: item.create_timestamp,
         }
     }
 }
 
-#[derive(Debug, Clone, Default, Deserialize, Queryable, Serialize)]
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Queryable, Serialize)]
 pub struct LoginResponse {
     /// User metadata associated with the login request
     pub user: PublicUser,
@@ -193,21 +1419,656 @@ pub struct LoginResponse {
     pub jwt: String,
 }
 
-#[derive(Debug, Clone, Default, Deserialize, Queryable, Serialize)]
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Queryable, Serialize)]
 pub struct SignUpResponse {
     pub user: PublicUser,
 }
 
-#[derive(Debug, Clone, Default, Deserialize, Queryable, Serialize)]
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Queryable, Serialize)]
 pub struct AuthenticationResponse {
     pub user_uuid: Uuid,
+    /// What the credential may do
+    pub scopes: Vec<Scope>,
 }
-#[derive(Debug, Clone, Default, Deserialize, Queryable, Serialize)]
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Queryable, Serialize)]
 pub struct AuthenticationRequest {
-    pub json_web_token: String,
+    /// A JWT from `/login` or a personal access token
+    pub token: String,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Queryable, Serialize)]
+pub struct UpdateBuddyResponse {
+    /// The version of the buddy after the update
+    pub version: i32,
+}
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Queryable, Serialize)]
+pub struct UpdateInteractionResponse {
+    /// The version of the interaction after the update
+    pub version: i32,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Queryable, Serialize)]
+pub struct UpdateUserRequest {
+    pub user_id: Uuid,
+    pub digest_frequency: Option<DigestFrequency>,
+}
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Queryable, Serialize)]
+pub struct UpdateUserResponse {}
+
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Queryable, Serialize)]
+pub struct GetDigestRequest {
+    pub user_id: Uuid,
+}
+
+/// A buddy whose cadence says you should have reached out by now
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Queryable, Serialize)]
+pub struct OverdueBuddy {
+    pub buddy_id: Uuid,
+    pub name: String,
+    /// The day we should have reached out by
+    pub due: Datestamp,
+    pub days_overdue: i64,
+    /// Notes from the most recent interaction with this buddy
+    pub last_interaction_notes: Option<String>,
+}
+
+/// A follow up that should have been done by now
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Queryable, Serialize)]
+pub struct OverdueFollowUp {
+    pub follow_up_id: Uuid,
+    pub interaction_id: Uuid,
+    pub buddy_id: Option<Uuid>,
+    /// The name of the buddy this is for
+    pub name: Option<String>,
+    pub notes: String,
+    pub due: Datestamp,
+    pub days_overdue: i64,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Queryable, Serialize)]
+pub struct UpcomingBirthday {
+    pub buddy_id: Uuid,
+    pub name: String,
+    /// The next day this buddy celebrates their birthday
+    pub date: Datestamp,
+    pub days_until: i64,
+}
+
+#[derive(Debug, Clone, Deserialize, JsonSchema, Queryable, Serialize)]
+pub struct UpcomingDate {
+    pub important_date_id: Uuid,
+    pub buddy_id: Uuid,
+    pub name: String,
+    pub kind: ImportantDateKind,
+    /// The label, or a description of the kind
+    pub description: String,
+    /// The next day this comes around
+    pub date: Datestamp,
+    pub days_until: i64,
+}
+
+/// Everything a user should know about their buddies for a period
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Queryable, Serialize)]
+pub struct Digest {
+    pub user_id: Uuid,
+    pub email: String,
+    pub frequency: DigestFrequency,
+    /// The day this digest was generated for
+    pub date: Datestamp,
+    /// How many days ahead we looked for upcoming events
+    pub lookahead_days: i64,
+    pub overdue: Vec<OverdueBuddy>,
+    pub overdue_follow_ups: Vec<OverdueFollowUp>,
+    pub upcoming_birthdays: Vec<UpcomingBirthday>,
+    pub upcoming_dates: Vec<UpcomingDate>,
 }
 
-#[derive(Debug, Clone, Default, Deserialize, Queryable, Serialize)]
-pub struct UpdateBuddyResponse {}
-#[derive(Debug, Clone, Default, Deserialize, Queryable, Serialize)]
-pub struct UpdateInteractionResponse {}
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Queryable, Serialize)]
+pub struct GetDigestResponse {
+    pub digest: Digest,
+    /// The digest rendered as plain text
+    pub text: String,
+    /// The digest rendered as html
+    pub html: String,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Queryable, Serialize)]
+pub struct CreateCalendarTokenRequest {
+    pub user_id: Uuid,
+}
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Queryable, Serialize)]
+pub struct CreateCalendarTokenResponse {
+    /// Secret token for the calendar feed. Any previous token stops working.
+    pub token: String,
+    /// Path the calendar feed can be subscribed to at
+    pub path: String,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Queryable, Serialize)]
+pub struct GetCalendarRequest {
+    pub token: String,
+}
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Queryable, Serialize)]
+pub struct GetCalendarResponse {
+    /// The RFC 5545 iCalendar document
+    pub ics: String,
+}
+
+/// Filters for listing interactions. Every filter that is set must match.
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Queryable, Serialize)]
+pub struct InteractionFilter {
+    /// Only interactions of this kind
+    pub kind: Option<InteractionKind>,
+    /// Only interactions this buddy participated in
+    pub participant: Option<Uuid>,
+    /// Only interactions started by this user or buddy
+    pub initiator: Option<Uuid>,
+    /// Only interactions on or after this date
+    pub since: Option<Datestamp>,
+    /// Only interactions on or before this date
+    pub until: Option<Datestamp>,
+    /// Return at most this many interactions
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Queryable, Serialize)]
+pub struct ListInteractionsRequest {
+    pub user_id: Uuid,
+    pub filter: InteractionFilter,
+}
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Queryable, Serialize)]
+pub struct ListInteractionsResponse {
+    /// Matching interactions, most recent first
+    pub interactions: Vec<Interaction>,
+}
+
+/// When each buddy was contacted over some window of time
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Queryable, Serialize)]
+pub struct ContactHistory {
+    /// How many interactions happened in the window
+    pub total_interactions: usize,
+    /// Map from buddy_id to the dates they were contacted, oldest first
+    pub contact_dates: HashMap<Uuid, Vec<Datestamp>>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Queryable, Serialize)]
+pub struct GetStatsRequest {
+    pub user_id: Uuid,
+    /// How many days back to look. Defaults to a year.
+    pub window_days: Option<i64>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Queryable, Serialize)]
+pub struct BuddyStats {
+    pub buddy_id: Uuid,
+    pub name: String,
+    pub interaction_count: usize,
+    pub interactions_per_month: f64,
+    /// Average number of days between contacts
+    pub average_gap_days: Option<f64>,
+    /// Percentage of gaps between contacts that were within the buddy's cadence
+    pub cadence_adherence: Option<f64>,
+    /// Consecutive periods (of cadence, or a month) ending now with a contact in each
+    pub current_streak: u32,
+    /// Most consecutive periods with a contact in each
+    pub longest_streak: u32,
+    pub last_contacted: Option<Datestamp>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Queryable, Serialize)]
+pub struct AggregateStats {
+    pub total_interactions: usize,
+    pub interactions_per_month: f64,
+    /// Average number of days between contacts, across every buddy
+    pub average_gap_days: Option<f64>,
+    /// Percentage of gaps between contacts that were within cadence, across every buddy
+    pub cadence_adherence: Option<f64>,
+    /// Buddy ids, most contacted first
+    pub most_contacted: Vec<Uuid>,
+    /// Buddy ids, least contacted first
+    pub least_contacted: Vec<Uuid>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Queryable, Serialize)]
+pub struct GetStatsResponse {
+    /// First day included in the stats
+    pub window_start: Datestamp,
+    /// Last day included in the stats
+    pub window_end: Datestamp,
+    pub aggregate: AggregateStats,
+    pub buddies: Vec<BuddyStats>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Queryable, Serialize)]
+pub struct ApplySuggestedCadenceRequest {
+    pub user_id: Uuid,
+    /// Only apply suggestions to these buddies. Defaults to every buddy with a suggestion.
+    pub buddy_ids: Option<HashSet<Uuid>>,
+}
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Queryable, Serialize)]
+pub struct ApplySuggestedCadenceResponse {
+    /// Map from buddy_id to the cadence that buddy now has
+    pub updated: HashMap<Uuid, Duration>,
+}
+
+#[derive(Debug, Clone, Deserialize, JsonSchema, Queryable, Serialize)]
+pub struct CreateRelationshipRequest {
+    pub user_id: Uuid,
+    pub from_buddy_id: Uuid,
+    pub to_buddy_id: Uuid,
+    pub kind: RelationshipKind,
+    pub bidirectional: bool,
+}
+#[derive(Debug, Clone, Deserialize, JsonSchema, Queryable, Serialize)]
+pub struct CreateRelationshipResponse {
+    pub relationship: Relationship,
+}
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Queryable, Serialize)]
+pub struct UpdateRelationshipRequest {
+    pub user_id: Uuid,
+    pub relationship_id: Uuid,
+    pub kind: Option<RelationshipKind>,
+    pub bidirectional: Option<bool>,
+}
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Queryable, Serialize)]
+pub struct UpdateRelationshipResponse {}
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Queryable, Serialize)]
+pub struct ArchiveRelationshipRequest {
+    pub id: Uuid,
+    pub user_id: Uuid,
+}
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Queryable, Serialize)]
+pub struct ArchiveRelationshipResponse {}
+
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Queryable, Serialize)]
+pub struct GetBuddyGraphRequest {
+    pub user_id: Uuid,
+    pub buddy_id: Uuid,
+}
+/// Which way a relationship points, seen from the buddy at the center of a graph
+#[derive(Debug, Clone, Copy, Deserialize, JsonSchema, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EdgeDirection {
+    /// From the center buddy to the connected one
+    Outgoing,
+    /// From the connected buddy to the center one
+    Incoming,
+    /// The relationship reads the same both ways
+    Mutual,
+}
+/// One relationship in a graph, as an edge between the center buddy and a connected one
+#[derive(Debug, Clone, Deserialize, JsonSchema, Serialize)]
+pub struct GraphEdge {
+    pub relationship_id: Uuid,
+    pub kind: RelationshipKind,
+    pub direction: EdgeDirection,
+}
+/// A buddy connected to the buddy at the center of a graph
+#[derive(Debug, Clone, Deserialize, JsonSchema, Queryable, Serialize)]
+pub struct Connection {
+    pub buddy: Buddy,
+    /// Relationships recorded between the two buddies, in either direction
+    pub relationships: Vec<Relationship>,
+    /// The same relationships as edges. One way relationships only point one way.
+    pub edges: Vec<GraphEdge>,
+    /// How many interactions both buddies participated in
+    pub shared_interactions: usize,
+    /// The most recent date both buddies participated in an interaction
+    pub last_shared_interaction: Option<Datestamp>,
+}
+#[derive(Debug, Clone, Deserialize, JsonSchema, Queryable, Serialize)]
+pub struct GetBuddyGraphResponse {
+    pub buddy_id: Uuid,
+    /// Everyone connected to the buddy, most shared interactions first
+    pub connections: Vec<Connection>,
+}
+
+#[derive(Debug, Clone, Deserialize, JsonSchema, Queryable, Serialize)]
+pub struct CreateContactMethodRequest {
+    pub user_id: Uuid,
+    pub buddy_id: Uuid,
+    pub kind: ContactMethodKind,
+    pub value: String,
+    pub label: Option<String>,
+    #[serde(default)]
+    pub preferred: bool,
+}
+#[derive(Debug, Clone, Deserialize, JsonSchema, Queryable, Serialize)]
+pub struct CreateContactMethodResponse {
+    pub contact_method: ContactMethod,
+}
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Queryable, Serialize)]
+pub struct UpdateContactMethodRequest {
+    pub user_id: Uuid,
+    pub contact_method_id: Uuid,
+    pub value: Option<String>,
+    pub label: Option<String>,
+    pub preferred: Option<bool>,
+}
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Queryable, Serialize)]
+pub struct UpdateContactMethodResponse {}
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Queryable, Serialize)]
+pub struct ArchiveContactMethodRequest {
+    pub id: Uuid,
+    pub user_id: Uuid,
+}
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Queryable, Serialize)]
+pub struct ArchiveContactMethodResponse {}
+
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Queryable, Serialize)]
+pub struct ExportVCardRequest {
+    pub user_id: Uuid,
+    /// Only export this buddy. Defaults to every buddy.
+    pub buddy_id: Option<Uuid>,
+}
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Queryable, Serialize)]
+pub struct ExportVCardResponse {
+    /// One vCard per buddy
+    pub vcard: String,
+}
+
+#[derive(Debug, Clone, Deserialize, JsonSchema, Queryable, Serialize)]
+pub struct CreateImportantDateRequest {
+    pub user_id: Uuid,
+    pub buddy_id: Uuid,
+    pub kind: ImportantDateKind,
+    pub label: Option<String>,
+    pub date: Datestamp,
+    #[serde(default)]
+    pub recurrence: Recurrence,
+}
+#[derive(Debug, Clone, Deserialize, JsonSchema, Queryable, Serialize)]
+pub struct CreateImportantDateResponse {
+    pub important_date: ImportantDate,
+}
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Queryable, Serialize)]
+pub struct UpdateImportantDateRequest {
+    pub user_id: Uuid,
+    pub important_date_id: Uuid,
+    pub kind: Option<ImportantDateKind>,
+    pub label: Option<String>,
+    pub date: Option<Datestamp>,
+    pub recurrence: Option<Recurrence>,
+}
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Queryable, Serialize)]
+pub struct UpdateImportantDateResponse {}
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Queryable, Serialize)]
+pub struct ArchiveImportantDateRequest {
+    pub id: Uuid,
+    pub user_id: Uuid,
+}
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Queryable, Serialize)]
+pub struct ArchiveImportantDateResponse {}
+
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Queryable, Serialize)]
+pub struct GetUpcomingDatesRequest {
+    pub user_id: Uuid,
+    /// How many days ahead to look. Defaults to 30
+    pub days: Option<i64>,
+}
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Queryable, Serialize)]
+pub struct GetUpcomingDatesResponse {
+    /// Soonest first
+    pub birthdays: Vec<UpcomingBirthday>,
+    /// Soonest first
+    pub important_dates: Vec<UpcomingDate>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Queryable, Serialize)]
+pub struct CreateFollowUpRequest {
+    pub user_id: Uuid,
+    pub interaction_id: Uuid,
+    /// Must be one of the interaction's participants
+    pub buddy_id: Option<Uuid>,
+    pub notes: String,
+    pub due: Option<Datestamp>,
+}
+#[derive(Debug, Clone, Deserialize, JsonSchema, Queryable, Serialize)]
+pub struct CreateFollowUpResponse {
+    pub follow_up: FollowUp,
+}
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Queryable, Serialize)]
+pub struct CompleteFollowUpRequest {
+    pub id: Uuid,
+    pub user_id: Uuid,
+}
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Queryable, Serialize)]
+pub struct CompleteFollowUpResponse {}
+
+/// Filters for listing open follow ups. Every filter that is set must match.
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Queryable, Serialize)]
+pub struct FollowUpFilter {
+    /// Only follow ups for this buddy
+    pub buddy: Option<Uuid>,
+    /// Only follow ups from this interaction
+    pub interaction: Option<Uuid>,
+    /// Only follow ups that are past due
+    #[serde(default)]
+    pub overdue: bool,
+}
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Queryable, Serialize)]
+pub struct ListFollowUpsRequest {
+    pub user_id: Uuid,
+    pub filter: FollowUpFilter,
+}
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Queryable, Serialize)]
+pub struct ListFollowUpsResponse {
+    /// Open follow ups, soonest due first. Those without a due date come last.
+    pub follow_ups: Vec<FollowUp>,
+}
+
+#[derive(Debug, Clone, Deserialize, JsonSchema, Queryable, Serialize)]
+pub struct CreateIdeaRequest {
+    pub user_id: Uuid,
+    pub buddy_id: Uuid,
+    pub kind: IdeaKind,
+    pub description: String,
+    #[serde(default)]
+    pub status: IdeaStatus,
+    /// Must be an interaction the buddy participated in
+    pub interaction_id: Option<Uuid>,
+}
+#[derive(Debug, Clone, Deserialize, JsonSchema, Queryable, Serialize)]
+pub struct CreateIdeaResponse {
+    pub idea: Idea,
+}
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Queryable, Serialize)]
+pub struct UpdateIdeaRequest {
+    pub user_id: Uuid,
+    pub idea_id: Uuid,
+    pub kind: Option<IdeaKind>,
+    pub description: Option<String>,
+    pub status: Option<IdeaStatus>,
+    pub interaction_id: Option<Uuid>,
+}
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Queryable, Serialize)]
+pub struct UpdateIdeaResponse {}
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Queryable, Serialize)]
+pub struct ArchiveIdeaRequest {
+    pub id: Uuid,
+    pub user_id: Uuid,
+}
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Queryable, Serialize)]
+pub struct ArchiveIdeaResponse {}
+
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Queryable, Serialize)]
+pub struct GetHistoryRequest {
+    pub user_id: Uuid,
+    /// The buddy or interaction to get the history of
+    pub entity_id: Uuid,
+}
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Queryable, Serialize)]
+pub struct GetHistoryResponse {
+    /// Oldest first
+    pub revisions: Vec<Revision>,
+}
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Queryable, Serialize)]
+pub struct RevertBuddyRequest {
+    pub user_id: Uuid,
+    pub buddy_id: Uuid,
+    /// The buddy goes back to how it was right after this revision
+    pub revision_id: Uuid,
+    /// The version of the buddy this revert was based on. Stale reverts are rejected.
+    #[serde(default)]
+    pub version: Option<i32>,
+}
+#[derive(Debug, Clone, Deserialize, JsonSchema, Queryable, Serialize)]
+pub struct RevertBuddyResponse {
+    pub buddy: Buddy,
+}
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Queryable, Serialize)]
+pub struct RevertInteractionRequest {
+    pub user_id: Uuid,
+    pub interaction_id: Uuid,
+    /// The interaction goes back to how it was right after this revision
+    pub revision_id: Uuid,
+    /// The version of the interaction this revert was based on. Stale reverts are rejected.
+    #[serde(default)]
+    pub version: Option<i32>,
+}
+#[derive(Debug, Clone, Deserialize, JsonSchema, Queryable, Serialize)]
+pub struct RevertInteractionResponse {
+    pub interaction: Interaction,
+}
+
+/// Where to look for buddies. Either coordinates with a radius, or any of city,
+/// region and country code, or both.
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Serialize)]
+pub struct NearFilter {
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    /// How far from the coordinates to look. Defaults to 50km
+    pub radius_km: Option<f64>,
+    pub city: Option<String>,
+    pub region: Option<String>,
+    pub country_code: Option<String>,
+}
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Serialize)]
+pub struct FindBuddiesNearRequest {
+    pub user_id: Uuid,
+    pub near: NearFilter,
+}
+#[derive(Debug, Clone, Deserialize, JsonSchema, Serialize)]
+pub struct NearbyBuddy {
+    pub buddy: Buddy,
+    /// Only known when searching by coordinates
+    pub distance_km: Option<f64>,
+}
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Serialize)]
+pub struct FindBuddiesNearResponse {
+    /// Closest first
+    pub buddies: Vec<NearbyBuddy>,
+}
+
+/// Why two buddies look like the same person
+#[derive(Debug, Clone, Copy, Deserialize, JsonSchema, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DuplicateReason {
+    SimilarName,
+    SameBirthday,
+    SharedContactMethod,
+}
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Serialize)]
+pub struct GetDuplicatesRequest {
+    pub user_id: Uuid,
+}
+/// Two buddies that are likely the same person
+#[derive(Debug, Clone, Deserialize, JsonSchema, Serialize)]
+pub struct DuplicatePair {
+    /// The buddy that was added first
+    pub first: Buddy,
+    pub second: Buddy,
+    /// How alike the names are, from 0 to 1
+    pub name_similarity: f64,
+    pub reasons: Vec<DuplicateReason>,
+}
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Serialize)]
+pub struct GetDuplicatesResponse {
+    /// Strongest matches first
+    pub duplicates: Vec<DuplicatePair>,
+}
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Serialize)]
+pub struct MergeBuddiesRequest {
+    pub user_id: Uuid,
+    /// The buddy that is kept
+    pub winner_id: Uuid,
+    /// The buddy that is folded into the winner and archived
+    pub loser_id: Uuid,
+}
+#[derive(Debug, Clone, Deserialize, JsonSchema, Serialize)]
+pub struct MergeBuddiesResponse {
+    /// The winner, after the merge
+    pub buddy: Buddy,
+    /// The interactions the loser was moved out of
+    pub interactions_updated: Vec<Uuid>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Serialize)]
+pub struct CreateWebhookRequest {
+    pub user_id: Uuid,
+    /// Where to POST changes to. Must be http or https.
+    pub url: String,
+}
+#[derive(Debug, Clone, Deserialize, JsonSchema, Serialize)]
+pub struct CreateWebhookResponse {
+    pub webhook: Webhook,
+    /// Deliveries carry an `X-Buddies-Signature` header of `sha256=` and the hex
+    /// HMAC-SHA256 of the body under this secret. It isn't shown again.
+    pub secret: String,
+}
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Serialize)]
+pub struct ListWebhooksRequest {
+    pub user_id: Uuid,
+}
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Serialize)]
+pub struct ListWebhooksResponse {
+    /// Oldest first
+    pub webhooks: Vec<Webhook>,
+}
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Serialize)]
+pub struct ArchiveWebhookRequest {
+    pub id: Uuid,
+    pub user_id: Uuid,
+}
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Serialize)]
+pub struct ArchiveWebhookResponse {}
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Serialize)]
+pub struct ListWebhookDeliveriesRequest {
+    pub user_id: Uuid,
+    pub webhook_id: Uuid,
+}
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Serialize)]
+pub struct ListWebhookDeliveriesResponse {
+    /// Newest first
+    pub deliveries: Vec<WebhookDelivery>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Serialize)]
+pub struct CreatePersonalAccessTokenRequest {
+    pub user_id: Uuid,
+    pub name: String,
+    /// At least one
+    pub scopes: Vec<Scope>,
+}
+#[derive(Debug, Clone, Deserialize, JsonSchema, Serialize)]
+pub struct CreatePersonalAccessTokenResponse {
+    pub personal_access_token: PersonalAccessToken,
+    /// Send this as `Authorization: Bearer <token>`. It isn't shown again.
+    pub token: String,
+}
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Serialize)]
+pub struct ListPersonalAccessTokensRequest {
+    pub user_id: Uuid,
+}
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Serialize)]
+pub struct ListPersonalAccessTokensResponse {
+    /// Tokens that haven't been revoked, oldest first
+    pub personal_access_tokens: Vec<PersonalAccessToken>,
+}
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Serialize)]
+pub struct RevokePersonalAccessTokenRequest {
+    pub id: Uuid,
+    pub user_id: Uuid,
+}
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema, Serialize)]
+pub struct RevokePersonalAccessTokenResponse {}
@@ -0,0 +1,135 @@
+use crate::lib::calendar::{escape_text, fold_line};
+use crate::lib::types::{Buddy, ContactMethodKind};
+
+/// Render a buddy as a vCard 3.0 (RFC 2426), which every address book understands
+pub fn buddy_to_vcard(buddy: &Buddy) -> String {
+    let mut lines = vec![
+        "BEGIN:VCARD".to_string(),
+        "VERSION:3.0".to_string(),
+        format!("UID:urn:uuid:{}", buddy.id),
+        format!("FN:{}", escape_text(&buddy.name)),
+        // We only know a single name, so it all goes in the family name slot
+        format!("N:{};;;;", escape_text(&buddy.name)),
+    ];
+    if let Some(birthday) = &buddy.birthday {
+        lines.push(format!("BDAY:{}", birthday.0));
+    }
+    if let Some(location) = &buddy.location {
+        let component =
+            |part: &Option<String>| part.as_deref().map(escape_text).unwrap_or_default();
+        // Free text we couldn't make sense of goes in the street address
+        let street = if location.city.is_none()
+            && location.region.is_none()
+            && location.country_code.is_none()
+        {
+            component(&location.text)
+        } else {
+            String::new()
+        };
+        lines.push(format!(
+            "ADR:;;{};{};{};;{}",
+            street,
+            component(&location.city),
+            component(&location.region),
+            component(&location.country_code)
+        ));
+        if let (Some(latitude), Some(longitude)) = (location.latitude, location.longitude) {
+            lines.push(format!("GEO:{};{}", latitude, longitude));
+        }
+        if let Some(timezone) = &location.timezone {
+            lines.push(format!("TZ;VALUE=text:{}", escape_text(timezone)));
+        }
+    }
+    if !buddy.notes.is_empty() {
+        lines.push(format!("NOTE:{}", escape_text(&buddy.notes)));
+    }
+
+    let mut contact_methods: Vec<_> = buddy.contact_methods.iter().collect();
+    contact_methods.sort_by_key(|method| (!method.preferred, method.create_timestamp.0));
+    for method in contact_methods {
+        let mut types = Vec::new();
+        if let Some(label) = &method.label {
+            types.push(escape_text(label));
+        }
+        if method.preferred {
+            types.push("pref".to_string());
+        }
+        let params = if types.is_empty() {
+            String::new()
+        } else {
+            format!(";TYPE={}", types.join(","))
+        };
+        let value = escape_text(&method.value);
+        let line = match method.kind {
+            ContactMethodKind::Phone => format!("TEL{}:{}", params, value),
+            ContactMethodKind::Email => format!("EMAIL{}:{}", params, value),
+            ContactMethodKind::Url => format!("URL{}:{}", params, value),
+            ContactMethodKind::Social => format!("X-SOCIALPROFILE{}:{}", params, value),
+        };
+        lines.push(line);
+    }
+    lines.push("END:VCARD".to_string());
+
+    lines.iter().map(|line| fold_line(line)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::testing::buddy;
+    use crate::lib::types::{ContactMethod, Location, Timestamp};
+    use uuid::Uuid;
+
+    fn lines(vcard: &str) -> Vec<&str> {
+        vcard
+            .split("\r\n")
+            .filter(|line| !line.is_empty())
+            .collect()
+    }
+
+    #[test]
+    fn separators_in_values_are_escaped() {
+        let mut buddy = buddy("Smith, Jo; Esq.");
+        buddy.notes = "Likes tea\nand C:\\ drives".to_string();
+        buddy.location = Some(Location {
+            text: None,
+            city: Some("Paris, Texas".to_string()),
+            region: Some("TX".to_string()),
+            country_code: Some("US".to_string()),
+            latitude: None,
+            longitude: None,
+            timezone: None,
+        });
+        buddy.contact_methods.push(ContactMethod {
+            id: Uuid::new_v4(),
+            buddy_id: buddy.id,
+            kind: ContactMethodKind::Social,
+            value: "@jo;smith".to_string(),
+            label: Some("home, mostly".to_string()),
+            preferred: true,
+            create_timestamp: Timestamp(0),
+            last_update_timestamp: Timestamp(0),
+            delete_timestamp: None,
+            user_id: Uuid::nil(),
+        });
+
+        let vcard = buddy_to_vcard(&buddy);
+        let lines = lines(&vcard);
+        assert!(lines.contains(&r"FN:Smith\, Jo\; Esq."));
+        assert!(lines.contains(&r"N:Smith\, Jo\; Esq.;;;;"));
+        assert!(lines.contains(&r"NOTE:Likes tea\nand C:\\ drives"));
+        assert!(lines.contains(&r"ADR:;;;Paris\, Texas;TX;;US"));
+        assert!(lines.contains(&r"X-SOCIALPROFILE;TYPE=home\, mostly,pref:@jo\;smith"));
+    }
+
+    #[test]
+    fn long_notes_are_folded() {
+        let mut buddy = buddy("Jo");
+        buddy.notes = "word ".repeat(40);
+
+        let vcard = buddy_to_vcard(&buddy);
+        assert!(vcard.ends_with("END:VCARD\r\n"));
+        assert!(lines(&vcard).iter().all(|line| line.len() <= 75));
+        assert!(vcard.contains("\r\n word"));
+    }
+}
@@ -0,0 +1,438 @@
+use crate::lib::events::Events;
+use crate::lib::storage::BuddiesStore;
+use crate::lib::types::{DeliveryStatus, InvalidRequestError, Timestamp, Webhook, WebhookDelivery};
+use anyhow::{anyhow, Context, Result};
+use futures::stream::{self, StreamExt};
+use hmac::{Hmac, Mac, NewMac};
+use log::{error, warn};
+use reqwest::header::CONTENT_TYPE;
+use reqwest::redirect::Policy;
+use reqwest::Client;
+use sha2::Sha256;
+use std::net::IpAddr;
+use std::time::{Duration, SystemTime};
+use tokio::net::lookup_host;
+use tokio::time::delay_for;
+use url::{Host, Url};
+
+/// Carries `sha256=` and the hex HMAC-SHA256 of the body under the webhook's secret
+pub const SIGNATURE_HEADER: &str = "X-Buddies-Signature";
+/// Carries the delivery's id, which stays the same across retries
+pub const DELIVERY_HEADER: &str = "X-Buddies-Delivery";
+/// Tries before we give up on a delivery
+const MAX_ATTEMPTS: i32 = 8;
+/// How long to wait before the first retry. Each one after waits twice as long.
+const FIRST_RETRY_DELAY_SECS: u64 = 30;
+/// How long a webhook gets to respond
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+/// How often to look for retries that have come due when nothing is changing
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+/// How many deliveries can be waiting on a response at once
+const MAX_IN_FLIGHT: usize = 16;
+
+/// The value of the signature header for a body
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_varkey(secret.as_bytes()).expect("HMAC takes any key");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// Whether an address is out on the internet, rather than loopback, link-local or private
+pub fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [first, second, ..] = ip.octets();
+            // 100.64.0.0/10 is shared between carrier-grade NAT customers
+            let shared = first == 100 && second & 0xc0 == 64;
+            !(ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_multicast()
+                || shared)
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public(IpAddr::V4(ip)),
+            None => {
+                !(ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_multicast()
+                    || ip.is_unique_local()
+                    || ip.is_unicast_link_local())
+            }
+        },
+    }
+}
+
+/// Refuse webhook urls that point into our own network. Names are checked again each time
+/// they're looked up for a delivery, since they can be pointed anywhere.
+pub fn check_webhook_url(url: &Url) -> Result<()> {
+    let allowed = match url.host() {
+        Some(Host::Ipv4(ip)) => is_public(IpAddr::V4(ip)),
+        Some(Host::Ipv6(ip)) => is_public(IpAddr::V6(ip)),
+        Some(Host::Domain(domain)) => {
+            let domain = domain.trim_end_matches('.').to_ascii_lowercase();
+            domain != "localhost" && !domain.ends_with(".localhost")
+        }
+        None => false,
+    };
+    if allowed {
+        Ok(())
+    } else {
+        Err(InvalidRequestError {
+            message: format!("Webhook url {} must be on a public host", url),
+        }
+        .into())
+    }
+}
+
+/// Look up where a url points, failing if any of its addresses aren't `allowed`. The client
+/// looks the name up again when it connects, so this narrows rather than closes the window
+/// for a name that changes in between.
+async fn check_addresses(url: &Url, allowed: fn(IpAddr) -> bool) -> Result<()> {
+    let port = url.port_or_known_default().unwrap_or(80);
+    let addresses: Vec<IpAddr> = match url.host() {
+        Some(Host::Ipv4(ip)) => vec![IpAddr::V4(ip)],
+        Some(Host::Ipv6(ip)) => vec![IpAddr::V6(ip)],
+        Some(Host::Domain(domain)) => lookup_host((domain, port))
+            .await
+            .context(format!("Looking up {}", domain))?
+            .map(|address| address.ip())
+            .collect(),
+        None => Vec::new(),
+    };
+    if addresses.is_empty() {
+        return Err(anyhow!("{} doesn't resolve to any address", url));
+    }
+    match addresses.into_iter().find(|ip| !allowed(*ip)) {
+        Some(ip) => Err(anyhow!("{} resolves to {}, which isn't public", url, ip)),
+        None => Ok(()),
+    }
+}
+
+/// How long to wait after a delivery has failed `attempts` times
+fn retry_delay(attempts: i32) -> u64 {
+    FIRST_RETRY_DELAY_SECS << (attempts - 1).clamp(0, MAX_ATTEMPTS)
+}
+
+/// Send every delivery that's due, then wait for changes or retries to come due. Runs forever.
+pub async fn deliver_webhooks<S: BuddiesStore>(mut storage: S, events: Events) {
+    // A redirect could send us somewhere the address checks would have refused
+    let client = Client::builder()
+        .timeout(DELIVERY_TIMEOUT)
+        .redirect(Policy::none())
+        .build();
+    let client = match client {
+        Ok(client) => client,
+        Err(e) => {
+            error!(
+                "Webhooks won't be delivered, couldn't build a client {:?}",
+                e
+            );
+            return;
+        }
+    };
+    let mut changes = events.listen();
+    loop {
+        if let Err(e) = deliver_due(&client, &mut storage, is_public).await {
+            error!("Delivering webhooks {:?}", e);
+        }
+        // Changes queue deliveries, so go again as soon as there is one
+        tokio::select! {
+            _ = changes.recv() => {}
+            _ = delay_for(POLL_INTERVAL) => {}
+        }
+    }
+}
+
+/// Send every delivery that's due, a few at a time, to addresses that are `allowed`
+async fn deliver_due<S: BuddiesStore>(
+    client: &Client,
+    storage: &mut S,
+    allowed: fn(IpAddr) -> bool,
+) -> Result<()> {
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)?
+        .as_secs();
+    let due = storage
+        .get_due_webhook_deliveries(Timestamp(now))
+        .context("getting due webhook deliveries")?;
+    let mut attempts = Vec::new();
+    for delivery in due {
+        let webhook = storage
+            .get_webhooks(delivery.user_id)
+            .context("getting webhooks")?
+            .remove(&delivery.webhook_id);
+        attempts.push((delivery, webhook));
+    }
+
+    let mut attempts = stream::iter(attempts)
+        .map(|(mut delivery, webhook)| async move {
+            match webhook {
+                Some(webhook) => {
+                    let outcome = send(client, &webhook, &delivery, allowed).await;
+                    record_attempt(&mut delivery, outcome, now);
+                }
+                // Archived since the change was queued, so there's no one to tell
+                None => {
+                    delivery.status = DeliveryStatus::Failed;
+                    delivery.next_attempt_timestamp = None;
+                    delivery.error = Some("The webhook was archived".to_string());
+                    delivery.last_update_timestamp = Timestamp(now);
+                }
+            }
+            delivery
+        })
+        .buffer_unordered(MAX_IN_FLIGHT);
+    while let Some(delivery) = attempts.next().await {
+        storage
+            .update_webhook_delivery(delivery)
+            .context("recording webhook delivery attempt")?;
+    }
+    Ok(())
+}
+
+/// POST a delivery's payload, returning the status code it got back
+async fn send(
+    client: &Client,
+    webhook: &Webhook,
+    delivery: &WebhookDelivery,
+    allowed: fn(IpAddr) -> bool,
+) -> Result<u16> {
+    let url = Url::parse(&webhook.url).context(format!("Parsing {}", webhook.url))?;
+    check_addresses(&url, allowed).await?;
+    let body = delivery.payload.to_string();
+    let response = client
+        .post(url)
+        .header(CONTENT_TYPE, "application/json")
+        .header(SIGNATURE_HEADER, sign(&webhook.secret, body.as_bytes()))
+        .header(DELIVERY_HEADER, delivery.id.to_string())
+        .body(body)
+        .send()
+        .await
+        .context(format!("Posting to {}", webhook.url))?;
+    Ok(response.status().as_u16())
+}
+
+/// Mark a delivery as done, or schedule its next try until it's out of them
+fn record_attempt(delivery: &mut WebhookDelivery, outcome: Result<u16>, now: u64) {
+    delivery.attempts += 1;
+    delivery.last_update_timestamp = Timestamp(now);
+    let failure = match outcome {
+        Ok(status) => {
+            delivery.response_status = Some(status.into());
+            if (200..300).contains(&status) {
+                None
+            } else {
+                Some(format!("Responded with {}", status))
+            }
+        }
+        Err(e) => {
+            delivery.response_status = None;
+            Some(format!("{:#}", e))
+        }
+    };
+    match failure {
+        None => {
+            delivery.status = DeliveryStatus::Delivered;
+            delivery.next_attempt_timestamp = None;
+            delivery.error = None;
+        }
+        Some(failure) => {
+            warn!(
+                "Delivery {} to webhook {} failed {}",
+                delivery.id, delivery.webhook_id, failure
+            );
+            delivery.error = Some(failure);
+            if delivery.attempts >= MAX_ATTEMPTS {
+                delivery.status = DeliveryStatus::Failed;
+                delivery.next_attempt_timestamp = None;
+            } else {
+                let delay = retry_delay(delivery.attempts);
+                delivery.next_attempt_timestamp = Some(Timestamp(now + delay));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::storage::MemoryBuddiesStore;
+    use serde_json::json;
+    use std::sync::{Arc, Mutex};
+    use uuid::Uuid;
+    use warp::http::{HeaderMap, StatusCode};
+    use warp::hyper::body::Bytes;
+    use warp::Filter;
+
+    /// The headers and body of every request a receiver got
+    type Received = Arc<Mutex<Vec<(HeaderMap, String)>>>;
+
+    /// Start a webhook receiver on a local port that answers with each of `statuses` in
+    /// turn, then with 200s
+    fn receiver(statuses: Vec<u16>) -> (Url, Received) {
+        let received = Received::default();
+        let log = received.clone();
+        let statuses = Arc::new(Mutex::new(statuses.into_iter()));
+        let hook = warp::post()
+            .and(warp::path("hook"))
+            .and(warp::header::headers_cloned())
+            .and(warp::body::bytes())
+            .map(move |headers, body: Bytes| {
+                let body = String::from_utf8(body.to_vec()).unwrap();
+                log.lock().unwrap().push((headers, body));
+                let status = statuses.lock().unwrap().next().unwrap_or(200);
+                warp::reply::with_status("", StatusCode::from_u16(status).unwrap())
+            });
+        let (address, server) = warp::serve(hook).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        (
+            Url::parse(&format!("http://{}/hook", address)).unwrap(),
+            received,
+        )
+    }
+
+    /// Save a webhook for `url` and a delivery to it that's due now
+    fn queue(store: &mut MemoryBuddiesStore, url: &Url) -> WebhookDelivery {
+        let user_id = Uuid::new_v4();
+        let webhook = Webhook {
+            id: Uuid::new_v4(),
+            url: url.to_string(),
+            secret: "secret".to_string(),
+            create_timestamp: Timestamp(0),
+            delete_timestamp: None,
+            user_id,
+        };
+        let delivery = WebhookDelivery {
+            id: Uuid::new_v4(),
+            webhook_id: webhook.id,
+            payload: json!({ "kind": "buddy_created" }),
+            status: DeliveryStatus::Pending,
+            attempts: 0,
+            next_attempt_timestamp: None,
+            response_status: None,
+            error: None,
+            create_timestamp: Timestamp(0),
+            last_update_timestamp: Timestamp(0),
+            user_id,
+        };
+        store.create_webhook(webhook).unwrap();
+        store.create_webhook_delivery(delivery.clone()).unwrap();
+        delivery
+    }
+
+    fn saved(store: &MemoryBuddiesStore, delivery: &WebhookDelivery) -> WebhookDelivery {
+        store
+            .get_webhook_deliveries(delivery.user_id, delivery.webhook_id)
+            .unwrap()
+            .pop()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn deliveries_are_signed_and_retried_until_they_succeed() {
+        let (url, received) = receiver(vec![500]);
+        let mut store = MemoryBuddiesStore::new();
+        let delivery = queue(&mut store, &url);
+        let client = Client::new();
+
+        deliver_due(&client, &mut store, |_| true).await.unwrap();
+        {
+            let received = received.lock().unwrap();
+            assert_eq!(received.len(), 1);
+            let (headers, body) = &received[0];
+            assert_eq!(headers[SIGNATURE_HEADER], sign("secret", body.as_bytes()));
+            assert_eq!(headers[DELIVERY_HEADER], delivery.id.to_string());
+        }
+        let failed = saved(&store, &delivery);
+        assert_eq!(failed.status, DeliveryStatus::Pending);
+        assert_eq!(failed.attempts, 1);
+        assert_eq!(failed.response_status, Some(500));
+        assert_eq!(
+            failed.next_attempt_timestamp,
+            Some(Timestamp(
+                failed.last_update_timestamp.0 + FIRST_RETRY_DELAY_SECS
+            ))
+        );
+
+        // Nothing is sent again until the retry comes due
+        deliver_due(&client, &mut store, |_| true).await.unwrap();
+        assert_eq!(received.lock().unwrap().len(), 1);
+
+        store
+            .update_webhook_delivery(WebhookDelivery {
+                next_attempt_timestamp: Some(Timestamp(0)),
+                ..failed
+            })
+            .unwrap();
+        deliver_due(&client, &mut store, |_| true).await.unwrap();
+        {
+            let received = received.lock().unwrap();
+            assert_eq!(received.len(), 2);
+            assert_eq!(received[1].0[DELIVERY_HEADER], delivery.id.to_string());
+        }
+        let delivered = saved(&store, &delivery);
+        assert_eq!(delivered.status, DeliveryStatus::Delivered);
+        assert_eq!(delivered.attempts, 2);
+        assert_eq!(delivered.response_status, Some(200));
+        assert_eq!(delivered.next_attempt_timestamp, None);
+        assert_eq!(delivered.error, None);
+    }
+
+    #[test]
+    fn retries_back_off() {
+        assert_eq!(retry_delay(1), FIRST_RETRY_DELAY_SECS);
+        assert_eq!(retry_delay(2), FIRST_RETRY_DELAY_SECS * 2);
+        assert_eq!(retry_delay(3), FIRST_RETRY_DELAY_SECS * 4);
+    }
+
+    #[tokio::test]
+    async fn nothing_is_sent_to_private_addresses() {
+        let (url, received) = receiver(vec![]);
+        let mut store = MemoryBuddiesStore::new();
+        let delivery = queue(&mut store, &url);
+
+        deliver_due(&Client::new(), &mut store, is_public)
+            .await
+            .unwrap();
+        assert!(received.lock().unwrap().is_empty());
+        let refused = saved(&store, &delivery);
+        assert_eq!(refused.attempts, 1);
+        assert_eq!(refused.response_status, None);
+        assert!(refused.error.unwrap().contains("isn't public"));
+    }
+
+    #[test]
+    fn webhook_urls_must_be_on_public_hosts() {
+        for url in &[
+            "http://127.0.0.1/hook",
+            "http://localhost:8080/hook",
+            "http://api.localhost/hook",
+            "http://169.254.169.254/latest/meta-data",
+            "http://10.1.2.3/hook",
+            "http://172.16.0.1/hook",
+            "http://192.168.1.1/hook",
+            "http://100.64.0.1/hook",
+            "http://0.0.0.0/hook",
+            "http://[::1]/hook",
+            "http://[::ffff:127.0.0.1]/hook",
+            "http://[fd00::1]/hook",
+            "http://[fe80::1]/hook",
+        ] {
+            assert!(
+                check_webhook_url(&Url::parse(url).unwrap()).is_err(),
+                "{} was let through",
+                url
+            );
+        }
+        for url in &["https://example.com/hook", "http://93.184.216.34/hook"] {
+            assert!(
+                check_webhook_url(&Url::parse(url).unwrap()).is_ok(),
+                "{}",
+                url
+            );
+        }
+    }
+}
@@ -1,14 +1,19 @@
+#![recursion_limit = "256"]
+
 use anyhow::{anyhow, Context, Result};
 use clap::arg_enum;
 use env_logger::Env;
 use lib::routes::build_warp_routes;
-use lib::service::{AuthHandler, RequestHandler};
-use lib::storage::{MemoryBuddiesStore, PsqlBuddiesStore};
+use lib::service::{AuthHandler, BuddiesService, RequestHandler};
+use lib::storage::{BuddiesStore, MemoryBuddiesStore, PsqlBuddiesStore};
+use lib::types::GetDigestRequest;
+use lib::webhooks::deliver_webhooks;
 use log::info;
 use std::env;
 use std::fs;
 use std::path::PathBuf;
 use structopt::StructOpt;
+use uuid::Uuid;
 
 // This is for the psql impl that's not yet built
 #[macro_use]
@@ -50,6 +55,37 @@ struct Args {
     private_key: Option<String>,
     #[structopt(long, env = "PUBLIC_KEY", hidden = true)]
     public_key: Option<String>,
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, StructOpt)]
+enum Command {
+    /// Generate the digest of who to reach out to for a user
+    Digest {
+        /// The id of the user to generate the digest for
+        #[structopt(long)]
+        user: Uuid,
+        /// Print the digest instead of sending it
+        #[structopt(long)]
+        dry_run: bool,
+    },
+}
+
+fn run_digest<S: BuddiesStore>(storage: S, user_id: Uuid, dry_run: bool) -> Result<()> {
+    let handler = RequestHandler::new(storage);
+    let response = handler
+        .get_digest(GetDigestRequest { user_id })
+        .context("Generating digest")?;
+    if !dry_run {
+        return Err(anyhow!(
+            "Email delivery is not configured yet. Use --dry-run to preview the digest"
+        ));
+    }
+    println!("To: {}\n", response.digest.email);
+    println!("{}", response.text);
+    println!("{}", response.html);
+    Ok(())
 }
 
 #[tokio::main]
@@ -63,6 +99,16 @@ async fn main() -> Result<()> {
         env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
     }
 
+    if let Some(Command::Digest { user, dry_run }) = args.command {
+        return match args.storage_type {
+            Storage::Psql => {
+                info!("Connecting to database at url: {}", args.database_url);
+                run_digest(PsqlBuddiesStore::new(&args.database_url), user, dry_run)
+            }
+            Storage::Memory => run_digest(MemoryBuddiesStore::new(), user, dry_run),
+        };
+    }
+
     let port = match env::var("PORT") {
         Ok(port) => {
             info!(
@@ -101,7 +147,8 @@ async fn main() -> Result<()> {
             let buddies_store = PsqlBuddiesStore::new(&args.database_url);
             let auth_handler = AuthHandler::new(buddies_store.clone(), secret, public)
                 .context("creating auth handler")?;
-            let handler = RequestHandler::new(buddies_store);
+            let handler = RequestHandler::new(buddies_store.clone());
+            tokio::spawn(deliver_webhooks(buddies_store, handler.events.clone()));
             let routes = build_warp_routes(auth_handler, handler);
             info!("Running server on port {}", port);
             warp::serve(routes).run(([0, 0, 0, 0], port)).await;
@@ -111,7 +158,8 @@ async fn main() -> Result<()> {
             let buddies_store = MemoryBuddiesStore::new();
             let auth_handler = AuthHandler::new(buddies_store.clone(), secret, public)
                 .context("creating auth handler")?;
-            let handler = RequestHandler::new(buddies_store);
+            let handler = RequestHandler::new(buddies_store.clone());
+            tokio::spawn(deliver_webhooks(buddies_store, handler.events.clone()));
             let routes = build_warp_routes(auth_handler, handler);
             info!("Running server on port {}", port);
             warp::serve(routes).run(([0, 0, 0, 0], port)).await;